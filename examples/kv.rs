@@ -0,0 +1,246 @@
+//! A key-value store on a TAPIR cluster over TCP, with each replica's record in a log file.
+//!
+//! ```text
+//! cargo run --example kv -- launch examples/kv-cluster.conf data
+//! cargo run --example kv -- client examples/kv-cluster.conf put alice 10
+//! cargo run --example kv -- client examples/kv-cluster.conf
+//! > cas alice 10 20
+//! > get alice
+//! ```
+//!
+//! `launch` starts a `serve` process for every node in the config file, which lists one node
+//! per line as its id and address, such as `1 127.0.0.1:7001`; lines starting with `#` are
+//! comments. `serve <config> <id> <data-dir>` runs a single node, keeping its log in
+//! `<data-dir>/node-<id>.log`. `client` runs one command, or reads commands from stdin.
+//!
+//! A fresh node bootstraps view 0 of the configured members. A node restarted on its log
+//! reloads its record and view, and stays in recovery: this crate does not yet run the view
+//! change that would bring it back. The cluster keeps serving while a quorum of its nodes has
+//! not been restarted, and starting it over needs an empty data directory.
+
+use inconsistent_replication_ir::hlc::{HybridLogicalClock, SystemTimeSource};
+use inconsistent_replication_ir::layers::{IRNetworkExt, Layered, TimeoutLayer};
+use inconsistent_replication_ir::tapir::{TapirClient, TapirError, TapirMessage, TapirReplica};
+use inconsistent_replication_ir::tcp::{self, TcpNetwork};
+use inconsistent_replication_ir::{
+    BootstrapError, InconsistentReplicationClient, InconsistentReplicationServer, LogStorage,
+    MembersClientStorage,
+};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpListener;
+
+type Message = TapirMessage<String, String>;
+type Network = Layered<TcpNetwork<u64, Message>, TimeoutLayer, u64, Message>;
+type Client =
+    TapirClient<Network, MembersClientStorage<u64>, u64, String, String, SystemTimeSource>;
+
+/// How long the client waits for a node before treating it as unreachable
+const CALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+const USAGE: &str = "usage: kv launch <config> <data-dir>
+       kv serve <config> <node-id> <data-dir>
+       kv client <config> [get <key> | put <key> <value> | cas <key> <expected|-> <new>]";
+
+/// The address of each node, from a config file
+fn read_config(path: &str) -> Result<BTreeMap<u64, SocketAddr>, String> {
+    let config = std::fs::read_to_string(path).map_err(|error| format!("{path}: {error}"))?;
+    let mut addresses = BTreeMap::new();
+    for line in config.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (id, address) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("{path}: expected `<id> <address>`, found {line:?}"))?;
+        let id = id
+            .parse()
+            .map_err(|_| format!("{path}: invalid node id {id:?}"))?;
+        let address = address
+            .trim()
+            .parse()
+            .map_err(|_| format!("{path}: invalid address {address:?}"))?;
+        if addresses.insert(id, address).is_some() {
+            return Err(format!("{path}: node {id} is listed twice"));
+        }
+    }
+    if addresses.len() < 3 {
+        return Err(format!("{path}: a cluster needs at least 3 nodes"));
+    }
+    Ok(addresses)
+}
+
+/// Start a `serve` process for every node, and wait for them to exit
+async fn launch(config: &str, data_dir: &str) -> Result<(), String> {
+    let addresses = read_config(config)?;
+    let program = std::env::current_exe().map_err(|error| error.to_string())?;
+    let mut nodes = Vec::with_capacity(addresses.len());
+    for id in addresses.keys() {
+        let node = tokio::process::Command::new(&program)
+            .args(["serve", config, &id.to_string(), data_dir])
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|error| format!("starting node {id}: {error}"))?;
+        nodes.push((id, node));
+    }
+    for (id, mut node) in nodes {
+        let status = node.wait().await.map_err(|error| error.to_string())?;
+        println!("node {id} exited: {status}");
+    }
+    Ok(())
+}
+
+/// Run one node until its listener fails
+async fn serve(config: &str, id: &str, data_dir: &str) -> Result<(), String> {
+    let addresses = read_config(config)?;
+    let id: u64 = id.parse().map_err(|_| format!("invalid node id {id:?}"))?;
+    let address = *addresses
+        .get(&id)
+        .ok_or_else(|| format!("{config}: node {id} is not listed"))?;
+    let members: Vec<u64> = addresses.keys().copied().collect();
+    std::fs::create_dir_all(data_dir).map_err(|error| format!("{data_dir}: {error}"))?;
+    let log = Path::new(data_dir).join(format!("node-{id}.log"));
+    let storage: LogStorage<u64, Message> = LogStorage::open(&log, members.clone())
+        .map_err(|error| format!("{}: {error}", log.display()))?;
+
+    let server = InconsistentReplicationServer::new(
+        TcpNetwork::new(addresses),
+        storage,
+        TapirReplica::new(),
+        id,
+    )
+    .await;
+    match server.bootstrap(members).await {
+        Ok(()) => println!("node {id}: bootstrapped view 0"),
+        Err(BootstrapError::ExistingState(view)) => println!(
+            "node {id}: recovered view {} from {}, waiting for a view change",
+            view.view,
+            log.display()
+        ),
+        Err(error) => return Err(format!("node {id}: {error:?}")),
+    }
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|error| format!("{address}: {error}"))?;
+    println!("node {id}: listening on {address}");
+    tcp::serve(listener, Arc::new(server))
+        .await
+        .map_err(|error| format!("node {id}: {error}"))
+}
+
+/// A client number for this process, as every client must have a different one
+fn client_number() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default();
+    nanos ^ (u64::from(std::process::id()) << 32)
+}
+
+async fn connect(config: &str) -> Result<Client, String> {
+    let addresses = read_config(config)?;
+    let members = addresses.keys().copied().collect();
+    let network = TcpNetwork::new(addresses).with_layer(TimeoutLayer::new(CALL_TIMEOUT));
+    let client_number = client_number();
+    let client = InconsistentReplicationClient::new(
+        network,
+        MembersClientStorage::new(members),
+        client_number,
+    )
+    .await;
+    Ok(TapirClient::new(
+        client,
+        client_number,
+        HybridLogicalClock::new(SystemTimeSource),
+    ))
+}
+
+async fn get(client: &Client, key: String) -> Result<Option<String>, TapirError> {
+    let mut transaction = client.begin();
+    client.read(&mut transaction, key).await
+}
+
+async fn put(client: &Client, key: String, value: String) -> Result<(), TapirError> {
+    let mut transaction = client.begin();
+    transaction.write(key, value);
+    client.commit(transaction).await?;
+    Ok(())
+}
+
+/// Write `new` if the key holds `expected`, where `-` stands for no value
+async fn cas(
+    client: &Client,
+    key: String,
+    expected: String,
+    new: String,
+) -> Result<bool, TapirError> {
+    let mut transaction = client.begin();
+    let current = client.read(&mut transaction, key.clone()).await?;
+    if current.as_deref().unwrap_or("-") != expected {
+        return Ok(false);
+    }
+    transaction.write(key, new);
+    client.commit(transaction).await?;
+    Ok(true)
+}
+
+async fn execute(client: &Client, command: &[&str]) -> String {
+    let result = match command {
+        ["get", key] => get(client, key.to_string())
+            .await
+            .map(|value| value.unwrap_or_else(|| "(none)".to_string())),
+        ["put", key, value] => put(client, key.to_string(), value.to_string())
+            .await
+            .map(|_| "ok".to_string()),
+        ["cas", key, expected, new] => cas(
+            client,
+            key.to_string(),
+            expected.to_string(),
+            new.to_string(),
+        )
+        .await
+        .map(|written| if written { "ok" } else { "mismatch" }.to_string()),
+        _ => return "usage: get <key> | put <key> <value> | cas <key> <expected|-> <new>".into(),
+    };
+    result.unwrap_or_else(|error| format!("error: {error:?}"))
+}
+
+/// Run a command, or each command read from stdin
+async fn client(config: &str, command: &[&str]) -> Result<(), String> {
+    let client = connect(config).await?;
+    if !command.is_empty() {
+        println!("{}", execute(&client, command).await);
+        return Ok(());
+    }
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let command: Vec<&str> = line.split_whitespace().collect();
+        match command.as_slice() {
+            [] => continue,
+            ["quit"] | ["exit"] => break,
+            command => println!("{}", execute(&client, command).await),
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["launch", config, data_dir] => launch(config, data_dir).await,
+        ["serve", config, id, data_dir] => serve(config, id, data_dir).await,
+        ["client", config, command @ ..] => client(config, command).await,
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
@@ -99,14 +99,15 @@ impl DecideFunction<LinearizableComputeOperation> for TestDecideFunction {
     fn decide<'a, S: IntoIterator<Item = &'a LinearizableComputeOperation>>(
         &self,
         choices: S,
-    ) -> &'a LinearizableComputeOperation {
-        choices.into_iter().next().unwrap()
+        _f: usize,
+    ) -> LinearizableComputeOperation {
+        choices.into_iter().next().unwrap().clone()
     }
 }
 
 fuzz_target!(|data: TestScenario| {
     // Create cluster
-    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _, LinearizableComputer>>::new();
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, LinearizableComputer>::new();
     let members: Vec<usize> = (0..data.nodes).collect();
     for i in 0..data.nodes {
         smol::block_on(async {
@@ -114,7 +115,8 @@ fuzz_target!(|data: TestScenario| {
                 i,
                 InconsistentReplicationServer::new(
                     network.clone(),
-                    FakeIRStorage::new(members.clone(), LinearizableComputer::new()),
+                    FakeIRStorage::new(members.clone()),
+                    LinearizableComputer::new(),
                     i,
                 )
                 .await,
@@ -131,8 +133,7 @@ fuzz_target!(|data: TestScenario| {
         let client = smol::block_on(async move {
             InconsistentReplicationClient::new(
                 network_clone,
-                // The computer isn't used on the client, but it is part of the shared storage interface
-                FakeIRStorage::new(members_clone, LinearizableComputer::new()),
+                FakeIRStorage::new(members_clone),
                 client_id,
             )
             .await
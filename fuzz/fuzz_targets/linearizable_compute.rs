@@ -1,7 +1,10 @@
 use crate::{KEY, MAX_KEYS, VALUE};
 use arbitrary::{Arbitrary, Unstructured};
-use inconsistent_replication_ir::test_utils::MockOperationHandler;
+use inconsistent_replication_ir::types::NodeID;
+use inconsistent_replication_ir::{IRApplication, IROperation};
 use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::{Arc, RwLock};
 
 #[derive(Clone, Default)]
@@ -25,57 +28,33 @@ pub enum LinearizableComputeOperation {
     },
 }
 
-impl MockOperationHandler<LinearizableComputeOperation> for LinearizableComputer {
-    fn evaluate_inconsistent(
+impl<I: NodeID> IRApplication<I, LinearizableComputeOperation> for LinearizableComputer {
+    fn exec_inconsistent(
         &self,
         message: LinearizableComputeOperation,
-    ) -> LinearizableComputeOperation {
-        self.exec(message)
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let _unused = self.exec(message);
+        Box::pin(async {})
     }
 
-    fn exec_inconsistent(
+    fn exec_consensus(
         &self,
         message: LinearizableComputeOperation,
-    ) -> LinearizableComputeOperation {
-        self.exec(message)
+    ) -> Pin<Box<dyn Future<Output = LinearizableComputeOperation> + Send>> {
+        let result = self.exec(message);
+        Box::pin(async move { result })
     }
 
-    fn exec_consistent(
-        &self,
-        message: LinearizableComputeOperation,
-    ) -> LinearizableComputeOperation {
-        self.exec(message)
+    fn decide(&self, choices: Vec<LinearizableComputeOperation>, _f: usize) -> LinearizableComputeOperation {
+        choices.into_iter().next().unwrap()
     }
 
-    fn reconcile_consistent(
+    fn sync(
         &self,
-        _previous_response: Option<LinearizableComputeOperation>,
-        decided_response: LinearizableComputeOperation,
-    ) -> LinearizableComputeOperation {
-        match decided_response {
-            LinearizableComputeOperation::ReadOperation {
-                key,
-                computed_value,
-            } => {
-                // There is nothing to reconcile for read operations
-                LinearizableComputeOperation::ReadOperation {
-                    key,
-                    computed_value,
-                }
-            }
-            LinearizableComputeOperation::WriteOperation {
-                key,
-                requested_value,
-                computed_value,
-            } => {
-                // We are going to ignore our previous response and just apply the decided response
-                LinearizableComputeOperation::WriteOperation {
-                    key,
-                    requested_value,
-                    computed_value,
-                }
-            }
-        }
+        _record: Vec<IROperation<I, LinearizableComputeOperation>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        // We are going to ignore our previous responses and just keep the applied state
+        Box::pin(async {})
     }
 }
 
@@ -0,0 +1,93 @@
+use crate::server::IROperation;
+use crate::types::{IRMessage, NodeID};
+use std::future::Future;
+use std::pin::Pin;
+
+/// The operations of a merged record
+type MergedRecord<ID, MSG> =
+    Pin<Box<dyn Future<Output = Vec<IROperation<ID, MSG>>> + Send + 'static>>;
+
+/// The application upcalls that the replicas make into the replicated service.
+/// These follow the interface in Figure 2 of the extended paper; the record and views are
+/// handled by `IRStorage` so any storage backend can be used with any application.
+///
+/// Messages are used both for the operation and its result, so an upcall returning a message
+/// is returning the operation with its result filled in.
+pub trait IRApplication<ID: NodeID, MSG: IRMessage>: Clone + Send + Sync + 'static {
+    /// ExecInconsistent(op) - invoked when an inconsistent operation is finalized
+    fn exec_inconsistent(&self, message: MSG)
+        -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+    /// ExecConsensus(op) - invoked when a consensus operation is proposed, returning the result
+    /// The result is tentative until the operation is finalized
+    fn exec_consensus(&self, message: MSG) -> Pin<Box<dyn Future<Output = MSG> + Send + 'static>>;
+
+    /// Invoked on learners when a consensus operation is finalized
+    /// Learners do not take part in proposals, so they see only the decided result.
+    ///
+    /// The default implementation executes the result with `exec_consensus`.
+    fn exec_finalized_consensus(
+        &self,
+        message: MSG,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let execution = self.exec_consensus(message);
+        Box::pin(async move {
+            execution.await;
+        })
+    }
+
+    /// Invoked when an inconsistent operation is proposed, returning the reply to the client
+    /// The operation is not finalized yet, so this must not change the application state.
+    ///
+    /// The default implementation replies with the operation itself, which `invoke_inconsistent`
+    /// relies on to find a quorum. Applications that reply with their state, such as a read,
+    /// are invoked with `invoke_inconsistent_merged` instead.
+    fn reply_inconsistent(
+        &self,
+        message: MSG,
+    ) -> Pin<Box<dyn Future<Output = MSG> + Send + 'static>> {
+        Box::pin(async move { message })
+    }
+
+    /// Decide(results) - pick a single result from the candidates of a consensus operation
+    /// Invoked by the leader during a view change for operations that did not reach a quorum
+    /// `f` is the number of failures the view tolerates, see `DecideFunction`.
+    fn decide(&self, choices: Vec<MSG>, f: usize) -> MSG;
+
+    /// Merge(d, u) - resolve the tentative consensus operations during a view change
+    /// `decided` contains operations that had a majority result, `undecided` contains every
+    /// reported version of an operation that had no majority result.
+    ///
+    /// The default implementation keeps the majority results and uses `decide` for the rest.
+    fn merge(
+        &self,
+        decided: Vec<IROperation<ID, MSG>>,
+        undecided: Vec<Vec<IROperation<ID, MSG>>>,
+        f: usize,
+    ) -> MergedRecord<ID, MSG> {
+        let mut merged: Vec<IROperation<ID, MSG>> = decided
+            .into_iter()
+            .map(IROperation::into_finalized)
+            .collect();
+        for versions in undecided {
+            let first = match versions.first() {
+                None => continue,
+                Some(first) => first,
+            };
+            let choices = versions.iter().map(|op| op.message().clone()).collect();
+            merged.push(IROperation::ConsistentFinalize {
+                client: first.client().clone(),
+                sequence: *first.sequence(),
+                message: self.decide(choices, f),
+            });
+        }
+        Box::pin(async move { merged })
+    }
+
+    /// Sync(R) - bring the application state in line with the master record after a view change
+    /// Tentative consensus results that differ from the master record must be rolled back
+    fn sync(
+        &self,
+        record: Vec<IROperation<ID, MSG>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+}
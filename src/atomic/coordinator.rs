@@ -0,0 +1,196 @@
+use crate::atomic::{AtomicDecide, AtomicMessage, Outcome, TransactionId, TransactionState, Vote};
+use crate::client::{InconsistentReplicationClient, KeyedMessage, Partitioner};
+use crate::io::{IRClientStorage, IRNetwork};
+use crate::types::NodeID;
+use futures::future::join_all;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Why a transaction did not commit
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CommitError {
+    /// A participant group voted against the transaction, and it was aborted everywhere
+    Aborted,
+    /// The outcome was decided but not delivered to every participant; `resolve` finishes it
+    InDoubt(TransactionId, Outcome),
+    /// A group could not be reached, so nothing was decided
+    Replication(&'static str),
+}
+
+/// Commits transactions atomically across the groups of a sharded deployment
+/// Operations are routed to groups by their key, as with `ShardedClient`.
+pub struct CommitCoordinator<N, S, I, M, P>
+where
+    N: IRNetwork<I, AtomicMessage<M>>,
+    S: IRClientStorage<I, AtomicMessage<M>>,
+    I: NodeID,
+    M: KeyedMessage,
+    P: Partitioner<M::Key>,
+{
+    shards: Vec<InconsistentReplicationClient<N, S, I, AtomicMessage<M>>>,
+    partitioner: P,
+    /// Included in transaction ids, so it must be unique among coordinators
+    coordinator_number: u64,
+    transactions: AtomicU64,
+}
+
+impl<N, S, I, M, P> CommitCoordinator<N, S, I, M, P>
+where
+    N: IRNetwork<I, AtomicMessage<M>> + 'static,
+    S: IRClientStorage<I, AtomicMessage<M>> + 'static,
+    I: NodeID + 'static,
+    M: KeyedMessage,
+    P: Partitioner<M::Key>,
+{
+    /// The clients of the groups, in shard order
+    /// Panics if there are no shards.
+    pub fn new(
+        shards: Vec<InconsistentReplicationClient<N, S, I, AtomicMessage<M>>>,
+        partitioner: P,
+        coordinator_number: u64,
+    ) -> Self {
+        assert!(
+            !shards.is_empty(),
+            "A commit coordinator needs at least one shard"
+        );
+        CommitCoordinator {
+            shards,
+            partitioner,
+            coordinator_number,
+            transactions: AtomicU64::new(0),
+        }
+    }
+
+    /// Start at a sequence number other than 0, such as after a restart
+    pub fn with_first_sequence(self, first_sequence: u64) -> Self {
+        self.transactions.store(first_sequence, Ordering::SeqCst);
+        self
+    }
+
+    /// A new transaction id
+    /// Callers that may need to resolve the transaction after a crash should record it
+    /// before calling `execute`.
+    pub fn begin(&self) -> TransactionId {
+        TransactionId {
+            coordinator: self.coordinator_number,
+            sequence: self.transactions.fetch_add(1, Ordering::SeqCst),
+        }
+    }
+
+    /// Prepare the operations on every group they belong to, then commit them if every group
+    /// voted to, or abort them otherwise
+    pub async fn execute(
+        &self,
+        transaction: TransactionId,
+        operations: Vec<M>,
+    ) -> Result<(), CommitError> {
+        let mut participants: BTreeMap<usize, Vec<M>> = BTreeMap::new();
+        for operation in operations {
+            let shard = self.partitioner.shard(&operation.key(), self.shards.len());
+            participants.entry(shard).or_default().push(operation);
+        }
+        let shards: Vec<usize> = participants.keys().copied().collect();
+        let votes = join_all(participants.into_iter().map(|(shard, operations)| {
+            self.shards[shard].invoke_consistent(
+                AtomicMessage::Prepare {
+                    transaction,
+                    operations,
+                    vote: None,
+                },
+                AtomicDecide,
+            )
+        }))
+        .await;
+
+        let mut unreachable = None;
+        let mut commit = true;
+        for vote in votes {
+            match vote {
+                Ok(AtomicMessage::Prepare {
+                    vote: Some(Vote::Commit),
+                    ..
+                }) => {}
+                Ok(_) => commit = false,
+                Err(error) => {
+                    commit = false;
+                    unreachable = Some(error);
+                }
+            }
+        }
+        let outcome = if commit {
+            Outcome::Committed
+        } else {
+            Outcome::Aborted
+        };
+        if !self.finish(transaction, outcome, &shards).await {
+            return Err(CommitError::InDoubt(transaction, outcome));
+        }
+        match (outcome, unreachable) {
+            (Outcome::Committed, _) => Ok(()),
+            (Outcome::Aborted, Some(error)) => Err(CommitError::Replication(error)),
+            (Outcome::Aborted, None) => Err(CommitError::Aborted),
+        }
+    }
+
+    /// Finish a transaction whose coordinator crashed, by querying every group
+    /// The transaction commits if any group has committed it, as only a coordinator that saw
+    /// every participant vote to commit sends commits; otherwise it is aborted everywhere.
+    ///
+    /// This must only be used once the coordinator is known to have stopped, as it could
+    /// otherwise abort a transaction the coordinator is about to commit.
+    pub async fn resolve(&self, transaction: TransactionId) -> Result<Outcome, CommitError> {
+        let states = join_all(self.shards.iter().map(|shard| {
+            shard.invoke_consistent(
+                AtomicMessage::Query {
+                    transaction,
+                    state: None,
+                },
+                AtomicDecide,
+            )
+        }))
+        .await;
+
+        let committed = states.iter().any(|state| {
+            matches!(
+                state,
+                Ok(AtomicMessage::Query {
+                    state: Some(TransactionState::Committed),
+                    ..
+                })
+            )
+        });
+        if !committed {
+            if let Some(Err(error)) = states.iter().find(|state| state.is_err()) {
+                return Err(CommitError::Replication(error));
+            }
+        }
+        let outcome = if committed {
+            Outcome::Committed
+        } else {
+            Outcome::Aborted
+        };
+        // Groups that never heard of the transaction record the abort, so a delayed prepare
+        // cannot prepare it later
+        let shards: Vec<usize> = (0..self.shards.len()).collect();
+        if !self.finish(transaction, outcome, &shards).await {
+            return Err(CommitError::InDoubt(transaction, outcome));
+        }
+        Ok(outcome)
+    }
+
+    /// Deliver the outcome to the groups, returning whether every group received it
+    async fn finish(&self, transaction: TransactionId, outcome: Outcome, shards: &[usize]) -> bool {
+        let message = match outcome {
+            Outcome::Committed => AtomicMessage::Commit { transaction },
+            Outcome::Aborted => AtomicMessage::Abort { transaction },
+        };
+        join_all(
+            shards
+                .iter()
+                .map(|shard| self.shards[*shard].invoke_inconsistent(message.clone())),
+        )
+        .await
+        .iter()
+        .all(Result::is_ok)
+    }
+}
@@ -0,0 +1,109 @@
+//! Atomic commit of operations that span several IR groups, with two-phase commit.
+//!
+//! The `CommitCoordinator` splits a transaction's operations by shard and proposes a `Prepare`
+//! to each participant group as a consensus operation, on which the replicas vote. If every
+//! group votes to commit, the transaction is committed everywhere with an inconsistent
+//! operation; otherwise it is aborted the same way.
+//!
+//! ```ignore
+//! let transaction = coordinator.begin();
+//! // record the transaction id durably, so it can be resolved if this client crashes
+//! coordinator.execute(transaction, vec![debit, credit]).await?;
+//! ```
+//!
+//! Replicas run a `ParticipantReplica`, which keeps track of each transaction and hands the
+//! operations to a `Participant` to validate, apply or release. If a coordinator crashes, any
+//! client can `resolve` its in-doubt transactions by querying the groups.
+
+mod coordinator;
+mod participant;
+#[cfg(test)]
+mod test;
+
+pub use coordinator::{CommitCoordinator, CommitError};
+pub use participant::{Participant, ParticipantReplica};
+
+use crate::types::{DecideFunction, IRMessage};
+
+/// Identifies a transaction across its participant groups
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TransactionId {
+    /// Must be unique among coordinators
+    pub coordinator: u64,
+    pub sequence: u64,
+}
+
+/// A replica's vote on whether a transaction can commit
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Vote {
+    Commit,
+    Abort,
+}
+
+/// What a replica knows of a transaction
+/// Ordered by precedence when replicas of a group report different states.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum TransactionState {
+    Unknown,
+    Prepared,
+    Aborted,
+    Committed,
+}
+
+/// How a transaction ended
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Committed,
+    Aborted,
+}
+
+/// The operations of two-phase commit, wrapping the application's own messages
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum AtomicMessage<M: IRMessage> {
+    /// Consensus; replicas validate the operations and fill in their vote
+    Prepare {
+        transaction: TransactionId,
+        operations: Vec<M>,
+        vote: Option<Vote>,
+    },
+    /// Inconsistent; applies the operations of a prepared transaction
+    Commit { transaction: TransactionId },
+    /// Inconsistent; releases a prepared transaction, or prevents it from being prepared later
+    Abort { transaction: TransactionId },
+    /// Consensus; replicas fill in what they know of the transaction
+    Query {
+        transaction: TransactionId,
+        state: Option<TransactionState>,
+    },
+}
+
+/// Decides consensus results when replicas disagree, on the coordinator and during view
+/// changes
+/// Any `Abort` vote aborts a prepare, and a query takes the state with the most precedence.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AtomicDecide;
+
+impl<M: IRMessage> DecideFunction<AtomicMessage<M>> for AtomicDecide {
+    fn decide<'a, S: IntoIterator<Item = &'a AtomicMessage<M>>>(
+        &self,
+        choices: S,
+        _f: usize,
+    ) -> AtomicMessage<M> {
+        let choices: Vec<_> = choices.into_iter().collect();
+        choices[decide_index(&choices)].clone()
+    }
+}
+
+/// The index of the decided choice, shared by `AtomicDecide` and `ParticipantReplica::decide`
+fn decide_index<M: IRMessage>(choices: &[&AtomicMessage<M>]) -> usize {
+    choices
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, choice)| match choice {
+            AtomicMessage::Prepare { vote, .. } => (*vote == Some(Vote::Abort), None),
+            AtomicMessage::Query { state, .. } => (false, *state),
+            AtomicMessage::Commit { .. } | AtomicMessage::Abort { .. } => (false, None),
+        })
+        .map(|(index, _)| index)
+        .unwrap_or(0)
+}
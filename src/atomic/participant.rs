@@ -0,0 +1,226 @@
+use crate::application::IRApplication;
+use crate::atomic::{decide_index, AtomicMessage, TransactionId, TransactionState, Vote};
+use crate::server::IROperation;
+use crate::types::{IRMessage, NodeID};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// The application of a group that takes part in atomic commits
+pub trait Participant<M: IRMessage>: Send + Sync + 'static {
+    /// Validate the operations of a transaction, holding whatever they need until it commits
+    /// or aborts
+    /// Returns whether the transaction can commit.
+    fn prepare(&self, transaction: TransactionId, operations: &[M]) -> bool;
+
+    /// Apply the operations of a transaction that its group committed
+    /// Commits are authoritative, so this replica may not have prepared the operations itself,
+    /// or may have voted against them.
+    fn commit(&self, transaction: TransactionId, operations: Vec<M>);
+
+    /// Release what `prepare` held for a transaction that will not commit
+    fn abort(&self, transaction: TransactionId);
+}
+
+enum Entry<M> {
+    Prepared {
+        operations: Vec<M>,
+        vote: Vote,
+    },
+    /// Committed before this replica learnt the operations, which are applied once a prepare
+    /// or the master record carries them
+    Committing,
+    Committed,
+    Aborted,
+}
+
+/// The replica side of atomic commit, an `IRApplication` wrapping a `Participant`
+/// A transaction is prepared at most once, and aborting a transaction that was never prepared
+/// stops it from being prepared later, so a delayed prepare cannot revive a transaction that
+/// was resolved as aborted.
+pub struct ParticipantReplica<P: Participant<M>, M: IRMessage> {
+    participant: Arc<P>,
+    transactions: Arc<Mutex<BTreeMap<TransactionId, Entry<M>>>>,
+}
+
+impl<P: Participant<M>, M: IRMessage> Clone for ParticipantReplica<P, M> {
+    fn clone(&self) -> Self {
+        ParticipantReplica {
+            participant: self.participant.clone(),
+            transactions: self.transactions.clone(),
+        }
+    }
+}
+
+impl<P: Participant<M>, M: IRMessage> ParticipantReplica<P, M> {
+    pub fn new(participant: P) -> Self {
+        ParticipantReplica {
+            participant: Arc::new(participant),
+            transactions: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    pub fn participant(&self) -> &P {
+        &self.participant
+    }
+
+    /// What this replica knows of a transaction
+    pub fn state(&self, transaction: &TransactionId) -> TransactionState {
+        match self.transactions.lock().unwrap().get(transaction) {
+            None => TransactionState::Unknown,
+            Some(Entry::Prepared { .. }) => TransactionState::Prepared,
+            Some(Entry::Committing | Entry::Committed) => TransactionState::Committed,
+            Some(Entry::Aborted) => TransactionState::Aborted,
+        }
+    }
+
+    fn prepare(&self, transaction: TransactionId, operations: Vec<M>) -> Vote {
+        let mut transactions = self.transactions.lock().unwrap();
+        match transactions.get(&transaction) {
+            Some(Entry::Prepared { vote, .. }) => *vote,
+            Some(Entry::Committing) => {
+                self.participant.commit(transaction, operations);
+                transactions.insert(transaction, Entry::Committed);
+                Vote::Commit
+            }
+            Some(Entry::Committed) => Vote::Commit,
+            Some(Entry::Aborted) => Vote::Abort,
+            None => {
+                let vote = if self.participant.prepare(transaction, &operations) {
+                    Vote::Commit
+                } else {
+                    Vote::Abort
+                };
+                transactions.insert(transaction, Entry::Prepared { operations, vote });
+                vote
+            }
+        }
+    }
+
+    /// The group decided to commit, so the operations are applied whatever this replica voted
+    fn commit(&self, transaction: TransactionId) {
+        let mut transactions = self.transactions.lock().unwrap();
+        let entry = match transactions.remove(&transaction) {
+            Some(Entry::Prepared { operations, .. }) => {
+                self.participant.commit(transaction, operations);
+                Entry::Committed
+            }
+            Some(Entry::Committed) => Entry::Committed,
+            Some(Entry::Committing | Entry::Aborted) | None => Entry::Committing,
+        };
+        transactions.insert(transaction, entry);
+    }
+
+    /// Install the vote the master record decided for a prepare, in place of this replica's
+    /// own
+    /// The participant is not asked to prepare the operations again, so a decided `Commit`
+    /// holds nothing on a replica that did not prepare them itself.
+    fn install(&self, transaction: TransactionId, operations: Vec<M>, vote: Vote) {
+        let mut transactions = self.transactions.lock().unwrap();
+        let entry = match transactions.remove(&transaction) {
+            Some(Entry::Committing) => {
+                self.participant.commit(transaction, operations);
+                Entry::Committed
+            }
+            Some(Entry::Committed) => Entry::Committed,
+            Some(Entry::Aborted) => Entry::Aborted,
+            Some(Entry::Prepared {
+                vote: Vote::Commit, ..
+            }) if vote == Vote::Abort => {
+                self.participant.abort(transaction);
+                Entry::Prepared { operations, vote }
+            }
+            Some(Entry::Prepared { .. }) | None => Entry::Prepared { operations, vote },
+        };
+        transactions.insert(transaction, entry);
+    }
+
+    fn abort(&self, transaction: TransactionId) {
+        let mut transactions = self.transactions.lock().unwrap();
+        if let Some(Entry::Committing | Entry::Committed) = transactions.get(&transaction) {
+            return;
+        }
+        if let Some(Entry::Prepared {
+            vote: Vote::Commit, ..
+        }) = transactions.insert(transaction, Entry::Aborted)
+        {
+            self.participant.abort(transaction);
+        }
+    }
+}
+
+impl<I: NodeID, P: Participant<M>, M: IRMessage> IRApplication<I, AtomicMessage<M>>
+    for ParticipantReplica<P, M>
+{
+    fn exec_inconsistent(
+        &self,
+        message: AtomicMessage<M>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        match message {
+            AtomicMessage::Commit { transaction } => self.commit(transaction),
+            AtomicMessage::Abort { transaction } => self.abort(transaction),
+            AtomicMessage::Prepare { .. } | AtomicMessage::Query { .. } => {}
+        }
+        Box::pin(async {})
+    }
+
+    fn exec_consensus(
+        &self,
+        message: AtomicMessage<M>,
+    ) -> Pin<Box<dyn Future<Output = AtomicMessage<M>> + Send + 'static>> {
+        let result = match message {
+            AtomicMessage::Prepare {
+                transaction,
+                operations,
+                ..
+            } => AtomicMessage::Prepare {
+                transaction,
+                vote: Some(self.prepare(transaction, operations.clone())),
+                operations,
+            },
+            AtomicMessage::Query { transaction, .. } => AtomicMessage::Query {
+                transaction,
+                state: Some(self.state(&transaction)),
+            },
+            other => other,
+        };
+        Box::pin(async move { result })
+    }
+
+    fn decide(&self, choices: Vec<AtomicMessage<M>>, _f: usize) -> AtomicMessage<M> {
+        let index = decide_index(&choices.iter().collect::<Vec<_>>());
+        choices.into_iter().nth(index).unwrap()
+    }
+
+    /// Install the votes the master record decided for prepares, then apply the commits and
+    /// aborts in the order they were finalized
+    fn sync(
+        &self,
+        record: Vec<IROperation<I, AtomicMessage<M>>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        for operation in record {
+            match operation {
+                IROperation::ConsistentFinalize {
+                    message:
+                        AtomicMessage::Prepare {
+                            transaction,
+                            operations,
+                            vote: Some(vote),
+                        },
+                    ..
+                } => self.install(transaction, operations, vote),
+                IROperation::InconsistentFinalize {
+                    message: AtomicMessage::Commit { transaction },
+                    ..
+                } => self.commit(transaction),
+                IROperation::InconsistentFinalize {
+                    message: AtomicMessage::Abort { transaction },
+                    ..
+                } => self.abort(transaction),
+                _ => {}
+            }
+        }
+        Box::pin(async {})
+    }
+}
@@ -0,0 +1,305 @@
+use crate::atomic::{
+    AtomicDecide, AtomicMessage, CommitCoordinator, CommitError, Outcome, Participant,
+    ParticipantReplica, TransactionId, TransactionState, Vote,
+};
+use crate::io::test_utils::{fake_cluster, FakeIRNetwork, FakeIRStorage};
+use crate::server::IROperation;
+use crate::{IRApplication, InconsistentReplicationClient, KeyedMessage, RangePartitioner};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+/// Writes a value to a key
+type Write = (u64, u64);
+type Message = AtomicMessage<Write>;
+type Replica = ParticipantReplica<Store, Write>;
+type Network = FakeIRNetwork<u64, Message, FakeIRStorage<u64, Message>, Replica>;
+type Client = InconsistentReplicationClient<Network, FakeIRStorage<u64, Message>, u64, Message>;
+
+impl KeyedMessage for Write {
+    type Key = u64;
+
+    fn key(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A store that locks keys while a transaction is prepared, and refuses to write 0
+#[derive(Default)]
+struct Store {
+    locked: Mutex<BTreeSet<u64>>,
+    values: Mutex<BTreeMap<u64, u64>>,
+}
+
+impl Participant<Write> for Store {
+    fn prepare(&self, _transaction: TransactionId, operations: &[Write]) -> bool {
+        let mut locked = self.locked.lock().unwrap();
+        let valid = operations
+            .iter()
+            .all(|(key, value)| *value != 0 && !locked.contains(key));
+        if valid {
+            locked.extend(operations.iter().map(|(key, _)| *key));
+        }
+        valid
+    }
+
+    fn commit(&self, _transaction: TransactionId, operations: Vec<Write>) {
+        let mut locked = self.locked.lock().unwrap();
+        let mut values = self.values.lock().unwrap();
+        for (key, value) in operations {
+            locked.remove(&key);
+            values.insert(key, value);
+        }
+    }
+
+    fn abort(&self, _transaction: TransactionId) {
+        self.locked.lock().unwrap().clear();
+    }
+}
+
+struct Group {
+    network: Network,
+    replicas: Vec<Replica>,
+}
+
+impl Group {
+    async fn client(&self) -> Client {
+        InconsistentReplicationClient::new(
+            self.network.clone(),
+            FakeIRStorage::new(vec![1, 2, 3]),
+            0,
+        )
+        .await
+    }
+
+    fn value(&self, key: u64) -> Vec<Option<u64>> {
+        self.replicas
+            .iter()
+            .map(|replica| {
+                replica
+                    .participant()
+                    .values
+                    .lock()
+                    .unwrap()
+                    .get(&key)
+                    .copied()
+            })
+            .collect()
+    }
+
+    fn locked(&self) -> bool {
+        self.replicas
+            .iter()
+            .any(|replica| !replica.participant().locked.lock().unwrap().is_empty())
+    }
+}
+
+/// A group of three replicas that have bootstrapped view 0
+async fn group() -> Group {
+    let network = Network::new();
+    let replicas = fake_cluster(&network, &[1, 2, 3], || {
+        ParticipantReplica::new(Store::default())
+    })
+    .await;
+    Group { network, replicas }
+}
+
+/// Two groups, splitting the keys at 100, and a coordinator for them
+async fn groups() -> (
+    Vec<Group>,
+    CommitCoordinator<Network, FakeIRStorage<u64, Message>, u64, Write, RangePartitioner<u64>>,
+) {
+    let groups = vec![group().await, group().await];
+    let clients = vec![groups[0].client().await, groups[1].client().await];
+    let coordinator = CommitCoordinator::new(clients, RangePartitioner::new(vec![100]), 1);
+    (groups, coordinator)
+}
+
+/// Prepare a transaction on both groups as a coordinator would, then crash
+async fn prepare_and_crash(groups: &[Group], transaction: TransactionId) -> Vec<Client> {
+    let mut clients = vec![];
+    for (group, write) in groups.iter().zip([(5, 50), (500, 5000)]) {
+        let client = group.client().await;
+        client
+            .invoke_consistent(
+                AtomicMessage::Prepare {
+                    transaction,
+                    operations: vec![write],
+                    vote: None,
+                },
+                AtomicDecide,
+            )
+            .await
+            .unwrap();
+        clients.push(client);
+    }
+    clients
+}
+
+#[tokio::test]
+async fn transactions_commit_on_every_participant_group() {
+    // given two groups
+    let (groups, coordinator) = groups().await;
+
+    // when a transaction writes to both
+    let transaction = coordinator.begin();
+    let result = coordinator
+        .execute(transaction, vec![(5, 50), (500, 5000)])
+        .await;
+
+    // then both groups apply their writes
+    assert_eq!(result, Ok(()));
+    assert_eq!(groups[0].value(5), vec![Some(50); 3]);
+    assert_eq!(groups[1].value(500), vec![Some(5000); 3]);
+    assert!(!groups[0].locked() && !groups[1].locked());
+}
+
+#[tokio::test]
+async fn a_vote_to_abort_aborts_every_participant_group() {
+    // given two groups
+    let (groups, coordinator) = groups().await;
+
+    // when one group refuses its part of a transaction
+    let transaction = coordinator.begin();
+    let result = coordinator
+        .execute(transaction, vec![(5, 50), (500, 0)])
+        .await;
+
+    // then neither group applies it, and the other group releases its locks
+    assert_eq!(result, Err(CommitError::Aborted));
+    assert_eq!(groups[0].value(5), vec![None; 3]);
+    assert!(!groups[0].locked());
+    for replica in &groups[0].replicas {
+        assert_eq!(replica.state(&transaction), TransactionState::Aborted);
+    }
+}
+
+#[tokio::test]
+async fn in_doubt_transactions_commit_if_any_group_committed() {
+    // given a coordinator that crashed after committing on the first group only
+    let (groups, coordinator) = groups().await;
+    let transaction = TransactionId {
+        coordinator: 2,
+        sequence: 0,
+    };
+    let crashed = prepare_and_crash(&groups, transaction).await;
+    crashed[0]
+        .invoke_inconsistent(AtomicMessage::Commit { transaction })
+        .await
+        .unwrap();
+
+    // when another client resolves the transaction
+    let outcome = coordinator.resolve(transaction).await;
+
+    // then it commits on the second group too
+    assert_eq!(outcome, Ok(Outcome::Committed));
+    assert_eq!(groups[1].value(500), vec![Some(5000); 3]);
+}
+
+#[tokio::test]
+async fn in_doubt_transactions_abort_if_no_group_committed() {
+    // given a coordinator that crashed after preparing
+    let (groups, coordinator) = groups().await;
+    let transaction = TransactionId {
+        coordinator: 2,
+        sequence: 0,
+    };
+    prepare_and_crash(&groups, transaction).await;
+
+    // when another client resolves the transaction
+    let outcome = coordinator.resolve(transaction).await;
+
+    // then it aborts, releasing the locks of both groups
+    assert_eq!(outcome, Ok(Outcome::Aborted));
+    assert!(!groups[0].locked() && !groups[1].locked());
+    assert_eq!(groups[0].value(5), vec![None; 3]);
+}
+
+#[tokio::test]
+async fn sync_installs_the_decided_votes_of_the_master_record() {
+    let transaction = |sequence| TransactionId {
+        coordinator: 1,
+        sequence,
+    };
+    let prepare = |sequence, vote| IROperation::ConsistentFinalize {
+        client: 0,
+        sequence,
+        message: AtomicMessage::Prepare {
+            transaction: transaction(sequence),
+            operations: vec![(5, 50 + sequence)],
+            vote: Some(vote),
+        },
+    };
+
+    // given a replica that voted to commit the first transaction, locking key 5
+    let replica = Replica::new(Store::default());
+    IRApplication::<u64, Message>::exec_consensus(
+        &replica,
+        AtomicMessage::Prepare {
+            transaction: transaction(0),
+            operations: vec![(5, 50)],
+            vote: None,
+        },
+    )
+    .await;
+
+    // when the master record aborted it, and committed a second transaction on the same key
+    replica
+        .sync(vec![
+            prepare(0, Vote::Abort),
+            prepare(1, Vote::Commit),
+            IROperation::InconsistentFinalize {
+                client: 0,
+                sequence: 2,
+                message: AtomicMessage::Commit {
+                    transaction: transaction(1),
+                },
+            },
+        ])
+        .await;
+
+    // then the first is released rather than kept, and the second is applied although this
+    // replica could not have prepared it
+    assert_eq!(replica.state(&transaction(0)), TransactionState::Prepared);
+    assert_eq!(replica.state(&transaction(1)), TransactionState::Committed);
+    assert!(replica.participant().locked.lock().unwrap().is_empty());
+    assert_eq!(
+        replica.participant().values.lock().unwrap().get(&5),
+        Some(&51)
+    );
+}
+
+#[tokio::test]
+async fn commits_apply_operations_learnt_after_the_commit() {
+    // given a replica that receives a commit for a transaction it never prepared
+    let transaction = TransactionId {
+        coordinator: 1,
+        sequence: 0,
+    };
+    let replica = Replica::new(Store::default());
+    IRApplication::<u64, Message>::exec_inconsistent(
+        &replica,
+        AtomicMessage::Commit { transaction },
+    )
+    .await;
+    assert_eq!(replica.state(&transaction), TransactionState::Committed);
+
+    // when the master record carries the prepare
+    replica
+        .sync(vec![IROperation::ConsistentFinalize {
+            client: 0,
+            sequence: 0,
+            message: AtomicMessage::Prepare {
+                transaction,
+                operations: vec![(5, 50)],
+                vote: Some(Vote::Commit),
+            },
+        }])
+        .await;
+
+    // then its operations are applied
+    assert_eq!(
+        replica.participant().values.lock().unwrap().get(&5),
+        Some(&50)
+    );
+    assert!(replica.participant().locked.lock().unwrap().is_empty());
+}
@@ -0,0 +1,126 @@
+use crate::server::View;
+use crate::types::{IRMessage, NodeID, OperationSequence};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// How far along an outstanding operation is
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OperationStage {
+    /// Waiting for a quorum of proposal responses
+    Proposing,
+    /// A result has been chosen and is being finalized on the replicas
+    Finalizing,
+}
+
+/// The client's record of an operation that has not yet completed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InFlightOperation<I: NodeID, M: IRMessage> {
+    pub sequence: OperationSequence,
+    pub message: M,
+    /// The view the operation was started in; it is kept for the lifetime of the operation
+    /// so that a view refresh does not change which replicas it is sent to
+    pub view: View<I>,
+    /// The number of times the proposal has been sent
+    pub attempts: usize,
+    pub stage: OperationStage,
+}
+
+/// Bounds and tracks the operations a client has outstanding
+pub(crate) struct InFlightOperations<I: NodeID, M: IRMessage> {
+    window: Semaphore,
+    max_in_flight: usize,
+    operations: Mutex<BTreeMap<OperationSequence, InFlightOperation<I, M>>>,
+}
+
+impl<I: NodeID, M: IRMessage> InFlightOperations<I, M> {
+    pub(crate) fn new(max_in_flight: usize) -> Self {
+        InFlightOperations {
+            window: Semaphore::new(max_in_flight),
+            max_in_flight,
+            operations: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Wait for room in the window, then allocate consecutive sequence numbers to the messages
+    /// A batch larger than the window takes the whole window.
+    pub(crate) async fn begin<'a>(
+        &'a self,
+        sequence: &AtomicU64,
+        view: &View<I>,
+        messages: &[M],
+    ) -> InFlightGuard<'a, I, M> {
+        let permits = messages.len().min(self.max_in_flight) as u32;
+        let permit = self
+            .window
+            .acquire_many(permits)
+            .await
+            .expect("the window is never closed");
+        let first_sequence = sequence.fetch_add(messages.len() as u64, Ordering::SeqCst);
+        let mut operations = self.operations.lock().unwrap();
+        for (sequence, message) in (first_sequence..).zip(messages) {
+            operations.insert(
+                sequence,
+                InFlightOperation {
+                    sequence,
+                    message: message.clone(),
+                    view: view.clone(),
+                    attempts: 0,
+                    stage: OperationStage::Proposing,
+                },
+            );
+        }
+        InFlightGuard {
+            operations: &self.operations,
+            first_sequence,
+            count: messages.len() as u64,
+            _permit: permit,
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<InFlightOperation<I, M>> {
+        self.operations.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// Holds a place in the window; the operations are forgotten once it is dropped, including
+/// when the invocation is cancelled or fails
+pub(crate) struct InFlightGuard<'a, I: NodeID, M: IRMessage> {
+    operations: &'a Mutex<BTreeMap<OperationSequence, InFlightOperation<I, M>>>,
+    first_sequence: OperationSequence,
+    count: u64,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl<'a, I: NodeID, M: IRMessage> InFlightGuard<'a, I, M> {
+    pub(crate) fn first_sequence(&self) -> OperationSequence {
+        self.first_sequence
+    }
+
+    pub(crate) fn record_attempt(&self) {
+        self.update(|operation| operation.attempts += 1);
+    }
+
+    pub(crate) fn set_stage(&self, stage: OperationStage) {
+        self.update(|operation| operation.stage = stage);
+    }
+
+    fn update<F: Fn(&mut InFlightOperation<I, M>)>(&self, update: F) {
+        let mut operations = self.operations.lock().unwrap();
+        for sequence in self.first_sequence..self.first_sequence + self.count {
+            if let Some(operation) = operations.get_mut(&sequence) {
+                update(operation);
+            }
+        }
+    }
+}
+
+impl<'a, I: NodeID, M: IRMessage> Drop for InFlightGuard<'a, I, M> {
+    fn drop(&mut self) {
+        let mut operations = self.operations.lock().unwrap();
+        for sequence in self.first_sequence..self.first_sequence + self.count {
+            operations.remove(&sequence);
+        }
+    }
+}
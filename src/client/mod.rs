@@ -1,26 +1,44 @@
+mod in_flight;
+mod sharded;
 #[cfg(test)]
 mod test;
 
-use crate::io::{IRClientStorage, IRNetwork};
-use crate::server::View;
-use crate::types::{DecideFunction, IRMessage, NodeID};
-use crate::utils::{find_quorum, Quorum, QuorumType, QuorumVote};
-use crate::IRStorage;
-use futures::StreamExt;
+pub use in_flight::{InFlightOperation, OperationStage};
+pub use sharded::{HashPartitioner, KeyedMessage, Partitioner, RangePartitioner, ShardedClient};
+
+use crate::client::in_flight::{InFlightGuard, InFlightOperations};
+use crate::io::{IRClientStorage, IRNetwork, IRNetworkError};
+use crate::metrics::{
+    Metrics, NoopMetrics, Timer, NODE_UNREACHABLE_TOTAL, NO_QUORUM_TOTAL,
+    OPERATION_DURATION_SECONDS, QUORUM_TOTAL,
+};
+use crate::quorum::{QuorumError, QuorumPolicy};
+use crate::server::{IROperation, View, ViewState};
+use crate::types::{DecideFunction, IRMessage, NodeID, OperationSequence, TraceId};
+use crate::utils::{find_quorum, validate_view, NoQuorum, Quorum, QuorumType, QuorumVote};
+use futures::future::join_all;
+use std::collections::BTreeMap;
 use std::future::Future;
 use std::marker::PhantomData;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::pin::Pin;
+use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tracing::Span;
+
+/// A node's results for each operation of a batch
+type BatchResponse<ID, MSG> = (ID, Vec<(MSG, View<ID>)>);
 
 /// Cluster size is 2f+1, as per page 4 of the extended paper (3.1.2 IR Guarantees)
 /// Minimum cluster size of f=1 is 3
 const MINIMUM_CLUSTER_SIZE: usize = 3;
 
-/// This is how many retries will happen in total
-/// Since the network should be performing the retries, this is set to 0
-/// And is only used to enforce an end cycle to the loop
-const MAX_ATTEMPTS: u8 = 0;
+/// The number of operations a client may have outstanding, unless configured otherwise
+const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+
+/// The number of times a proposal is sent to replicas that have not responded, unless
+/// configured otherwise
+const DEFAULT_PROPOSAL_ATTEMPTS: usize = 3;
 
 /// The client used to interact with the IR cluster.
 /// Addresses are provided via the view on the storage interface.
@@ -34,17 +52,15 @@ pub struct InconsistentReplicationClient<
     storage: S,
     client_id: I,
     sequence: AtomicU64,
-    latest_view: View<I>,
+    latest_view: RwLock<View<I>>,
+    in_flight: InFlightOperations<I, M>,
+    metrics: Arc<dyn Metrics>,
+    quorum_policy: QuorumPolicy<I>,
+    proposal_attempts: usize,
     additional_nodes: RwLock<Vec<I>>,
     _a: PhantomData<M>,
 }
 
-struct ResponseIntermediary<I: NodeID, M: IRMessage> {
-    node: I,
-    message: M,
-    view: View<I>,
-}
-
 impl<
         NET: IRNetwork<ID, MSG> + 'static,
         STO: IRClientStorage<ID, MSG> + 'static,
@@ -59,17 +75,78 @@ impl<
             storage,
             client_id,
             sequence: AtomicU64::new(0),
-            latest_view: view,
+            latest_view: RwLock::new(view),
+            in_flight: InFlightOperations::new(DEFAULT_MAX_IN_FLIGHT),
+            metrics: Arc::new(NoopMetrics),
+            quorum_policy: QuorumPolicy::majority(),
+            proposal_attempts: DEFAULT_PROPOSAL_ATTEMPTS,
             additional_nodes: RwLock::new(Vec::with_capacity(2)),
             _a: PhantomData,
         }
     }
 
+    /// Limit the number of operations that can be outstanding at once
+    /// Invocations beyond the limit wait until an earlier operation completes.
+    /// Panics if the limit is 0, as no operation could ever start.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        assert!(
+            max_in_flight > 0,
+            "A client needs room for at least one operation in flight"
+        );
+        self.in_flight = InFlightOperations::new(max_in_flight);
+        self
+    }
+
+    /// Report quorum outcomes, unreachable nodes and operation latency
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Use a quorum policy other than a majority of equally weighted members
+    /// Replicas should be configured with the same policy.
+    pub fn with_quorum_policy(mut self, quorum_policy: QuorumPolicy<ID>) -> Self {
+        self.quorum_policy = quorum_policy;
+        self
+    }
+
+    /// Send each proposal at most this many times to replicas that have not responded
+    /// A network wrapped in `layers::RetryLayer` retries every one of these sends, so the
+    /// attempts multiply; set this to 1 to leave retries to the layer.
+    pub fn with_proposal_attempts(mut self, attempts: usize) -> Self {
+        self.proposal_attempts = attempts.max(1);
+        self
+    }
+
+    pub fn client_id(&self) -> &ID {
+        &self.client_id
+    }
+
+    /// The operations that have been invoked but have not completed
+    pub fn in_flight_operations(&self) -> Vec<InFlightOperation<ID, MSG>> {
+        self.in_flight.snapshot()
+    }
+
+    /// Reload the view from storage, if it is newer than the one the client knows of
+    /// Operations already in flight keep the view they started with.
+    pub async fn refresh_view(&self) {
+        let view = self.storage.recover_current_view().await;
+        self.observe_view(&view).await;
+    }
+
     /// Make an inconsistent request to the cluster
     /// Inconsistent requests happen in any order
     /// Conflict resolution is done by the client after receiving responses
+    #[tracing::instrument(
+        name = "ir_client",
+        level = "debug",
+        skip_all,
+        fields(operation = "inconsistent", client = ?self.client_id, sequence, trace_id, view)
+    )]
     pub async fn invoke_inconsistent(&self, message: MSG) -> Result<MSG, &'static str> {
-        let nodes = &self.latest_view.members;
+        let _timer = self.timer("inconsistent");
+        let view = self.latest_view.read().await.clone();
+        let nodes = &view.members;
         let nodes_len = nodes.len();
 
         if nodes_len < MINIMUM_CLUSTER_SIZE {
@@ -77,26 +154,34 @@ impl<
         }
 
         // Initiate requests
-        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
+        let operation = self
+            .in_flight
+            .begin(&self.sequence, &view, std::slice::from_ref(&message))
+            .await;
+        let sequence = operation.first_sequence();
+        self.record_operation(sequence, &view);
         let responses = self
-            .network
-            .propose_inconsistent(&nodes, self.client_id.clone(), sequence, message, None)
+            .propose_with_retries(&operation, nodes, |nodes| {
+                self.network.propose_inconsistent(
+                    nodes,
+                    self.client_id.clone(),
+                    sequence,
+                    message.clone(),
+                    None,
+                )
+            })
             .await;
-        let responses: Vec<_> = responses
-            .into_iter()
-            .filter(|(i, r)| r.is_ok())
-            .map(|(i, r)| (i, r.unwrap()))
-            .collect();
-        let quorum: Quorum<ID, MSG> =
-            find_quorum(responses.iter().map(|(node_id, (msg, view))| QuorumVote {
-                node: node_id,
-                message: msg,
-                view,
-            }))
+        let quorum: Quorum<ID, MSG> = self
+            .record_quorum(
+                "inconsistent",
+                find_quorum(&self.quorum_policy, votes(&responses)),
+            )
             .map_err(|_| "Quorum not found")?;
+        operation.set_stage(OperationStage::Finalizing);
+        self.observe_view(quorum.view).await;
         self.network
             .async_finalize_inconsistent(
-                &quorum.view.members,
+                &finalize_destinations(quorum.view),
                 self.client_id.clone(),
                 sequence,
                 quorum.message.clone(),
@@ -105,83 +190,337 @@ impl<
         Ok(quorum.message.clone())
     }
 
-    /// Make a consistent request to the cluster
+    /// Make an inconsistent request whose replies differ between replicas, such as a read
+    /// The replies of a slow quorum in the latest view are combined by `merge`, and the
+    /// operation is finalized as it was sent. Replicas reply through
+    /// `IRApplication::reply_inconsistent`.
+    #[tracing::instrument(
+        name = "ir_client",
+        level = "debug",
+        skip_all,
+        fields(operation = "inconsistent_merged", client = ?self.client_id, sequence, trace_id, view)
+    )]
+    pub async fn invoke_inconsistent_merged<F: FnOnce(Vec<MSG>) -> MSG>(
+        &self,
+        message: MSG,
+        merge: F,
+    ) -> Result<MSG, &'static str> {
+        let _timer = self.timer("inconsistent_merged");
+        let view = self.latest_view.read().await.clone();
+        let nodes = &view.members;
+
+        if nodes.len() < MINIMUM_CLUSTER_SIZE {
+            return Err("Cluster size is too small");
+        }
+
+        // Initiate requests
+        let operation = self
+            .in_flight
+            .begin(&self.sequence, &view, std::slice::from_ref(&message))
+            .await;
+        let sequence = operation.first_sequence();
+        self.record_operation(sequence, &view);
+        let responses = self
+            .propose_with_retries(&operation, nodes, |nodes| {
+                self.network.propose_inconsistent(
+                    nodes,
+                    self.client_id.clone(),
+                    sequence,
+                    message.clone(),
+                    None,
+                )
+            })
+            .await;
+        let latest = responses
+            .iter()
+            .map(|(_, (_, view))| view)
+            .max_by_key(|view| view.view)
+            .cloned()
+            .ok_or("Quorum not found")?;
+        let (voters, replies): (Vec<_>, Vec<_>) = responses
+            .into_iter()
+            .filter(|(node, (_, view))| view.view == latest.view && latest.members.contains(node))
+            .map(|(node, (reply, _))| (node, reply))
+            .unzip();
+        let slow_quorum = self
+            .quorum_policy
+            .slow_quorum(&latest.members)
+            .map_err(|_| "Quorum not found")?;
+        if self.quorum_policy.weight_of(&voters) < slow_quorum {
+            tracing::warn!("quorum not found");
+            return Err("Quorum not found");
+        }
+        operation.set_stage(OperationStage::Finalizing);
+        self.observe_view(&latest).await;
+        self.network
+            .async_finalize_inconsistent(
+                &finalize_destinations(&latest),
+                self.client_id.clone(),
+                sequence,
+                message,
+            )
+            .await;
+        Ok(merge(replies))
+    }
+
+    /// Make a consistent request to the cluster, returning the result of the operation
     /// Consistent requests happen in any order
-    /// A provided function helps resolve conflicts once detected
+    /// A provided function helps resolve conflicts once detected, deciding the result when
+    /// replicas did not agree in a fast quorum.
     /// This same function is used during recovery
+    #[tracing::instrument(
+        name = "ir_client",
+        level = "debug",
+        skip_all,
+        fields(operation = "consistent", client = ?self.client_id, sequence, trace_id, view)
+    )]
     pub async fn invoke_consistent<F: DecideFunction<MSG>>(
         &self,
         message: MSG,
         decide_function: F,
-    ) -> Result<(), &'static str> {
-        let current_view = self.storage.recover_current_view().await;
-        let nodes = current_view.members;
+    ) -> Result<MSG, &'static str> {
+        let _timer = self.timer("consistent");
+        let current_view = self.latest_view.read().await.clone();
+        let nodes = &current_view.members;
 
         if nodes.len() < MINIMUM_CLUSTER_SIZE {
             return Err("Cluster size is too small");
         }
 
         // Initiate requests
-        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
-        let client_id = self.client_id.clone();
+        let operation = self
+            .in_flight
+            .begin(
+                &self.sequence,
+                &current_view,
+                std::slice::from_ref(&message),
+            )
+            .await;
+        let sequence = operation.first_sequence();
+        self.record_operation(sequence, &current_view);
         let responses = self
-            .network
-            .propose_consistent(&nodes, client_id, sequence, message)
+            .propose_with_retries(&operation, nodes, |nodes| {
+                self.network.propose_consistent(
+                    nodes,
+                    self.client_id.clone(),
+                    sequence,
+                    message.clone(),
+                )
+            })
             .await;
-        let responses: Vec<_> = responses
-            .into_iter()
-            .filter(|(id, r)| r.is_ok())
-            .map(|(id, r)| (id, r.unwrap()))
-            .collect();
-        let quorum = find_quorum(responses.iter().map(|(node_id, (msg, view))| QuorumVote {
-            node: node_id,
-            message: msg,
-            view,
-        }))
-        .map_err(|_| "Quorum not found")?;
-
-        match quorum.quorum_type {
-            QuorumType::FastQuorum => {
+        let view = match self.record_quorum(
+            "consistent",
+            find_quorum(&self.quorum_policy, votes(&responses)),
+        ) {
+            Ok(quorum) if quorum.quorum_type == QuorumType::FastQuorum => {
                 // We can do async finalize
+                operation.set_stage(OperationStage::Finalizing);
+                self.observe_view(quorum.view).await;
                 self.network
                     .async_finalize_consistent(
-                        &quorum.view.members,
+                        &finalize_destinations(quorum.view),
                         self.client_id.clone(),
                         sequence,
                         quorum.message.clone(),
                     )
                     .await;
+                return Ok(quorum.message.clone());
             }
-            QuorumType::NormalQuorum => {
-                // TODO This is actually incorrect, we should always invoke decide if FastQuorum
-                // cannot be obtained
-
-                let responses = self
-                    .network
-                    .sync_finalize_consistent(
-                        &quorum.view.members,
-                        self.client_id.clone(),
-                        sequence,
-                        quorum.message.clone(),
-                    )
-                    .await;
-                let responses: Vec<_> = responses
-                    .into_iter()
-                    .filter(|(i, r)| r.is_ok())
-                    .map(|(i, r)| (i, r.unwrap()))
-                    .collect();
-
-                let _quorum =
-                    find_quorum(responses.iter().map(|(node_id, (msg, view))| QuorumVote {
-                        node: node_id,
-                        message: msg,
-                        view,
-                    }))
-                    .map_err(|_| "Unable to get enough confirm messages for consistent finalize")?;
+            Ok(quorum) => quorum.view.clone(),
+            Err(Some(no_quorum)) => no_quorum.view.clone(),
+            Err(None) => return Err("Quorum not found"),
+        };
+
+        // Without a fast quorum the result is decided from the responses of a slow quorum,
+        // as in the slow path of the paper
+        let decided = self.decide_slow_path(&decide_function, votes(&responses), &view)?;
+        operation.set_stage(OperationStage::Finalizing);
+        self.observe_view(&view).await;
+        let responses = self
+            .network
+            .sync_finalize_consistent(
+                &finalize_destinations(&view),
+                self.client_id.clone(),
+                sequence,
+                decided.clone(),
+            )
+            .await;
+        let responses = self.successful_responses(responses);
+        find_quorum(&self.quorum_policy, votes(&responses))
+            .map_err(|_| "Unable to get enough confirm messages for consistent finalize")?;
+        Ok(decided)
+    }
+
+    /// Make several inconsistent requests to the cluster at once
+    /// The operations take a contiguous range of sequence numbers and are proposed with a
+    /// single message per node. Quorums are found per operation, so each has its own result.
+    #[tracing::instrument(
+        name = "ir_client",
+        level = "debug",
+        skip_all,
+        fields(operation = "inconsistent_batch", client = ?self.client_id, sequence, trace_id, view)
+    )]
+    pub async fn invoke_inconsistent_batch(
+        &self,
+        messages: Vec<MSG>,
+    ) -> Result<Vec<Result<MSG, &'static str>>, &'static str> {
+        let _timer = self.timer("inconsistent_batch");
+        let view = self.latest_view.read().await.clone();
+        let nodes = &view.members;
+
+        if nodes.len() < MINIMUM_CLUSTER_SIZE {
+            return Err("Cluster size is too small");
+        }
+
+        // Initiate requests
+        let count = messages.len();
+        let operations = self.in_flight.begin(&self.sequence, &view, &messages).await;
+        let first_sequence = operations.first_sequence();
+        self.record_operation(first_sequence, &view);
+        operations.record_attempt();
+        let responses = self
+            .network
+            .propose_inconsistent_batch(
+                nodes,
+                self.client_id.clone(),
+                first_sequence,
+                messages,
+                None,
+            )
+            .await;
+        let responses = self.successful_responses(responses);
+
+        let mut results = Vec::with_capacity(count);
+        let mut finalizes: BTreeMap<Vec<ID>, Vec<IROperation<ID, MSG>>> = BTreeMap::new();
+        for (index, sequence) in (first_sequence..).take(count).enumerate() {
+            match self.record_quorum(
+                "inconsistent_batch",
+                find_quorum(&self.quorum_policy, batch_votes(&responses, index)),
+            ) {
+                Ok(quorum) => {
+                    self.observe_view(quorum.view).await;
+                    finalizes
+                        .entry(finalize_destinations(quorum.view))
+                        .or_default()
+                        .push(IROperation::InconsistentFinalize {
+                            client: self.client_id.clone(),
+                            sequence,
+                            message: quorum.message.clone(),
+                        });
+                    results.push(Ok(quorum.message.clone()));
+                }
+                Err(_) => results.push(Err("Quorum not found")),
             }
         }
+        operations.set_stage(OperationStage::Finalizing);
+        for (members, finalizes) in finalizes {
+            self.network.finalize_batch(&members, finalizes).await;
+        }
+        Ok(results)
+    }
 
-        Ok(())
+    /// Make several consistent requests to the cluster at once
+    /// The operations take a contiguous range of sequence numbers and are proposed with a
+    /// single message per node. Quorums are found per operation, so each has its own result,
+    /// which is decided as in `invoke_consistent` when the replicas did not agree in a fast
+    /// quorum.
+    #[tracing::instrument(
+        name = "ir_client",
+        level = "debug",
+        skip_all,
+        fields(operation = "consistent_batch", client = ?self.client_id, sequence, trace_id, view)
+    )]
+    pub async fn invoke_consistent_batch<F: DecideFunction<MSG>>(
+        &self,
+        messages: Vec<MSG>,
+        decide_function: F,
+    ) -> Result<Vec<Result<MSG, &'static str>>, &'static str> {
+        let _timer = self.timer("consistent_batch");
+        let current_view = self.latest_view.read().await.clone();
+        let nodes = current_view.members.clone();
+
+        if nodes.len() < MINIMUM_CLUSTER_SIZE {
+            return Err("Cluster size is too small");
+        }
+
+        // Initiate requests
+        let count = messages.len();
+        let operations = self
+            .in_flight
+            .begin(&self.sequence, &current_view, &messages)
+            .await;
+        let first_sequence = operations.first_sequence();
+        self.record_operation(first_sequence, &current_view);
+        operations.record_attempt();
+        let responses = self
+            .network
+            .propose_consistent_batch(&nodes, self.client_id.clone(), first_sequence, messages)
+            .await;
+        let responses = self.successful_responses(responses);
+
+        let mut results = Vec::with_capacity(count);
+        let mut finalizes: BTreeMap<Vec<ID>, Vec<IROperation<ID, MSG>>> = BTreeMap::new();
+        let mut confirmations = Vec::new();
+        for (index, sequence) in (first_sequence..).take(count).enumerate() {
+            let view = match self.record_quorum(
+                "consistent_batch",
+                find_quorum(&self.quorum_policy, batch_votes(&responses, index)),
+            ) {
+                Ok(quorum) if quorum.quorum_type == QuorumType::FastQuorum => {
+                    // We can do async finalize
+                    self.observe_view(quorum.view).await;
+                    finalizes
+                        .entry(finalize_destinations(quorum.view))
+                        .or_default()
+                        .push(IROperation::ConsistentFinalize {
+                            client: self.client_id.clone(),
+                            sequence,
+                            message: quorum.message.clone(),
+                        });
+                    results.push(Ok(quorum.message.clone()));
+                    continue;
+                }
+                Ok(quorum) => quorum.view,
+                Err(Some(no_quorum)) => no_quorum.view,
+                Err(None) => {
+                    results.push(Err("Quorum not found"));
+                    continue;
+                }
+            };
+            let decided =
+                match self.decide_slow_path(&decide_function, batch_votes(&responses, index), view)
+                {
+                    Ok(decided) => decided,
+                    Err(error) => {
+                        results.push(Err(error));
+                        continue;
+                    }
+                };
+            self.observe_view(view).await;
+            confirmations.push((
+                index,
+                self.network.sync_finalize_consistent(
+                    &finalize_destinations(view),
+                    self.client_id.clone(),
+                    sequence,
+                    decided.clone(),
+                ),
+            ));
+            results.push(Ok(decided));
+        }
+        operations.set_stage(OperationStage::Finalizing);
+        for (members, finalizes) in finalizes {
+            self.network.finalize_batch(&members, finalizes).await;
+        }
+        let (indices, confirmations): (Vec<_>, Vec<_>) = confirmations.into_iter().unzip();
+        for (index, responses) in indices.into_iter().zip(join_all(confirmations).await) {
+            let responses = self.successful_responses(responses);
+            if find_quorum(&self.quorum_policy, votes(&responses)).is_err() {
+                results[index] =
+                    Err("Unable to get enough confirm messages for consistent finalize");
+            }
+        }
+        Ok(results)
     }
 
     /// Use this function to make the client additionally make requests to these nodes
@@ -191,8 +530,223 @@ impl<
     /// If the nodes respond with a normal view (i.e. caught up), a view change will be initiated.
     ///
     /// The list of additional nodes is cleared on view change.
-    pub async fn do_view_change(&self, nodes: Vec<ID>) {
+    ///
+    /// Learners of the current view that are listed are promoted to voting members.
+    ///
+    /// The view that the nodes would be voted into is checked against the quorum policy first,
+    /// and the nodes are refused if its quorums would not intersect as IR requires.
+    pub async fn do_view_change(&self, nodes: Vec<ID>) -> Result<(), QuorumError<ID>> {
         let mut additional_nodes = self.additional_nodes.write().await;
+        let latest_view = self.latest_view.read().await;
+        let proposed = View {
+            view: latest_view.view + 1,
+            members: latest_view
+                .members
+                .iter()
+                .chain(additional_nodes.iter())
+                .chain(nodes.iter())
+                .cloned()
+                .collect(),
+            learners: latest_view
+                .learners
+                .iter()
+                .filter(|learner| !nodes.contains(learner) && !additional_nodes.contains(learner))
+                .cloned()
+                .collect(),
+            state: ViewState::ViewChanging,
+        };
+        validate_view(&self.quorum_policy, &proposed)?;
         additional_nodes.extend(nodes);
+        Ok(())
     }
+
+    /// Send a proposal, re-sending to the nodes that have not responded until a quorum is
+    /// found or the attempts run out. Retries keep the sequence number, so replicas treat
+    /// them as the same operation.
+    async fn propose_with_retries<P>(
+        &self,
+        operation: &InFlightGuard<'_, ID, MSG>,
+        nodes: &[ID],
+        propose: P,
+    ) -> Vec<(ID, (MSG, View<ID>))>
+    where
+        P: Fn(
+            &[ID],
+        ) -> Pin<
+            Box<dyn Future<Output = Vec<(ID, Result<(MSG, View<ID>), IRNetworkError<ID>>)>> + Send>,
+        >,
+    {
+        let mut responses: Vec<(ID, (MSG, View<ID>))> = Vec::with_capacity(nodes.len());
+        for _attempt in 0..self.proposal_attempts {
+            let pending: Vec<ID> = nodes
+                .iter()
+                .filter(|node| !responses.iter().any(|(responded, _)| responded == *node))
+                .cloned()
+                .collect();
+            if pending.is_empty() {
+                break;
+            }
+            operation.record_attempt();
+            responses.extend(self.successful_responses(propose(&pending).await));
+            if find_quorum(&self.quorum_policy, votes(&responses)).is_ok() {
+                break;
+            }
+        }
+        responses
+    }
+
+    /// Decide the result of a consistent operation from the responses of the members of a view
+    /// The responses must come from a slow quorum, and the decide function is told f so that
+    /// it can require f+1 matching results of the members rather than of the responses.
+    fn decide_slow_path<'a, F: DecideFunction<MSG>>(
+        &self,
+        decide_function: &F,
+        votes: impl Iterator<Item = QuorumVote<'a, ID, MSG>>,
+        view: &View<ID>,
+    ) -> Result<MSG, &'static str>
+    where
+        ID: 'a,
+        MSG: 'a,
+    {
+        let choices: Vec<QuorumVote<'a, ID, MSG>> = votes
+            .filter(|vote| vote.view == view && view.members.contains(vote.node))
+            .collect();
+        let sizes = self
+            .quorum_policy
+            .sizes(&view.members)
+            .map_err(|_| "Quorum not found")?;
+        if self
+            .quorum_policy
+            .weight_of(choices.iter().map(|vote| vote.node))
+            < sizes.slow
+        {
+            return Err("Quorum not found");
+        }
+        Ok(decide_function.decide(choices.iter().map(|vote| vote.message), sizes.f))
+    }
+
+    /// Keep the responses that succeeded, counting the nodes that could not be reached
+    fn successful_responses<T>(
+        &self,
+        responses: Vec<(ID, Result<T, IRNetworkError<ID>>)>,
+    ) -> Vec<(ID, T)> {
+        let mut successful = Vec::with_capacity(responses.len());
+        for (node, response) in responses {
+            match response {
+                Ok(response) => successful.push((node, response)),
+                Err(error @ (IRNetworkError::NodeUnreachable(_) | IRNetworkError::Timeout(_))) => {
+                    tracing::warn!(node = ?node, ?error, "node did not respond");
+                    self.metrics.increment_counter(
+                        NODE_UNREACHABLE_TOTAL,
+                        &[("node", format!("{:?}", node))],
+                    );
+                }
+                Err(IRNetworkError::IRServerError(error)) => {
+                    tracing::debug!(node = ?node, ?error, "node rejected the request");
+                }
+            }
+        }
+        successful
+    }
+
+    /// Count whether a fast or slow quorum was found, or none at all
+    fn record_quorum<'a>(
+        &self,
+        operation: &'static str,
+        quorum: Result<Quorum<'a, ID, MSG>, Option<NoQuorum<'a, ID, MSG>>>,
+    ) -> Result<Quorum<'a, ID, MSG>, Option<NoQuorum<'a, ID, MSG>>> {
+        match &quorum {
+            Ok(quorum) => tracing::debug!(
+                quorum_type = ?quorum.quorum_type,
+                view = quorum.view.view,
+                "found quorum"
+            ),
+            Err(_) => tracing::warn!("quorum not found"),
+        }
+        match &quorum {
+            Ok(Quorum {
+                quorum_type: QuorumType::FastQuorum,
+                ..
+            }) => self
+                .metrics
+                .increment_counter(QUORUM_TOTAL, &[("type", "fast".to_string())]),
+            Ok(Quorum {
+                quorum_type: QuorumType::NormalQuorum,
+                ..
+            }) => self
+                .metrics
+                .increment_counter(QUORUM_TOTAL, &[("type", "slow".to_string())]),
+            Err(_) => self
+                .metrics
+                .increment_counter(NO_QUORUM_TOTAL, &[("operation", operation.to_string())]),
+        }
+        quorum
+    }
+
+    /// Add the sequence and trace id of the operation to the current `ir_client` span
+    fn record_operation(&self, sequence: OperationSequence, view: &View<ID>) {
+        let span = Span::current();
+        span.record("sequence", sequence);
+        span.record(
+            "trace_id",
+            tracing::field::display(TraceId::new(self.client_id.clone(), sequence)),
+        );
+        span.record("view", view.view);
+    }
+
+    fn timer(&self, operation: &'static str) -> Timer {
+        Timer::start(
+            self.metrics.clone(),
+            OPERATION_DURATION_SECONDS,
+            vec![("operation", operation.to_string())],
+        )
+    }
+
+    /// Adopt a view if it is newer than the one the client knows of
+    /// Operations already in flight keep the view they started with.
+    async fn observe_view(&self, view: &View<ID>) {
+        let mut latest_view = self.latest_view.write().await;
+        if view.view > latest_view.view {
+            tracing::info!(
+                from = latest_view.view,
+                to = view.view,
+                "observed newer view"
+            );
+            *latest_view = view.clone();
+        }
+    }
+}
+
+/// Finalized operations are sent to the learners of a view as well as its members
+fn finalize_destinations<ID: NodeID>(view: &View<ID>) -> Vec<ID> {
+    view.members
+        .iter()
+        .chain(view.learners.iter())
+        .cloned()
+        .collect()
+}
+
+/// The votes of each node for an operation
+fn votes<ID: NodeID, MSG: IRMessage>(
+    responses: &[(ID, (MSG, View<ID>))],
+) -> impl Iterator<Item = QuorumVote<'_, ID, MSG>> {
+    responses.iter().map(|(node, (message, view))| QuorumVote {
+        node,
+        message,
+        view,
+    })
+}
+
+/// The votes of each node for a single operation of a batch
+fn batch_votes<ID: NodeID, MSG: IRMessage>(
+    responses: &[BatchResponse<ID, MSG>],
+    index: usize,
+) -> impl Iterator<Item = QuorumVote<'_, ID, MSG>> {
+    responses.iter().filter_map(move |(node, results)| {
+        results.get(index).map(|(message, view)| QuorumVote {
+            node,
+            message,
+            view,
+        })
+    })
 }
@@ -0,0 +1,219 @@
+use crate::client::InconsistentReplicationClient;
+use crate::io::{IRClientStorage, IRNetwork};
+use crate::types::{DecideFunction, IRMessage, NodeID};
+use futures::future::join_all;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+
+/// A message that belongs to the shard owning its key
+pub trait KeyedMessage: IRMessage {
+    type Key;
+
+    fn key(&self) -> Self::Key;
+}
+
+/// Chooses which shard owns a key
+/// Every client of a deployment must use the same partitioner and the same number of shards.
+pub trait Partitioner<K>: Send + Sync {
+    /// The index of the owning shard, less than `shards`
+    fn shard(&self, key: &K, shards: usize) -> usize;
+}
+
+/// Spreads keys evenly over the shards by their hash
+/// FNV-1a is used rather than the std `DefaultHasher`, whose output may change between Rust
+/// releases, so that clients built separately agree on where keys live.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HashPartitioner;
+
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+impl<K: Hash> Partitioner<K> for HashPartitioner {
+    fn shard(&self, key: &K, shards: usize) -> usize {
+        let mut hasher = Fnv1a(0xcbf29ce484222325);
+        key.hash(&mut hasher);
+        (hasher.finish() % shards as u64) as usize
+    }
+}
+
+/// Assigns contiguous ranges of keys to shards, so that range scans touch few shards
+/// Shard 0 owns the keys below the first split point, shard 1 those from the first split
+/// point up to the second, and the last shard everything from the final split point.
+#[derive(Clone, Debug)]
+pub struct RangePartitioner<K: Ord> {
+    split_points: Vec<K>,
+}
+
+impl<K: Ord> RangePartitioner<K> {
+    pub fn new(mut split_points: Vec<K>) -> Self {
+        split_points.sort();
+        split_points.dedup();
+        RangePartitioner { split_points }
+    }
+}
+
+impl<K: Ord + Send + Sync> Partitioner<K> for RangePartitioner<K> {
+    fn shard(&self, key: &K, shards: usize) -> usize {
+        let shard = self.split_points.partition_point(|point| point <= key);
+        shard.min(shards - 1)
+    }
+}
+
+/// A client of several IR groups, each owning a shard of the keys
+/// Operations are routed to a group by the key of their message. Each group has its own
+/// `InconsistentReplicationClient`, which keeps that group's view fresh from the responses of
+/// its replicas.
+pub struct ShardedClient<N, S, I, M, P>
+where
+    N: IRNetwork<I, M>,
+    S: IRClientStorage<I, M>,
+    I: NodeID,
+    M: KeyedMessage,
+    P: Partitioner<M::Key>,
+{
+    shards: Vec<InconsistentReplicationClient<N, S, I, M>>,
+    partitioner: P,
+}
+
+impl<N, S, I, M, P> ShardedClient<N, S, I, M, P>
+where
+    N: IRNetwork<I, M> + 'static,
+    S: IRClientStorage<I, M> + 'static,
+    I: NodeID + 'static,
+    M: KeyedMessage,
+    P: Partitioner<M::Key>,
+{
+    /// The clients of the groups, in shard order
+    /// Panics if there are no shards.
+    pub fn new(shards: Vec<InconsistentReplicationClient<N, S, I, M>>, partitioner: P) -> Self {
+        assert!(
+            !shards.is_empty(),
+            "A sharded client needs at least one shard"
+        );
+        ShardedClient {
+            shards,
+            partitioner,
+        }
+    }
+
+    /// The index of the shard that a message is routed to
+    pub fn shard_of(&self, message: &M) -> usize {
+        self.partitioner.shard(&message.key(), self.shards.len())
+    }
+
+    /// The client of one group, for operations such as view changes that are not routed
+    pub fn shard(&self, index: usize) -> &InconsistentReplicationClient<N, S, I, M> {
+        &self.shards[index]
+    }
+
+    pub fn shards(&self) -> &[InconsistentReplicationClient<N, S, I, M>] {
+        &self.shards
+    }
+
+    /// Reload the view of every group from storage
+    pub async fn refresh_views(&self) {
+        join_all(self.shards.iter().map(|shard| shard.refresh_view())).await;
+    }
+
+    /// Make an inconsistent request to the group owning the message's key
+    pub async fn invoke_inconsistent(&self, message: M) -> Result<M, &'static str> {
+        self.shards[self.shard_of(&message)]
+            .invoke_inconsistent(message)
+            .await
+    }
+
+    /// Make a consistent request to the group owning the message's key
+    pub async fn invoke_consistent<F: DecideFunction<M>>(
+        &self,
+        message: M,
+        decide_function: F,
+    ) -> Result<M, &'static str> {
+        self.shards[self.shard_of(&message)]
+            .invoke_consistent(message, decide_function)
+            .await
+    }
+
+    /// Make several inconsistent requests, as one batch per group involved
+    /// Results are in the order of the messages. If a group refuses its whole batch, each of
+    /// its operations fails with that error.
+    pub async fn invoke_inconsistent_batch(
+        &self,
+        messages: Vec<M>,
+    ) -> Result<Vec<Result<M, &'static str>>, &'static str> {
+        Ok(self
+            .scatter(messages, |shard, messages| {
+                self.shards[shard].invoke_inconsistent_batch(messages)
+            })
+            .await)
+    }
+
+    /// Make several consistent requests, as one batch per group involved
+    /// Results are in the order of the messages. If a group refuses its whole batch, each of
+    /// its operations fails with that error.
+    pub async fn invoke_consistent_batch<F: DecideFunction<M> + Clone>(
+        &self,
+        messages: Vec<M>,
+        decide_function: F,
+    ) -> Result<Vec<Result<M, &'static str>>, &'static str> {
+        Ok(self
+            .scatter(messages, |shard, messages| {
+                self.shards[shard].invoke_consistent_batch(messages, decide_function.clone())
+            })
+            .await)
+    }
+
+    /// Split messages by shard, invoke each shard's batch concurrently, and put the results
+    /// back in the order of the messages
+    async fn scatter<R, INV, FUT>(
+        &self,
+        messages: Vec<M>,
+        invoke: INV,
+    ) -> Vec<Result<R, &'static str>>
+    where
+        INV: Fn(usize, Vec<M>) -> FUT,
+        FUT: Future<Output = Result<Vec<Result<R, &'static str>>, &'static str>>,
+    {
+        let count = messages.len();
+        let mut batches: BTreeMap<usize, (Vec<usize>, Vec<M>)> = BTreeMap::new();
+        for (index, message) in messages.into_iter().enumerate() {
+            let batch = batches.entry(self.shard_of(&message)).or_default();
+            batch.0.push(index);
+            batch.1.push(message);
+        }
+        let (indexes, invocations): (Vec<_>, Vec<_>) = batches
+            .into_iter()
+            .map(|(shard, (indexes, messages))| (indexes, invoke(shard, messages)))
+            .unzip();
+        let mut results: Vec<Option<Result<R, &'static str>>> = (0..count).map(|_| None).collect();
+        for (indexes, batch) in indexes.into_iter().zip(join_all(invocations).await) {
+            match batch {
+                Ok(batch) => {
+                    for (index, result) in indexes.into_iter().zip(batch) {
+                        results[index] = Some(result);
+                    }
+                }
+                Err(error) => {
+                    for index in indexes {
+                        results[index] = Some(Err(error));
+                    }
+                }
+            }
+        }
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or(Err("No result for operation")))
+            .collect()
+    }
+}
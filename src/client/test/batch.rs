@@ -0,0 +1,141 @@
+use crate::client::test::mock_cluster;
+use crate::server::IROperation;
+use crate::test_utils::mock_computers::NoopComputer;
+use crate::test_utils::{fake_cluster, FakeIRNetwork, FakeIRStorage};
+use crate::types::DecideFunction;
+use crate::{IRApplication, InconsistentReplicationClient};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+struct FirstChoice;
+
+impl DecideFunction<u64> for FirstChoice {
+    fn decide<'a, S: IntoIterator<Item = &'a u64>>(&self, choices: S, _f: usize) -> u64 {
+        *choices.into_iter().next().unwrap()
+    }
+}
+
+#[derive(Clone)]
+struct HighestChoice;
+
+impl DecideFunction<u64> for HighestChoice {
+    fn decide<'a, S: IntoIterator<Item = &'a u64>>(&self, choices: S, _f: usize) -> u64 {
+        *choices.into_iter().max().unwrap()
+    }
+}
+
+/// An application whose replicas add a different offset to consensus results
+#[derive(Clone)]
+struct OffsetComputer {
+    offset: u64,
+}
+
+impl IRApplication<u64, u64> for OffsetComputer {
+    fn exec_inconsistent(&self, _message: u64) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async {})
+    }
+
+    fn exec_consensus(&self, message: u64) -> Pin<Box<dyn Future<Output = u64> + Send>> {
+        let offset = self.offset;
+        Box::pin(async move { message + offset })
+    }
+
+    fn decide(&self, choices: Vec<u64>, _f: usize) -> u64 {
+        choices.into_iter().max().unwrap()
+    }
+
+    fn sync(
+        &self,
+        _record: Vec<IROperation<u64, u64>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async {})
+    }
+}
+
+#[tokio::test]
+async fn inconsistent_batch_has_a_result_per_operation() {
+    // given a cluster
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, NoopComputer<_>>::new();
+    let members = vec![1, 2, 3];
+    let storage = FakeIRStorage::new(members.clone());
+    mock_cluster(&network, members).await;
+
+    // and a client
+    let client = InconsistentReplicationClient::new(network.clone(), storage, 0).await;
+
+    // when the batch cannot reach any node
+    for node in [1, 2, 3] {
+        network.drop_requests_add(node, 1);
+    }
+    let results = client.invoke_inconsistent_batch(vec![4, 5, 6]).await;
+
+    // then every operation fails on its own
+    assert_eq!(results, Ok(vec![Err("Quorum not found"); 3]));
+}
+
+#[tokio::test]
+async fn consistent_batch_has_a_result_per_operation() {
+    // given a cluster
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, NoopComputer<_>>::new();
+    let members = vec![1, 2, 3];
+    let storage = FakeIRStorage::new(members.clone());
+    mock_cluster(&network, members).await;
+
+    // and a client
+    let client = InconsistentReplicationClient::new(network.clone(), storage, 0).await;
+
+    // when the batch cannot reach any node
+    for node in [1, 2, 3] {
+        network.drop_requests_add(node, 1);
+    }
+    let results = client
+        .invoke_consistent_batch(vec![4, 5, 6], FirstChoice)
+        .await;
+
+    // then every operation fails on its own
+    assert_eq!(results, Ok(vec![Err("Quorum not found"); 3]));
+}
+
+#[tokio::test]
+async fn batch_rejected_if_cluster_too_small() {
+    // given a cluster of two
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, NoopComputer<_>>::new();
+    let members = vec![1, 2];
+    let storage = FakeIRStorage::new(members.clone());
+    mock_cluster(&network, members).await;
+
+    // and a client
+    let client = InconsistentReplicationClient::new(network.clone(), storage, 0).await;
+
+    // when a batch is submitted
+    let results = client.invoke_inconsistent_batch(vec![4, 5, 6]).await;
+
+    // then the whole batch is rejected
+    assert_eq!(results, Err("Cluster size is too small"));
+}
+
+#[tokio::test]
+async fn consistent_batch_decides_operations_without_a_fast_quorum() {
+    // given a cluster in which the third replica computes different results
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, OffsetComputer>::new();
+    let members = vec![1, 2, 3];
+    let replicas = Arc::new(AtomicU64::new(0));
+    fake_cluster(&network, &members, || OffsetComputer {
+        offset: replicas.fetch_add(1, Ordering::SeqCst) / 2,
+    })
+    .await;
+
+    // and a client
+    let client =
+        InconsistentReplicationClient::new(network.clone(), FakeIRStorage::new(members), 0).await;
+
+    // when a batch is submitted
+    let results = client
+        .invoke_consistent_batch(vec![4, 5], HighestChoice)
+        .await;
+
+    // then each result is decided from the slow quorum rather than taken from its majority
+    assert_eq!(results, Ok(vec![Ok(5), Ok(6)]));
+}
@@ -0,0 +1,40 @@
+use crate::client::test::mock_cluster;
+use crate::test_utils::mock_computers::NoopComputer;
+use crate::test_utils::{FakeIRNetwork, FakeIRStorage};
+use crate::types::DecideFunction;
+use crate::InconsistentReplicationClient;
+use std::sync::Arc;
+
+struct FirstChoice;
+
+impl DecideFunction<u64> for FirstChoice {
+    fn decide<'a, S: IntoIterator<Item = &'a u64>>(&self, choices: S, _f: usize) -> u64 {
+        *choices.into_iter().next().unwrap()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn client_requests_can_be_spawned_on_multi_threaded_runtime() {
+    // given a cluster
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, NoopComputer<_>>::new();
+    let members = vec![1, 2, 3];
+    let storage = FakeIRStorage::new(members.clone());
+    mock_cluster(&network, members).await;
+
+    // and a client shared between tasks
+    let client = Arc::new(InconsistentReplicationClient::new(network.clone(), storage, 0).await);
+
+    // when the requests are spawned onto the runtime
+    let handles: Vec<_> = (0..4)
+        .map(|message| {
+            let client = client.clone();
+            tokio::spawn(async move { client.invoke_consistent(message, FirstChoice).await })
+        })
+        .collect();
+
+    // then they all complete with their own result
+    for (message, handle) in handles.into_iter().enumerate() {
+        let result = handle.await.unwrap();
+        assert_eq!(result, Ok(message as u64));
+    }
+}
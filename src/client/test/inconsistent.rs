@@ -6,9 +6,9 @@ use crate::InconsistentReplicationClient;
 #[tokio::test]
 async fn client_can_make_inconsistent_requests() {
     // given a cluster
-    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _, _>>::new();
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, NoopComputer<_>>::new();
     let members = vec![1, 2, 3];
-    let storage = FakeIRStorage::new(members.clone(), NoopComputer::new());
+    let storage = FakeIRStorage::new(members.clone());
     mock_cluster(&network, members).await;
 
     // and a client
@@ -24,9 +24,9 @@ async fn client_can_make_inconsistent_requests() {
 #[tokio::test]
 async fn client_fails_inconsistent_request_no_quorum() {
     // given a cluster
-    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _, _>>::new();
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, NoopComputer<_>>::new();
     let members = vec![1, 2, 3];
-    let storage = FakeIRStorage::new(members.clone(), NoopComputer::new());
+    let storage = FakeIRStorage::new(members.clone());
     mock_cluster(&network, members).await;
 
     // and a client
@@ -1,3 +1,8 @@
+use crate::quorum::{QuorumError, QuorumPolicy};
+use crate::test_utils::mock_computers::NoopComputer;
+use crate::test_utils::{FakeIRNetwork, FakeIRStorage};
+use crate::InconsistentReplicationClient;
+
 #[tokio::test]
 pub async fn nodes_that_fail_to_respond_repeatedly_cause_view_change() {
     todo!()
@@ -7,3 +12,26 @@ pub async fn nodes_that_fail_to_respond_repeatedly_cause_view_change() {
 pub async fn nodes_that_are_not_members_that_report_they_are_caught_up_get_voted_in() {
     todo!()
 }
+
+#[tokio::test]
+pub async fn view_changes_to_unsafe_memberships_are_refused() {
+    // given a client that tolerates one failure, where node 4 would outweigh the others
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, NoopComputer<u32>>::new();
+    let storage = FakeIRStorage::new(vec![1, 2, 3]);
+    let policy = QuorumPolicy::majority().with_f(1).with_weight(4, 5);
+    let client = InconsistentReplicationClient::new(network, storage, 0)
+        .await
+        .with_quorum_policy(policy);
+
+    // when nodes are added that would make quorums unsafe
+    let duplicate = client.do_view_change(vec![3]).await;
+    let heavy = client.do_view_change(vec![4]).await;
+
+    // then they are refused, but safe additions are accepted
+    assert_eq!(duplicate, Err(QuorumError::DuplicateMember(3)));
+    assert_eq!(
+        heavy,
+        Err(QuorumError::TooManyFailures { f: 1, members: 4 })
+    );
+    assert_eq!(client.do_view_change(vec![5]).await, Ok(()));
+}
@@ -0,0 +1,71 @@
+use crate::client::test::mock_cluster;
+use crate::metrics::{
+    PrometheusMetrics, NODE_UNREACHABLE_TOTAL, NO_QUORUM_TOTAL, OPERATION_DURATION_SECONDS,
+};
+use crate::test_utils::mock_computers::NoopComputer;
+use crate::test_utils::{FakeIRNetwork, FakeIRStorage};
+use crate::InconsistentReplicationClient;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn client_reports_unreachable_nodes_and_missing_quorums() {
+    // given a cluster that drops every request
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, NoopComputer<_>>::new();
+    let members = vec![1, 2, 3];
+    let storage = FakeIRStorage::new(members.clone());
+    mock_cluster(&network, members.clone()).await;
+    for node in &members {
+        network.drop_requests_add(*node, 100);
+    }
+
+    // and a client with metrics
+    let metrics = Arc::new(PrometheusMetrics::new());
+    let client = InconsistentReplicationClient::new(network.clone(), storage, 0)
+        .await
+        .with_metrics(metrics.clone());
+
+    // when the client makes a request
+    let result = client.invoke_inconsistent(4).await;
+
+    // then the missing quorum, every unreachable attempt and the latency are reported
+    assert!(result.is_err());
+    let operation = [("operation", "inconsistent".to_string())];
+    assert_eq!(metrics.counter(NO_QUORUM_TOTAL, &operation), 1);
+    for node in &members {
+        let node = [("node", node.to_string())];
+        assert_eq!(metrics.counter(NODE_UNREACHABLE_TOTAL, &node), 3);
+    }
+    assert_eq!(
+        metrics.histogram_count(OPERATION_DURATION_SECONDS, &operation),
+        1
+    );
+}
+
+#[tokio::test]
+async fn client_sends_proposals_the_configured_number_of_times() {
+    // given a cluster that drops every request
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, NoopComputer<_>>::new();
+    let members = vec![1, 2, 3];
+    let storage = FakeIRStorage::new(members.clone());
+    mock_cluster(&network, members.clone()).await;
+    for node in &members {
+        network.drop_requests_add(*node, 100);
+    }
+
+    // and a client that leaves retries to the network
+    let metrics = Arc::new(PrometheusMetrics::new());
+    let client = InconsistentReplicationClient::new(network.clone(), storage, 0)
+        .await
+        .with_metrics(metrics.clone())
+        .with_proposal_attempts(1);
+
+    // when the client makes a request
+    let result = client.invoke_inconsistent(4).await;
+
+    // then each node is sent the proposal once
+    assert!(result.is_err());
+    for node in &members {
+        let node = [("node", node.to_string())];
+        assert_eq!(metrics.counter(NODE_UNREACHABLE_TOTAL, &node), 1);
+    }
+}
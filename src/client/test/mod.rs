@@ -1,6 +1,11 @@
+mod batch;
 mod consistent;
 mod inconsistent;
 mod membership;
+mod metrics;
+mod pipelining;
+mod sharded;
+mod trace_id;
 
 use crate::io::test_utils::{FakeIRNetwork, FakeIRStorage};
 use crate::test_utils::mock_computers::NoopComputer;
@@ -8,18 +13,19 @@ use crate::types::{IRMessage, NodeID};
 use crate::InconsistentReplicationServer;
 
 async fn mock_cluster<ID: NodeID, MSG: IRMessage>(
-    network: &FakeIRNetwork<ID, MSG, FakeIRStorage<ID, MSG, NoopComputer<MSG>>>,
+    network: &FakeIRNetwork<ID, MSG, FakeIRStorage<ID, MSG>, NoopComputer<MSG>>,
     nodes: Vec<ID>,
 ) {
     for node_id in &nodes {
-        network.register_node(
+        let server = InconsistentReplicationServer::new(
+            network.clone(),
+            FakeIRStorage::new(nodes.clone()),
+            NoopComputer::new(),
             node_id.clone(),
-            InconsistentReplicationServer::new(
-                network.clone(),
-                FakeIRStorage::new(nodes.clone(), NoopComputer::new()),
-                node_id.clone(),
-            )
-            .await,
-        );
+        )
+        .await;
+        // Clusters too small to form quorums are left recovering, for the client to refuse
+        let _ = server.bootstrap(nodes.clone()).await;
+        network.register_node(node_id.clone(), server);
     }
 }
@@ -0,0 +1,250 @@
+use crate::io::{IRNetworkError, NodeResponses};
+use crate::server::{View, ViewState};
+use crate::test_utils::FakeIRStorage;
+use crate::types::{DecideFunction, OperationSequence};
+use crate::{IRNetwork, InconsistentReplicationClient, OperationStage};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// The destinations and sequence of a proposal
+type Proposal = (Vec<u64>, OperationSequence);
+
+/// Holds every proposal until the test lets it through, then agrees with the message
+#[derive(Clone)]
+struct GatedNetwork {
+    gate: Arc<Semaphore>,
+    proposals: Arc<Mutex<Vec<Proposal>>>,
+    /// The view the replicas reply with, rather than view 1 of the destinations
+    reply_view: Arc<Mutex<Option<View<u64>>>>,
+}
+
+impl GatedNetwork {
+    fn new() -> Self {
+        GatedNetwork {
+            gate: Arc::new(Semaphore::new(0)),
+            proposals: Arc::new(Mutex::new(Vec::new())),
+            reply_view: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn propose(
+        &self,
+        destinations: &[u64],
+        sequence: OperationSequence,
+        message: u64,
+    ) -> NodeResponses<u64, u64> {
+        self.proposals
+            .lock()
+            .unwrap()
+            .push((destinations.to_vec(), sequence));
+        let gate = self.gate.clone();
+        let view = self.reply_view.lock().unwrap().clone().unwrap_or(View {
+            view: 1,
+            members: destinations.to_vec(),
+            learners: vec![],
+            state: ViewState::Normal,
+        });
+        let destinations = destinations.to_vec();
+        Box::pin(async move {
+            gate.acquire().await.unwrap().forget();
+            destinations
+                .into_iter()
+                .map(|d| (d, Ok((message, view.clone()))))
+                .collect()
+        })
+    }
+}
+
+impl IRNetwork<u64, u64> for GatedNetwork {
+    fn propose_inconsistent(
+        &self,
+        destinations: &[u64],
+        _client_id: u64,
+        sequence: OperationSequence,
+        message: u64,
+        _highest_observed_view: Option<View<u64>>,
+    ) -> Pin<
+        Box<dyn Future<Output = Vec<(u64, Result<(u64, View<u64>), IRNetworkError<u64>>)>> + Send>,
+    > {
+        self.propose(destinations, sequence, message)
+    }
+
+    fn propose_consistent(
+        &self,
+        destinations: &[u64],
+        _client_id: u64,
+        sequence: OperationSequence,
+        message: u64,
+    ) -> Pin<
+        Box<dyn Future<Output = Vec<(u64, Result<(u64, View<u64>), IRNetworkError<u64>>)>> + Send>,
+    > {
+        self.propose(destinations, sequence, message)
+    }
+
+    fn async_finalize_inconsistent(
+        &self,
+        _destinations: &[u64],
+        _client_id: u64,
+        _sequence: OperationSequence,
+        _message: u64,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async {})
+    }
+
+    fn async_finalize_consistent(
+        &self,
+        _destinations: &[u64],
+        _client_id: u64,
+        _sequence: OperationSequence,
+        _message: u64,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async {})
+    }
+
+    fn sync_finalize_consistent(
+        &self,
+        destinations: &[u64],
+        _client_id: u64,
+        sequence: OperationSequence,
+        message: u64,
+    ) -> Pin<
+        Box<dyn Future<Output = Vec<(u64, Result<(u64, View<u64>), IRNetworkError<u64>>)>> + Send>,
+    > {
+        self.propose(destinations, sequence, message)
+    }
+}
+
+struct FirstChoice;
+
+impl DecideFunction<u64> for FirstChoice {
+    fn decide<'a, S: IntoIterator<Item = &'a u64>>(&self, choices: S, _f: usize) -> u64 {
+        *choices.into_iter().next().unwrap()
+    }
+}
+
+/// Give spawned tasks a chance to reach the network
+async fn settle() {
+    tokio::time::sleep(Duration::from_millis(50)).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn outstanding_operations_are_bounded_by_the_window() {
+    // given a client that allows two operations in flight
+    let network = GatedNetwork::new();
+    let storage = FakeIRStorage::<u64, u64>::new(vec![1, 2, 3]);
+    let client = Arc::new(
+        InconsistentReplicationClient::new(network.clone(), storage, 0)
+            .await
+            .with_max_in_flight(2),
+    );
+
+    // when four operations are pipelined
+    let handles: Vec<_> = (10..14)
+        .map(|message| {
+            let client = client.clone();
+            tokio::spawn(async move { client.invoke_inconsistent(message).await })
+        })
+        .collect();
+    settle().await;
+
+    // then only two are outstanding, each tracked with its own sequence
+    let in_flight = client.in_flight_operations();
+    assert_eq!(in_flight.len(), 2);
+    assert_ne!(in_flight[0].sequence, in_flight[1].sequence);
+    assert!(in_flight
+        .iter()
+        .all(|op| op.stage == OperationStage::Proposing && op.attempts == 1));
+
+    // and once the replicas respond every operation completes with its own result
+    network.gate.add_permits(4);
+    let mut results = Vec::new();
+    for handle in handles {
+        results.push(handle.await.unwrap());
+    }
+    assert_eq!(results, vec![Ok(10), Ok(11), Ok(12), Ok(13)]);
+    assert!(client.in_flight_operations().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn view_refresh_does_not_change_in_flight_operations() {
+    // given a client with an operation in flight
+    let network = GatedNetwork::new();
+    let storage = FakeIRStorage::<u64, u64>::new(vec![1, 2, 3]);
+    let client =
+        Arc::new(InconsistentReplicationClient::new(network.clone(), storage.clone(), 0).await);
+    let handle = {
+        let client = client.clone();
+        tokio::spawn(async move { client.invoke_inconsistent(7).await })
+    };
+    settle().await;
+    let original_view = client.in_flight_operations()[0].view.clone();
+
+    // when the view changes and the client refreshes
+    storage
+        .set_current_view(View {
+            view: 5,
+            members: vec![4, 5, 6],
+            learners: vec![],
+            state: ViewState::Normal,
+        })
+        .await;
+    client.refresh_view().await;
+
+    // then the in flight operation keeps the view it started with
+    assert_eq!(client.in_flight_operations()[0].view, original_view);
+    network.gate.add_permits(1);
+    assert_eq!(handle.await.unwrap(), Ok(7));
+    assert_eq!(network.proposals.lock().unwrap()[0], (vec![1, 2, 3], 0));
+
+    // and new operations use the refreshed view
+    network.gate.add_permits(1);
+    assert_eq!(client.invoke_inconsistent(8).await, Ok(8));
+    assert_eq!(network.proposals.lock().unwrap()[1], (vec![4, 5, 6], 1));
+}
+
+#[tokio::test]
+async fn operations_use_the_view_observed_from_replies() {
+    // given a client whose storage has view 0, and replicas that have moved to view 2
+    let network = GatedNetwork::new();
+    let storage = FakeIRStorage::<u64, u64>::new(vec![1, 2, 3]);
+    let client = InconsistentReplicationClient::new(network.clone(), storage, 0).await;
+    *network.reply_view.lock().unwrap() = Some(View {
+        view: 2,
+        members: vec![1, 2, 3, 4],
+        learners: vec![],
+        state: ViewState::Normal,
+    });
+    network.gate.add_permits(10);
+
+    // when an inconsistent batch is answered in view 2
+    let batch = client.invoke_inconsistent_batch(vec![5]).await;
+    assert_eq!(batch, Ok(vec![Ok(5)]));
+
+    // then consistent operations and batches are sent to the members of view 2
+    assert_eq!(client.invoke_consistent(6, FirstChoice).await, Ok(6));
+    let batch = client.invoke_consistent_batch(vec![7], FirstChoice).await;
+    assert_eq!(batch, Ok(vec![Ok(7)]));
+    let proposals = network.proposals.lock().unwrap().clone();
+    assert_eq!(
+        proposals,
+        vec![
+            (vec![1, 2, 3], 0),
+            (vec![1, 2, 3, 4], 1),
+            (vec![1, 2, 3, 4], 2)
+        ]
+    );
+}
+
+#[tokio::test]
+#[should_panic(expected = "at least one operation in flight")]
+async fn a_window_of_zero_is_refused() {
+    // given a client
+    let storage = FakeIRStorage::<u64, u64>::new(vec![1, 2, 3]);
+    let client = InconsistentReplicationClient::new(GatedNetwork::new(), storage, 0).await;
+
+    // when it is limited to no operations in flight, then it panics
+    let _client = client.with_max_in_flight(0);
+}
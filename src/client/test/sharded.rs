@@ -0,0 +1,129 @@
+use crate::server::{View, ViewState};
+use crate::test_utils::mock_computers::NoopComputer;
+use crate::test_utils::{FakeIRNetwork, FakeIRStorage};
+use crate::{
+    HashPartitioner, InconsistentReplicationClient, InconsistentReplicationServer, KeyedMessage,
+    Partitioner, RangePartitioner, ShardedClient,
+};
+use futures::{stream, StreamExt};
+
+type Network = FakeIRNetwork<u64, u64, FakeIRStorage<u64, u64>, NoopComputer<u64>>;
+type Server =
+    InconsistentReplicationServer<Network, FakeIRStorage<u64, u64>, NoopComputer<u64>, u64, u64>;
+
+impl KeyedMessage for u64 {
+    type Key = u64;
+
+    fn key(&self) -> u64 {
+        *self
+    }
+}
+
+/// A group of replicas on its own network that has started view 0
+async fn normal_group(network: &Network, members: Vec<u64>) -> Server {
+    let mut servers = vec![];
+    for node_id in &members {
+        let server = InconsistentReplicationServer::new(
+            network.clone(),
+            FakeIRStorage::new(members.clone()),
+            NoopComputer::new(),
+            *node_id,
+        )
+        .await;
+        server
+            .start_view(
+                View {
+                    view: 0,
+                    members: members.clone(),
+                    learners: vec![],
+                    state: ViewState::Normal,
+                },
+                stream::empty(),
+            )
+            .await
+            .unwrap();
+        network.register_node(*node_id, server.clone());
+        servers.push(server);
+    }
+    servers.remove(0)
+}
+
+#[test]
+fn range_partitioner_assigns_keys_by_split_point() {
+    let partitioner = RangePartitioner::new(vec![200, 100]);
+    assert_eq!(partitioner.shard(&5, 3), 0);
+    assert_eq!(partitioner.shard(&100, 3), 1);
+    assert_eq!(partitioner.shard(&199, 3), 1);
+    assert_eq!(partitioner.shard(&500, 3), 2);
+    // keys past the last shard stay on it
+    assert_eq!(partitioner.shard(&500, 2), 1);
+}
+
+#[test]
+fn hash_partitioner_is_stable_and_in_range() {
+    let shards: Vec<_> = (0..100u64)
+        .map(|key| HashPartitioner.shard(&key, 4))
+        .collect();
+    assert!(shards.iter().all(|shard| *shard < 4));
+    assert!((0..4).all(|shard| shards.contains(&shard)));
+    assert_eq!(
+        shards,
+        (0..100u64)
+            .map(|key| HashPartitioner.shard(&key, 4))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn operations_are_routed_to_the_group_owning_their_key() {
+    // given two groups, each on their own network
+    let mut replicas = vec![];
+    let mut clients = vec![];
+    for members in [vec![1, 2, 3], vec![4, 5, 6]] {
+        let network = Network::new();
+        replicas.push(normal_group(&network, members.clone()).await);
+        clients.push(
+            InconsistentReplicationClient::new(network.clone(), FakeIRStorage::new(members), 0)
+                .await,
+        );
+    }
+
+    // and a client splitting the keys at 100
+    let client = ShardedClient::new(clients, RangePartitioner::new(vec![100]));
+
+    // when operations are made on keys either side of the split
+    assert_eq!(client.invoke_inconsistent(5).await, Ok(5));
+    assert_eq!(client.invoke_inconsistent(500).await, Ok(500));
+
+    // then each group only finalizes the operation on its own key
+    let mut first = Box::pin(replicas[0].subscribe(None));
+    let mut second = Box::pin(replicas[1].subscribe(None));
+    assert_eq!(first.next().await.unwrap().unwrap().1.message(), &5);
+    assert_eq!(second.next().await.unwrap().unwrap().1.message(), &500);
+}
+
+#[tokio::test]
+async fn batches_are_split_across_groups_and_keep_their_order() {
+    // given two groups, the second of which cannot be reached
+    let ready = Network::new();
+    normal_group(&ready, vec![1, 2, 3]).await;
+    let unreachable = Network::new();
+    normal_group(&unreachable, vec![4, 5, 6]).await;
+    for node in [4, 5, 6] {
+        unreachable.drop_requests_add(node, 1);
+    }
+    let client = ShardedClient::new(
+        vec![
+            InconsistentReplicationClient::new(ready, FakeIRStorage::new(vec![1, 2, 3]), 0).await,
+            InconsistentReplicationClient::new(unreachable, FakeIRStorage::new(vec![4, 5, 6]), 0)
+                .await,
+        ],
+        RangePartitioner::new(vec![100]),
+    );
+
+    // when a batch spans both groups
+    let results = client.invoke_inconsistent_batch(vec![5, 500, 6]).await;
+
+    // then the results are in the order of the messages, failing only in the unreachable group
+    assert_eq!(results, Ok(vec![Ok(5), Err("Quorum not found"), Ok(6)]));
+}
@@ -0,0 +1,121 @@
+use crate::client::test::mock_cluster;
+use crate::test_utils::mock_computers::NoopComputer;
+use crate::test_utils::{FakeIRNetwork, FakeIRStorage};
+use crate::types::DecideFunction;
+use crate::InconsistentReplicationClient;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use tracing_core::span::Current;
+
+type Fields = BTreeMap<String, String>;
+
+/// Keeps the name and fields of every span
+#[derive(Clone, Default)]
+struct SpanRecorder {
+    spans: Arc<Mutex<Vec<(&'static str, Fields)>>>,
+    metadata: Arc<Mutex<Vec<&'static Metadata<'static>>>>,
+    entered: Arc<Mutex<Vec<Id>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+}
+
+impl Subscriber for SpanRecorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = Fields::new();
+        span.record(&mut FieldVisitor(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((span.metadata().name(), fields));
+        self.metadata.lock().unwrap().push(span.metadata());
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut FieldVisitor(
+            &mut spans[span.into_u64() as usize - 1].1,
+        ));
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, _span: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> Current {
+        match self.entered.lock().unwrap().last() {
+            Some(span) => Current::new(
+                span.clone(),
+                self.metadata.lock().unwrap()[span.into_u64() as usize - 1],
+            ),
+            None => Current::none(),
+        }
+    }
+}
+
+struct FirstChoice;
+
+impl DecideFunction<u32> for FirstChoice {
+    fn decide<'a, S: IntoIterator<Item = &'a u32>>(&self, choices: S, _f: usize) -> u32 {
+        *choices.into_iter().next().unwrap()
+    }
+}
+
+#[tokio::test]
+async fn client_and_replicas_share_the_operation_trace_id() {
+    // given a cluster and a client, with spans being recorded
+    let recorder = SpanRecorder::default();
+    let _guard = tracing::subscriber::set_default(recorder.clone());
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<_, _>, NoopComputer<_>>::new();
+    let members = vec![1, 2, 3];
+    let storage = FakeIRStorage::new(members.clone());
+    mock_cluster(&network, members.clone()).await;
+    let client = InconsistentReplicationClient::new(network.clone(), storage, 0).await;
+
+    // when the client makes a request
+    let _ = client.invoke_consistent(4, FirstChoice).await;
+
+    // then the client span and the span on every replica have the same trace id
+    let spans = recorder.spans.lock().unwrap().clone();
+    let client_span = spans
+        .iter()
+        .find(|(name, _)| *name == "ir_client")
+        .map(|(_, fields)| fields.clone())
+        .unwrap();
+    assert_eq!(client_span["trace_id"], "0/0");
+    assert_eq!(client_span["sequence"], "0");
+    let replicas: BTreeSet<_> = spans
+        .iter()
+        .filter(|(name, fields)| *name == "ir_server" && fields["trace_id"] == "0/0")
+        .map(|(_, fields)| fields["node"].clone())
+        .collect();
+    assert_eq!(
+        replicas,
+        BTreeSet::from(["1".to_string(), "2".to_string(), "3".to_string()])
+    );
+}
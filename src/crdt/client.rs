@@ -0,0 +1,64 @@
+use crate::client::InconsistentReplicationClient;
+use crate::crdt::{merge_states, Crdt, CrdtDecide, CrdtMessage};
+use crate::io::{IRClientStorage, IRNetwork};
+use crate::types::NodeID;
+
+/// A typed client of a CRDT
+pub struct CrdtClient<N, S, I, C>
+where
+    N: IRNetwork<I, CrdtMessage<C>>,
+    S: IRClientStorage<I, CrdtMessage<C>>,
+    I: NodeID,
+    C: Crdt,
+{
+    client: InconsistentReplicationClient<N, S, I, CrdtMessage<C>>,
+    inconsistent_reads: bool,
+}
+
+impl<N, S, I, C> CrdtClient<N, S, I, C>
+where
+    N: IRNetwork<I, CrdtMessage<C>> + 'static,
+    S: IRClientStorage<I, CrdtMessage<C>> + 'static,
+    I: NodeID,
+    C: Crdt,
+{
+    pub fn new(client: InconsistentReplicationClient<N, S, I, CrdtMessage<C>>) -> Self {
+        CrdtClient {
+            client,
+            inconsistent_reads: false,
+        }
+    }
+
+    /// Serve reads as inconsistent operations, merging the states of a slow quorum
+    /// These take a single round trip, but may miss an update whose finalize has not reached
+    /// the replicas yet.
+    pub fn with_inconsistent_reads(mut self) -> Self {
+        self.inconsistent_reads = true;
+        self
+    }
+
+    /// Merge a delta into the replicated state
+    pub async fn update(&self, delta: C) -> Result<(), &'static str> {
+        self.client
+            .invoke_inconsistent(CrdtMessage::Update { delta })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn read(&self) -> Result<C, &'static str> {
+        let read = CrdtMessage::Read { state: None };
+        let result = if self.inconsistent_reads {
+            self.client
+                .invoke_inconsistent_merged(read, |replies| CrdtMessage::Read {
+                    state: Some(merge_states(&replies)),
+                })
+                .await?
+        } else {
+            self.client.invoke_consistent(read, CrdtDecide).await?
+        };
+        match result {
+            CrdtMessage::Read { state: Some(state) } => Ok(state),
+            _ => Err("Unexpected response to read"),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! An adapter running any state-based CRDT as an IR application.
+//!
+//! Inconsistent operations must commute, which is what a CRDT's merge guarantees, so updates
+//! are inconsistent operations carrying deltas that replicas merge into their state when the
+//! operation is finalized and when syncing after a view change. Reads are consensus operations
+//! by default, and can instead be served as inconsistent operations whose replies are merged.
+
+mod client;
+mod replica;
+#[cfg(test)]
+mod test;
+
+pub use client::CrdtClient;
+pub use replica::CrdtReplica;
+
+use crate::types::{DecideFunction, IRMessage};
+
+/// A state-based CRDT, replicated as deltas
+/// Deltas are states too, so applying a delta is merging it. `merge` must be commutative,
+/// associative and idempotent, and the default state must be the empty one.
+pub trait Crdt: IRMessage + Default {
+    fn merge(&mut self, other: &Self);
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum CrdtMessage<C: Crdt> {
+    /// Inconsistent
+    Update { delta: C },
+    /// Consensus or inconsistent; replicas fill in their state
+    Read { state: Option<C> },
+}
+
+/// Decides the result of a consensus read on the client, as the merge of the states read
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CrdtDecide;
+
+impl<C: Crdt> DecideFunction<CrdtMessage<C>> for CrdtDecide {
+    fn decide<'a, S: IntoIterator<Item = &'a CrdtMessage<C>>>(
+        &self,
+        choices: S,
+        _f: usize,
+    ) -> CrdtMessage<C> {
+        CrdtMessage::Read {
+            state: Some(merge_states(choices)),
+        }
+    }
+}
+
+/// The merge of the states in the `Read` replies
+fn merge_states<'a, C: Crdt>(replies: impl IntoIterator<Item = &'a CrdtMessage<C>>) -> C {
+    let mut merged = C::default();
+    for reply in replies {
+        if let CrdtMessage::Read { state: Some(state) } = reply {
+            merged.merge(state);
+        }
+    }
+    merged
+}
@@ -0,0 +1,103 @@
+use crate::application::IRApplication;
+use crate::crdt::{Crdt, CrdtDecide, CrdtMessage};
+use crate::server::IROperation;
+use crate::types::{DecideFunction, NodeID};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// The replica side of a CRDT, an `IRApplication` holding the merged state
+pub struct CrdtReplica<C: Crdt> {
+    state: Arc<Mutex<C>>,
+}
+
+impl<C: Crdt> Clone for CrdtReplica<C> {
+    fn clone(&self) -> Self {
+        CrdtReplica {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<C: Crdt> Default for CrdtReplica<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Crdt> CrdtReplica<C> {
+    pub fn new() -> Self {
+        CrdtReplica {
+            state: Arc::new(Mutex::new(C::default())),
+        }
+    }
+
+    pub fn state(&self) -> C {
+        self.state.lock().unwrap().clone()
+    }
+
+    fn read(&self, message: CrdtMessage<C>) -> CrdtMessage<C> {
+        match message {
+            CrdtMessage::Read { .. } => CrdtMessage::Read {
+                state: Some(self.state()),
+            },
+            update => update,
+        }
+    }
+}
+
+impl<C: Crdt, I: NodeID> IRApplication<I, CrdtMessage<C>> for CrdtReplica<C> {
+    fn exec_inconsistent(
+        &self,
+        message: CrdtMessage<C>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        if let CrdtMessage::Update { delta } = message {
+            self.state.lock().unwrap().merge(&delta);
+        }
+        Box::pin(async {})
+    }
+
+    /// Reply to inconsistent reads with the state, for `CrdtClient::with_inconsistent_reads`
+    fn reply_inconsistent(
+        &self,
+        message: CrdtMessage<C>,
+    ) -> Pin<Box<dyn Future<Output = CrdtMessage<C>> + Send + 'static>> {
+        let reply = self.read(message);
+        Box::pin(async move { reply })
+    }
+
+    fn exec_consensus(
+        &self,
+        message: CrdtMessage<C>,
+    ) -> Pin<Box<dyn Future<Output = CrdtMessage<C>> + Send + 'static>> {
+        let result = self.read(message);
+        Box::pin(async move { result })
+    }
+
+    /// Learners have no state to report, and reads change nothing
+    fn exec_finalized_consensus(
+        &self,
+        _message: CrdtMessage<C>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        Box::pin(async {})
+    }
+
+    /// Merge the states the replicas read
+    fn decide(&self, choices: Vec<CrdtMessage<C>>, f: usize) -> CrdtMessage<C> {
+        CrdtDecide.decide(&choices, f)
+    }
+
+    /// Merge the deltas this replica missed
+    fn sync(
+        &self,
+        record: Vec<IROperation<I, CrdtMessage<C>>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut state = self.state.lock().unwrap();
+        for operation in &record {
+            if let CrdtMessage::Update { delta } = operation.message() {
+                state.merge(delta);
+            }
+        }
+        Box::pin(async {})
+    }
+}
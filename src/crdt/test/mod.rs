@@ -0,0 +1,138 @@
+use crate::crdt::{Crdt, CrdtClient, CrdtMessage, CrdtReplica};
+use crate::io::test_utils::{fake_cluster, partitioned_fake_cluster, FakeIRNetwork, FakeIRStorage};
+use crate::{IRApplication, InconsistentReplicationClient};
+use std::collections::BTreeMap;
+
+/// A grow-only counter, keeping the count of each node
+#[derive(Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+struct GCounter(BTreeMap<u64, u64>);
+
+impl GCounter {
+    fn delta(node: u64, count: u64) -> Self {
+        GCounter(BTreeMap::from([(node, count)]))
+    }
+
+    fn value(&self) -> u64 {
+        self.0.values().sum()
+    }
+}
+
+impl Crdt for GCounter {
+    fn merge(&mut self, other: &Self) {
+        for (node, count) in &other.0 {
+            let entry = self.0.entry(*node).or_default();
+            *entry = (*entry).max(*count);
+        }
+    }
+}
+
+type Message = CrdtMessage<GCounter>;
+type Network = FakeIRNetwork<u64, Message, FakeIRStorage<u64, Message>, CrdtReplica<GCounter>>;
+
+async fn crdt_client(
+    network: &Network,
+    members: Vec<u64>,
+    client_id: u64,
+) -> CrdtClient<Network, FakeIRStorage<u64, Message>, u64, GCounter> {
+    CrdtClient::new(
+        InconsistentReplicationClient::new(network.clone(), FakeIRStorage::new(members), client_id)
+            .await,
+    )
+}
+
+#[tokio::test]
+async fn updates_merge_into_every_replica() {
+    // given a cluster and two clients
+    let network = Network::new();
+    let members = vec![1, 2, 3];
+    let replicas = fake_cluster(&network, &members, CrdtReplica::new).await;
+    let first = crdt_client(&network, members.clone(), 100).await;
+    let second = crdt_client(&network, members, 200).await;
+
+    // when both send deltas, one of them twice
+    first.update(GCounter::delta(100, 2)).await.unwrap();
+    second.update(GCounter::delta(200, 5)).await.unwrap();
+    first.update(GCounter::delta(100, 2)).await.unwrap();
+
+    // then every replica merged them, and a read returns the merged state
+    for replica in &replicas {
+        assert_eq!(replica.state().value(), 7);
+    }
+    assert_eq!(first.read().await.map(|state| state.value()), Ok(7));
+}
+
+#[tokio::test]
+async fn inconsistent_reads_merge_the_states_of_the_replicas() {
+    // given replicas that each hold an update the others have not seen
+    let network = Network::new();
+    let members = vec![1, 2, 3];
+    let replicas = fake_cluster(&network, &members, CrdtReplica::new).await;
+    for (node, replica) in members.iter().zip(&replicas) {
+        IRApplication::<u64, Message>::exec_inconsistent(
+            replica,
+            CrdtMessage::Update {
+                delta: GCounter::delta(*node, 1),
+            },
+        )
+        .await;
+    }
+    let client = crdt_client(&network, members, 100)
+        .await
+        .with_inconsistent_reads();
+
+    // when the client reads
+    let state = client.read().await.unwrap();
+
+    // then it sees the merge of a slow quorum of the replicas, leaving their states as they were
+    assert!(state.value() >= 2);
+    for replica in &replicas {
+        assert_eq!(replica.state().value(), 1);
+    }
+}
+
+#[tokio::test]
+async fn consensus_reads_merge_the_states_of_the_reachable_replicas() {
+    // given 5 members of which 3 are reachable, each holding an update the others have not seen
+    let network = Network::new();
+    let members = vec![1, 2, 3, 4, 5];
+    let replicas = partitioned_fake_cluster(&network, &members, &[1, 2, 3], CrdtReplica::new).await;
+    for (node, replica) in members.iter().zip(&replicas) {
+        IRApplication::<u64, Message>::exec_inconsistent(
+            replica,
+            CrdtMessage::Update {
+                delta: GCounter::delta(*node, 1),
+            },
+        )
+        .await;
+    }
+    let client = crdt_client(&network, members, 100).await;
+
+    // when the client reads, and the replies disagree
+    let state = client.read().await.unwrap();
+
+    // then the decided result is the merge of every reply, not one of them
+    assert_eq!(state.value(), 3);
+}
+
+#[tokio::test]
+async fn decide_merges_the_states_read() {
+    // given the states read by several replicas
+    let replica = CrdtReplica::<GCounter>::new();
+    let read = |delta| CrdtMessage::Read { state: Some(delta) };
+
+    // when a view change decides the read
+    let decided = IRApplication::<u64, Message>::decide(
+        &replica,
+        vec![
+            read(GCounter::delta(1, 3)),
+            read(GCounter::delta(2, 4)),
+            read(GCounter::delta(1, 1)),
+        ],
+        1,
+    );
+
+    // then the result is their merge
+    let mut merged = GCounter::delta(1, 3);
+    merged.merge(&GCounter::delta(2, 4));
+    assert_eq!(decided, read(merged));
+}
@@ -0,0 +1,294 @@
+//! A compare-and-set register. Compare-and-set does not commute, so it runs in two steps like
+//! a lock: a consensus `Propose` where replicas promise the register's next version to one
+//! operation, then an inconsistent `Commit` installing the value if f+1 replicas promised.
+
+use crate::application::IRApplication;
+use crate::client::InconsistentReplicationClient;
+use crate::datatypes::{decide, DataTypeDecide, DataValue, OperationId, ResolveChoices};
+use crate::io::{IRClientStorage, IRNetwork};
+use crate::server::IROperation;
+use crate::types::NodeID;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A value with the version it was written at; versions count the successful writes
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Versioned<V: DataValue> {
+    pub version: u64,
+    pub value: Option<V>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum CasMessage<V: DataValue, I: NodeID> {
+    /// Consensus; replicas accept if the register is at `version` and they have not promised
+    /// the next version to another operation
+    Propose {
+        id: OperationId<I>,
+        version: u64,
+        value: V,
+        accepted: Option<bool>,
+    },
+    /// Inconsistent; installs the value of an accepted proposal
+    Commit { version: u64, value: V },
+    /// Inconsistent; withdraws the promises made to a refused proposal
+    Release { id: OperationId<I>, version: u64 },
+    /// Consensus; replicas fill in their value
+    Read { current: Option<Versioned<V>> },
+}
+
+impl<V: DataValue, I: NodeID> ResolveChoices for CasMessage<V, I> {
+    /// A proposal is only accepted if at least f+1 replicas accepted it, where f is the number
+    /// of failures the view tolerates, as a replica can promise a version to one proposal
+    /// only. A read returns the latest version any replica reports.
+    fn resolve(choices: &[&Self], f: usize) -> Self {
+        let proposal = choices.iter().find_map(|choice| match choice {
+            CasMessage::Propose {
+                id, version, value, ..
+            } => Some((id, version, value)),
+            _ => None,
+        });
+        if let Some((id, version, value)) = proposal {
+            let accepts = choices
+                .iter()
+                .filter(|choice| {
+                    matches!(
+                        choice,
+                        CasMessage::Propose {
+                            accepted: Some(true),
+                            ..
+                        }
+                    )
+                })
+                .count();
+            return CasMessage::Propose {
+                id: id.clone(),
+                version: *version,
+                value: value.clone(),
+                accepted: Some(accepts > f),
+            };
+        }
+        super::max_by(choices, |choice| match choice {
+            CasMessage::Read { current } => current.as_ref().map(|current| current.version),
+            _ => None,
+        })
+    }
+}
+
+struct Register<V: DataValue, I: NodeID> {
+    current: Versioned<V>,
+    /// The proposal promised the version after `current`
+    promised: Option<OperationId<I>>,
+}
+
+impl<V: DataValue, I: NodeID> Register<V, I> {
+    fn commit(&mut self, version: u64, value: V) {
+        if version >= self.current.version {
+            self.current = Versioned {
+                version: version + 1,
+                value: Some(value),
+            };
+            self.promised = None;
+        }
+    }
+}
+
+/// The replica side of a compare-and-set register
+#[derive(Clone)]
+pub struct CasRegisterReplica<V: DataValue, I: NodeID> {
+    register: Arc<Mutex<Register<V, I>>>,
+}
+
+impl<V: DataValue, I: NodeID> Default for CasRegisterReplica<V, I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: DataValue, I: NodeID> CasRegisterReplica<V, I> {
+    pub fn new() -> Self {
+        CasRegisterReplica {
+            register: Arc::new(Mutex::new(Register {
+                current: Versioned {
+                    version: 0,
+                    value: None,
+                },
+                promised: None,
+            })),
+        }
+    }
+
+    pub fn current(&self) -> Versioned<V> {
+        self.register.lock().unwrap().current.clone()
+    }
+}
+
+impl<V: DataValue, I: NodeID> IRApplication<I, CasMessage<V, I>> for CasRegisterReplica<V, I> {
+    fn exec_inconsistent(
+        &self,
+        message: CasMessage<V, I>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut register = self.register.lock().unwrap();
+        match message {
+            CasMessage::Commit { version, value } => register.commit(version, value),
+            CasMessage::Release { id, version }
+                if version == register.current.version
+                    && register.promised.as_ref() == Some(&id) =>
+            {
+                register.promised = None;
+            }
+            _ => {}
+        }
+        Box::pin(async {})
+    }
+
+    fn exec_consensus(
+        &self,
+        message: CasMessage<V, I>,
+    ) -> Pin<Box<dyn Future<Output = CasMessage<V, I>> + Send + 'static>> {
+        let mut register = self.register.lock().unwrap();
+        let result = match message {
+            CasMessage::Propose {
+                id, version, value, ..
+            } => {
+                let accepted = version == register.current.version
+                    && register
+                        .promised
+                        .as_ref()
+                        .is_none_or(|promised| *promised == id);
+                if accepted {
+                    register.promised = Some(id.clone());
+                }
+                CasMessage::Propose {
+                    id,
+                    version,
+                    value,
+                    accepted: Some(accepted),
+                }
+            }
+            CasMessage::Read { .. } => CasMessage::Read {
+                current: Some(register.current.clone()),
+            },
+            update => update,
+        };
+        Box::pin(async move { result })
+    }
+
+    fn decide(&self, choices: Vec<CasMessage<V, I>>, f: usize) -> CasMessage<V, I> {
+        decide(choices, f)
+    }
+
+    /// Apply the writes in the master record, dropping promises
+    /// Accepted proposals count as writes, so a register is not left waiting on a client that
+    /// failed before committing.
+    fn sync(
+        &self,
+        record: Vec<IROperation<I, CasMessage<V, I>>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut writes = BTreeMap::new();
+        for operation in record {
+            match operation {
+                IROperation::InconsistentFinalize {
+                    message: CasMessage::Commit { version, value },
+                    ..
+                }
+                | IROperation::ConsistentFinalize {
+                    message:
+                        CasMessage::Propose {
+                            version,
+                            value,
+                            accepted: Some(true),
+                            ..
+                        },
+                    ..
+                } => {
+                    writes.insert(version, value);
+                }
+                _ => {}
+            }
+        }
+        let mut register = self.register.lock().unwrap();
+        register.promised = None;
+        for (version, value) in writes {
+            register.commit(version, value);
+        }
+        Box::pin(async {})
+    }
+}
+
+/// A typed client of a compare-and-set register
+pub struct CasRegisterClient<N, S, I, V>
+where
+    N: IRNetwork<I, CasMessage<V, I>>,
+    S: IRClientStorage<I, CasMessage<V, I>>,
+    I: NodeID,
+    V: DataValue,
+{
+    client: InconsistentReplicationClient<N, S, I, CasMessage<V, I>>,
+    proposals: AtomicU64,
+}
+
+impl<N, S, I, V> CasRegisterClient<N, S, I, V>
+where
+    N: IRNetwork<I, CasMessage<V, I>> + 'static,
+    S: IRClientStorage<I, CasMessage<V, I>> + 'static,
+    I: NodeID,
+    V: DataValue,
+{
+    pub fn new(client: InconsistentReplicationClient<N, S, I, CasMessage<V, I>>) -> Self {
+        CasRegisterClient {
+            client,
+            proposals: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn read(&self) -> Result<Versioned<V>, &'static str> {
+        match self
+            .client
+            .invoke_consistent(CasMessage::Read { current: None }, DataTypeDecide)
+            .await?
+        {
+            CasMessage::Read {
+                current: Some(current),
+            } => Ok(current),
+            _ => Err("Unexpected response to read"),
+        }
+    }
+
+    /// Write a value if the register is still at the version read, returning whether it was
+    /// written
+    pub async fn compare_and_set(&self, version: u64, value: V) -> Result<bool, &'static str> {
+        let id = OperationId {
+            client: self.client.client_id().clone(),
+            sequence: self.proposals.fetch_add(1, Ordering::SeqCst),
+        };
+        let result = self
+            .client
+            .invoke_consistent(
+                CasMessage::Propose {
+                    id: id.clone(),
+                    version,
+                    value: value.clone(),
+                    accepted: None,
+                },
+                DataTypeDecide,
+            )
+            .await?;
+        let accepted = matches!(
+            result,
+            CasMessage::Propose {
+                accepted: Some(true),
+                ..
+            }
+        );
+        let update = if accepted {
+            CasMessage::Commit { version, value }
+        } else {
+            CasMessage::Release { id, version }
+        };
+        self.client.invoke_inconsistent(update).await?;
+        Ok(accepted)
+    }
+}
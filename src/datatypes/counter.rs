@@ -0,0 +1,173 @@
+//! A replicated counter. Increments and decrements commute, so they are inconsistent
+//! operations; each carries an id so that no replica applies it twice.
+
+use crate::application::IRApplication;
+use crate::client::InconsistentReplicationClient;
+use crate::datatypes::{decide, DataTypeDecide, OperationId, ResolveChoices};
+use crate::io::{IRClientStorage, IRNetwork};
+use crate::server::IROperation;
+use crate::types::NodeID;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum CounterMessage<I: NodeID> {
+    /// Inconsistent
+    Add { id: OperationId<I>, amount: i64 },
+    /// Consensus; replicas fill in their value
+    Read { value: Option<i64> },
+}
+
+impl<I: NodeID> ResolveChoices for CounterMessage<I> {
+    /// The value reported by the most replicas, as replicas that missed an update report less
+    /// or more than the others
+    fn resolve(choices: &[&Self], _f: usize) -> Self {
+        let value = |choice: &Self| match choice {
+            CounterMessage::Read { value } => *value,
+            CounterMessage::Add { .. } => None,
+        };
+        super::max_by(choices, |choice| {
+            let count = choices
+                .iter()
+                .filter(|other| value(other) == value(choice))
+                .count();
+            (count, value(choice))
+        })
+    }
+}
+
+struct Counter<I: NodeID> {
+    value: i64,
+    applied: BTreeSet<OperationId<I>>,
+}
+
+/// The replica side of a counter
+#[derive(Clone)]
+pub struct CounterReplica<I: NodeID> {
+    counter: Arc<Mutex<Counter<I>>>,
+}
+
+impl<I: NodeID> Default for CounterReplica<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: NodeID> CounterReplica<I> {
+    pub fn new() -> Self {
+        CounterReplica {
+            counter: Arc::new(Mutex::new(Counter {
+                value: 0,
+                applied: BTreeSet::new(),
+            })),
+        }
+    }
+
+    pub fn value(&self) -> i64 {
+        self.counter.lock().unwrap().value
+    }
+
+    fn apply(&self, id: OperationId<I>, amount: i64) {
+        let mut counter = self.counter.lock().unwrap();
+        if counter.applied.insert(id) {
+            counter.value += amount;
+        }
+    }
+}
+
+impl<I: NodeID> IRApplication<I, CounterMessage<I>> for CounterReplica<I> {
+    fn exec_inconsistent(
+        &self,
+        message: CounterMessage<I>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        if let CounterMessage::Add { id, amount } = message {
+            self.apply(id, amount);
+        }
+        Box::pin(async {})
+    }
+
+    fn exec_consensus(
+        &self,
+        message: CounterMessage<I>,
+    ) -> Pin<Box<dyn Future<Output = CounterMessage<I>> + Send + 'static>> {
+        let result = match message {
+            CounterMessage::Read { .. } => CounterMessage::Read {
+                value: Some(self.value()),
+            },
+            add => add,
+        };
+        Box::pin(async move { result })
+    }
+
+    fn decide(&self, choices: Vec<CounterMessage<I>>, f: usize) -> CounterMessage<I> {
+        decide(choices, f)
+    }
+
+    /// Apply the updates this replica missed
+    fn sync(
+        &self,
+        record: Vec<IROperation<I, CounterMessage<I>>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        for operation in record {
+            if let IROperation::InconsistentFinalize {
+                message: CounterMessage::Add { id, amount },
+                ..
+            } = operation
+            {
+                self.apply(id, amount);
+            }
+        }
+        Box::pin(async {})
+    }
+}
+
+/// A typed client of a counter
+pub struct CounterClient<N, S, I>
+where
+    N: IRNetwork<I, CounterMessage<I>>,
+    S: IRClientStorage<I, CounterMessage<I>>,
+    I: NodeID,
+{
+    client: InconsistentReplicationClient<N, S, I, CounterMessage<I>>,
+    updates: AtomicU64,
+}
+
+impl<N, S, I> CounterClient<N, S, I>
+where
+    N: IRNetwork<I, CounterMessage<I>> + 'static,
+    S: IRClientStorage<I, CounterMessage<I>> + 'static,
+    I: NodeID,
+{
+    pub fn new(client: InconsistentReplicationClient<N, S, I, CounterMessage<I>>) -> Self {
+        CounterClient {
+            client,
+            updates: AtomicU64::new(0),
+        }
+    }
+
+    /// Add to the counter, or subtract with a negative amount
+    pub async fn add(&self, amount: i64) -> Result<(), &'static str> {
+        let id = OperationId {
+            client: self.client.client_id().clone(),
+            sequence: self.updates.fetch_add(1, Ordering::SeqCst),
+        };
+        self.client
+            .invoke_inconsistent(CounterMessage::Add { id, amount })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn value(&self) -> Result<i64, &'static str> {
+        match self
+            .client
+            .invoke_consistent(CounterMessage::Read { value: None }, DataTypeDecide)
+            .await?
+        {
+            CounterMessage::Read { value: Some(value) } => Ok(value),
+            _ => Err("Unexpected response to read"),
+        }
+    }
+}
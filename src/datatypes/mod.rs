@@ -0,0 +1,68 @@
+//! Ready-made replicated data types, each an `IRApplication` with a typed client.
+//!
+//! Updates that commute are inconsistent operations, so they complete in one round trip and
+//! replicas apply them in any order:
+//!
+//! - `counter`: a counter with increments and decrements
+//! - `register`: a last-writer-wins register, ordered by hybrid logical timestamps
+//! - `set`: a grow-only set, and an observed-remove set that also supports removal
+//!
+//! A compare-and-set register (`cas`) needs replicas to agree, so it is built from consensus
+//! operations. Reads of every type are consensus operations, as those are the ones that
+//! return results; when replicas report different values, `DataTypeDecide` picks the most
+//! up to date.
+
+pub mod cas;
+pub mod counter;
+pub mod register;
+pub mod set;
+#[cfg(test)]
+mod test;
+
+use crate::types::{DecideFunction, IRMessage, NodeID};
+use std::fmt::Debug;
+
+/// A value stored in a replicated data type
+pub trait DataValue: Clone + Ord + Debug + Send + Sync + 'static {}
+
+impl<A> DataValue for A where A: Clone + Ord + Debug + Send + Sync + 'static {}
+
+/// Identifies an update, so that replicas can recognise one they have already applied
+/// Client ids must not be reused, as each client numbers its updates from 0.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct OperationId<I: NodeID> {
+    pub client: I,
+    pub sequence: u64,
+}
+
+/// A message whose consensus results can be resolved when replicas disagree
+pub trait ResolveChoices: IRMessage {
+    /// The result to use given those reported by replicas, where f is the number of failures
+    /// the view tolerates
+    fn resolve(choices: &[&Self], f: usize) -> Self;
+}
+
+/// Decides consensus results of the data types, on clients and during view changes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DataTypeDecide;
+
+impl<M: ResolveChoices> DecideFunction<M> for DataTypeDecide {
+    fn decide<'a, S: IntoIterator<Item = &'a M>>(&self, choices: S, f: usize) -> M {
+        let choices: Vec<_> = choices.into_iter().collect();
+        M::resolve(&choices, f)
+    }
+}
+
+/// `IRApplication::decide` for a message type, in terms of `ResolveChoices`
+fn decide<M: ResolveChoices>(choices: Vec<M>, f: usize) -> M {
+    M::resolve(&choices.iter().collect::<Vec<_>>(), f)
+}
+
+/// The choice with the greatest key
+fn max_by<M: Clone, K: Ord>(choices: &[&M], key: impl Fn(&M) -> K) -> M {
+    choices
+        .iter()
+        .max_by_key(|choice| key(choice))
+        .map(|choice| (*choice).clone())
+        .expect("a decision needs at least one choice")
+}
@@ -0,0 +1,205 @@
+//! A last-writer-wins register. Writes are inconsistent operations stamped by the writer's
+//! hybrid logical clock, and every replica keeps the write with the greatest version.
+
+use crate::application::IRApplication;
+use crate::client::InconsistentReplicationClient;
+use crate::datatypes::{decide, DataTypeDecide, DataValue, ResolveChoices};
+use crate::hlc::{HlcTimestamp, HybridLogicalClock, TimeSource, Timestamped};
+use crate::io::{IRClientStorage, IRNetwork};
+use crate::server::IROperation;
+use crate::types::NodeID;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// A value with the version that wrote it
+type Versioned<V, I> = (V, RegisterVersion<I>);
+
+/// Orders writes, with the writer breaking ties between equal timestamps
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct RegisterVersion<I: NodeID> {
+    pub timestamp: HlcTimestamp,
+    pub writer: I,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum RegisterMessage<V: DataValue, I: NodeID> {
+    /// Inconsistent
+    Write {
+        value: V,
+        version: RegisterVersion<I>,
+    },
+    /// Consensus; replicas fill in their latest write
+    Read {
+        value: Option<V>,
+        version: Option<RegisterVersion<I>>,
+    },
+}
+
+impl<V: DataValue, I: NodeID> ResolveChoices for RegisterMessage<V, I> {
+    /// The latest write any replica reports
+    fn resolve(choices: &[&Self], _f: usize) -> Self {
+        super::max_by(choices, |choice| match choice {
+            RegisterMessage::Read { version, .. } => version.clone(),
+            RegisterMessage::Write { .. } => None,
+        })
+    }
+}
+
+impl<V: DataValue, I: NodeID> Timestamped for RegisterMessage<V, I> {
+    fn timestamp(&self) -> Option<HlcTimestamp> {
+        match self {
+            RegisterMessage::Write { version, .. } => Some(version.timestamp),
+            RegisterMessage::Read { version, .. } => {
+                version.as_ref().map(|version| version.timestamp)
+            }
+        }
+    }
+}
+
+/// The replica side of a last-writer-wins register
+#[derive(Clone)]
+pub struct LwwRegisterReplica<V: DataValue, I: NodeID> {
+    latest: Arc<Mutex<Option<Versioned<V, I>>>>,
+}
+
+impl<V: DataValue, I: NodeID> Default for LwwRegisterReplica<V, I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: DataValue, I: NodeID> LwwRegisterReplica<V, I> {
+    pub fn new() -> Self {
+        LwwRegisterReplica {
+            latest: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn value(&self) -> Option<V> {
+        self.latest
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(value, _)| value.clone())
+    }
+
+    fn apply(&self, value: V, version: RegisterVersion<I>) {
+        let mut latest = self.latest.lock().unwrap();
+        if latest
+            .as_ref()
+            .is_none_or(|(_, current)| *current < version)
+        {
+            *latest = Some((value, version));
+        }
+    }
+}
+
+impl<V: DataValue, I: NodeID> IRApplication<I, RegisterMessage<V, I>> for LwwRegisterReplica<V, I> {
+    fn exec_inconsistent(
+        &self,
+        message: RegisterMessage<V, I>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        if let RegisterMessage::Write { value, version } = message {
+            self.apply(value, version);
+        }
+        Box::pin(async {})
+    }
+
+    fn exec_consensus(
+        &self,
+        message: RegisterMessage<V, I>,
+    ) -> Pin<Box<dyn Future<Output = RegisterMessage<V, I>> + Send + 'static>> {
+        let result = match message {
+            RegisterMessage::Read { .. } => {
+                let latest = self.latest.lock().unwrap().clone();
+                let (value, version) = latest.unzip();
+                RegisterMessage::Read { value, version }
+            }
+            write => write,
+        };
+        Box::pin(async move { result })
+    }
+
+    fn decide(&self, choices: Vec<RegisterMessage<V, I>>, f: usize) -> RegisterMessage<V, I> {
+        decide(choices, f)
+    }
+
+    /// Apply the writes this replica missed
+    fn sync(
+        &self,
+        record: Vec<IROperation<I, RegisterMessage<V, I>>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        for operation in record {
+            if let IROperation::InconsistentFinalize {
+                message: RegisterMessage::Write { value, version },
+                ..
+            } = operation
+            {
+                self.apply(value, version);
+            }
+        }
+        Box::pin(async {})
+    }
+}
+
+/// A typed client of a last-writer-wins register
+/// Its clock observes the versions it reads, so a write after a read always wins over the
+/// write that was read, however far the writers' clocks have drifted.
+pub struct LwwRegisterClient<N, S, I, V, T>
+where
+    N: IRNetwork<I, RegisterMessage<V, I>>,
+    S: IRClientStorage<I, RegisterMessage<V, I>>,
+    I: NodeID,
+    V: DataValue,
+    T: TimeSource,
+{
+    client: InconsistentReplicationClient<N, S, I, RegisterMessage<V, I>>,
+    clock: HybridLogicalClock<T>,
+}
+
+impl<N, S, I, V, T> LwwRegisterClient<N, S, I, V, T>
+where
+    N: IRNetwork<I, RegisterMessage<V, I>> + 'static,
+    S: IRClientStorage<I, RegisterMessage<V, I>> + 'static,
+    I: NodeID,
+    V: DataValue,
+    T: TimeSource,
+{
+    pub fn new(
+        client: InconsistentReplicationClient<N, S, I, RegisterMessage<V, I>>,
+        clock: HybridLogicalClock<T>,
+    ) -> Self {
+        LwwRegisterClient { client, clock }
+    }
+
+    pub async fn write(&self, value: V) -> Result<(), &'static str> {
+        let version = RegisterVersion {
+            timestamp: self.clock.now(),
+            writer: self.client.client_id().clone(),
+        };
+        self.client
+            .invoke_inconsistent(RegisterMessage::Write { value, version })
+            .await?;
+        Ok(())
+    }
+
+    /// The latest value, or `None` if the register was never written
+    pub async fn read(&self) -> Result<Option<V>, &'static str> {
+        let result = self
+            .client
+            .invoke_consistent(
+                RegisterMessage::Read {
+                    value: None,
+                    version: None,
+                },
+                DataTypeDecide,
+            )
+            .await?;
+        self.clock.observe(&result);
+        match result {
+            RegisterMessage::Read { value, .. } => Ok(value),
+            _ => Err("Unexpected response to read"),
+        }
+    }
+}
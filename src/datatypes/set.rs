@@ -0,0 +1,345 @@
+//! Replicated sets, updated by inconsistent operations.
+//!
+//! A grow-only set only supports adding elements, so replicas need no more than the union of
+//! the adds. An observed-remove set also supports removal: each add is tagged, a remove takes
+//! away the tags the client observed, and an element is present while any tag remains, so an
+//! add that is concurrent with a remove wins.
+
+use crate::application::IRApplication;
+use crate::client::InconsistentReplicationClient;
+use crate::datatypes::{decide, DataTypeDecide, DataValue, OperationId, ResolveChoices};
+use crate::io::{IRClientStorage, IRNetwork};
+use crate::server::IROperation;
+use crate::types::NodeID;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum GSetMessage<E: DataValue> {
+    /// Inconsistent
+    Add { element: E },
+    /// Consensus; replicas fill in their elements
+    Read { elements: Option<BTreeSet<E>> },
+}
+
+impl<E: DataValue> ResolveChoices for GSetMessage<E> {
+    /// The largest set any replica reports, as the replicas' sets only differ by adds some
+    /// have not applied yet
+    fn resolve(choices: &[&Self], _f: usize) -> Self {
+        super::max_by(choices, |choice| match choice {
+            GSetMessage::Read {
+                elements: Some(elements),
+            } => elements.len(),
+            _ => 0,
+        })
+    }
+}
+
+/// The replica side of a grow-only set
+#[derive(Clone)]
+pub struct GSetReplica<E: DataValue> {
+    elements: Arc<Mutex<BTreeSet<E>>>,
+}
+
+impl<E: DataValue> Default for GSetReplica<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: DataValue> GSetReplica<E> {
+    pub fn new() -> Self {
+        GSetReplica {
+            elements: Arc::new(Mutex::new(BTreeSet::new())),
+        }
+    }
+
+    pub fn elements(&self) -> BTreeSet<E> {
+        self.elements.lock().unwrap().clone()
+    }
+}
+
+impl<E: DataValue, I: NodeID> IRApplication<I, GSetMessage<E>> for GSetReplica<E> {
+    fn exec_inconsistent(
+        &self,
+        message: GSetMessage<E>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        if let GSetMessage::Add { element } = message {
+            self.elements.lock().unwrap().insert(element);
+        }
+        Box::pin(async {})
+    }
+
+    fn exec_consensus(
+        &self,
+        message: GSetMessage<E>,
+    ) -> Pin<Box<dyn Future<Output = GSetMessage<E>> + Send + 'static>> {
+        let result = match message {
+            GSetMessage::Read { .. } => GSetMessage::Read {
+                elements: Some(self.elements()),
+            },
+            add => add,
+        };
+        Box::pin(async move { result })
+    }
+
+    fn decide(&self, choices: Vec<GSetMessage<E>>, f: usize) -> GSetMessage<E> {
+        decide(choices, f)
+    }
+
+    /// Apply the adds this replica missed
+    fn sync(
+        &self,
+        record: Vec<IROperation<I, GSetMessage<E>>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut elements = self.elements.lock().unwrap();
+        for operation in record {
+            if let IROperation::InconsistentFinalize {
+                message: GSetMessage::Add { element },
+                ..
+            } = operation
+            {
+                elements.insert(element);
+            }
+        }
+        Box::pin(async {})
+    }
+}
+
+/// A typed client of a grow-only set
+pub struct GSetClient<N, S, I, E>
+where
+    N: IRNetwork<I, GSetMessage<E>>,
+    S: IRClientStorage<I, GSetMessage<E>>,
+    I: NodeID,
+    E: DataValue,
+{
+    client: InconsistentReplicationClient<N, S, I, GSetMessage<E>>,
+}
+
+impl<N, S, I, E> GSetClient<N, S, I, E>
+where
+    N: IRNetwork<I, GSetMessage<E>> + 'static,
+    S: IRClientStorage<I, GSetMessage<E>> + 'static,
+    I: NodeID,
+    E: DataValue,
+{
+    pub fn new(client: InconsistentReplicationClient<N, S, I, GSetMessage<E>>) -> Self {
+        GSetClient { client }
+    }
+
+    pub async fn insert(&self, element: E) -> Result<(), &'static str> {
+        self.client
+            .invoke_inconsistent(GSetMessage::Add { element })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn elements(&self) -> Result<BTreeSet<E>, &'static str> {
+        match self
+            .client
+            .invoke_consistent(GSetMessage::Read { elements: None }, DataTypeDecide)
+            .await?
+        {
+            GSetMessage::Read {
+                elements: Some(elements),
+            } => Ok(elements),
+            _ => Err("Unexpected response to read"),
+        }
+    }
+}
+
+/// The tags of the adds of each element of an observed-remove set
+pub type OrSetTags<E, I> = BTreeMap<E, BTreeSet<OperationId<I>>>;
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum OrSetMessage<E: DataValue, I: NodeID> {
+    /// Inconsistent
+    Add { element: E, tag: OperationId<I> },
+    /// Inconsistent; removes only the adds with these tags
+    Remove {
+        element: E,
+        tags: BTreeSet<OperationId<I>>,
+    },
+    /// Consensus; replicas fill in their elements and tags
+    Read { elements: Option<OrSetTags<E, I>> },
+}
+
+impl<E: DataValue, I: NodeID> ResolveChoices for OrSetMessage<E, I> {
+    /// The set with the most tags any replica reports, which has applied the most adds
+    fn resolve(choices: &[&Self], _f: usize) -> Self {
+        super::max_by(choices, |choice| match choice {
+            OrSetMessage::Read {
+                elements: Some(elements),
+            } => elements.values().map(BTreeSet::len).sum(),
+            _ => 0,
+        })
+    }
+}
+
+struct OrSet<E: DataValue, I: NodeID> {
+    tags: OrSetTags<E, I>,
+    /// Tags that were removed, so that an add arriving after its remove stays removed
+    removed: BTreeSet<OperationId<I>>,
+}
+
+impl<E: DataValue, I: NodeID> OrSet<E, I> {
+    fn apply(&mut self, message: OrSetMessage<E, I>) {
+        match message {
+            OrSetMessage::Add { element, tag } => {
+                if !self.removed.contains(&tag) {
+                    self.tags.entry(element).or_default().insert(tag);
+                }
+            }
+            OrSetMessage::Remove { element, tags } => {
+                if let Some(present) = self.tags.get_mut(&element) {
+                    present.retain(|tag| !tags.contains(tag));
+                    if present.is_empty() {
+                        self.tags.remove(&element);
+                    }
+                }
+                self.removed.extend(tags);
+            }
+            OrSetMessage::Read { .. } => {}
+        }
+    }
+}
+
+/// The replica side of an observed-remove set
+#[derive(Clone)]
+pub struct OrSetReplica<E: DataValue, I: NodeID> {
+    set: Arc<Mutex<OrSet<E, I>>>,
+}
+
+impl<E: DataValue, I: NodeID> Default for OrSetReplica<E, I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: DataValue, I: NodeID> OrSetReplica<E, I> {
+    pub fn new() -> Self {
+        OrSetReplica {
+            set: Arc::new(Mutex::new(OrSet {
+                tags: BTreeMap::new(),
+                removed: BTreeSet::new(),
+            })),
+        }
+    }
+
+    pub fn elements(&self) -> BTreeSet<E> {
+        self.set.lock().unwrap().tags.keys().cloned().collect()
+    }
+}
+
+impl<E: DataValue, I: NodeID> IRApplication<I, OrSetMessage<E, I>> for OrSetReplica<E, I> {
+    fn exec_inconsistent(
+        &self,
+        message: OrSetMessage<E, I>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.set.lock().unwrap().apply(message);
+        Box::pin(async {})
+    }
+
+    fn exec_consensus(
+        &self,
+        message: OrSetMessage<E, I>,
+    ) -> Pin<Box<dyn Future<Output = OrSetMessage<E, I>> + Send + 'static>> {
+        let result = match message {
+            OrSetMessage::Read { .. } => OrSetMessage::Read {
+                elements: Some(self.set.lock().unwrap().tags.clone()),
+            },
+            update => update,
+        };
+        Box::pin(async move { result })
+    }
+
+    fn decide(&self, choices: Vec<OrSetMessage<E, I>>, f: usize) -> OrSetMessage<E, I> {
+        decide(choices, f)
+    }
+
+    /// Apply the adds and removes this replica missed
+    fn sync(
+        &self,
+        record: Vec<IROperation<I, OrSetMessage<E, I>>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut set = self.set.lock().unwrap();
+        for operation in record {
+            if let IROperation::InconsistentFinalize { message, .. } = operation {
+                set.apply(message);
+            }
+        }
+        Box::pin(async {})
+    }
+}
+
+/// A typed client of an observed-remove set
+pub struct OrSetClient<N, S, I, E>
+where
+    N: IRNetwork<I, OrSetMessage<E, I>>,
+    S: IRClientStorage<I, OrSetMessage<E, I>>,
+    I: NodeID,
+    E: DataValue,
+{
+    client: InconsistentReplicationClient<N, S, I, OrSetMessage<E, I>>,
+    adds: AtomicU64,
+}
+
+impl<N, S, I, E> OrSetClient<N, S, I, E>
+where
+    N: IRNetwork<I, OrSetMessage<E, I>> + 'static,
+    S: IRClientStorage<I, OrSetMessage<E, I>> + 'static,
+    I: NodeID,
+    E: DataValue,
+{
+    pub fn new(client: InconsistentReplicationClient<N, S, I, OrSetMessage<E, I>>) -> Self {
+        OrSetClient {
+            client,
+            adds: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn insert(&self, element: E) -> Result<(), &'static str> {
+        let tag = OperationId {
+            client: self.client.client_id().clone(),
+            sequence: self.adds.fetch_add(1, Ordering::SeqCst),
+        };
+        self.client
+            .invoke_inconsistent(OrSetMessage::Add { element, tag })
+            .await?;
+        Ok(())
+    }
+
+    /// Remove the adds of an element that a read observes
+    /// Adds the read does not observe, such as concurrent ones, keep the element present.
+    pub async fn remove(&self, element: E) -> Result<(), &'static str> {
+        let tags = match self.read().await?.remove(&element) {
+            Some(tags) => tags,
+            None => return Ok(()),
+        };
+        self.client
+            .invoke_inconsistent(OrSetMessage::Remove { element, tags })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn elements(&self) -> Result<BTreeSet<E>, &'static str> {
+        Ok(self.read().await?.into_keys().collect())
+    }
+
+    async fn read(&self) -> Result<OrSetTags<E, I>, &'static str> {
+        match self
+            .client
+            .invoke_consistent(OrSetMessage::Read { elements: None }, DataTypeDecide)
+            .await?
+        {
+            OrSetMessage::Read {
+                elements: Some(elements),
+            } => Ok(elements),
+            _ => Err("Unexpected response to read"),
+        }
+    }
+}
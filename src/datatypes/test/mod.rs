@@ -0,0 +1,270 @@
+use crate::datatypes::cas::{CasMessage, CasRegisterClient, CasRegisterReplica, Versioned};
+use crate::datatypes::counter::{CounterClient, CounterMessage, CounterReplica};
+use crate::datatypes::register::{LwwRegisterClient, LwwRegisterReplica, RegisterMessage};
+use crate::datatypes::set::{
+    GSetClient, GSetMessage, GSetReplica, OrSetClient, OrSetMessage, OrSetReplica,
+};
+use crate::datatypes::OperationId;
+use crate::hlc::{HybridLogicalClock, ManualTimeSource};
+use crate::io::test_utils::{fake_cluster, FakeIRNetwork, FakeIRStorage};
+use crate::server::IROperation;
+use crate::types::IRMessage;
+use crate::{IRApplication, InconsistentReplicationClient};
+use std::collections::BTreeSet;
+
+type Network<M, A> = FakeIRNetwork<u64, M, FakeIRStorage<u64, M>, A>;
+
+const MEMBERS: [u64; 3] = [1, 2, 3];
+
+async fn client<M, A>(
+    network: &Network<M, A>,
+    client_id: u64,
+) -> InconsistentReplicationClient<Network<M, A>, FakeIRStorage<u64, M>, u64, M>
+where
+    M: IRMessage,
+    A: IRApplication<u64, M>,
+{
+    InconsistentReplicationClient::new(
+        network.clone(),
+        FakeIRStorage::new(MEMBERS.to_vec()),
+        client_id,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn counter_sums_the_updates_of_every_client() {
+    // given a counter and two clients
+    let network = Network::<CounterMessage<u64>, CounterReplica<u64>>::new();
+    let replicas = fake_cluster(&network, &MEMBERS, CounterReplica::new).await;
+    let first = CounterClient::new(client(&network, 100).await);
+    let second = CounterClient::new(client(&network, 200).await);
+
+    // when both update it
+    first.add(5).await.unwrap();
+    second.add(3).await.unwrap();
+    first.add(-2).await.unwrap();
+
+    // then every replica and a read see the sum
+    assert_eq!(second.value().await, Ok(6));
+    for replica in &replicas {
+        assert_eq!(replica.value(), 6);
+    }
+}
+
+#[tokio::test]
+async fn counter_sync_applies_each_update_once() {
+    let add = |sequence, amount| IROperation::InconsistentFinalize {
+        client: 100,
+        sequence,
+        message: CounterMessage::Add {
+            id: OperationId {
+                client: 100,
+                sequence,
+            },
+            amount,
+        },
+    };
+
+    // given a replica that applied one of two updates
+    let replica = CounterReplica::<u64>::new();
+    IRApplication::<u64, CounterMessage<u64>>::exec_inconsistent(
+        &replica,
+        CounterMessage::Add {
+            id: OperationId {
+                client: 100,
+                sequence: 0,
+            },
+            amount: 4,
+        },
+    )
+    .await;
+
+    // when it syncs with a master record holding both
+    replica.sync(vec![add(0, 4), add(1, 10)]).await;
+
+    // then it applied each once
+    assert_eq!(replica.value(), 14);
+}
+
+#[tokio::test]
+async fn register_keeps_the_write_with_the_latest_timestamp() {
+    // given a register and two writers whose clocks have drifted
+    let network = Network::<RegisterMessage<String, u64>, LwwRegisterReplica<String, u64>>::new();
+    let replicas = fake_cluster(&network, &MEMBERS, LwwRegisterReplica::new).await;
+    let ahead = LwwRegisterClient::new(
+        client(&network, 100).await,
+        HybridLogicalClock::new(ManualTimeSource::new(1_000)),
+    );
+    let behind = LwwRegisterClient::new(
+        client(&network, 200).await,
+        HybridLogicalClock::new(ManualTimeSource::new(10)),
+    );
+
+    // when the writer whose clock is behind writes after the other
+    ahead.write("first".to_string()).await.unwrap();
+    behind.write("second".to_string()).await.unwrap();
+
+    // then the earlier timestamp loses, until the writer reads and its clock catches up
+    assert_eq!(behind.read().await, Ok(Some("first".to_string())));
+    behind.write("third".to_string()).await.unwrap();
+    assert_eq!(ahead.read().await, Ok(Some("third".to_string())));
+    for replica in &replicas {
+        assert_eq!(replica.value(), Some("third".to_string()));
+    }
+}
+
+#[tokio::test]
+async fn grow_only_set_holds_every_added_element() {
+    // given a grow-only set
+    let network = Network::<GSetMessage<u32>, GSetReplica<u32>>::new();
+    fake_cluster(&network, &MEMBERS, GSetReplica::new).await;
+    let set = GSetClient::new(client(&network, 100).await);
+
+    // when elements are added, some twice
+    for element in [3, 1, 3, 2] {
+        set.insert(element).await.unwrap();
+    }
+
+    // then it holds each of them
+    assert_eq!(set.elements().await, Ok(BTreeSet::from([1, 2, 3])));
+}
+
+#[tokio::test]
+async fn observed_remove_set_removes_only_observed_adds() {
+    // given an observed-remove set with an element added by two clients
+    let network = Network::<OrSetMessage<u32, u64>, OrSetReplica<u32, u64>>::new();
+    let replicas = fake_cluster(&network, &MEMBERS, OrSetReplica::new).await;
+    let first = OrSetClient::new(client(&network, 100).await);
+    let second = OrSetClient::new(client(&network, 200).await);
+    first.insert(1).await.unwrap();
+    second.insert(1).await.unwrap();
+    first.insert(2).await.unwrap();
+
+    // when one client removes it
+    first.remove(1).await.unwrap();
+
+    // then both adds are gone, and a later add makes it present again
+    assert_eq!(second.elements().await, Ok(BTreeSet::from([2])));
+    second.insert(1).await.unwrap();
+    assert_eq!(first.elements().await, Ok(BTreeSet::from([1, 2])));
+
+    // and a remove arriving before the add it observed keeps the element removed
+    let replica = &replicas[0];
+    let tag = OperationId {
+        client: 300,
+        sequence: 0,
+    };
+    IRApplication::<u64, OrSetMessage<u32, u64>>::exec_inconsistent(
+        replica,
+        OrSetMessage::Remove {
+            element: 7,
+            tags: BTreeSet::from([tag.clone()]),
+        },
+    )
+    .await;
+    IRApplication::<u64, OrSetMessage<u32, u64>>::exec_inconsistent(
+        replica,
+        OrSetMessage::Add { element: 7, tag },
+    )
+    .await;
+    assert_eq!(replica.elements(), BTreeSet::from([1, 2]));
+}
+
+#[tokio::test]
+async fn compare_and_set_only_succeeds_at_the_version_read() {
+    // given a compare-and-set register and two clients that read it
+    let network = Network::<CasMessage<String, u64>, CasRegisterReplica<String, u64>>::new();
+    let replicas = fake_cluster(&network, &MEMBERS, CasRegisterReplica::new).await;
+    let first = CasRegisterClient::new(client(&network, 100).await);
+    let second = CasRegisterClient::new(client(&network, 200).await);
+    let read = first.read().await.unwrap();
+    assert_eq!(second.read().await.unwrap(), read);
+
+    // when both set it at the version they read
+    let won = first
+        .compare_and_set(read.version, "first".to_string())
+        .await;
+    let lost = second
+        .compare_and_set(read.version, "second".to_string())
+        .await;
+
+    // then only the first write succeeds
+    assert_eq!(won, Ok(true));
+    assert_eq!(lost, Ok(false));
+    let expected = Versioned {
+        version: 1,
+        value: Some("first".to_string()),
+    };
+    assert_eq!(second.read().await, Ok(expected.clone()));
+    for replica in &replicas {
+        assert_eq!(replica.current(), expected);
+    }
+}
+
+#[tokio::test]
+async fn compare_and_set_needs_f_plus_one_of_the_members_to_accept() {
+    // given 5 members, and two clients that each reach 3 of them, sharing only node 3
+    let network = Network::<CasMessage<String, u64>, CasRegisterReplica<String, u64>>::new();
+    let members = [1, 2, 3, 4, 5];
+    let replicas = fake_cluster(&network, &members, CasRegisterReplica::new).await;
+    let partitioned_client = |reachable: &[u64], client_id| {
+        InconsistentReplicationClient::new(
+            network.partition(reachable),
+            FakeIRStorage::new(members.to_vec()),
+            client_id,
+        )
+    };
+    let first = CasRegisterClient::new(partitioned_client(&[1, 2, 3], 100).await);
+    let second = CasRegisterClient::new(partitioned_client(&[3, 4, 5], 200).await);
+
+    // when both set it at version 0, the second first
+    let won = second.compare_and_set(0, "second".to_string()).await;
+    let lost = first.compare_and_set(0, "first".to_string()).await;
+
+    // then the first's two accepts are not f+1 of the members, so only the second is written
+    assert_eq!(won, Ok(true));
+    assert_eq!(lost, Ok(false));
+    let versions: Vec<_> = replicas
+        .iter()
+        .map(|replica| replica.current().version)
+        .collect();
+    assert_eq!(versions, vec![0, 0, 1, 1, 1]);
+    assert_eq!(replicas[2].current().value, Some("second".to_string()));
+}
+
+#[tokio::test]
+async fn compare_and_set_sync_installs_accepted_proposals() {
+    // given a replica that promised a proposal whose client failed before committing
+    let replica = CasRegisterReplica::<String, u64>::new();
+    let id = OperationId {
+        client: 100,
+        sequence: 0,
+    };
+    let proposal = CasMessage::Propose {
+        id,
+        version: 0,
+        value: "pending".to_string(),
+        accepted: None,
+    };
+    let accepted =
+        IRApplication::<u64, CasMessage<String, u64>>::exec_consensus(&replica, proposal).await;
+
+    // when it syncs with a master record where the proposal was accepted
+    replica
+        .sync(vec![IROperation::ConsistentFinalize {
+            client: 100,
+            sequence: 0,
+            message: accepted,
+        }])
+        .await;
+
+    // then the value is installed, and the next version is free to propose
+    assert_eq!(
+        replica.current(),
+        Versioned {
+            version: 1,
+            value: Some("pending".to_string()),
+        }
+    );
+}
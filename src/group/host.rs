@@ -0,0 +1,363 @@
+use crate::application::IRApplication;
+use crate::group::{GroupId, GroupNetwork, GroupStorage, Grouped};
+use crate::io::IRNetwork;
+use crate::server::{
+    IROperation, IRServerError, InconsistentReplicationServer, ServerBatchResult, ServerResult,
+    ServerResults, View,
+};
+use crate::types::{IRMessage, NodeID, OperationSequence};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+type GroupServer<N, S, A, G, I, M> = InconsistentReplicationServer<
+    GroupNetwork<N, G, I, M>,
+    <S as GroupStorage<G, I, M>>::Storage,
+    A,
+    I,
+    M,
+>;
+
+type Groups<N, S, A, G, I, M> = BTreeMap<G, GroupServer<N, S, A, G, I, M>>;
+
+/// This node's replicas of many IR groups, sharing one transport and one storage engine
+/// Each group has its own `InconsistentReplicationServer`, and so its own view state. Messages
+/// received on the transport are routed to the group named in their `Grouped` envelope.
+pub struct MultiGroupHost<N, S, A, G, I, M>
+where
+    N: IRNetwork<I, Grouped<G, M>> + 'static,
+    S: GroupStorage<G, I, M>,
+    A: IRApplication<I, M>,
+    G: GroupId,
+    I: NodeID,
+    M: IRMessage,
+{
+    network: Arc<N>,
+    storage: S,
+    node_id: I,
+    groups: RwLock<Groups<N, S, A, G, I, M>>,
+}
+
+impl<N, S, A, G, I, M> MultiGroupHost<N, S, A, G, I, M>
+where
+    N: IRNetwork<I, Grouped<G, M>> + 'static,
+    S: GroupStorage<G, I, M>,
+    A: IRApplication<I, M>,
+    G: GroupId,
+    I: NodeID + 'static,
+    M: IRMessage + 'static,
+{
+    pub fn new(network: N, storage: S, node_id: I) -> Self {
+        MultiGroupHost {
+            network: Arc::new(network),
+            storage,
+            node_id,
+            groups: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Start this node's replica of a group, replacing any existing one
+    /// The replica recovers its view from the group's storage and starts in recovery, as a
+    /// standalone server would.
+    pub async fn add_group(&self, group: G, application: A) -> GroupServer<N, S, A, G, I, M> {
+        let server = InconsistentReplicationServer::new(
+            GroupNetwork::new(self.network.clone(), group.clone()),
+            self.storage.group(&group),
+            application,
+            self.node_id.clone(),
+        )
+        .await;
+        self.groups.write().unwrap().insert(group, server.clone());
+        server
+    }
+
+    /// Stop hosting a group; its records remain in the storage engine
+    pub fn remove_group(&self, group: &G) -> Option<GroupServer<N, S, A, G, I, M>> {
+        self.groups.write().unwrap().remove(group)
+    }
+
+    /// The replica of a group, for calls that are not routed by the host such as view changes
+    pub fn group(&self, group: &G) -> Option<GroupServer<N, S, A, G, I, M>> {
+        self.groups.read().unwrap().get(group).cloned()
+    }
+
+    pub fn groups(&self) -> Vec<G> {
+        self.groups.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Pass a message to its group's replica, wrapping the result in the same group
+    fn route<F>(&self, message: Grouped<G, M>, call: F) -> ServerResult<I, Grouped<G, M>>
+    where
+        F: FnOnce(&GroupServer<N, S, A, G, I, M>, M) -> ServerResult<I, M>,
+    {
+        let Grouped { group, message } = message;
+        let Some(server) = self.group(&group) else {
+            return Box::pin(async move {
+                Err(IRServerError::InternalError(
+                    format!("group {:?} is not hosted", group).into(),
+                ))
+            });
+        };
+        let result = call(&server, message);
+        Box::pin(async move {
+            result
+                .await
+                .map(|(message, view)| (Grouped { group, message }, view))
+        })
+    }
+
+    /// Pass a batch to its group's replica, wrapping the results in the same group
+    /// Every message of a batch must belong to the same group.
+    fn route_batch<F>(
+        &self,
+        messages: Vec<Grouped<G, M>>,
+        call: F,
+    ) -> ServerBatchResult<I, Grouped<G, M>>
+    where
+        F: FnOnce(&GroupServer<N, S, A, G, I, M>, Vec<M>) -> ServerBatchResult<I, M>,
+    {
+        let Some(group) = messages.first().map(|message| message.group.clone()) else {
+            return Box::pin(async { Ok(Vec::new()) });
+        };
+        if messages.iter().any(|message| message.group != group) {
+            return Box::pin(async {
+                Err(IRServerError::InternalError(
+                    "a batch must belong to one group".into(),
+                ))
+            });
+        }
+        let Some(server) = self.group(&group) else {
+            return Box::pin(async move {
+                Err(IRServerError::InternalError(
+                    format!("group {:?} is not hosted", group).into(),
+                ))
+            });
+        };
+        let messages = messages
+            .into_iter()
+            .map(|message| message.message)
+            .collect();
+        let results = call(&server, messages);
+        Box::pin(async move {
+            let results = results.await?;
+            Ok(results
+                .into_iter()
+                .map(|(message, view)| {
+                    let group = group.clone();
+                    (Grouped { group, message }, view)
+                })
+                .collect())
+        })
+    }
+
+    /// Invoked on propose message
+    pub fn propose_inconsistent(
+        &self,
+        client_id: I,
+        operation_sequence: OperationSequence,
+        message: Grouped<G, M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> ServerResult<I, Grouped<G, M>> {
+        self.route(message, |server, message| {
+            server.propose_inconsistent(
+                client_id,
+                operation_sequence,
+                message,
+                highest_observed_view,
+            )
+        })
+    }
+
+    /// Invoked on finalize message
+    pub fn finalize_inconsistent(
+        &self,
+        client_id: I,
+        operation_sequence: OperationSequence,
+        message: Grouped<G, M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> ServerResult<I, Grouped<G, M>> {
+        self.route(message, |server, message| {
+            server.finalize_inconsistent(
+                client_id,
+                operation_sequence,
+                message,
+                highest_observed_view,
+            )
+        })
+    }
+
+    /// Proposes a consistent operation
+    pub fn propose_consistent(
+        &self,
+        client_id: I,
+        operation_sequence: OperationSequence,
+        message: Grouped<G, M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> ServerResult<I, Grouped<G, M>> {
+        self.route(message, |server, message| {
+            server.propose_consistent(
+                client_id,
+                operation_sequence,
+                message,
+                highest_observed_view,
+            )
+        })
+    }
+
+    /// Finalize and execute a consistent operation
+    pub fn finalize_consistent(
+        &self,
+        client_id: I,
+        operation_sequence: OperationSequence,
+        message: Grouped<G, M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> ServerResult<I, Grouped<G, M>> {
+        self.route(message, |server, message| {
+            server.finalize_consistent(
+                client_id,
+                operation_sequence,
+                message,
+                highest_observed_view,
+            )
+        })
+    }
+
+    /// Invoked on a batch of inconsistent proposals with consecutive sequence numbers
+    pub fn propose_inconsistent_batch(
+        &self,
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<Grouped<G, M>>,
+        highest_observed_view: Option<View<I>>,
+    ) -> ServerBatchResult<I, Grouped<G, M>> {
+        self.route_batch(messages, |server, messages| {
+            server.propose_inconsistent_batch(
+                client_id,
+                first_sequence,
+                messages,
+                highest_observed_view,
+            )
+        })
+    }
+
+    /// Invoked on a batch of consistent proposals with consecutive sequence numbers
+    pub fn propose_consistent_batch(
+        &self,
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<Grouped<G, M>>,
+        highest_observed_view: Option<View<I>>,
+    ) -> ServerBatchResult<I, Grouped<G, M>> {
+        self.route_batch(messages, |server, messages| {
+            server.propose_consistent_batch(
+                client_id,
+                first_sequence,
+                messages,
+                highest_observed_view,
+            )
+        })
+    }
+
+    /// Invoked on a batch of finalize messages, which may belong to different groups
+    /// Consecutive finalizes of one group are passed to its replica together, and each run is
+    /// applied in order, so every finalize keeps its place and has its own result
+    pub fn finalize_batch(
+        &self,
+        operations: Vec<IROperation<I, Grouped<G, M>>>,
+        highest_observed_view: Option<View<I>>,
+    ) -> ServerResults<I, Grouped<G, M>> {
+        let mut runs: Vec<(G, Vec<IROperation<I, M>>)> = Vec::new();
+        for operation in operations {
+            let (group, operation) = ungroup(operation);
+            match runs.last_mut() {
+                Some((last, run)) if *last == group => run.push(operation),
+                _ => runs.push((group, vec![operation])),
+            }
+        }
+        let runs: Vec<_> = runs
+            .into_iter()
+            .map(|(group, run)| {
+                let count = run.len();
+                let results = self
+                    .group(&group)
+                    .map(|server| server.finalize_batch(run, highest_observed_view.clone()));
+                (group, count, results)
+            })
+            .collect();
+        Box::pin(async move {
+            let mut results = Vec::new();
+            for (group, count, run) in runs {
+                let Some(run) = run else {
+                    results.extend((0..count).map(|_| {
+                        Err(IRServerError::InternalError(
+                            format!("group {:?} is not hosted", group).into(),
+                        ))
+                    }));
+                    continue;
+                };
+                results.extend(run.await.into_iter().map(|result| {
+                    result.map(|(message, view)| {
+                        let group = group.clone();
+                        (Grouped { group, message }, view)
+                    })
+                }));
+            }
+            results
+        })
+    }
+}
+
+/// Split an operation into its group and the operation on the group's messages
+fn ungroup<I: NodeID, G: GroupId, M: IRMessage>(
+    operation: IROperation<I, Grouped<G, M>>,
+) -> (G, IROperation<I, M>) {
+    match operation {
+        IROperation::InconsistentPropose {
+            client,
+            sequence,
+            message: Grouped { group, message },
+        } => (
+            group,
+            IROperation::InconsistentPropose {
+                client,
+                sequence,
+                message,
+            },
+        ),
+        IROperation::InconsistentFinalize {
+            client,
+            sequence,
+            message: Grouped { group, message },
+        } => (
+            group,
+            IROperation::InconsistentFinalize {
+                client,
+                sequence,
+                message,
+            },
+        ),
+        IROperation::ConsistentPropose {
+            client,
+            sequence,
+            message: Grouped { group, message },
+        } => (
+            group,
+            IROperation::ConsistentPropose {
+                client,
+                sequence,
+                message,
+            },
+        ),
+        IROperation::ConsistentFinalize {
+            client,
+            sequence,
+            message: Grouped { group, message },
+        } => (
+            group,
+            IROperation::ConsistentFinalize {
+                client,
+                sequence,
+                message,
+            },
+        ),
+    }
+}
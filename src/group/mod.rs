@@ -0,0 +1,53 @@
+//! Hosting many IR groups in one process, over one transport and one storage engine.
+//!
+//! Each group is an independent IR cluster with its own views, records and application, such
+//! as one shard of a `ShardedClient`. A `MultiGroupHost` runs this node's replica of every
+//! group it hosts:
+//!
+//! ```ignore
+//! let host = MultiGroupHost::new(transport, storage_engine, node_id);
+//! host.add_group(shard, application).await;
+//! // the transport hands received messages to the host, which routes them by group
+//! host.propose_inconsistent(client, sequence, grouped_message, None).await;
+//! ```
+//!
+//! Every message on the shared transport is a `Grouped` envelope carrying the group id, and
+//! clients of one group wrap the transport in a `GroupNetwork`. The storage engine implements
+//! `GroupStorage`, keeping each group's records and views apart by including the group id in
+//! every key, as `LogStorage` does in the records of its log.
+
+mod host;
+mod network;
+#[cfg(test)]
+mod test;
+
+pub use host::MultiGroupHost;
+pub use network::GroupNetwork;
+
+use crate::io::IRStorage;
+use crate::types::{IRMessage, NodeID};
+use std::fmt::Debug;
+
+/// Identifies a group among those sharing a transport and storage engine
+pub trait GroupId: Clone + Ord + Debug + Send + Sync + 'static {}
+
+impl<A> GroupId for A where A: Clone + Ord + Debug + Send + Sync + 'static {}
+
+/// A message of one group, as sent over a transport shared by several groups
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Grouped<G: GroupId, M: IRMessage> {
+    pub group: G,
+    pub message: M,
+}
+
+/// A storage engine shared by the groups of a host
+/// Each group gets its own `IRStorage`. The engine must include the group id in the key of
+/// everything it stores, as operations of different groups can have the same client and
+/// sequence number, and each group has its own views.
+pub trait GroupStorage<G: GroupId, I: NodeID, M: IRMessage>: Send + Sync + 'static {
+    type Storage: IRStorage<I, M>;
+
+    /// The storage of one group
+    /// Handles for the same group must share their state.
+    fn group(&self, group: &G) -> Self::Storage;
+}
@@ -0,0 +1,361 @@
+use crate::group::{GroupId, Grouped};
+use crate::io::{CarrierRequest, IRNetwork, IRNetworkError};
+use crate::server::{IROperation, View};
+use crate::types::{IRMessage, NodeID, OperationSequence};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The network of one group, over a transport shared with other groups
+/// Outgoing messages are wrapped in a `Grouped` envelope with the group id, and responses are
+/// unwrapped. Responses from another group are treated as the node being unreachable.
+pub struct GroupNetwork<N, G, I, M>
+where
+    N: IRNetwork<I, Grouped<G, M>>,
+    G: GroupId,
+    I: NodeID,
+    M: IRMessage,
+{
+    inner: Arc<N>,
+    group: G,
+    _a: PhantomData<(I, M)>,
+}
+
+impl<N, G, I, M> Clone for GroupNetwork<N, G, I, M>
+where
+    N: IRNetwork<I, Grouped<G, M>>,
+    G: GroupId,
+    I: NodeID,
+    M: IRMessage,
+{
+    fn clone(&self) -> Self {
+        GroupNetwork {
+            inner: self.inner.clone(),
+            group: self.group.clone(),
+            _a: PhantomData,
+        }
+    }
+}
+
+impl<N, G, I, M> GroupNetwork<N, G, I, M>
+where
+    N: IRNetwork<I, Grouped<G, M>>,
+    G: GroupId,
+    I: NodeID,
+    M: IRMessage,
+{
+    pub fn new(inner: Arc<N>, group: G) -> Self {
+        GroupNetwork {
+            inner,
+            group,
+            _a: PhantomData,
+        }
+    }
+
+    pub fn group(&self) -> &G {
+        &self.group
+    }
+
+    fn wrap(&self, message: M) -> Grouped<G, M> {
+        Grouped {
+            group: self.group.clone(),
+            message,
+        }
+    }
+}
+
+/// Unwrap the message of a response, which must belong to the group
+fn unwrap_response<G: GroupId, I: NodeID, M: IRMessage>(
+    group: &G,
+    node: &I,
+    response: (Grouped<G, M>, View<I>),
+) -> Result<(M, View<I>), IRNetworkError<I>> {
+    let (grouped, view) = response;
+    if grouped.group == *group {
+        Ok((grouped.message, view))
+    } else {
+        Err(IRNetworkError::NodeUnreachable(node.clone()))
+    }
+}
+
+type Responses<I, M> = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>;
+type BatchResponses<I, M> = Vec<(I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>)>;
+
+fn unwrap_responses<G: GroupId, I: NodeID, M: IRMessage>(
+    group: G,
+    responses: Responses<I, Grouped<G, M>>,
+) -> Responses<I, M> {
+    responses
+        .into_iter()
+        .map(|(node, response)| {
+            let response = response.and_then(|response| unwrap_response(&group, &node, response));
+            (node, response)
+        })
+        .collect()
+}
+
+fn unwrap_batch_responses<G: GroupId, I: NodeID, M: IRMessage>(
+    group: G,
+    responses: BatchResponses<I, Grouped<G, M>>,
+) -> BatchResponses<I, M> {
+    responses
+        .into_iter()
+        .map(|(node, response)| {
+            let response = response.and_then(|responses| {
+                responses
+                    .into_iter()
+                    .map(|response| unwrap_response(&group, &node, response))
+                    .collect()
+            });
+            (node, response)
+        })
+        .collect()
+}
+
+fn wrap_operation<G: GroupId, I: NodeID, M: IRMessage>(
+    group: &G,
+    operation: IROperation<I, M>,
+) -> IROperation<I, Grouped<G, M>> {
+    let wrap = |message| Grouped {
+        group: group.clone(),
+        message,
+    };
+    match operation {
+        IROperation::InconsistentPropose {
+            client,
+            sequence,
+            message,
+        } => IROperation::InconsistentPropose {
+            client,
+            sequence,
+            message: wrap(message),
+        },
+        IROperation::InconsistentFinalize {
+            client,
+            sequence,
+            message,
+        } => IROperation::InconsistentFinalize {
+            client,
+            sequence,
+            message: wrap(message),
+        },
+        IROperation::ConsistentPropose {
+            client,
+            sequence,
+            message,
+        } => IROperation::ConsistentPropose {
+            client,
+            sequence,
+            message: wrap(message),
+        },
+        IROperation::ConsistentFinalize {
+            client,
+            sequence,
+            message,
+        } => IROperation::ConsistentFinalize {
+            client,
+            sequence,
+            message: wrap(message),
+        },
+    }
+}
+
+fn wrap_request<G: GroupId, I: NodeID, M: IRMessage>(
+    group: &G,
+    request: CarrierRequest<I, M>,
+) -> CarrierRequest<I, Grouped<G, M>> {
+    let wrap = |message| Grouped {
+        group: group.clone(),
+        message,
+    };
+    match request {
+        CarrierRequest::ProposeInconsistent {
+            client,
+            first_sequence,
+            messages,
+            highest_observed_view,
+        } => CarrierRequest::ProposeInconsistent {
+            client,
+            first_sequence,
+            messages: messages.into_iter().map(wrap).collect(),
+            highest_observed_view,
+        },
+        CarrierRequest::ProposeConsistent {
+            client,
+            first_sequence,
+            messages,
+        } => CarrierRequest::ProposeConsistent {
+            client,
+            first_sequence,
+            messages: messages.into_iter().map(wrap).collect(),
+        },
+        CarrierRequest::FinalizeConsistent {
+            client,
+            sequence,
+            message,
+        } => CarrierRequest::FinalizeConsistent {
+            client,
+            sequence,
+            message: wrap(message),
+        },
+    }
+}
+
+impl<N, G, I, M> IRNetwork<I, M> for GroupNetwork<N, G, I, M>
+where
+    N: IRNetwork<I, Grouped<G, M>> + 'static,
+    G: GroupId,
+    I: NodeID,
+    M: IRMessage,
+{
+    fn propose_inconsistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+        highest_observed_view: Option<View<I>>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, M>> + Send + 'static>> {
+        let responses = self.inner.propose_inconsistent(
+            destinations,
+            client_id,
+            sequence,
+            self.wrap(message),
+            highest_observed_view,
+        );
+        let group = self.group.clone();
+        Box::pin(async move { unwrap_responses(group, responses.await) })
+    }
+
+    fn propose_consistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, M>> + Send + 'static>> {
+        let responses =
+            self.inner
+                .propose_consistent(destinations, client_id, sequence, self.wrap(message));
+        let group = self.group.clone();
+        Box::pin(async move { unwrap_responses(group, responses.await) })
+    }
+
+    fn async_finalize_inconsistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.inner.async_finalize_inconsistent(
+            destinations,
+            client_id,
+            sequence,
+            self.wrap(message),
+        )
+    }
+
+    fn async_finalize_consistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.inner
+            .async_finalize_consistent(destinations, client_id, sequence, self.wrap(message))
+    }
+
+    fn sync_finalize_consistent(
+        &self,
+        destination: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, M>> + Send + 'static>> {
+        let responses = self.inner.sync_finalize_consistent(
+            destination,
+            client_id,
+            sequence,
+            self.wrap(message),
+        );
+        let group = self.group.clone();
+        Box::pin(async move { unwrap_responses(group, responses.await) })
+    }
+
+    fn propose_inconsistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> Pin<Box<dyn Future<Output = BatchResponses<I, M>> + Send + 'static>> {
+        let responses = self.inner.propose_inconsistent_batch(
+            destinations,
+            client_id,
+            first_sequence,
+            messages
+                .into_iter()
+                .map(|message| self.wrap(message))
+                .collect(),
+            highest_observed_view,
+        );
+        let group = self.group.clone();
+        Box::pin(async move { unwrap_batch_responses(group, responses.await) })
+    }
+
+    fn propose_consistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+    ) -> Pin<Box<dyn Future<Output = BatchResponses<I, M>> + Send + 'static>> {
+        let responses = self.inner.propose_consistent_batch(
+            destinations,
+            client_id,
+            first_sequence,
+            messages
+                .into_iter()
+                .map(|message| self.wrap(message))
+                .collect(),
+        );
+        let group = self.group.clone();
+        Box::pin(async move { unwrap_batch_responses(group, responses.await) })
+    }
+
+    fn finalize_batch(
+        &self,
+        destinations: &[I],
+        operations: Vec<IROperation<I, M>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.inner.finalize_batch(
+            destinations,
+            operations
+                .into_iter()
+                .map(|operation| wrap_operation(&self.group, operation))
+                .collect(),
+        )
+    }
+
+    fn send_with_finalizes(
+        &self,
+        destinations: &[I],
+        finalizes: Vec<IROperation<I, M>>,
+        request: CarrierRequest<I, M>,
+    ) -> Pin<Box<dyn Future<Output = BatchResponses<I, M>> + Send + 'static>> {
+        let responses = self.inner.send_with_finalizes(
+            destinations,
+            finalizes
+                .into_iter()
+                .map(|operation| wrap_operation(&self.group, operation))
+                .collect(),
+            wrap_request(&self.group, request),
+        );
+        let group = self.group.clone();
+        Box::pin(async move { unwrap_batch_responses(group, responses.await) })
+    }
+}
@@ -0,0 +1,286 @@
+use crate::group::{GroupNetwork, Grouped, MultiGroupHost};
+use crate::io::{IRNetwork, IRNetworkError};
+use crate::server::{IROperation, IRServerError, View, ViewState};
+use crate::test_utils::mock_computers::NoopComputer;
+use crate::test_utils::{FakeGroupStorage, FakeIRStorage};
+use crate::types::{DecideFunction, OperationSequence};
+use crate::InconsistentReplicationClient;
+use futures::{stream, StreamExt};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+type Message = Grouped<u64, u64>;
+type Host =
+    MultiGroupHost<HostNetwork, FakeGroupStorage<u64, u64, u64>, NoopComputer<u64>, u64, u64, u64>;
+type Responses = Vec<(u64, Result<(Message, View<u64>), IRNetworkError<u64>>)>;
+type HostResult =
+    Pin<Box<dyn Future<Output = Result<(Message, View<u64>), IRServerError<u64>>> + Send>>;
+
+/// A transport shared by every group, delivering each message to the destination's host
+#[derive(Clone, Default)]
+struct HostNetwork {
+    hosts: Arc<RwLock<BTreeMap<u64, Arc<Host>>>>,
+}
+
+impl HostNetwork {
+    fn send<F: Fn(&Host) -> HostResult>(
+        &self,
+        destinations: &[u64],
+        call: F,
+    ) -> Pin<Box<dyn Future<Output = Responses> + Send + 'static>> {
+        let hosts = self.hosts.read().unwrap();
+        let calls: Vec<_> = destinations
+            .iter()
+            .map(|node| (*node, hosts.get(node).map(|host| call(host))))
+            .collect();
+        Box::pin(async move {
+            let mut responses = Vec::with_capacity(calls.len());
+            for (node, call) in calls {
+                let response = match call {
+                    Some(call) => call.await.map_err(IRNetworkError::from),
+                    None => Err(IRNetworkError::NodeUnreachable(node)),
+                };
+                responses.push((node, response));
+            }
+            responses
+        })
+    }
+}
+
+impl IRNetwork<u64, Message> for HostNetwork {
+    fn propose_inconsistent(
+        &self,
+        destinations: &[u64],
+        client_id: u64,
+        sequence: OperationSequence,
+        message: Message,
+        highest_observed_view: Option<View<u64>>,
+    ) -> Pin<Box<dyn Future<Output = Responses> + Send + 'static>> {
+        self.send(destinations, |host| {
+            host.propose_inconsistent(
+                client_id,
+                sequence,
+                message.clone(),
+                highest_observed_view.clone(),
+            )
+        })
+    }
+
+    fn propose_consistent(
+        &self,
+        destinations: &[u64],
+        client_id: u64,
+        sequence: OperationSequence,
+        message: Message,
+    ) -> Pin<Box<dyn Future<Output = Responses> + Send + 'static>> {
+        self.send(destinations, |host| {
+            host.propose_consistent(client_id, sequence, message.clone(), None)
+        })
+    }
+
+    fn async_finalize_inconsistent(
+        &self,
+        destinations: &[u64],
+        client_id: u64,
+        sequence: OperationSequence,
+        message: Message,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let responses = self.send(destinations, |host| {
+            host.finalize_inconsistent(client_id, sequence, message.clone(), None)
+        });
+        Box::pin(async move {
+            responses.await;
+        })
+    }
+
+    fn async_finalize_consistent(
+        &self,
+        destinations: &[u64],
+        client_id: u64,
+        sequence: OperationSequence,
+        message: Message,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let responses = self.sync_finalize_consistent(destinations, client_id, sequence, message);
+        Box::pin(async move {
+            responses.await;
+        })
+    }
+
+    fn sync_finalize_consistent(
+        &self,
+        destinations: &[u64],
+        client_id: u64,
+        sequence: OperationSequence,
+        message: Message,
+    ) -> Pin<Box<dyn Future<Output = Responses> + Send + 'static>> {
+        self.send(destinations, |host| {
+            host.finalize_consistent(client_id, sequence, message.clone(), None)
+        })
+    }
+}
+
+struct FirstChoice;
+
+impl DecideFunction<u64> for FirstChoice {
+    fn decide<'a, S: IntoIterator<Item = &'a u64>>(&self, choices: S, _f: usize) -> u64 {
+        *choices.into_iter().next().unwrap()
+    }
+}
+
+/// Hosts for the members, each with a replica of the groups, of which `started` are normal
+async fn hosts(network: &HostNetwork, members: &[u64], groups: &[u64], started: &[u64]) {
+    for node_id in members {
+        let host = Host::new(
+            network.clone(),
+            FakeGroupStorage::new(members.to_vec()),
+            *node_id,
+        );
+        for group in groups {
+            let server = host.add_group(*group, NoopComputer::new()).await;
+            if started.contains(group) {
+                let view = View {
+                    view: 0,
+                    members: members.to_vec(),
+                    learners: vec![],
+                    state: ViewState::Normal,
+                };
+                server.start_view(view, stream::empty()).await.unwrap();
+            }
+        }
+        network
+            .hosts
+            .write()
+            .unwrap()
+            .insert(*node_id, Arc::new(host));
+    }
+}
+
+async fn group_client(
+    network: &HostNetwork,
+    members: &[u64],
+    group: u64,
+) -> InconsistentReplicationClient<
+    GroupNetwork<HostNetwork, u64, u64, u64>,
+    FakeIRStorage<u64, u64>,
+    u64,
+    u64,
+> {
+    InconsistentReplicationClient::new(
+        GroupNetwork::new(Arc::new(network.clone()), group),
+        FakeIRStorage::new(members.to_vec()),
+        0,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn groups_sharing_a_transport_keep_their_operations_apart() {
+    // given hosts with two groups each
+    let network = HostNetwork::default();
+    let members = [1, 2, 3];
+    hosts(&network, &members, &[10, 20], &[10, 20]).await;
+
+    // when the same client makes an operation with the same sequence number in each group
+    let first = group_client(&network, &members, 10).await;
+    let second = group_client(&network, &members, 20).await;
+    assert_eq!(first.invoke_inconsistent(5).await, Ok(5));
+    assert_eq!(second.invoke_inconsistent(6).await, Ok(6));
+
+    // then each group's replicas only finalized their own operation
+    for node_id in members {
+        let host = network.hosts.read().unwrap()[&node_id].clone();
+        let mut first = Box::pin(host.group(&10).unwrap().subscribe(None));
+        let mut second = Box::pin(host.group(&20).unwrap().subscribe(None));
+        assert_eq!(first.next().await.unwrap().unwrap().1.message(), &5);
+        assert_eq!(second.next().await.unwrap().unwrap().1.message(), &6);
+    }
+}
+
+#[tokio::test]
+async fn each_group_has_its_own_view_state() {
+    // given hosts where one group has started and the other is still recovering
+    let network = HostNetwork::default();
+    let members = [1, 2, 3];
+    hosts(&network, &members, &[10, 20], &[10]).await;
+
+    // when consistent operations are made in both
+    let started = group_client(&network, &members, 10).await;
+    let recovering = group_client(&network, &members, 20).await;
+
+    // then only the started group accepts them
+    assert_eq!(started.invoke_consistent(5, FirstChoice).await, Ok(5));
+    assert_eq!(
+        recovering.invoke_consistent(6, FirstChoice).await,
+        Err("Quorum not found")
+    );
+}
+
+#[tokio::test]
+async fn messages_for_groups_that_are_not_hosted_are_refused() {
+    let network = HostNetwork::default();
+    hosts(&network, &[1, 2, 3], &[10], &[10]).await;
+    let host = network.hosts.read().unwrap()[&1].clone();
+
+    let result = host
+        .propose_inconsistent(
+            0,
+            0,
+            Grouped {
+                group: 30,
+                message: 5,
+            },
+            None,
+        )
+        .await;
+
+    assert!(matches!(result, Err(IRServerError::InternalError(_))));
+}
+
+#[tokio::test]
+async fn batches_are_handled_by_their_groups_replicas() {
+    let grouped = |group, message| Grouped { group, message };
+
+    // given a host with two started groups
+    let network = HostNetwork::default();
+    hosts(&network, &[1, 2, 3], &[10, 20], &[10, 20]).await;
+    let host = network.hosts.read().unwrap()[&1].clone();
+
+    // when a proposal batch mixes groups, then it is refused
+    let mixed = host
+        .propose_inconsistent_batch(0, 0, vec![grouped(10, 5), grouped(20, 6)], None)
+        .await;
+    assert!(matches!(mixed, Err(IRServerError::InternalError(_))));
+
+    // when a proposal batch for one group is followed by finalizes for several
+    let proposed = host
+        .propose_inconsistent_batch(0, 0, vec![grouped(10, 5), grouped(10, 6)], None)
+        .await
+        .unwrap();
+    let finalize = |sequence, message| IROperation::InconsistentFinalize {
+        client: 0,
+        sequence,
+        message,
+    };
+    let results = host
+        .finalize_batch(
+            vec![
+                finalize(0, grouped(10, 5)),
+                finalize(0, grouped(30, 7)),
+                finalize(1, grouped(10, 6)),
+            ],
+            None,
+        )
+        .await;
+
+    // then each result keeps its group and its place, and unhosted groups are refused
+    let proposed: Vec<_> = proposed.into_iter().map(|(message, _)| message).collect();
+    assert_eq!(proposed, vec![grouped(10, 5), grouped(10, 6)]);
+    assert_eq!(results[0].as_ref().unwrap().0, grouped(10, 5));
+    assert!(matches!(results[1], Err(IRServerError::InternalError(_))));
+    assert_eq!(results[2].as_ref().unwrap().0, grouped(10, 6));
+    let mut finalized = Box::pin(host.group(&10).unwrap().subscribe(None));
+    assert_eq!(finalized.next().await.unwrap().unwrap().1.message(), &5);
+    assert_eq!(finalized.next().await.unwrap().unwrap().1.message(), &6);
+}
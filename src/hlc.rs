@@ -0,0 +1,205 @@
+//! Hybrid logical clocks, for timestamping operations with loosely synchronized time.
+//!
+//! A hybrid logical clock follows physical time, but never goes backwards and always moves
+//! past the timestamps it has seen, so timestamps respect causality even when physical clocks
+//! drift. Put an `HlcTimestamp` in messages, take a new one with `now` when sending, and pass
+//! the messages returned by `invoke_inconsistent` and `invoke_consistent` to `observe`:
+//!
+//! ```ignore
+//! let clock = HybridLogicalClock::new(SystemTimeSource);
+//! let result = client.invoke_consistent(Prepare { timestamp: clock.now(), .. }, decide).await?;
+//! clock.observe(&result);
+//! ```
+//!
+//! Physical time comes from a `TimeSource`. Simulations use a `ManualTimeSource`, so that
+//! runs over `FakeIRNetwork` are deterministic.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A point in hybrid logical time
+/// Ordered by physical time, then by the logical counter that orders events within it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct HlcTimestamp {
+    /// The highest physical time seen, in the units of the `TimeSource`
+    pub physical: u64,
+    pub logical: u64,
+}
+
+/// Provides the physical component of timestamps
+pub trait TimeSource: Send + Sync + 'static {
+    fn now(&self) -> u64;
+}
+
+/// Microseconds since the Unix epoch, from the system clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_micros() as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// A time source that only moves when told to, for deterministic tests and simulations
+/// Clones share the same time.
+#[derive(Clone, Debug, Default)]
+pub struct ManualTimeSource {
+    time: Arc<AtomicU64>,
+}
+
+impl ManualTimeSource {
+    pub fn new(time: u64) -> Self {
+        ManualTimeSource {
+            time: Arc::new(AtomicU64::new(time)),
+        }
+    }
+
+    pub fn set(&self, time: u64) {
+        self.time.store(time, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, by: u64) {
+        self.time.fetch_add(by, Ordering::SeqCst);
+    }
+}
+
+impl TimeSource for ManualTimeSource {
+    fn now(&self) -> u64 {
+        self.time.load(Ordering::SeqCst)
+    }
+}
+
+/// A message that carries a hybrid logical timestamp
+pub trait Timestamped {
+    /// The timestamp of the message, if it has one
+    fn timestamp(&self) -> Option<HlcTimestamp>;
+}
+
+/// A hybrid logical clock, as described by Kulkarni et al.
+/// It can be shared between tasks; every timestamp it returns is greater than the previous.
+pub struct HybridLogicalClock<T: TimeSource> {
+    source: T,
+    latest: Mutex<HlcTimestamp>,
+}
+
+impl<T: TimeSource> HybridLogicalClock<T> {
+    pub fn new(source: T) -> Self {
+        HybridLogicalClock {
+            source,
+            latest: Mutex::new(HlcTimestamp::default()),
+        }
+    }
+
+    /// A new timestamp for a local event, such as sending a message
+    pub fn now(&self) -> HlcTimestamp {
+        let physical = self.source.now();
+        let mut latest = self.latest.lock().unwrap();
+        *latest = if physical > latest.physical {
+            HlcTimestamp {
+                physical,
+                logical: 0,
+            }
+        } else {
+            HlcTimestamp {
+                physical: latest.physical,
+                logical: latest.logical + 1,
+            }
+        };
+        *latest
+    }
+
+    /// Move the clock past a timestamp received from elsewhere, returning the new time
+    pub fn update(&self, received: HlcTimestamp) -> HlcTimestamp {
+        let physical = self.source.now();
+        let mut latest = self.latest.lock().unwrap();
+        let greatest = physical.max(latest.physical).max(received.physical);
+        let logical = if greatest == latest.physical && greatest == received.physical {
+            latest.logical.max(received.logical) + 1
+        } else if greatest == latest.physical {
+            latest.logical + 1
+        } else if greatest == received.physical {
+            received.logical + 1
+        } else {
+            0
+        };
+        *latest = HlcTimestamp {
+            physical: greatest,
+            logical,
+        };
+        *latest
+    }
+
+    /// Move the clock past the timestamp of a message, such as an operation's result
+    /// Messages without a timestamp leave the clock as it is.
+    pub fn observe<M: Timestamped>(&self, message: &M) -> HlcTimestamp {
+        match message.timestamp() {
+            Some(received) => self.update(received),
+            None => self.latest(),
+        }
+    }
+
+    /// The last timestamp returned, without advancing the clock
+    pub fn latest(&self) -> HlcTimestamp {
+        *self.latest.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::hlc::{HlcTimestamp, HybridLogicalClock, ManualTimeSource, Timestamped};
+
+    fn timestamp(physical: u64, logical: u64) -> HlcTimestamp {
+        HlcTimestamp { physical, logical }
+    }
+
+    #[test]
+    fn timestamps_increase_while_physical_time_stands_still() {
+        let time = ManualTimeSource::new(100);
+        let clock = HybridLogicalClock::new(time.clone());
+
+        assert_eq!(clock.now(), timestamp(100, 0));
+        assert_eq!(clock.now(), timestamp(100, 1));
+        time.advance(5);
+        assert_eq!(clock.now(), timestamp(105, 0));
+        // a clock that goes backwards does not move the timestamps back
+        time.set(50);
+        assert_eq!(clock.now(), timestamp(105, 1));
+    }
+
+    #[test]
+    fn updates_move_past_received_timestamps() {
+        let clock = HybridLogicalClock::new(ManualTimeSource::new(100));
+        clock.now();
+
+        // ahead of the local clock
+        assert_eq!(clock.update(timestamp(200, 3)), timestamp(200, 4));
+        // at the same physical time
+        assert_eq!(clock.update(timestamp(200, 7)), timestamp(200, 8));
+        // behind the local clock
+        assert_eq!(clock.update(timestamp(150, 9)), timestamp(200, 9));
+        assert!(clock.now() > timestamp(200, 9));
+    }
+
+    #[test]
+    fn observing_results_without_timestamps_leaves_the_clock() {
+        struct Response(Option<HlcTimestamp>);
+
+        impl Timestamped for Response {
+            fn timestamp(&self) -> Option<HlcTimestamp> {
+                self.0
+            }
+        }
+
+        let clock = HybridLogicalClock::new(ManualTimeSource::new(100));
+        assert_eq!(clock.observe(&Response(None)), timestamp(0, 0));
+        assert_eq!(
+            clock.observe(&Response(Some(timestamp(300, 0)))),
+            timestamp(300, 1)
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use crate::io::{CarrierRequest, IRNetwork, IRNetworkError};
+use crate::server::{IROperation, IRServerError, View};
+use crate::types::{IRMessage, NodeID, OperationSequence};
+use futures::future::join_all;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+/// Controls how long finalize messages may be held back before they are sent
+#[derive(Clone, Debug)]
+pub struct BatchingConfig {
+    /// A destination's batch is sent as soon as it holds this many finalizes
+    pub max_batch_size: usize,
+    /// A batch is flushed after this delay if no other message to the destination went out
+    pub max_delay: Duration,
+}
+
+impl Default for BatchingConfig {
+    fn default() -> Self {
+        BatchingConfig {
+            max_batch_size: 64,
+            max_delay: Duration::from_millis(5),
+        }
+    }
+}
+
+type PendingFinalizes<I, M> = Arc<Mutex<BTreeMap<I, Vec<IROperation<I, M>>>>>;
+
+/// The response of each node to a request
+type Responses<I, T> =
+    Pin<Box<dyn Future<Output = Vec<(I, Result<T, IRNetworkError<I>>)>> + Send + 'static>>;
+
+/// Converts the results of a carried request to those of the request sent alone
+type CarriedResult<I, M, T> = fn(Vec<(M, View<I>)>) -> Result<T, IRNetworkError<I>>;
+
+/// Wraps a network so that asynchronous finalize messages are coalesced per destination.
+/// A destination's buffered finalizes are carried by the next proposal (or synchronous
+/// finalize) sent to it, with `IRNetwork::send_with_finalizes`, so they need no message of
+/// their own. They are sent as a `finalize_batch` message when the batch is full, or if no
+/// request to the destination went out within `max_delay`.
+pub struct BatchingNetwork<N: IRNetwork<I, M> + 'static, I: NodeID, M: IRMessage> {
+    inner: Arc<N>,
+    pending: PendingFinalizes<I, M>,
+    config: BatchingConfig,
+    /// Runs the timers that flush batches
+    runtime: Handle,
+    _a: PhantomData<M>,
+}
+
+impl<N: IRNetwork<I, M> + 'static, I: NodeID, M: IRMessage> Clone for BatchingNetwork<N, I, M> {
+    fn clone(&self) -> Self {
+        BatchingNetwork {
+            inner: self.inner.clone(),
+            pending: self.pending.clone(),
+            config: self.config.clone(),
+            runtime: self.runtime.clone(),
+            _a: PhantomData,
+        }
+    }
+}
+
+impl<N: IRNetwork<I, M> + 'static, I: NodeID, M: IRMessage> BatchingNetwork<N, I, M> {
+    /// Panics if called outside a tokio runtime, which the network uses to flush batches after
+    /// their delay
+    pub fn new(inner: N, config: BatchingConfig) -> Self {
+        let runtime =
+            Handle::try_current().expect("a BatchingNetwork flushes batches on a tokio runtime");
+        BatchingNetwork {
+            inner: Arc::new(inner),
+            pending: Arc::new(Mutex::new(BTreeMap::new())),
+            config,
+            runtime,
+            _a: PhantomData,
+        }
+    }
+
+    /// Send every buffered finalize immediately
+    pub async fn flush(&self) {
+        let destinations: Vec<I> = self.pending.lock().unwrap().keys().cloned().collect();
+        Self::send_pending(&self.inner, &self.pending, &destinations).await;
+    }
+
+    /// Buffer a finalize, flushing if the batch is full and arming the timer if it is new
+    /// The timer is armed straight away, so the batch is flushed even if the returned future is
+    /// never awaited.
+    fn buffer(
+        &self,
+        destinations: &[I],
+        operation: IROperation<I, M>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut full = Vec::new();
+        let mut started = Vec::new();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            for destination in destinations {
+                let batch = pending.entry(destination.clone()).or_default();
+                if batch.is_empty() {
+                    started.push(destination.clone());
+                }
+                batch.push(operation.clone());
+                if batch.len() >= self.config.max_batch_size {
+                    full.push(destination.clone());
+                }
+            }
+        }
+        if !started.is_empty() {
+            let inner = self.inner.clone();
+            let pending = self.pending.clone();
+            let max_delay = self.config.max_delay;
+            self.runtime.spawn(async move {
+                tokio::time::sleep(max_delay).await;
+                Self::send_pending(&inner, &pending, &started).await;
+            });
+        }
+        let inner = self.inner.clone();
+        let pending = self.pending.clone();
+        Box::pin(async move { Self::send_pending(&inner, &pending, &full).await })
+    }
+
+    /// Send the finalizes buffered for the destinations as batches of their own
+    async fn send_pending(inner: &Arc<N>, pending: &PendingFinalizes<I, M>, destinations: &[I]) {
+        let batches: Vec<_> = {
+            let mut pending = pending.lock().unwrap();
+            destinations
+                .iter()
+                .filter_map(|destination| pending.remove(destination).map(|b| (destination, b)))
+                .filter(|(_destination, batch)| !batch.is_empty())
+                .map(|(destination, batch)| {
+                    inner.finalize_batch(std::slice::from_ref(destination), batch)
+                })
+                .collect()
+        };
+        join_all(batches).await;
+    }
+
+    /// Send a request to the destinations, carrying the finalizes buffered for each of them
+    /// Destinations without buffered finalizes are sent the request on its own with `send`.
+    /// The results of a carried request are converted with `result`, and the responses are in
+    /// the order of the destinations.
+    fn send_carrying<T: Send + 'static>(
+        &self,
+        destinations: &[I],
+        request: CarrierRequest<I, M>,
+        send: impl FnOnce(&[I]) -> Responses<I, T>,
+        result: CarriedResult<I, M, T>,
+    ) -> Responses<I, T> {
+        let mut carried = Vec::new();
+        let mut alone = Vec::new();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            for destination in destinations {
+                match pending.remove(destination) {
+                    Some(finalizes) if !finalizes.is_empty() => {
+                        carried.push(self.inner.send_with_finalizes(
+                            std::slice::from_ref(destination),
+                            finalizes,
+                            request.clone(),
+                        ))
+                    }
+                    _ => alone.push(destination.clone()),
+                }
+            }
+        }
+        let sent = if alone.is_empty() {
+            Box::pin(async { Vec::new() })
+        } else {
+            send(&alone)
+        };
+        let destinations = destinations.to_vec();
+        Box::pin(async move {
+            let (carried, sent) = futures::join!(join_all(carried), sent);
+            let mut responses: Vec<_> = carried
+                .into_iter()
+                .flatten()
+                .map(|(node, response)| (node, response.and_then(result)))
+                .chain(sent)
+                .collect();
+            responses.sort_by_key(|(node, _)| destinations.iter().position(|d| d == node));
+            responses
+        })
+    }
+}
+
+/// The result of a single operation, carried as a request with one message
+fn single<I: NodeID, M: IRMessage>(
+    mut results: Vec<(M, View<I>)>,
+) -> Result<(M, View<I>), IRNetworkError<I>> {
+    match (results.pop(), results.is_empty()) {
+        (Some(result), true) => Ok(result),
+        _ => Err(IRNetworkError::IRServerError(IRServerError::InternalError(
+            "expected a single result".into(),
+        ))),
+    }
+}
+
+impl<N: IRNetwork<I, M> + 'static, I: NodeID, M: IRMessage> IRNetwork<I, M>
+    for BatchingNetwork<N, I, M>
+{
+    fn propose_inconsistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+        highest_observed_view: Option<View<I>>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + Send + 'static,
+        >,
+    > {
+        let request = CarrierRequest::ProposeInconsistent {
+            client: client_id.clone(),
+            first_sequence: sequence,
+            messages: vec![message.clone()],
+            highest_observed_view: highest_observed_view.clone(),
+        };
+        self.send_carrying(
+            destinations,
+            request,
+            |destinations| {
+                self.inner.propose_inconsistent(
+                    destinations,
+                    client_id,
+                    sequence,
+                    message,
+                    highest_observed_view,
+                )
+            },
+            single,
+        )
+    }
+
+    fn propose_consistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + Send + 'static,
+        >,
+    > {
+        let request = CarrierRequest::ProposeConsistent {
+            client: client_id.clone(),
+            first_sequence: sequence,
+            messages: vec![message.clone()],
+        };
+        self.send_carrying(
+            destinations,
+            request,
+            |destinations| {
+                self.inner
+                    .propose_consistent(destinations, client_id, sequence, message)
+            },
+            single,
+        )
+    }
+
+    fn async_finalize_inconsistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.buffer(
+            destinations,
+            IROperation::InconsistentFinalize {
+                client: client_id,
+                sequence,
+                message,
+            },
+        )
+    }
+
+    fn async_finalize_consistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.buffer(
+            destinations,
+            IROperation::ConsistentFinalize {
+                client: client_id,
+                sequence,
+                message,
+            },
+        )
+    }
+
+    fn sync_finalize_consistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + Send + 'static,
+        >,
+    > {
+        let request = CarrierRequest::FinalizeConsistent {
+            client: client_id.clone(),
+            sequence,
+            message: message.clone(),
+        };
+        self.send_carrying(
+            destinations,
+            request,
+            |destinations| {
+                self.inner
+                    .sync_finalize_consistent(destinations, client_id, sequence, message)
+            },
+            single,
+        )
+    }
+
+    fn propose_inconsistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>)>>
+                + Send
+                + 'static,
+        >,
+    > {
+        let request = CarrierRequest::ProposeInconsistent {
+            client: client_id.clone(),
+            first_sequence,
+            messages: messages.clone(),
+            highest_observed_view: highest_observed_view.clone(),
+        };
+        self.send_carrying(
+            destinations,
+            request,
+            |destinations| {
+                self.inner.propose_inconsistent_batch(
+                    destinations,
+                    client_id,
+                    first_sequence,
+                    messages,
+                    highest_observed_view,
+                )
+            },
+            Ok,
+        )
+    }
+
+    fn propose_consistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>)>>
+                + Send
+                + 'static,
+        >,
+    > {
+        let request = CarrierRequest::ProposeConsistent {
+            client: client_id.clone(),
+            first_sequence,
+            messages: messages.clone(),
+        };
+        self.send_carrying(
+            destinations,
+            request,
+            |destinations| {
+                self.inner.propose_consistent_batch(
+                    destinations,
+                    client_id,
+                    first_sequence,
+                    messages,
+                )
+            },
+            Ok,
+        )
+    }
+
+    fn finalize_batch(
+        &self,
+        destinations: &[I],
+        operations: Vec<IROperation<I, M>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.inner.finalize_batch(destinations, operations)
+    }
+
+    fn send_with_finalizes(
+        &self,
+        destinations: &[I],
+        finalizes: Vec<IROperation<I, M>>,
+        request: CarrierRequest<I, M>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>)>>
+                + Send
+                + 'static,
+        >,
+    > {
+        self.inner
+            .send_with_finalizes(destinations, finalizes, request)
+    }
+}
@@ -0,0 +1,32 @@
+use crate::io::{IRClientStorage, StorageShared};
+use crate::server::{View, ViewState};
+use crate::types::{IRMessage, NodeID};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Client storage that starts every client in view 0 of the configured members
+/// Clients learn later views from the replicas' responses, so nothing needs to be persisted.
+#[derive(Clone, Debug)]
+pub struct MembersClientStorage<ID: NodeID> {
+    members: Vec<ID>,
+}
+
+impl<ID: NodeID> MembersClientStorage<ID> {
+    pub fn new(members: Vec<ID>) -> Self {
+        MembersClientStorage { members }
+    }
+}
+
+impl<ID: NodeID> StorageShared<ID> for MembersClientStorage<ID> {
+    fn recover_current_view(&self) -> Pin<Box<dyn Future<Output = View<ID>> + Send + 'static>> {
+        let view = View {
+            view: 0,
+            members: self.members.clone(),
+            learners: vec![],
+            state: ViewState::Normal,
+        };
+        Box::pin(async move { view })
+    }
+}
+
+impl<ID: NodeID, MSG: IRMessage> IRClientStorage<ID, MSG> for MembersClientStorage<ID> {}
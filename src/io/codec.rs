@@ -0,0 +1,343 @@
+//! A compact binary encoding for transports and storage that write bytes.
+//!
+//! Integers are little endian, and strings, collections and options are prefixed with their
+//! length or a tag. Node ids and messages implement `Codec` to be sent by `TcpNetwork` and
+//! stored by `LogStorage`:
+//!
+//! ```ignore
+//! let mut bytes = Vec::new();
+//! view.encode(&mut bytes);
+//! let decoded = View::<u64>::decode(&mut bytes.as_slice())?;
+//! ```
+
+use crate::hlc::HlcTimestamp;
+use crate::io::CarrierRequest;
+use crate::server::{ChangeCursor, IROperation, View, ViewState};
+use crate::types::{IRMessage, NodeID};
+use std::collections::BTreeMap;
+
+/// Converts a value to and from bytes
+pub trait Codec: Sized {
+    /// Append the value to `out`
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Read a value from the front of `input`, advancing it past the bytes read
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError>;
+}
+
+/// Why bytes could not be decoded
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// The input ended in the middle of a value
+    UnexpectedEnd,
+    /// An enum or option tag that no variant has
+    InvalidTag(u8),
+    /// A string that is not UTF-8
+    InvalidUtf8,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::InvalidTag(tag) => write!(f, "invalid tag {tag}"),
+            DecodeError::InvalidUtf8 => write!(f, "invalid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Take `len` bytes from the front of `input`
+pub(crate) fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < len {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Ok(taken)
+}
+
+/// Read a length prefix, refusing lengths longer than the remaining input so that corrupt
+/// input cannot cause a huge allocation
+fn decode_len(input: &mut &[u8]) -> Result<usize, DecodeError> {
+    let len = u64::decode(input)?;
+    if len > input.len() as u64 {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    Ok(len as usize)
+}
+
+impl Codec for u8 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(take(input, 1)?[0])
+    }
+}
+
+impl Codec for u64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let bytes = take(input, 8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().expect("8 bytes taken")))
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+impl<T: Codec> Codec for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+impl<T: Codec> Codec for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode(out);
+        for value in self {
+            value.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        // Every value takes at least a byte, so the length is bounded by the input
+        let len = decode_len(input)?;
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            values.push(T::decode(input)?);
+        }
+        Ok(values)
+    }
+}
+
+impl<K: Codec + Ord, V: Codec> Codec for BTreeMap<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u64).encode(out);
+        for (key, value) in self {
+            key.encode(out);
+            value.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        let mut map = BTreeMap::new();
+        for _ in 0..len {
+            let key = K::decode(input)?;
+            map.insert(key, V::decode(input)?);
+        }
+        Ok(map)
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+impl Codec for HlcTimestamp {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.physical.encode(out);
+        self.logical.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(HlcTimestamp {
+            physical: u64::decode(input)?,
+            logical: u64::decode(input)?,
+        })
+    }
+}
+
+impl Codec for ViewState {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(match self {
+            ViewState::Normal => 0,
+            ViewState::ViewChanging => 1,
+            ViewState::Recovery => 2,
+        });
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(ViewState::Normal),
+            1 => Ok(ViewState::ViewChanging),
+            2 => Ok(ViewState::Recovery),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+impl<ID: NodeID + Codec> Codec for View<ID> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.view.encode(out);
+        self.members.encode(out);
+        self.learners.encode(out);
+        self.state.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(View {
+            view: u64::decode(input)?,
+            members: Vec::decode(input)?,
+            learners: Vec::decode(input)?,
+            state: ViewState::decode(input)?,
+        })
+    }
+}
+
+impl Codec for ChangeCursor {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.view.encode(out);
+        self.position.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(ChangeCursor {
+            view: u64::decode(input)?,
+            position: u64::decode(input)?,
+        })
+    }
+}
+
+impl<ID: NodeID + Codec, MSG: IRMessage + Codec> Codec for IROperation<ID, MSG> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(match self {
+            IROperation::InconsistentPropose { .. } => 0,
+            IROperation::InconsistentFinalize { .. } => 1,
+            IROperation::ConsistentPropose { .. } => 2,
+            IROperation::ConsistentFinalize { .. } => 3,
+        });
+        self.client().encode(out);
+        self.sequence().encode(out);
+        self.message().encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let tag = u8::decode(input)?;
+        let client = ID::decode(input)?;
+        let sequence = u64::decode(input)?;
+        let message = MSG::decode(input)?;
+        match tag {
+            0 => Ok(IROperation::InconsistentPropose {
+                client,
+                sequence,
+                message,
+            }),
+            1 => Ok(IROperation::InconsistentFinalize {
+                client,
+                sequence,
+                message,
+            }),
+            2 => Ok(IROperation::ConsistentPropose {
+                client,
+                sequence,
+                message,
+            }),
+            3 => Ok(IROperation::ConsistentFinalize {
+                client,
+                sequence,
+                message,
+            }),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+impl<ID: NodeID + Codec, MSG: IRMessage + Codec> Codec for CarrierRequest<ID, MSG> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            CarrierRequest::ProposeInconsistent {
+                client,
+                first_sequence,
+                messages,
+                highest_observed_view,
+            } => {
+                out.push(0);
+                client.encode(out);
+                first_sequence.encode(out);
+                messages.encode(out);
+                highest_observed_view.encode(out);
+            }
+            CarrierRequest::ProposeConsistent {
+                client,
+                first_sequence,
+                messages,
+            } => {
+                out.push(1);
+                client.encode(out);
+                first_sequence.encode(out);
+                messages.encode(out);
+            }
+            CarrierRequest::FinalizeConsistent {
+                client,
+                sequence,
+                message,
+            } => {
+                out.push(2);
+                client.encode(out);
+                sequence.encode(out);
+                message.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(CarrierRequest::ProposeInconsistent {
+                client: ID::decode(input)?,
+                first_sequence: u64::decode(input)?,
+                messages: Vec::decode(input)?,
+                highest_observed_view: Option::decode(input)?,
+            }),
+            1 => Ok(CarrierRequest::ProposeConsistent {
+                client: ID::decode(input)?,
+                first_sequence: u64::decode(input)?,
+                messages: Vec::decode(input)?,
+            }),
+            2 => Ok(CarrierRequest::FinalizeConsistent {
+                client: ID::decode(input)?,
+                sequence: u64::decode(input)?,
+                message: MSG::decode(input)?,
+            }),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
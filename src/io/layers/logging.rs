@@ -0,0 +1,55 @@
+use crate::io::layers::{IRNetworkLayer, NetworkCall, Responses, SendCall};
+use crate::types::NodeID;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Instant;
+
+/// Emits a `tracing` event for every call and for every destination's response
+/// Events are at debug level, with the method, client, sequence and `trace_id` as fields, so
+/// they can be correlated with the `ir_client` and `ir_server` spans of the operation.
+#[derive(Clone, Debug, Default)]
+pub struct LoggingLayer;
+
+impl LoggingLayer {
+    pub fn new() -> Self {
+        LoggingLayer
+    }
+}
+
+impl<I: NodeID> IRNetworkLayer<I> for LoggingLayer {
+    fn call<T: Debug + Send + 'static>(
+        &self,
+        call: NetworkCall<I>,
+        destinations: &[I],
+        send: SendCall<I, T>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, T>> + Send>> {
+        tracing::debug!(
+            method = ?call.method,
+            client = ?call.client_id,
+            sequence = call.sequence,
+            trace_id = call.trace_id().map(tracing::field::display),
+            ?destinations,
+            "network call"
+        );
+        let responses = send(destinations);
+        Box::pin(async move {
+            let started = Instant::now();
+            let responses = responses.await;
+            let elapsed = started.elapsed();
+            for (destination, response) in &responses {
+                tracing::debug!(
+                    method = ?call.method,
+                    client = ?call.client_id,
+                    sequence = call.sequence,
+                    trace_id = call.trace_id().map(tracing::field::display),
+                    ?destination,
+                    ?elapsed,
+                    ?response,
+                    "network response"
+                );
+            }
+            responses
+        })
+    }
+}
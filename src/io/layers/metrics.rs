@@ -0,0 +1,67 @@
+use crate::io::layers::{IRNetworkLayer, NetworkCall, Responses, SendCall};
+use crate::io::IRNetworkError;
+use crate::metrics::{
+    Metrics, NETWORK_CALLS_TOTAL, NETWORK_CALL_DURATION_SECONDS, NETWORK_FAILURES_TOTAL,
+    NETWORK_RESPONSES_TOTAL,
+};
+use crate::types::NodeID;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Reports calls, responses, failures and latency per method to a `Metrics` implementation
+/// See `NETWORK_CALLS_TOTAL` and the other `NETWORK_` metrics for what is reported.
+#[derive(Clone)]
+pub struct MetricsLayer {
+    metrics: Arc<dyn Metrics>,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<dyn Metrics>) -> Self {
+        MetricsLayer { metrics }
+    }
+}
+
+impl<I: NodeID> IRNetworkLayer<I> for MetricsLayer {
+    fn call<T: Debug + Send + 'static>(
+        &self,
+        call: NetworkCall<I>,
+        destinations: &[I],
+        send: SendCall<I, T>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, T>> + Send>> {
+        let metrics = self.metrics.clone();
+        let responses = send(destinations);
+        Box::pin(async move {
+            let started = Instant::now();
+            let responses = responses.await;
+            let method = ("method", call.method.label().to_string());
+            metrics.increment_counter(NETWORK_CALLS_TOTAL, std::slice::from_ref(&method));
+            metrics.observe_histogram(
+                NETWORK_CALL_DURATION_SECONDS,
+                std::slice::from_ref(&method),
+                started.elapsed().as_secs_f64(),
+            );
+            for (destination, response) in &responses {
+                let outcome = match response {
+                    Ok(_) => "ok",
+                    Err(IRNetworkError::NodeUnreachable(_)) => "unreachable",
+                    Err(IRNetworkError::Timeout(_)) => "timeout",
+                    Err(IRNetworkError::IRServerError(_)) => "server_error",
+                };
+                metrics.increment_counter(
+                    NETWORK_RESPONSES_TOTAL,
+                    &[method.clone(), ("outcome", outcome.to_string())],
+                );
+                if matches!(outcome, "unreachable" | "timeout") {
+                    metrics.increment_counter(
+                        NETWORK_FAILURES_TOTAL,
+                        &[("node", format!("{:?}", destination))],
+                    );
+                }
+            }
+            responses
+        })
+    }
+}
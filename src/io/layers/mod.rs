@@ -0,0 +1,401 @@
+//! Composable middleware for `IRNetwork`, in the spirit of tower layers.
+//!
+//! A layer sees every network call as a method, its destinations and a function that sends
+//! the call to any subset of those destinations. Wrap a network with `IRNetworkExt::with_layer`;
+//! the result is again an `IRNetwork`, so layers stack:
+//!
+//! ```ignore
+//! let network = transport
+//!     .with_layer(TimeoutLayer::new(Duration::from_millis(200)))
+//!     .with_layer(RetryLayer::new(3, Duration::from_millis(10)));
+//! ```
+//!
+//! The outermost layer is the last one added.
+
+mod logging;
+mod metrics;
+mod retry;
+mod timeout;
+
+pub use logging::LoggingLayer;
+pub use metrics::MetricsLayer;
+pub use retry::RetryLayer;
+pub use timeout::TimeoutLayer;
+
+use crate::io::{CarrierRequest, IRNetwork, IRNetworkError};
+use crate::server::{IROperation, View};
+use crate::types::{IRMessage, NodeID, OperationSequence, TraceId};
+use std::fmt::Debug;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The `IRNetwork` method being called
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum NetworkMethod {
+    ProposeInconsistent,
+    ProposeConsistent,
+    AsyncFinalizeInconsistent,
+    AsyncFinalizeConsistent,
+    SyncFinalizeConsistent,
+    ProposeInconsistentBatch,
+    ProposeConsistentBatch,
+    FinalizeBatch,
+    SendWithFinalizes,
+}
+
+impl NetworkMethod {
+    /// The method's name, as used in metric labels
+    pub fn label(&self) -> &'static str {
+        match self {
+            NetworkMethod::ProposeInconsistent => "propose_inconsistent",
+            NetworkMethod::ProposeConsistent => "propose_consistent",
+            NetworkMethod::AsyncFinalizeInconsistent => "async_finalize_inconsistent",
+            NetworkMethod::AsyncFinalizeConsistent => "async_finalize_consistent",
+            NetworkMethod::SyncFinalizeConsistent => "sync_finalize_consistent",
+            NetworkMethod::ProposeInconsistentBatch => "propose_inconsistent_batch",
+            NetworkMethod::ProposeConsistentBatch => "propose_consistent_batch",
+            NetworkMethod::FinalizeBatch => "finalize_batch",
+            NetworkMethod::SendWithFinalizes => "send_with_finalizes",
+        }
+    }
+}
+
+/// A description of a network call, for layers to act on
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NetworkCall<I: NodeID> {
+    pub method: NetworkMethod,
+    /// Not set for finalize batches, which can hold several clients' operations
+    pub client_id: Option<I>,
+    /// The first sequence number, for batches
+    pub sequence: Option<OperationSequence>,
+}
+
+impl<I: NodeID> NetworkCall<I> {
+    /// The trace id of the operation, or of the first operation of a batch
+    pub fn trace_id(&self) -> Option<TraceId<I>> {
+        match (&self.client_id, self.sequence) {
+            (Some(client), Some(sequence)) => Some(TraceId::new(client.clone(), sequence)),
+            _ => None,
+        }
+    }
+}
+
+/// The response of each destination of a call
+/// Asynchronous finalizes have no response, so they are reported as `Ok(())` once sent
+pub type Responses<I, T> = Vec<(I, Result<T, IRNetworkError<I>>)>;
+
+/// Sends the call to the given destinations; may be invoked more than once
+pub type SendCall<I, T> =
+    Arc<dyn Fn(&[I]) -> Pin<Box<dyn Future<Output = Responses<I, T>> + Send>> + Send + Sync>;
+
+/// Middleware applied to every call made through a network
+pub trait IRNetworkLayer<I: NodeID>: Send + Sync + 'static {
+    fn call<T: Debug + Send + 'static>(
+        &self,
+        call: NetworkCall<I>,
+        destinations: &[I],
+        send: SendCall<I, T>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, T>> + Send>>;
+}
+
+/// Decorate any network with layers
+pub trait IRNetworkExt<I: NodeID, M: IRMessage>: IRNetwork<I, M> + Sized + 'static {
+    fn with_layer<L: IRNetworkLayer<I>>(self, layer: L) -> Layered<Self, L, I, M> {
+        Layered {
+            inner: Arc::new(self),
+            layer: Arc::new(layer),
+            _a: PhantomData,
+        }
+    }
+}
+
+impl<I: NodeID, M: IRMessage, N: IRNetwork<I, M> + 'static> IRNetworkExt<I, M> for N {}
+
+/// A network with a layer applied to all of its calls
+pub struct Layered<N: IRNetwork<I, M>, L: IRNetworkLayer<I>, I: NodeID, M: IRMessage> {
+    inner: Arc<N>,
+    layer: Arc<L>,
+    _a: PhantomData<(I, M)>,
+}
+
+impl<N: IRNetwork<I, M>, L: IRNetworkLayer<I>, I: NodeID, M: IRMessage> Clone
+    for Layered<N, L, I, M>
+{
+    fn clone(&self) -> Self {
+        Layered {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+            _a: PhantomData,
+        }
+    }
+}
+
+/// Report every destination of a call without a response as acknowledged
+fn acknowledged<I: NodeID>(
+    destinations: &[I],
+    sent: Pin<Box<dyn Future<Output = ()> + Send>>,
+) -> Pin<Box<dyn Future<Output = Responses<I, ()>> + Send>> {
+    let destinations = destinations.to_vec();
+    Box::pin(async move {
+        sent.await;
+        destinations.into_iter().map(|d| (d, Ok(()))).collect()
+    })
+}
+
+impl<N: IRNetwork<I, M> + 'static, L: IRNetworkLayer<I>, I: NodeID, M: IRMessage>
+    Layered<N, L, I, M>
+{
+    fn call<T: Debug + Send + 'static>(
+        &self,
+        method: NetworkMethod,
+        client_id: Option<I>,
+        sequence: Option<OperationSequence>,
+        destinations: &[I],
+        send: SendCall<I, T>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, T>> + Send>> {
+        let call = NetworkCall {
+            method,
+            client_id,
+            sequence,
+        };
+        self.layer.call(call, destinations, send)
+    }
+
+    fn without_responses(
+        &self,
+        method: NetworkMethod,
+        client_id: Option<I>,
+        sequence: Option<OperationSequence>,
+        destinations: &[I],
+        send: SendCall<I, ()>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let responses = self.call(method, client_id, sequence, destinations, send);
+        Box::pin(async move {
+            responses.await;
+        })
+    }
+}
+
+impl<N: IRNetwork<I, M> + 'static, L: IRNetworkLayer<I>, I: NodeID, M: IRMessage> IRNetwork<I, M>
+    for Layered<N, L, I, M>
+{
+    fn propose_inconsistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+        highest_observed_view: Option<View<I>>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, (M, View<I>)>> + Send + 'static>> {
+        let inner = self.inner.clone();
+        let client = client_id.clone();
+        let send: SendCall<I, (M, View<I>)> = Arc::new(move |destinations: &[I]| {
+            inner.propose_inconsistent(
+                destinations,
+                client.clone(),
+                sequence,
+                message.clone(),
+                highest_observed_view.clone(),
+            )
+        });
+        self.call(
+            NetworkMethod::ProposeInconsistent,
+            Some(client_id),
+            Some(sequence),
+            destinations,
+            send,
+        )
+    }
+
+    fn propose_consistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, (M, View<I>)>> + Send + 'static>> {
+        let inner = self.inner.clone();
+        let client = client_id.clone();
+        let send: SendCall<I, (M, View<I>)> = Arc::new(move |destinations: &[I]| {
+            inner.propose_consistent(destinations, client.clone(), sequence, message.clone())
+        });
+        self.call(
+            NetworkMethod::ProposeConsistent,
+            Some(client_id),
+            Some(sequence),
+            destinations,
+            send,
+        )
+    }
+
+    fn async_finalize_inconsistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let inner = self.inner.clone();
+        let client = client_id.clone();
+        let send: SendCall<I, ()> = Arc::new(move |destinations: &[I]| {
+            acknowledged(
+                destinations,
+                inner.async_finalize_inconsistent(
+                    destinations,
+                    client.clone(),
+                    sequence,
+                    message.clone(),
+                ),
+            )
+        });
+        self.without_responses(
+            NetworkMethod::AsyncFinalizeInconsistent,
+            Some(client_id),
+            Some(sequence),
+            destinations,
+            send,
+        )
+    }
+
+    fn async_finalize_consistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let inner = self.inner.clone();
+        let client = client_id.clone();
+        let send: SendCall<I, ()> = Arc::new(move |destinations: &[I]| {
+            acknowledged(
+                destinations,
+                inner.async_finalize_consistent(
+                    destinations,
+                    client.clone(),
+                    sequence,
+                    message.clone(),
+                ),
+            )
+        });
+        self.without_responses(
+            NetworkMethod::AsyncFinalizeConsistent,
+            Some(client_id),
+            Some(sequence),
+            destinations,
+            send,
+        )
+    }
+
+    fn sync_finalize_consistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, (M, View<I>)>> + Send + 'static>> {
+        let inner = self.inner.clone();
+        let client = client_id.clone();
+        let send: SendCall<I, (M, View<I>)> = Arc::new(move |destinations: &[I]| {
+            inner.sync_finalize_consistent(destinations, client.clone(), sequence, message.clone())
+        });
+        self.call(
+            NetworkMethod::SyncFinalizeConsistent,
+            Some(client_id),
+            Some(sequence),
+            destinations,
+            send,
+        )
+    }
+
+    fn propose_inconsistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, Vec<(M, View<I>)>>> + Send + 'static>> {
+        let inner = self.inner.clone();
+        let client = client_id.clone();
+        let send: SendCall<I, Vec<(M, View<I>)>> = Arc::new(move |destinations: &[I]| {
+            inner.propose_inconsistent_batch(
+                destinations,
+                client.clone(),
+                first_sequence,
+                messages.clone(),
+                highest_observed_view.clone(),
+            )
+        });
+        self.call(
+            NetworkMethod::ProposeInconsistentBatch,
+            Some(client_id),
+            Some(first_sequence),
+            destinations,
+            send,
+        )
+    }
+
+    fn propose_consistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, Vec<(M, View<I>)>>> + Send + 'static>> {
+        let inner = self.inner.clone();
+        let client = client_id.clone();
+        let send: SendCall<I, Vec<(M, View<I>)>> = Arc::new(move |destinations: &[I]| {
+            inner.propose_consistent_batch(
+                destinations,
+                client.clone(),
+                first_sequence,
+                messages.clone(),
+            )
+        });
+        self.call(
+            NetworkMethod::ProposeConsistentBatch,
+            Some(client_id),
+            Some(first_sequence),
+            destinations,
+            send,
+        )
+    }
+
+    fn finalize_batch(
+        &self,
+        destinations: &[I],
+        operations: Vec<IROperation<I, M>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let inner = self.inner.clone();
+        let send: SendCall<I, ()> = Arc::new(move |destinations: &[I]| {
+            acknowledged(
+                destinations,
+                inner.finalize_batch(destinations, operations.clone()),
+            )
+        });
+        self.without_responses(NetworkMethod::FinalizeBatch, None, None, destinations, send)
+    }
+
+    fn send_with_finalizes(
+        &self,
+        destinations: &[I],
+        finalizes: Vec<IROperation<I, M>>,
+        request: CarrierRequest<I, M>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, Vec<(M, View<I>)>>> + Send + 'static>> {
+        let inner = self.inner.clone();
+        let client_id = request.client().clone();
+        let sequence = request.first_sequence();
+        let send: SendCall<I, Vec<(M, View<I>)>> = Arc::new(move |destinations: &[I]| {
+            inner.send_with_finalizes(destinations, finalizes.clone(), request.clone())
+        });
+        self.call(
+            NetworkMethod::SendWithFinalizes,
+            Some(client_id),
+            Some(sequence),
+            destinations,
+            send,
+        )
+    }
+}
@@ -0,0 +1,59 @@
+use crate::io::layers::{IRNetworkLayer, NetworkCall, Responses, SendCall};
+use crate::io::IRNetworkError;
+use crate::types::NodeID;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Re-sends a call to the destinations that were unreachable or timed out
+/// Errors returned by a server are not retried. Retries keep the client id and sequence, so
+/// replicas treat them as the same operation. Clients already re-send proposals, and each of
+/// their attempts is retried by this layer; see `with_proposal_attempts` on the client.
+#[derive(Clone, Debug)]
+pub struct RetryLayer {
+    attempts: usize,
+    backoff: Duration,
+}
+
+impl RetryLayer {
+    /// `attempts` includes the first send; `backoff` is the wait between attempts
+    pub fn new(attempts: usize, backoff: Duration) -> Self {
+        RetryLayer { attempts, backoff }
+    }
+}
+
+impl<I: NodeID> IRNetworkLayer<I> for RetryLayer {
+    fn call<T: Debug + Send + 'static>(
+        &self,
+        _call: NetworkCall<I>,
+        destinations: &[I],
+        send: SendCall<I, T>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, T>> + Send>> {
+        let attempts = self.attempts.max(1);
+        let backoff = self.backoff;
+        let mut pending = destinations.to_vec();
+        Box::pin(async move {
+            let mut responses = Vec::with_capacity(pending.len());
+            for attempt in 1..=attempts {
+                let mut failed = Vec::new();
+                for (destination, response) in send(&pending).await {
+                    match response {
+                        Err(IRNetworkError::NodeUnreachable(_) | IRNetworkError::Timeout(_))
+                            if attempt < attempts =>
+                        {
+                            failed.push(destination)
+                        }
+                        response => responses.push((destination, response)),
+                    }
+                }
+                if failed.is_empty() {
+                    break;
+                }
+                pending = failed;
+                tokio::time::sleep(backoff).await;
+            }
+            responses
+        })
+    }
+}
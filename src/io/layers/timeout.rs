@@ -0,0 +1,51 @@
+use crate::io::layers::{IRNetworkLayer, NetworkCall, Responses, SendCall};
+use crate::io::IRNetworkError;
+use crate::types::NodeID;
+use futures::future::join_all;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Gives up on a destination that has not responded within the timeout
+/// Each destination is sent to separately, so a slow node does not hold up the others.
+#[derive(Clone, Debug)]
+pub struct TimeoutLayer {
+    timeout: Duration,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        TimeoutLayer { timeout }
+    }
+}
+
+impl<I: NodeID> IRNetworkLayer<I> for TimeoutLayer {
+    fn call<T: Debug + Send + 'static>(
+        &self,
+        _call: NetworkCall<I>,
+        destinations: &[I],
+        send: SendCall<I, T>,
+    ) -> Pin<Box<dyn Future<Output = Responses<I, T>> + Send>> {
+        let timeout = self.timeout;
+        let calls: Vec<_> = destinations
+            .iter()
+            .map(|destination| {
+                let destination = destination.clone();
+                let response = send(std::slice::from_ref(&destination));
+                async move {
+                    match tokio::time::timeout(timeout, response).await {
+                        Ok(responses) => responses,
+                        Err(_elapsed) => {
+                            vec![(
+                                destination.clone(),
+                                Err(IRNetworkError::Timeout(destination)),
+                            )]
+                        }
+                    }
+                }
+            })
+            .collect();
+        Box::pin(async move { join_all(calls).await.into_iter().flatten().collect() })
+    }
+}
@@ -0,0 +1,897 @@
+use crate::codec::{take, Codec, DecodeError};
+use crate::group::{GroupId, GroupStorage};
+use crate::io::{ReadChange, StorageShared};
+use crate::server::{ChangeCursor, ChangeLogError, IROperation, View, ViewState};
+use crate::types::{IRMessage, NodeID, OperationSequence};
+use crate::IRStorage;
+use futures::{stream, Stream};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// The operations of a record, by client and sequence
+type Record<ID, MSG> = BTreeMap<(ID, OperationSequence), IROperation<ID, MSG>>;
+
+/// The position of the first operation kept of a view, and the operations
+type KeptChanges<ID, MSG> = (u64, Vec<IROperation<ID, MSG>>);
+
+/// The operations of the local record, with the view each was recorded in
+type LocalRecord<ID, MSG> = BTreeMap<(ID, OperationSequence), (View<ID>, IROperation<ID, MSG>)>;
+
+/// The result each node reported for the undecided operations of a view
+type UndecidedRecord<ID, MSG> = BTreeMap<(ID, OperationSequence), BTreeMap<ID, MSG>>;
+
+/// The encoded id of the group an entry belongs to, or `None` for the log's own records
+type GroupKey = Option<Vec<u8>>;
+
+/// Durable `IRStorage` in an append-only log file
+/// Every change is appended to the log and synced to disk before it is applied, and opening
+/// the log replays it. The state is also kept in memory, so reads do not touch the disk.
+/// Opening compacts the log down to the current state, dropping the view change records of
+/// views before the current one.
+///
+/// As a `GroupStorage`, the groups of a `MultiGroupHost` share one log. Every entry is keyed
+/// by the id of its group, and each group has its own state, starting in view 0 of the
+/// members the log was opened with.
+///
+/// The node cannot continue without durable storage, so failing to write the log panics.
+pub struct LogStorage<ID: NodeID, MSG: IRMessage> {
+    log: Arc<Mutex<Log<ID, MSG>>>,
+    /// The group whose state this handle reads and writes
+    group: GroupKey,
+}
+
+impl<ID: NodeID, MSG: IRMessage> Clone for LogStorage<ID, MSG> {
+    fn clone(&self) -> Self {
+        LogStorage {
+            log: self.log.clone(),
+            group: self.group.clone(),
+        }
+    }
+}
+
+struct Log<ID: NodeID, MSG: IRMessage> {
+    file: File,
+    /// The members of view 0, for groups that have not started a view
+    members: Vec<ID>,
+    groups: BTreeMap<GroupKey, State<ID, MSG>>,
+}
+
+impl<ID: NodeID, MSG: IRMessage> Log<ID, MSG> {
+    fn state(&mut self, group: &GroupKey) -> &mut State<ID, MSG> {
+        self.groups
+            .entry(group.clone())
+            .or_insert_with(|| State::new(self.members.clone()))
+    }
+}
+
+/// The state of a node's storage, as rebuilt from its log
+struct State<ID: NodeID, MSG: IRMessage> {
+    /// The view the node last started, or view 0 of the configured members
+    current_view: View<ID>,
+    view_recorded: bool,
+    /// The local record, with the view each operation was recorded in
+    records: LocalRecord<ID, MSG>,
+    /// Records received from peers during view changes
+    peer_records: BTreeMap<(View<ID>, ID), Record<ID, MSG>>,
+    /// The peers that have sent their full record, for each view change
+    complete_peers: BTreeMap<View<ID>, Vec<ID>>,
+    /// The main (master) records made by merging
+    main_records: BTreeMap<View<ID>, Record<ID, MSG>>,
+    undecided_records: BTreeMap<View<ID>, UndecidedRecord<ID, MSG>>,
+    changes: Changes<ID, MSG>,
+}
+
+struct Changes<ID: NodeID, MSG: IRMessage> {
+    /// The operations kept of each view, with the position of the first one
+    views: BTreeMap<u64, (u64, VecDeque<IROperation<ID, MSG>>)>,
+    /// Every operation ever logged, including those truncated
+    logged: BTreeSet<(ID, OperationSequence)>,
+    /// The position of the oldest operation kept; earlier ones have been truncated
+    oldest: ChangeCursor,
+    kept: u64,
+}
+
+/// A change to the state, as written to the log
+enum Entry<ID: NodeID, MSG: IRMessage> {
+    /// An operation of the local record, in the state it is now in
+    Record {
+        view: View<ID>,
+        operation: IROperation<ID, MSG>,
+    },
+    PeerOperation {
+        node: ID,
+        view: View<ID>,
+        operation: IROperation<ID, MSG>,
+    },
+    PeerComplete {
+        node: ID,
+        view: View<ID>,
+    },
+    MainOperation {
+        view: View<ID>,
+        operation: IROperation<ID, MSG>,
+    },
+    Undecided {
+        view: View<ID>,
+        node: ID,
+        operation: IROperation<ID, MSG>,
+    },
+    ChangesAppended {
+        view: u64,
+        operations: Vec<IROperation<ID, MSG>>,
+    },
+    ChangesTruncated {
+        retain: u64,
+    },
+    /// The whole change log, as written when the log is compacted
+    ChangesRestored {
+        oldest: ChangeCursor,
+        views: Vec<(u64, KeptChanges<ID, MSG>)>,
+        logged: Vec<(ID, OperationSequence)>,
+    },
+    CurrentView {
+        view: View<ID>,
+    },
+}
+
+impl<ID: NodeID + Codec, MSG: IRMessage + Codec> Codec for Entry<ID, MSG> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Entry::Record { view, operation } => {
+                out.push(0);
+                view.encode(out);
+                operation.encode(out);
+            }
+            Entry::PeerOperation {
+                node,
+                view,
+                operation,
+            } => {
+                out.push(1);
+                node.encode(out);
+                view.encode(out);
+                operation.encode(out);
+            }
+            Entry::PeerComplete { node, view } => {
+                out.push(2);
+                node.encode(out);
+                view.encode(out);
+            }
+            Entry::MainOperation { view, operation } => {
+                out.push(3);
+                view.encode(out);
+                operation.encode(out);
+            }
+            Entry::Undecided {
+                view,
+                node,
+                operation,
+            } => {
+                out.push(4);
+                view.encode(out);
+                node.encode(out);
+                operation.encode(out);
+            }
+            Entry::ChangesAppended { view, operations } => {
+                out.push(5);
+                view.encode(out);
+                operations.encode(out);
+            }
+            Entry::ChangesTruncated { retain } => {
+                out.push(6);
+                retain.encode(out);
+            }
+            Entry::ChangesRestored {
+                oldest,
+                views,
+                logged,
+            } => {
+                out.push(7);
+                oldest.encode(out);
+                views.encode(out);
+                logged.encode(out);
+            }
+            Entry::CurrentView { view } => {
+                out.push(8);
+                view.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(Entry::Record {
+                view: View::decode(input)?,
+                operation: IROperation::decode(input)?,
+            }),
+            1 => Ok(Entry::PeerOperation {
+                node: ID::decode(input)?,
+                view: View::decode(input)?,
+                operation: IROperation::decode(input)?,
+            }),
+            2 => Ok(Entry::PeerComplete {
+                node: ID::decode(input)?,
+                view: View::decode(input)?,
+            }),
+            3 => Ok(Entry::MainOperation {
+                view: View::decode(input)?,
+                operation: IROperation::decode(input)?,
+            }),
+            4 => Ok(Entry::Undecided {
+                view: View::decode(input)?,
+                node: ID::decode(input)?,
+                operation: IROperation::decode(input)?,
+            }),
+            5 => Ok(Entry::ChangesAppended {
+                view: u64::decode(input)?,
+                operations: Vec::decode(input)?,
+            }),
+            6 => Ok(Entry::ChangesTruncated {
+                retain: u64::decode(input)?,
+            }),
+            7 => Ok(Entry::ChangesRestored {
+                oldest: ChangeCursor::decode(input)?,
+                views: Vec::decode(input)?,
+                logged: Vec::decode(input)?,
+            }),
+            8 => Ok(Entry::CurrentView {
+                view: View::decode(input)?,
+            }),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+/// The key of an operation in a record
+fn key<ID: NodeID, MSG: IRMessage>(operation: &IROperation<ID, MSG>) -> (ID, OperationSequence) {
+    (operation.client().clone(), *operation.sequence())
+}
+
+impl<ID: NodeID, MSG: IRMessage> State<ID, MSG> {
+    fn new(members: Vec<ID>) -> Self {
+        State {
+            current_view: View {
+                view: 0,
+                members,
+                learners: vec![],
+                state: ViewState::Normal,
+            },
+            view_recorded: false,
+            records: BTreeMap::new(),
+            peer_records: BTreeMap::new(),
+            complete_peers: BTreeMap::new(),
+            main_records: BTreeMap::new(),
+            undecided_records: BTreeMap::new(),
+            changes: Changes {
+                views: BTreeMap::new(),
+                logged: BTreeSet::new(),
+                oldest: ChangeCursor {
+                    view: 0,
+                    position: 0,
+                },
+                kept: 0,
+            },
+        }
+    }
+
+    /// Apply a change, returning the number of operations appended to the change log
+    /// Replaying the entries of a log in order rebuilds the state they were written from.
+    fn apply(&mut self, entry: Entry<ID, MSG>) -> u64 {
+        match entry {
+            Entry::Record { view, operation } => {
+                let key = key(&operation);
+                // A retried proposal of an operation that is already finalized leaves it
+                // finalized
+                let finalized = self
+                    .records
+                    .get(&key)
+                    .is_some_and(|(_view, existing)| existing.finalized());
+                if !finalized || operation.finalized() {
+                    self.records.insert(key, (view, operation));
+                }
+            }
+            Entry::PeerOperation {
+                node,
+                view,
+                operation,
+            } => {
+                let record = self.peer_records.entry((view, node)).or_default();
+                // Finalized operations are always recorded, proposals only if they are new
+                if operation.finalized() || !record.contains_key(&key(&operation)) {
+                    record.insert(key(&operation), operation);
+                }
+            }
+            Entry::PeerComplete { node, view } => {
+                let peers = self.complete_peers.entry(view).or_default();
+                if !peers.contains(&node) {
+                    peers.push(node);
+                }
+            }
+            Entry::MainOperation { view, operation } => {
+                self.main_records
+                    .entry(view)
+                    .or_default()
+                    .insert(key(&operation), operation);
+            }
+            Entry::Undecided {
+                view,
+                node,
+                operation,
+            } => {
+                // Duplicate writes are a noop, as a node reports one result
+                self.undecided_records
+                    .entry(view)
+                    .or_default()
+                    .entry(key(&operation))
+                    .or_default()
+                    .entry(node)
+                    .or_insert_with(|| operation.message().clone());
+            }
+            Entry::ChangesAppended { view, operations } => {
+                let mut appended = 0;
+                for operation in operations {
+                    if self.changes.logged.insert(key(&operation)) {
+                        self.changes
+                            .views
+                            .entry(view)
+                            .or_default()
+                            .1
+                            .push_back(operation);
+                        appended += 1;
+                    }
+                }
+                self.changes.kept += appended;
+                return appended;
+            }
+            Entry::ChangesTruncated { retain } => self.truncate_changes(retain),
+            Entry::ChangesRestored {
+                oldest,
+                views,
+                logged,
+            } => {
+                let views: BTreeMap<u64, (u64, VecDeque<_>)> = views
+                    .into_iter()
+                    .map(|(view, (first, operations))| (view, (first, operations.into())))
+                    .collect();
+                self.changes = Changes {
+                    kept: views
+                        .values()
+                        .map(|(_first, operations)| operations.len() as u64)
+                        .sum(),
+                    views,
+                    logged: logged.into_iter().collect(),
+                    oldest,
+                };
+            }
+            Entry::CurrentView { view } => {
+                self.current_view = view;
+                self.view_recorded = true;
+            }
+        }
+        0
+    }
+
+    fn truncate_changes(&mut self, retain: u64) {
+        let changes = &mut self.changes;
+        while changes.kept > retain {
+            let Some(mut oldest) = changes.views.first_entry() else {
+                break;
+            };
+            let view = *oldest.key();
+            let (first, operations) = oldest.get_mut();
+            operations.pop_front();
+            *first += 1;
+            let next = ChangeCursor {
+                view,
+                position: *first,
+            };
+            if operations.is_empty() {
+                oldest.remove();
+            }
+            changes.oldest = next;
+            changes.kept -= 1;
+        }
+    }
+
+    fn read_change(&self, from: Option<ChangeCursor>) -> ReadChange<ID, MSG> {
+        let changes = &self.changes;
+        let from = from.unwrap_or(changes.oldest);
+        if from < changes.oldest {
+            return Err(ChangeLogError::CursorTooOld {
+                oldest: changes.oldest,
+            });
+        }
+        Ok(changes
+            .views
+            .range(from.view..)
+            .find_map(|(view, (first, operations))| {
+                let position = if *view == from.view {
+                    from.position.max(*first)
+                } else {
+                    *first
+                };
+                let operation = operations.get((position - first) as usize)?;
+                Some((
+                    ChangeCursor {
+                        view: *view,
+                        position,
+                    },
+                    operation.clone(),
+                ))
+            }))
+    }
+
+    /// The entries that rebuild this state, leaving out the view change records of views
+    /// before the current one
+    fn snapshot(&self) -> Vec<Entry<ID, MSG>> {
+        let current = self.current_view.view;
+        let mut entries = Vec::new();
+        if self.view_recorded {
+            entries.push(Entry::CurrentView {
+                view: self.current_view.clone(),
+            });
+        }
+        entries.extend(
+            self.records
+                .values()
+                .map(|(view, operation)| Entry::Record {
+                    view: view.clone(),
+                    operation: operation.clone(),
+                }),
+        );
+        for ((view, node), record) in &self.peer_records {
+            if view.view < current {
+                continue;
+            }
+            entries.extend(record.values().map(|operation| Entry::PeerOperation {
+                node: node.clone(),
+                view: view.clone(),
+                operation: operation.clone(),
+            }));
+        }
+        for (view, peers) in &self.complete_peers {
+            if view.view < current {
+                continue;
+            }
+            entries.extend(peers.iter().map(|node| Entry::PeerComplete {
+                node: node.clone(),
+                view: view.clone(),
+            }));
+        }
+        for (view, record) in &self.main_records {
+            if view.view < current {
+                continue;
+            }
+            entries.extend(record.values().map(|operation| Entry::MainOperation {
+                view: view.clone(),
+                operation: operation.clone(),
+            }));
+        }
+        for (view, undecided) in &self.undecided_records {
+            if view.view < current {
+                continue;
+            }
+            for ((client, sequence), results) in undecided {
+                entries.extend(results.iter().map(|(node, message)| Entry::Undecided {
+                    view: view.clone(),
+                    node: node.clone(),
+                    operation: IROperation::ConsistentPropose {
+                        client: client.clone(),
+                        sequence: *sequence,
+                        message: message.clone(),
+                    },
+                }));
+            }
+        }
+        entries.push(Entry::ChangesRestored {
+            oldest: self.changes.oldest,
+            views: self
+                .changes
+                .views
+                .iter()
+                .map(|(view, (first, operations))| {
+                    (*view, (*first, operations.iter().cloned().collect()))
+                })
+                .collect(),
+            logged: self.changes.logged.iter().cloned().collect(),
+        });
+        entries
+    }
+}
+
+/// Append entries to a log file, each prefixed with its length and keyed by its group, and
+/// sync them to disk
+fn write_entries<ID: NodeID + Codec, MSG: IRMessage + Codec>(
+    file: &mut File,
+    entries: &[(GroupKey, Entry<ID, MSG>)],
+) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    let mut entry = Vec::new();
+    for written in entries {
+        entry.clear();
+        written.encode(&mut entry);
+        (entry.len() as u64).encode(&mut bytes);
+        bytes.extend_from_slice(&entry);
+    }
+    file.write_all(&bytes)?;
+    file.sync_data()
+}
+
+/// Read the entries of a log
+/// An entry cut short at the end of the log was being written when the node stopped, so it
+/// is ignored; an entry that is complete but cannot be decoded is an error.
+fn read_entries<ID: NodeID + Codec, MSG: IRMessage + Codec>(
+    bytes: &[u8],
+) -> io::Result<Vec<(GroupKey, Entry<ID, MSG>)>> {
+    let mut input = bytes;
+    let mut entries = Vec::new();
+    while !input.is_empty() {
+        let Ok(len) = u64::decode(&mut input) else {
+            break;
+        };
+        let Ok(mut entry) = take(&mut input, len as usize) else {
+            break;
+        };
+        let decoded = <(GroupKey, Entry<ID, MSG>)>::decode(&mut entry)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        entries.push(decoded);
+    }
+    Ok(entries)
+}
+
+impl<ID: NodeID + Codec, MSG: IRMessage + Codec> LogStorage<ID, MSG> {
+    /// Open the log at `path`, creating it if it does not exist
+    /// Until a view is started, the node is in view 0 of `members`.
+    pub fn open<P: AsRef<Path>>(path: P, members: Vec<ID>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut bytes = Vec::new();
+        match File::open(path) {
+            Ok(mut file) => {
+                file.read_to_end(&mut bytes)?;
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        let mut groups: BTreeMap<GroupKey, State<ID, MSG>> = BTreeMap::new();
+        for (group, entry) in read_entries(&bytes)? {
+            groups
+                .entry(group)
+                .or_insert_with(|| State::new(members.clone()))
+                .apply(entry);
+        }
+        let snapshot: Vec<_> = groups
+            .iter()
+            .flat_map(|(group, state)| {
+                state
+                    .snapshot()
+                    .into_iter()
+                    .map(move |entry| (group.clone(), entry))
+            })
+            .collect();
+
+        // Compact into a new file, and only replace the log once it is on disk
+        let mut compacted_path = PathBuf::from(path);
+        compacted_path.as_mut_os_string().push(".compacting");
+        let mut compacted = File::create(&compacted_path)?;
+        write_entries(&mut compacted, &snapshot)?;
+        std::fs::rename(&compacted_path, path)?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(LogStorage {
+            log: Arc::new(Mutex::new(Log {
+                file,
+                members,
+                groups,
+            })),
+            group: None,
+        })
+    }
+
+    /// Write a change of this handle's group to the log and apply it
+    fn write(&self, entry: Entry<ID, MSG>) -> u64 {
+        let mut log = self.log.lock().unwrap();
+        let entries = [(self.group.clone(), entry)];
+        write_entries(&mut log.file, &entries).expect("writing the log");
+        let [(group, entry)] = entries;
+        log.state(&group).apply(entry)
+    }
+
+    /// Read the state of this handle's group
+    fn read<T>(&self, read: impl FnOnce(&State<ID, MSG>) -> T) -> T {
+        read(self.log.lock().unwrap().state(&self.group))
+    }
+
+    fn record(&self, view: View<ID>, operation: IROperation<ID, MSG>) {
+        self.write(Entry::Record { view, operation });
+    }
+}
+
+impl<ID: NodeID + Codec, MSG: IRMessage + Codec> StorageShared<ID> for LogStorage<ID, MSG> {
+    fn recover_current_view(&self) -> Pin<Box<dyn Future<Output = View<ID>> + Send + 'static>> {
+        let view = self.read(|state| state.current_view.clone());
+        Box::pin(async move { view })
+    }
+}
+
+impl<ID: NodeID + Codec, MSG: IRMessage + Codec> IRStorage<ID, MSG> for LogStorage<ID, MSG> {
+    fn record_tentative_inconsistent(
+        &self,
+        client: ID,
+        operation: OperationSequence,
+        view: View<ID>,
+        message: MSG,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.record(
+            view,
+            IROperation::InconsistentPropose {
+                client,
+                sequence: operation,
+                message,
+            },
+        );
+        Box::pin(async {})
+    }
+
+    fn promote_finalized_inconsistent(
+        &self,
+        client: ID,
+        operation: OperationSequence,
+        view: View<ID>,
+        message: MSG,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.record(
+            view,
+            IROperation::InconsistentFinalize {
+                client,
+                sequence: operation,
+                message,
+            },
+        );
+        Box::pin(async {})
+    }
+
+    fn record_tentative_consistent(
+        &self,
+        client: ID,
+        operation: OperationSequence,
+        view: View<ID>,
+        message: MSG,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.record(
+            view,
+            IROperation::ConsistentPropose {
+                client,
+                sequence: operation,
+                message,
+            },
+        );
+        Box::pin(async {})
+    }
+
+    fn promote_finalized_consistent(
+        &self,
+        client: ID,
+        operation: OperationSequence,
+        view: View<ID>,
+        message: MSG,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.record(
+            view,
+            IROperation::ConsistentFinalize {
+                client,
+                sequence: operation,
+                message,
+            },
+        );
+        Box::pin(async {})
+    }
+
+    fn add_peer_view_change_operation(
+        &self,
+        node_id: ID,
+        view: View<ID>,
+        operation: IROperation<ID, MSG>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.write(Entry::PeerOperation {
+            node: node_id,
+            view,
+            operation,
+        });
+        Box::pin(async {})
+    }
+
+    fn complete_peer_view_change_record(
+        &self,
+        node_id: ID,
+        view: View<ID>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.write(Entry::PeerComplete {
+            node: node_id,
+            view,
+        });
+        Box::pin(async {})
+    }
+
+    fn get_peers_with_full_records(
+        &self,
+        view: View<ID>,
+    ) -> Pin<Box<dyn Future<Output = Vec<ID>> + Send + 'static>> {
+        let peers = self.read(|state| state.complete_peers.get(&view).cloned().unwrap_or_default());
+        Box::pin(async move { peers })
+    }
+
+    fn get_view_record_operations(
+        &self,
+        node: ID,
+        view: View<ID>,
+    ) -> impl Stream<Item = IROperation<ID, MSG>> + Send {
+        let operations: Vec<_> = self.read(|state| {
+            state
+                .peer_records
+                .get(&(view, node))
+                .map(|record| record.values().cloned().collect())
+                .unwrap_or_default()
+        });
+        stream::iter(operations)
+    }
+
+    fn get_main_or_local_operation(
+        &self,
+        view: View<ID>,
+        client: ID,
+        operation_sequence: OperationSequence,
+    ) -> Pin<Box<dyn Future<Output = Option<IROperation<ID, MSG>>> + Send>> {
+        let key = (client, operation_sequence);
+        let found = self.read(|state| {
+            state
+                .main_records
+                .get(&view)
+                .and_then(|main| main.get(&key))
+                .or_else(|| state.records.get(&key).map(|(_view, operation)| operation))
+                .cloned()
+        });
+        Box::pin(async move { found })
+    }
+
+    fn record_main_operation(
+        &self,
+        view: View<ID>,
+        operation: IROperation<ID, MSG>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.write(Entry::MainOperation { view, operation });
+        Box::pin(async {})
+    }
+
+    fn record_main_operation_add_undecided(
+        &self,
+        view: View<ID>,
+        node: ID,
+        operation: IROperation<ID, MSG>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.write(Entry::Undecided {
+            view,
+            node,
+            operation,
+        });
+        Box::pin(async {})
+    }
+
+    fn get_main_record_operations(
+        &self,
+        view: View<ID>,
+    ) -> impl Stream<Item = IROperation<ID, MSG>> + Send {
+        let operations: Vec<_> = self.read(|state| {
+            state
+                .main_records
+                .get(&view)
+                .map(|record| record.values().cloned().collect())
+                .unwrap_or_default()
+        });
+        stream::iter(operations)
+    }
+
+    fn get_unresolved_record_operations(
+        &self,
+        view: View<ID>,
+    ) -> impl Stream<Item = Vec<(ID, IROperation<ID, MSG>)>> + Send {
+        let unresolved: Vec<Vec<_>> = self.read(|state| {
+            let main = state.main_records.get(&view);
+            state
+                .undecided_records
+                .get(&view)
+                .into_iter()
+                .flatten()
+                .filter(|(key, _results)| {
+                    !main
+                        .and_then(|main| main.get(*key))
+                        .is_some_and(IROperation::finalized)
+                })
+                .map(|((client, sequence), results)| {
+                    results
+                        .iter()
+                        .map(|(node, message)| {
+                            let operation = IROperation::ConsistentPropose {
+                                client: client.clone(),
+                                sequence: *sequence,
+                                message: message.clone(),
+                            };
+                            (node.clone(), operation)
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+        stream::iter(unresolved)
+    }
+
+    fn append_changes(
+        &self,
+        view: u64,
+        operations: Vec<IROperation<ID, MSG>>,
+    ) -> Pin<Box<dyn Future<Output = u64> + Send + 'static>> {
+        let appended = self.write(Entry::ChangesAppended { view, operations });
+        Box::pin(async move { appended })
+    }
+
+    fn read_change(
+        &self,
+        from: Option<ChangeCursor>,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Result<Option<(ChangeCursor, IROperation<ID, MSG>)>, ChangeLogError>,
+                > + Send
+                + 'static,
+        >,
+    > {
+        let change = self.read(|state| state.read_change(from));
+        Box::pin(async move { change })
+    }
+
+    fn truncate_changes(&self, retain: u64) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        if self.read(|state| state.changes.kept > retain) {
+            self.write(Entry::ChangesTruncated { retain });
+        }
+        Box::pin(async {})
+    }
+
+    fn record_current_view(
+        &self,
+        view: View<ID>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.write(Entry::CurrentView { view });
+        Box::pin(async {})
+    }
+
+    fn is_fresh(&self) -> Pin<Box<dyn Future<Output = bool> + Send + 'static>> {
+        let fresh = self.read(|state| {
+            !state.view_recorded
+                && state.records.is_empty()
+                && state.peer_records.is_empty()
+                && state.main_records.is_empty()
+        });
+        Box::pin(async move { fresh })
+    }
+}
+
+impl<G, ID, MSG> GroupStorage<G, ID, MSG> for LogStorage<ID, MSG>
+where
+    G: GroupId + Codec,
+    ID: NodeID + Codec,
+    MSG: IRMessage + Codec,
+{
+    type Storage = LogStorage<ID, MSG>;
+
+    fn group(&self, group: &G) -> LogStorage<ID, MSG> {
+        let mut key = Vec::new();
+        group.encode(&mut key);
+        LogStorage {
+            log: self.log.clone(),
+            group: Some(key),
+        }
+    }
+}
@@ -1,13 +1,87 @@
+mod batching;
+mod client_storage;
+pub mod codec;
+pub mod layers;
+mod log_storage;
+#[cfg(feature = "tcp")]
+pub mod tcp;
+#[cfg(test)]
+mod test;
 #[cfg(any(test, feature = "test"))]
 pub mod test_utils;
 
-use crate::server::{IROperation, IRServerError, View};
-use crate::types::{AsyncIterator, IRMessage, NodeID, OperationSequence};
+pub use batching::{BatchingConfig, BatchingNetwork};
+pub use client_storage::MembersClientStorage;
+pub use log_storage::LogStorage;
+
+use crate::server::{ChangeCursor, ChangeLogError, IROperation, IRServerError, View};
+use crate::types::{IRMessage, NodeID, OperationSequence};
+use futures::future::join_all;
+use futures::Stream;
 use std::future::Future;
 use std::pin::Pin;
 
+/// A node's response to a request
+type NodeResponse<I, M> = (I, Result<(M, View<I>), IRNetworkError<I>>);
+
+/// A node's response to a batch of requests, with a result for each
+type NodeBatchResponse<I, M> = (I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>);
+
+pub(crate) type NodeResponses<I, M> =
+    Pin<Box<dyn Future<Output = Vec<NodeResponse<I, M>>> + Send + 'static>>;
+
+type NodeBatchResponses<I, M> =
+    Pin<Box<dyn Future<Output = Vec<NodeBatchResponse<I, M>>> + Send + 'static>>;
+
+type OperationLookup<ID, MSG> = Pin<Box<dyn Future<Output = Option<IROperation<ID, MSG>>> + Send>>;
+
+/// The first change at or after a cursor, with its own cursor
+pub(crate) type ReadChange<ID, MSG> =
+    Result<Option<(ChangeCursor, IROperation<ID, MSG>)>, ChangeLogError>;
+
+/// A request that can carry finalizes held back for a node, see `IRNetwork::send_with_finalizes`
+/// Single operations are requests with one message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CarrierRequest<I: NodeID, M: IRMessage> {
+    ProposeInconsistent {
+        client: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+        highest_observed_view: Option<View<I>>,
+    },
+    ProposeConsistent {
+        client: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+    },
+    FinalizeConsistent {
+        client: I,
+        sequence: OperationSequence,
+        message: M,
+    },
+}
+
+impl<I: NodeID, M: IRMessage> CarrierRequest<I, M> {
+    pub fn client(&self) -> &I {
+        match self {
+            CarrierRequest::ProposeInconsistent { client, .. }
+            | CarrierRequest::ProposeConsistent { client, .. }
+            | CarrierRequest::FinalizeConsistent { client, .. } => client,
+        }
+    }
+
+    /// The sequence number of the request's first operation
+    pub fn first_sequence(&self) -> OperationSequence {
+        match self {
+            CarrierRequest::ProposeInconsistent { first_sequence, .. }
+            | CarrierRequest::ProposeConsistent { first_sequence, .. } => *first_sequence,
+            CarrierRequest::FinalizeConsistent { sequence, .. } => *sequence,
+        }
+    }
+}
+
 /// Tracks membership, ID to IP address mapping, and messaging
-pub trait IRNetwork<I: NodeID, M: IRMessage> {
+pub trait IRNetwork<I: NodeID, M: IRMessage>: Send + Sync {
     /// Used by clients to make an inconsistent request to a specific node
     fn propose_inconsistent(
         &self,
@@ -16,7 +90,7 @@ pub trait IRNetwork<I: NodeID, M: IRMessage> {
         sequence: OperationSequence,
         message: M,
         highest_observed_view: Option<View<I>>,
-    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + 'static>>;
+    ) -> NodeResponses<I, M>;
 
     /// Used by clients to make a consistent request to a specific node
     fn propose_consistent(
@@ -25,7 +99,7 @@ pub trait IRNetwork<I: NodeID, M: IRMessage> {
         client_id: I,
         sequence: OperationSequence,
         message: M,
-    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + 'static>>;
+    ) -> NodeResponses<I, M>;
 
     /// Send a finalize message to a node
     /// This does not need to be immediate, for example it can be buffered and sent
@@ -36,7 +110,7 @@ pub trait IRNetwork<I: NodeID, M: IRMessage> {
         client_id: I,
         sequence: OperationSequence,
         message: M,
-    ) -> Pin<Box<dyn Future<Output = ()> + 'static>>;
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
     /// Send a finalize message to a node
     /// This does not need to be immediate, for example it can be buffered and sent
@@ -52,7 +126,7 @@ pub trait IRNetwork<I: NodeID, M: IRMessage> {
         client_id: I,
         sequence: OperationSequence,
         message: M,
-    ) -> Pin<Box<dyn Future<Output = ()> + 'static>>;
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
     /// Send a finalize message to a node
     /// This *DOES* need to be immediate, though can be batched.
@@ -62,53 +136,220 @@ pub trait IRNetwork<I: NodeID, M: IRMessage> {
         client_id: I,
         sequence: OperationSequence,
         message: M,
-    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + 'static>>;
+    ) -> NodeResponses<I, M>;
+
+    /// Used by clients to propose several inconsistent operations with one message per node
+    /// The operations take consecutive sequence numbers starting at `first_sequence`, and each
+    /// node responds with the results in the same order.
+    ///
+    /// Transports should override this to send the batch as a single message, the default
+    /// proposes each operation on its own.
+    fn propose_inconsistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> NodeBatchResponses<I, M> {
+        let proposals: Vec<_> = (first_sequence..)
+            .zip(messages)
+            .map(|(sequence, message)| {
+                self.propose_inconsistent(
+                    destinations,
+                    client_id.clone(),
+                    sequence,
+                    message,
+                    highest_observed_view.clone(),
+                )
+            })
+            .collect();
+        let destinations = destinations.to_vec();
+        Box::pin(async move { collect_batch_responses(destinations, join_all(proposals).await) })
+    }
+
+    /// Used by clients to propose several consistent operations with one message per node
+    /// The operations take consecutive sequence numbers starting at `first_sequence`, and each
+    /// node responds with the results in the same order.
+    ///
+    /// Transports should override this to send the batch as a single message, the default
+    /// proposes each operation on its own.
+    fn propose_consistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+    ) -> NodeBatchResponses<I, M> {
+        let proposals: Vec<_> = (first_sequence..)
+            .zip(messages)
+            .map(|(sequence, message)| {
+                self.propose_consistent(destinations, client_id.clone(), sequence, message)
+            })
+            .collect();
+        let destinations = destinations.to_vec();
+        Box::pin(async move { collect_batch_responses(destinations, join_all(proposals).await) })
+    }
+
+    /// Send several finalize messages to a node at once
+    /// The operations are `InconsistentFinalize` or `ConsistentFinalize` and are unpacked by
+    /// `InconsistentReplicationServer::finalize_batch` on the receiving side.
+    ///
+    /// Transports should override this to send the batch as a single message, the default
+    /// sends each finalize on its own.
+    fn finalize_batch(
+        &self,
+        destinations: &[I],
+        operations: Vec<IROperation<I, M>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let finalizes: Vec<_> = operations
+            .into_iter()
+            .filter_map(|operation| match operation {
+                IROperation::InconsistentFinalize {
+                    client,
+                    sequence,
+                    message,
+                } => {
+                    Some(self.async_finalize_inconsistent(destinations, client, sequence, message))
+                }
+                IROperation::ConsistentFinalize {
+                    client,
+                    sequence,
+                    message,
+                } => Some(self.async_finalize_consistent(destinations, client, sequence, message)),
+                IROperation::InconsistentPropose { .. } | IROperation::ConsistentPropose { .. } => {
+                    None
+                }
+            })
+            .collect();
+        Box::pin(async move {
+            join_all(finalizes).await;
+        })
+    }
+
+    /// Send a request together with finalizes that were held back for the destinations, such
+    /// as by `BatchingNetwork`, so that the finalizes need no message of their own
+    /// Each node applies the finalizes before it handles the request, with
+    /// `InconsistentReplicationServer::handle_with_finalizes`, and responds with the results of
+    /// the request in order.
+    ///
+    /// Transports should override this to send both as a single message, the default sends
+    /// the finalizes as a batch of their own alongside the request.
+    fn send_with_finalizes(
+        &self,
+        destinations: &[I],
+        finalizes: Vec<IROperation<I, M>>,
+        request: CarrierRequest<I, M>,
+    ) -> NodeBatchResponses<I, M> {
+        let finalized = self.finalize_batch(destinations, finalizes);
+        let responses = match request {
+            CarrierRequest::ProposeInconsistent {
+                client,
+                first_sequence,
+                messages,
+                highest_observed_view,
+            } => self.propose_inconsistent_batch(
+                destinations,
+                client,
+                first_sequence,
+                messages,
+                highest_observed_view,
+            ),
+            CarrierRequest::ProposeConsistent {
+                client,
+                first_sequence,
+                messages,
+            } => self.propose_consistent_batch(destinations, client, first_sequence, messages),
+            CarrierRequest::FinalizeConsistent {
+                client,
+                sequence,
+                message,
+            } => {
+                let responses =
+                    self.sync_finalize_consistent(destinations, client, sequence, message);
+                let destinations = destinations.to_vec();
+                Box::pin(
+                    async move { collect_batch_responses(destinations, vec![responses.await]) },
+                )
+            }
+        };
+        Box::pin(async move { futures::join!(finalized, responses).1 })
+    }
 }
 
-pub trait StorageShared<ID: NodeID> {
+/// Regroup the per-operation responses of a batch into per-node responses
+/// A node's batch fails if any of its operations failed
+fn collect_batch_responses<I: NodeID, M: IRMessage>(
+    destinations: Vec<I>,
+    operations: Vec<Vec<NodeResponse<I, M>>>,
+) -> Vec<NodeBatchResponse<I, M>> {
+    let mut batches: Vec<_> = destinations
+        .into_iter()
+        .map(|destination| (destination, Ok(Vec::new())))
+        .collect();
+    for responses in operations {
+        for (node, response) in responses {
+            let batch = batches
+                .iter_mut()
+                .find(|(destination, _batch)| *destination == node)
+                .map(|(_destination, batch)| batch);
+            match (batch, response) {
+                (Some(Ok(results)), Ok(result)) => results.push(result),
+                (Some(batch @ Ok(_)), Err(e)) => *batch = Err(e),
+                (Some(Err(_)), _) | (None, _) => {}
+            }
+        }
+    }
+    batches
+}
+
+pub trait StorageShared<ID: NodeID>: Send + Sync {
     /// Used by clients and servers to recover the current view, thus obtaining members
-    fn recover_current_view(&self) -> Pin<Box<dyn Future<Output = View<ID>> + 'static>>;
+    fn recover_current_view(&self) -> Pin<Box<dyn Future<Output = View<ID>> + Send + 'static>>;
 }
 
 /// Provides access to a storage log for views and persistence
+/// Execution of operations is not the responsibility of storage, see `IRApplication`
 pub trait IRStorage<ID: NodeID, MSG: IRMessage>: StorageShared<ID> + Clone + 'static {
     /// Record a message as tentative for a client and operation number
     /// The message must be recorded as tentative even if the operation is rejected
     /// This is to resolve quorums
-    fn record_tentative_inconsistent_and_evaluate(
+    fn record_tentative_inconsistent(
         &self,
         client: ID,
         operation: OperationSequence,
         view: View<ID>,
         message: MSG,
-    ) -> Pin<Box<dyn Future<Output = MSG> + 'static>>;
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
-    /// Promote a tentative operation to finalized and execute it
-    fn promote_finalized_and_exec_inconsistent(
+    /// Promote a tentative operation to finalized
+    fn promote_finalized_inconsistent(
         &self,
         client: ID,
         operation: OperationSequence,
         view: View<ID>,
         message: MSG,
-    ) -> Pin<Box<dyn Future<Output = ()> + 'static>>;
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
-    /// Consistent operations are executed when they are proposed
-    fn record_tentative_and_exec_consistent(
+    /// Record the result of a consensus operation as tentative
+    /// The message is the result returned by the application
+    fn record_tentative_consistent(
         &self,
         client: ID,
         operation: OperationSequence,
         view: View<ID>,
         message: MSG,
-    ) -> Pin<Box<dyn Future<Output = MSG> + 'static>>;
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
-    /// Consistent operations may have their result changed and must be reconciled
-    fn promote_finalized_and_reconcile_consistent(
+    /// Promote a consensus operation to finalized with the result decided by the client
+    /// The result may be different to the tentative one
+    fn promote_finalized_consistent(
         &self,
         client: ID,
         operation: OperationSequence,
         view: View<ID>,
         message: MSG,
-    ) -> Pin<Box<dyn Future<Output = MSG> + 'static>>;
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
     /// Add a received operation from a peer node view to that peers record before merging
     fn add_peer_view_change_operation(
@@ -116,20 +357,27 @@ pub trait IRStorage<ID: NodeID, MSG: IRMessage>: StorageShared<ID> + Clone + 'st
         node_id: ID,
         view: View<ID>,
         operation: IROperation<ID, MSG>,
-    ) -> Pin<Box<dyn Future<Output = ()> + 'static>>;
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+    /// Mark that a peer has finished sending its record for the view change
+    fn complete_peer_view_change_record(
+        &self,
+        node_id: ID,
+        view: View<ID>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
     /// Receive the peer node list whos full records have been received
     fn get_peers_with_full_records(
         &self,
         view: View<ID>,
-    ) -> Pin<Box<dyn Future<Output = Vec<ID>> + 'static>>;
+    ) -> Pin<Box<dyn Future<Output = Vec<ID>> + Send + 'static>>;
 
-    /// Retrieve the list of operations that have been finalised (i.e. fully sent)
+    /// Stream the operations that have been finalised (i.e. fully sent)
     fn get_view_record_operations(
         &self,
         node: ID,
         view: View<ID>,
-    ) -> impl AsyncIterator<Item = IROperation<ID, MSG>>;
+    ) -> impl Stream<Item = IROperation<ID, MSG>> + Send;
 
     /// Retrieve the main record or the local record
     fn get_main_or_local_operation(
@@ -137,28 +385,66 @@ pub trait IRStorage<ID: NodeID, MSG: IRMessage>: StorageShared<ID> + Clone + 'st
         view: View<ID>,
         client: ID,
         operation_sequence: OperationSequence,
-    ) -> Pin<Box<dyn Future<Output = Option<IROperation<ID, MSG>>>>>;
+    ) -> OperationLookup<ID, MSG>;
 
     /// Store a resolved record in the main record store, during merging
     fn record_main_operation(
         &self,
         view: View<ID>,
         operation: IROperation<ID, MSG>,
-    ) -> Pin<Box<dyn Future<Output = ()> + 'static>>;
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
     /// Store a **NOT** resolved record in the main record store, during merging
-    /// Duplicate writes must be handled gracefully (noop)
+    /// The result is the one `node` reported, so that the merge can tally results by weight.
+    /// A node reports one result per operation, so duplicate writes must be handled gracefully
+    /// (noop)
     fn record_main_operation_add_undecided(
         &self,
         view: View<ID>,
+        node: ID,
         operation: IROperation<ID, MSG>,
-    ) -> Pin<Box<dyn Future<Output = ()> + 'static>>;
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+    /// Stream the operations of the main (master) record for a view, once merged
+    fn get_main_record_operations(
+        &self,
+        view: View<ID>,
+    ) -> impl Stream<Item = IROperation<ID, MSG>> + Send;
 
-    /// Iterate over unresolved operations
+    /// Stream the unresolved operations, each with every reported version and its reporter
     fn get_unresolved_record_operations(
         &self,
         view: View<ID>,
-    ) -> Pin<Box<dyn Future<Output = Box<dyn AsyncIterator<Item = Vec<IROperation<ID, MSG>>>>>>>;
+    ) -> impl Stream<Item = Vec<(ID, IROperation<ID, MSG>)>> + Send;
+
+    /// Append finalized operations to the change log of a view, returning how many were new
+    /// Operations that are in the change log of any view, or have been truncated from it, are
+    /// skipped, so that each is logged once.
+    fn append_changes(
+        &self,
+        view: u64,
+        operations: Vec<IROperation<ID, MSG>>,
+    ) -> Pin<Box<dyn Future<Output = u64> + Send + 'static>>;
+
+    /// The first change at or after a cursor, moving on to later views at the end of one, or
+    /// the oldest change kept if there is no cursor. `None` if there are no such changes yet.
+    fn read_change(
+        &self,
+        from: Option<ChangeCursor>,
+    ) -> Pin<Box<dyn Future<Output = ReadChange<ID, MSG>> + Send + 'static>>;
+
+    /// Drop the oldest changes so that at most `retain` are kept
+    fn truncate_changes(&self, retain: u64) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+    /// Persist the view the node has started, which `recover_current_view` returns from then on
+    fn record_current_view(
+        &self,
+        view: View<ID>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+    /// Whether nothing has been stored: no operations and no view recorded
+    /// Bootstrapping refuses storage that is not fresh, as its node already belongs to a cluster.
+    fn is_fresh(&self) -> Pin<Box<dyn Future<Output = bool> + Send + 'static>>;
 }
 
 /// Provides access to persistence for the client
@@ -167,6 +453,8 @@ pub trait IRClientStorage<ID: NodeID, MSG: IRMessage>: StorageShared<ID> {}
 #[derive(Debug)]
 pub enum IRNetworkError<ID: NodeID> {
     NodeUnreachable(ID),
+    /// The node did not respond in time, see `layers::TimeoutLayer`
+    Timeout(ID),
     IRServerError(IRServerError<ID>),
 }
 
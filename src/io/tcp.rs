@@ -0,0 +1,703 @@
+//! An `IRNetwork` over TCP, and the listener that hands received calls to a replica.
+//!
+//! Every call is one request frame followed by one response frame on a connection, and
+//! connections are kept open and reused for later calls to the same node. A frame is the
+//! length of its payload as a little endian `u64`, then the payload encoded with `Codec`.
+//!
+//! ```ignore
+//! let network = TcpNetwork::new(addresses);
+//! let server = Arc::new(InconsistentReplicationServer::new(network.clone(), storage, app, id).await);
+//! tokio::spawn(tcp::serve(TcpListener::bind(addresses[&id]).await?, server));
+//! ```
+//!
+//! The network does not time out calls to nodes that accept them but never respond; wrap it in
+//! a `layers::TimeoutLayer` for that.
+
+use crate::codec::{Codec, DecodeError};
+use crate::io::{CarrierRequest, IRNetworkError, IRStorage};
+use crate::server::{IROperation, IRServerError, View};
+use crate::types::{IRMessage, NodeID, OperationSequence};
+use crate::{IRApplication, IRNetwork, InconsistentReplicationServer};
+use futures::future::join_all;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// The response of each node to a call
+type Responses<I, T> =
+    Pin<Box<dyn Future<Output = Vec<(I, Result<T, IRNetworkError<I>>)>> + Send + 'static>>;
+
+/// A node's reply to a call, which may be an error from the replica
+type Reply<I, T> = (I, Result<Result<T, IRServerError<I>>, IRNetworkError<I>>);
+
+/// Frames larger than this are refused, so that a corrupt length cannot exhaust memory
+const MAX_FRAME_LEN: u64 = 64 * 1024 * 1024;
+
+/// Sends calls to nodes over TCP, at the addresses it was configured with
+pub struct TcpNetwork<I: NodeID, M: IRMessage> {
+    addresses: Arc<BTreeMap<I, SocketAddr>>,
+    /// Open connections that are not in use, by node
+    idle: Arc<Mutex<BTreeMap<I, Vec<TcpStream>>>>,
+    _a: PhantomData<M>,
+}
+
+impl<I: NodeID, M: IRMessage> Clone for TcpNetwork<I, M> {
+    fn clone(&self) -> Self {
+        TcpNetwork {
+            addresses: self.addresses.clone(),
+            idle: self.idle.clone(),
+            _a: PhantomData,
+        }
+    }
+}
+
+/// A call, as sent to a node
+enum Request<I: NodeID, M: IRMessage> {
+    ProposeInconsistent {
+        client: I,
+        sequence: OperationSequence,
+        message: M,
+        highest_observed_view: Option<View<I>>,
+    },
+    ProposeConsistent {
+        client: I,
+        sequence: OperationSequence,
+        message: M,
+    },
+    FinalizeInconsistent {
+        client: I,
+        sequence: OperationSequence,
+        message: M,
+    },
+    FinalizeConsistent {
+        client: I,
+        sequence: OperationSequence,
+        message: M,
+    },
+    ProposeInconsistentBatch {
+        client: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+        highest_observed_view: Option<View<I>>,
+    },
+    ProposeConsistentBatch {
+        client: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+    },
+    FinalizeBatch {
+        operations: Vec<IROperation<I, M>>,
+    },
+    WithFinalizes {
+        finalizes: Vec<IROperation<I, M>>,
+        request: CarrierRequest<I, M>,
+    },
+}
+
+impl<I: NodeID + Codec, M: IRMessage + Codec> Codec for Request<I, M> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Request::ProposeInconsistent {
+                client,
+                sequence,
+                message,
+                highest_observed_view,
+            } => {
+                out.push(0);
+                client.encode(out);
+                sequence.encode(out);
+                message.encode(out);
+                highest_observed_view.encode(out);
+            }
+            Request::ProposeConsistent {
+                client,
+                sequence,
+                message,
+            } => {
+                out.push(1);
+                client.encode(out);
+                sequence.encode(out);
+                message.encode(out);
+            }
+            Request::FinalizeInconsistent {
+                client,
+                sequence,
+                message,
+            } => {
+                out.push(2);
+                client.encode(out);
+                sequence.encode(out);
+                message.encode(out);
+            }
+            Request::FinalizeConsistent {
+                client,
+                sequence,
+                message,
+            } => {
+                out.push(3);
+                client.encode(out);
+                sequence.encode(out);
+                message.encode(out);
+            }
+            Request::ProposeInconsistentBatch {
+                client,
+                first_sequence,
+                messages,
+                highest_observed_view,
+            } => {
+                out.push(4);
+                client.encode(out);
+                first_sequence.encode(out);
+                messages.encode(out);
+                highest_observed_view.encode(out);
+            }
+            Request::ProposeConsistentBatch {
+                client,
+                first_sequence,
+                messages,
+            } => {
+                out.push(5);
+                client.encode(out);
+                first_sequence.encode(out);
+                messages.encode(out);
+            }
+            Request::FinalizeBatch { operations } => {
+                out.push(6);
+                operations.encode(out);
+            }
+            Request::WithFinalizes { finalizes, request } => {
+                out.push(7);
+                finalizes.encode(out);
+                request.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(Request::ProposeInconsistent {
+                client: I::decode(input)?,
+                sequence: u64::decode(input)?,
+                message: M::decode(input)?,
+                highest_observed_view: Option::decode(input)?,
+            }),
+            1 => Ok(Request::ProposeConsistent {
+                client: I::decode(input)?,
+                sequence: u64::decode(input)?,
+                message: M::decode(input)?,
+            }),
+            2 => Ok(Request::FinalizeInconsistent {
+                client: I::decode(input)?,
+                sequence: u64::decode(input)?,
+                message: M::decode(input)?,
+            }),
+            3 => Ok(Request::FinalizeConsistent {
+                client: I::decode(input)?,
+                sequence: u64::decode(input)?,
+                message: M::decode(input)?,
+            }),
+            4 => Ok(Request::ProposeInconsistentBatch {
+                client: I::decode(input)?,
+                first_sequence: u64::decode(input)?,
+                messages: Vec::decode(input)?,
+                highest_observed_view: Option::decode(input)?,
+            }),
+            5 => Ok(Request::ProposeConsistentBatch {
+                client: I::decode(input)?,
+                first_sequence: u64::decode(input)?,
+                messages: Vec::decode(input)?,
+            }),
+            6 => Ok(Request::FinalizeBatch {
+                operations: Vec::decode(input)?,
+            }),
+            7 => Ok(Request::WithFinalizes {
+                finalizes: Vec::decode(input)?,
+                request: CarrierRequest::decode(input)?,
+            }),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+/// Encode a replica's result; internal errors are sent as their message
+fn encode_result<I: NodeID + Codec, T: Codec>(
+    result: &Result<T, IRServerError<I>>,
+    out: &mut Vec<u8>,
+) {
+    match result {
+        Ok(value) => {
+            out.push(0);
+            value.encode(out);
+        }
+        Err(IRServerError::Recovering(view)) => {
+            out.push(1);
+            view.encode(out);
+        }
+        Err(IRServerError::InternalError(error)) => {
+            out.push(2);
+            error.to_string().encode(out);
+        }
+    }
+}
+
+fn decode_result<I: NodeID + Codec, T: Codec>(
+    input: &mut &[u8],
+) -> Result<Result<T, IRServerError<I>>, DecodeError> {
+    match u8::decode(input)? {
+        0 => Ok(Ok(T::decode(input)?)),
+        1 => Ok(Err(IRServerError::Recovering(View::decode(input)?))),
+        2 => Ok(Err(IRServerError::InternalError(
+            String::decode(input)?.into(),
+        ))),
+        tag => Err(DecodeError::InvalidTag(tag)),
+    }
+}
+
+async fn write_frame(stream: &mut TcpStream, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 8);
+    (payload.len() as u64).encode(&mut frame);
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame).await
+}
+
+/// Read a frame, or `None` if the connection was closed between frames
+async fn read_frame(stream: &mut TcpStream) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 8];
+    match stream.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    let len = u64::from_le_bytes(len);
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {len} bytes is too large"),
+        ));
+    }
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+impl<I: NodeID + Codec, M: IRMessage + Codec> TcpNetwork<I, M> {
+    /// Nodes without an address are unreachable
+    pub fn new(addresses: BTreeMap<I, SocketAddr>) -> Self {
+        TcpNetwork {
+            addresses: Arc::new(addresses),
+            idle: Arc::new(Mutex::new(BTreeMap::new())),
+            _a: PhantomData,
+        }
+    }
+
+    /// Send a request to each destination, decoding each response with `decode`
+    fn call<T: Send + 'static>(
+        &self,
+        destinations: &[I],
+        request: Request<I, M>,
+        decode: fn(&mut &[u8]) -> Result<T, DecodeError>,
+    ) -> Responses<I, T> {
+        let mut payload = Vec::new();
+        request.encode(&mut payload);
+        let payload = Arc::new(payload);
+        let calls: Vec<_> = destinations
+            .iter()
+            .map(|destination| {
+                let network = self.clone();
+                let destination = destination.clone();
+                let payload = payload.clone();
+                async move {
+                    let response = network
+                        .exchange(&destination, &payload)
+                        .await
+                        .map_err(|_| IRNetworkError::NodeUnreachable(destination.clone()))
+                        .and_then(|response| {
+                            decode(&mut response.as_slice()).map_err(|error| {
+                                IRNetworkError::IRServerError(IRServerError::InternalError(
+                                    error.into(),
+                                ))
+                            })
+                        });
+                    (destination, response)
+                }
+            })
+            .collect();
+        Box::pin(join_all(calls))
+    }
+
+    /// Send a request frame and read the response frame, on an idle connection if there is
+    /// one. Replicas do not recognise a repeated request as the same operation, so a request
+    /// is only resent on a new connection when the node cannot have received it: the idle
+    /// connection was already closed, or the frame could not be written whole.
+    async fn exchange(&self, destination: &I, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let idle = self
+            .idle
+            .lock()
+            .unwrap()
+            .get_mut(destination)
+            .and_then(Vec::pop);
+        if let Some(mut stream) = idle.filter(Self::is_open) {
+            if write_frame(&mut stream, payload).await.is_ok() {
+                let response = Self::response(&mut stream).await?;
+                self.release(destination, stream);
+                return Ok(response);
+            }
+        }
+        let address = self
+            .addresses
+            .get(destination)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for the node"))?;
+        let mut stream = TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+        write_frame(&mut stream, payload).await?;
+        let response = Self::response(&mut stream).await?;
+        self.release(destination, stream);
+        Ok(response)
+    }
+
+    /// Whether an idle connection can still carry a request
+    /// Nodes only send frames in response, so anything readable means the node closed it.
+    fn is_open(stream: &TcpStream) -> bool {
+        matches!(
+            stream.try_read(&mut [0; 1]),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock
+        )
+    }
+
+    async fn response(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+        read_frame(stream).await?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed by the node",
+            )
+        })
+    }
+
+    fn release(&self, destination: &I, stream: TcpStream) {
+        self.idle
+            .lock()
+            .unwrap()
+            .entry(destination.clone())
+            .or_default()
+            .push(stream);
+    }
+}
+
+impl<I: NodeID + Codec, M: IRMessage + Codec> IRNetwork<I, M> for TcpNetwork<I, M> {
+    fn propose_inconsistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+        highest_observed_view: Option<View<I>>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + Send + 'static,
+        >,
+    > {
+        let request = Request::ProposeInconsistent {
+            client: client_id,
+            sequence,
+            message,
+            highest_observed_view,
+        };
+        let responses = self.call(destinations, request, decode_result);
+        Box::pin(async move { flatten(responses.await) })
+    }
+
+    fn propose_consistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + Send + 'static,
+        >,
+    > {
+        let request = Request::ProposeConsistent {
+            client: client_id,
+            sequence,
+            message,
+        };
+        let responses = self.call(destinations, request, decode_result);
+        Box::pin(async move { flatten(responses.await) })
+    }
+
+    fn async_finalize_inconsistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let request = Request::FinalizeInconsistent {
+            client: client_id,
+            sequence,
+            message,
+        };
+        let responses = self.call(destinations, request, decode_result::<I, (M, View<I>)>);
+        Box::pin(async move {
+            responses.await;
+        })
+    }
+
+    fn async_finalize_consistent(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let request = Request::FinalizeConsistent {
+            client: client_id,
+            sequence,
+            message,
+        };
+        let responses = self.call(destinations, request, decode_result::<I, (M, View<I>)>);
+        Box::pin(async move {
+            responses.await;
+        })
+    }
+
+    fn sync_finalize_consistent(
+        &self,
+        destination: &[I],
+        client_id: I,
+        sequence: OperationSequence,
+        message: M,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + Send + 'static,
+        >,
+    > {
+        let request = Request::FinalizeConsistent {
+            client: client_id,
+            sequence,
+            message,
+        };
+        let responses = self.call(destination, request, decode_result);
+        Box::pin(async move { flatten(responses.await) })
+    }
+
+    fn propose_inconsistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>)>>
+                + Send
+                + 'static,
+        >,
+    > {
+        let request = Request::ProposeInconsistentBatch {
+            client: client_id,
+            first_sequence,
+            messages,
+            highest_observed_view,
+        };
+        let responses = self.call(destinations, request, decode_result);
+        Box::pin(async move { flatten(responses.await) })
+    }
+
+    fn propose_consistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>)>>
+                + Send
+                + 'static,
+        >,
+    > {
+        let request = Request::ProposeConsistentBatch {
+            client: client_id,
+            first_sequence,
+            messages,
+        };
+        let responses = self.call(destinations, request, decode_result);
+        Box::pin(async move { flatten(responses.await) })
+    }
+
+    fn finalize_batch(
+        &self,
+        destinations: &[I],
+        operations: Vec<IROperation<I, M>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let request = Request::FinalizeBatch { operations };
+        let responses = self.call(destinations, request, |input| {
+            let len = u64::decode(input)?;
+            (0..len)
+                .map(|_| decode_result::<I, (M, View<I>)>(input))
+                .collect::<Result<Vec<_>, _>>()
+        });
+        Box::pin(async move {
+            responses.await;
+        })
+    }
+
+    fn send_with_finalizes(
+        &self,
+        destinations: &[I],
+        finalizes: Vec<IROperation<I, M>>,
+        request: CarrierRequest<I, M>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Vec<(I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>)>>
+                + Send
+                + 'static,
+        >,
+    > {
+        let request = Request::WithFinalizes { finalizes, request };
+        let responses = self.call(destinations, request, decode_result);
+        Box::pin(async move { flatten(responses.await) })
+    }
+}
+
+/// Merge the replica's error into the network's
+fn flatten<I: NodeID, T>(responses: Vec<Reply<I, T>>) -> Vec<(I, Result<T, IRNetworkError<I>>)> {
+    responses
+        .into_iter()
+        .map(|(node, response)| (node, response.and_then(|result| result.map_err(Into::into))))
+        .collect()
+}
+
+/// Accept connections and hand the calls received on them to `server`, until accepting fails
+/// Each connection is served by its own task, one call at a time.
+pub async fn serve<N, S, A, I, M>(
+    listener: TcpListener,
+    server: Arc<InconsistentReplicationServer<N, S, A, I, M>>,
+) -> io::Result<()>
+where
+    N: IRNetwork<I, M> + 'static,
+    S: IRStorage<I, M> + 'static,
+    A: IRApplication<I, M>,
+    I: NodeID + Codec,
+    M: IRMessage + Codec,
+{
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(error) = serve_connection(stream, server).await {
+                tracing::debug!(%peer, %error, "closing connection");
+            }
+        });
+    }
+}
+
+async fn serve_connection<N, S, A, I, M>(
+    mut stream: TcpStream,
+    server: Arc<InconsistentReplicationServer<N, S, A, I, M>>,
+) -> io::Result<()>
+where
+    N: IRNetwork<I, M> + 'static,
+    S: IRStorage<I, M> + 'static,
+    A: IRApplication<I, M>,
+    I: NodeID + Codec,
+    M: IRMessage + Codec,
+{
+    stream.set_nodelay(true)?;
+    while let Some(payload) = read_frame(&mut stream).await? {
+        let request = Request::<I, M>::decode(&mut payload.as_slice())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let mut response = Vec::new();
+        match request {
+            Request::ProposeInconsistent {
+                client,
+                sequence,
+                message,
+                highest_observed_view,
+            } => encode_result(
+                &server
+                    .propose_inconsistent(client, sequence, message, highest_observed_view)
+                    .await,
+                &mut response,
+            ),
+            Request::ProposeConsistent {
+                client,
+                sequence,
+                message,
+            } => encode_result(
+                &server
+                    .propose_consistent(client, sequence, message, None)
+                    .await,
+                &mut response,
+            ),
+            Request::FinalizeInconsistent {
+                client,
+                sequence,
+                message,
+            } => encode_result(
+                &server
+                    .finalize_inconsistent(client, sequence, message, None)
+                    .await,
+                &mut response,
+            ),
+            Request::FinalizeConsistent {
+                client,
+                sequence,
+                message,
+            } => encode_result(
+                &server
+                    .finalize_consistent(client, sequence, message, None)
+                    .await,
+                &mut response,
+            ),
+            Request::ProposeInconsistentBatch {
+                client,
+                first_sequence,
+                messages,
+                highest_observed_view,
+            } => encode_result(
+                &server
+                    .propose_inconsistent_batch(
+                        client,
+                        first_sequence,
+                        messages,
+                        highest_observed_view,
+                    )
+                    .await,
+                &mut response,
+            ),
+            Request::ProposeConsistentBatch {
+                client,
+                first_sequence,
+                messages,
+            } => encode_result(
+                &server
+                    .propose_consistent_batch(client, first_sequence, messages, None)
+                    .await,
+                &mut response,
+            ),
+            Request::FinalizeBatch { operations } => {
+                let results = server.finalize_batch(operations, None).await;
+                (results.len() as u64).encode(&mut response);
+                for result in &results {
+                    encode_result(result, &mut response);
+                }
+            }
+            Request::WithFinalizes { finalizes, request } => encode_result(
+                &server.handle_with_finalizes(finalizes, request).await,
+                &mut response,
+            ),
+        }
+        write_frame(&mut stream, &response).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use crate::io::test::{RecordingNetwork, Sent};
+use crate::server::IROperation;
+use crate::types::OperationSequence;
+use crate::{BatchingConfig, BatchingNetwork, IRNetwork};
+use std::time::Duration;
+
+fn finalize(sequence: OperationSequence) -> IROperation<String, String> {
+    IROperation::InconsistentFinalize {
+        client: "client".to_string(),
+        sequence,
+        message: format!("message-{}", sequence),
+    }
+}
+
+fn members() -> Vec<String> {
+    ["1", "2"].iter().map(ToString::to_string).collect()
+}
+
+#[tokio::test]
+async fn finalizes_are_carried_by_the_next_propose() {
+    // given a batching network with a long delay
+    let inner = RecordingNetwork::default();
+    let network = BatchingNetwork::new(
+        inner.clone(),
+        BatchingConfig {
+            max_batch_size: 10,
+            max_delay: Duration::from_secs(60),
+        },
+    );
+
+    // when two finalizes are sent followed by a proposal to one node
+    for sequence in [1, 2] {
+        network
+            .async_finalize_inconsistent(
+                &members(),
+                "client".to_string(),
+                sequence,
+                format!("message-{}", sequence),
+            )
+            .await;
+    }
+    assert!(inner.sent.lock().unwrap().is_empty());
+    network
+        .propose_inconsistent(
+            &members()[..1],
+            "client".to_string(),
+            3,
+            "m".to_string(),
+            None,
+        )
+        .await;
+
+    // then only that node receives the finalizes, carried by the proposal
+    assert_eq!(
+        *inner.sent.lock().unwrap(),
+        vec![Sent::Carried(
+            "1".to_string(),
+            vec![finalize(1), finalize(2)],
+            3
+        )]
+    );
+
+    // and the other node's batch is still waiting
+    network.flush().await;
+    assert_eq!(
+        inner.sent.lock().unwrap()[1],
+        Sent::Batch("2".to_string(), vec![finalize(1), finalize(2)])
+    );
+}
+
+#[tokio::test]
+async fn finalizes_are_flushed_after_the_delay() {
+    // given a batching network with a short delay
+    let inner = RecordingNetwork::default();
+    let network = BatchingNetwork::new(
+        inner.clone(),
+        BatchingConfig {
+            max_batch_size: 10,
+            max_delay: Duration::from_millis(10),
+        },
+    );
+
+    // when a finalize is sent and nothing else follows
+    network
+        .async_finalize_inconsistent(
+            &members()[..1],
+            "client".to_string(),
+            1,
+            "message-1".to_string(),
+        )
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // then the batch has been sent on its own
+    assert_eq!(
+        *inner.sent.lock().unwrap(),
+        vec![Sent::Batch("1".to_string(), vec![finalize(1)])]
+    );
+}
+
+#[tokio::test]
+async fn finalizes_are_flushed_after_the_delay_without_being_awaited() {
+    // given a batching network with a short delay
+    let inner = RecordingNetwork::default();
+    let network = BatchingNetwork::new(
+        inner.clone(),
+        BatchingConfig {
+            max_batch_size: 10,
+            max_delay: Duration::from_millis(10),
+        },
+    );
+
+    // when a finalize is sent without awaiting it
+    drop(network.async_finalize_inconsistent(
+        &members()[..1],
+        "client".to_string(),
+        1,
+        "message-1".to_string(),
+    ));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // then the timer still flushes the batch
+    assert_eq!(
+        *inner.sent.lock().unwrap(),
+        vec![Sent::Batch("1".to_string(), vec![finalize(1)])]
+    );
+}
+
+#[tokio::test]
+async fn full_batches_are_sent_immediately() {
+    // given a batching network with a batch size of two
+    let inner = RecordingNetwork::default();
+    let network = BatchingNetwork::new(
+        inner.clone(),
+        BatchingConfig {
+            max_batch_size: 2,
+            max_delay: Duration::from_secs(60),
+        },
+    );
+
+    // when two finalizes are sent
+    for sequence in [1, 2] {
+        network
+            .async_finalize_inconsistent(
+                &members()[..1],
+                "client".to_string(),
+                sequence,
+                format!("message-{}", sequence),
+            )
+            .await;
+    }
+
+    // then the batch goes out without waiting for the timer
+    assert_eq!(
+        *inner.sent.lock().unwrap(),
+        vec![Sent::Batch("1".to_string(), vec![finalize(1), finalize(2)])]
+    );
+}
+
+#[tokio::test]
+async fn batch_proposals_carry_pending_finalizes() {
+    // given a batching network holding a finalize
+    let inner = RecordingNetwork::default();
+    let network = BatchingNetwork::new(
+        inner.clone(),
+        BatchingConfig {
+            max_batch_size: 10,
+            max_delay: Duration::from_secs(60),
+        },
+    );
+    network
+        .async_finalize_inconsistent(
+            &members()[..1],
+            "client".to_string(),
+            1,
+            "message-1".to_string(),
+        )
+        .await;
+
+    // when a batch of proposals is sent
+    let responses = network
+        .propose_inconsistent_batch(
+            &members()[..1],
+            "client".to_string(),
+            2,
+            vec!["a".to_string(), "b".to_string()],
+            None,
+        )
+        .await;
+
+    // then the batch carries the finalize
+    assert_eq!(
+        *inner.sent.lock().unwrap(),
+        vec![Sent::Carried("1".to_string(), vec![finalize(1)], 2)]
+    );
+
+    // and the node's results are regrouped in order
+    let (node, results) = responses.into_iter().next().unwrap();
+    let messages: Vec<String> = results.unwrap().into_iter().map(|(m, _view)| m).collect();
+    assert_eq!(node, "1");
+    assert_eq!(messages, vec!["a".to_string(), "b".to_string()]);
+}
+
+#[tokio::test]
+async fn proposals_to_nodes_without_pending_finalizes_are_sent_alone() {
+    // given a batching network holding a finalize for the first node only
+    let inner = RecordingNetwork::default();
+    let network = BatchingNetwork::new(
+        inner.clone(),
+        BatchingConfig {
+            max_batch_size: 10,
+            max_delay: Duration::from_secs(60),
+        },
+    );
+    network
+        .async_finalize_inconsistent(
+            &members()[..1],
+            "client".to_string(),
+            1,
+            "message-1".to_string(),
+        )
+        .await;
+
+    // when a proposal is sent to both nodes
+    let responses = network
+        .propose_consistent(&members(), "client".to_string(), 2, "m".to_string())
+        .await;
+
+    // then the first node's proposal carries the finalize, and the second's goes alone
+    assert_eq!(
+        *inner.sent.lock().unwrap(),
+        vec![
+            Sent::Carried("1".to_string(), vec![finalize(1)], 2),
+            Sent::Propose("2".to_string(), 2),
+        ]
+    );
+
+    // and the responses are in the order of the destinations
+    let nodes: Vec<_> = responses.iter().map(|(node, _)| node.clone()).collect();
+    assert_eq!(nodes, members());
+    assert!(responses
+        .iter()
+        .all(|(_, response)| matches!(response, Ok((message, _)) if message == "m")));
+}
+
+#[tokio::test]
+async fn finalizes_buffered_outside_the_runtime_are_flushed() {
+    // given a batching network with a short delay
+    let inner = RecordingNetwork::default();
+    let network = BatchingNetwork::new(
+        inner.clone(),
+        BatchingConfig {
+            max_batch_size: 10,
+            max_delay: Duration::from_millis(10),
+        },
+    );
+
+    // when a finalize is sent from a thread that is not running the runtime
+    let sender = network.clone();
+    std::thread::spawn(move || {
+        drop(sender.async_finalize_inconsistent(
+            &members()[..1],
+            "client".to_string(),
+            1,
+            "message-1".to_string(),
+        ))
+    })
+    .join()
+    .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // then the network's runtime flushes the batch
+    assert_eq!(
+        *inner.sent.lock().unwrap(),
+        vec![Sent::Batch("1".to_string(), vec![finalize(1)])]
+    );
+}
+
+#[test]
+#[should_panic(expected = "tokio runtime")]
+fn a_network_outside_a_runtime_is_refused() {
+    // when a batching network is created outside a runtime, then it panics
+    BatchingNetwork::new(RecordingNetwork::default(), BatchingConfig::default());
+}
@@ -0,0 +1,86 @@
+use crate::codec::{Codec, DecodeError};
+use crate::io::CarrierRequest;
+use crate::server::{IROperation, View, ViewState};
+
+fn round_trip<T: Codec>(value: &T) -> T {
+    let mut bytes = Vec::new();
+    value.encode(&mut bytes);
+    let mut input = bytes.as_slice();
+    let decoded = T::decode(&mut input).unwrap();
+    assert!(input.is_empty());
+    decoded
+}
+
+#[test]
+fn views_and_operations_round_trip() {
+    // given a view and an operation
+    let view = View {
+        view: 3,
+        members: vec![1u64, 2, 3],
+        learners: vec![4],
+        state: ViewState::ViewChanging,
+    };
+    let operation = IROperation::ConsistentFinalize {
+        client: 7u64,
+        sequence: 9,
+        message: "value".to_string(),
+    };
+
+    // when they are encoded and decoded
+    // then they are unchanged
+    assert_eq!(round_trip(&view), view);
+    assert_eq!(round_trip(&operation), operation);
+    assert_eq!(
+        round_trip(&Some(vec![(1u64, "a".to_string())])),
+        Some(vec![(1, "a".to_string())])
+    );
+}
+
+#[test]
+fn carrier_requests_round_trip() {
+    // given requests of each kind
+    let requests = vec![
+        CarrierRequest::ProposeInconsistent {
+            client: 7u64,
+            first_sequence: 9,
+            messages: vec!["a".to_string(), "b".to_string()],
+            highest_observed_view: Some(View {
+                view: 3,
+                members: vec![1, 2, 3],
+                learners: vec![],
+                state: ViewState::Normal,
+            }),
+        },
+        CarrierRequest::ProposeConsistent {
+            client: 7,
+            first_sequence: 11,
+            messages: vec!["c".to_string()],
+        },
+        CarrierRequest::FinalizeConsistent {
+            client: 7,
+            sequence: 12,
+            message: "d".to_string(),
+        },
+    ];
+
+    // when they are encoded and decoded
+    // then they are unchanged
+    for request in requests {
+        assert_eq!(round_trip(&request), request);
+    }
+}
+
+#[test]
+fn truncated_and_corrupt_input_is_refused() {
+    // given an encoded string
+    let mut bytes = Vec::new();
+    "value".to_string().encode(&mut bytes);
+
+    // when it is cut short, or a tag is unknown
+    let truncated = String::decode(&mut &bytes[..bytes.len() - 1]);
+    let corrupt = Option::<u64>::decode(&mut [7u8].as_slice());
+
+    // then decoding fails rather than reading past the input
+    assert_eq!(truncated, Err(DecodeError::UnexpectedEnd));
+    assert_eq!(corrupt, Err(DecodeError::InvalidTag(7)));
+}
@@ -0,0 +1,195 @@
+use crate::io::test::{RecordingNetwork, Sent};
+use crate::io::IRNetworkError;
+use crate::layers::{IRNetworkExt, LoggingLayer, MetricsLayer, RetryLayer, TimeoutLayer};
+use crate::metrics::{
+    PrometheusMetrics, NETWORK_CALLS_TOTAL, NETWORK_CALL_DURATION_SECONDS, NETWORK_FAILURES_TOTAL,
+    NETWORK_RESPONSES_TOTAL,
+};
+use crate::IRNetwork;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+type Fields = BTreeMap<String, String>;
+
+/// Keeps the fields of every event, ignoring spans
+#[derive(Clone, Default)]
+struct EventRecorder {
+    events: Arc<Mutex<Vec<Fields>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for EventRecorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::new();
+        event.record(&mut FieldVisitor(&mut fields));
+        self.events.lock().unwrap().push(fields);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+fn members() -> Vec<String> {
+    ["1", "2"].iter().map(ToString::to_string).collect()
+}
+
+#[tokio::test]
+async fn timeout_layer_gives_up_on_slow_nodes_only() {
+    // given a network where one node is slow
+    let inner = RecordingNetwork::default();
+    inner.delay("2", Duration::from_secs(60));
+    let network = inner.with_layer(TimeoutLayer::new(Duration::from_millis(50)));
+
+    // when a proposal is made
+    let responses = network
+        .propose_inconsistent(&members(), "client".to_string(), 1, "m".to_string(), None)
+        .await;
+
+    // then the fast node responds and the slow node times out
+    assert_eq!(responses.len(), 2);
+    for (node, response) in responses {
+        match (node.as_str(), response) {
+            ("1", Ok((message, _view))) => assert_eq!(message, "m"),
+            ("2", Err(IRNetworkError::Timeout(timed_out))) => assert_eq!(timed_out, "2"),
+            (node, response) => panic!("Unexpected response from {}: {:?}", node, response),
+        }
+    }
+}
+
+#[tokio::test]
+async fn retry_layer_resends_to_unreachable_nodes() {
+    // given a network where a node is unreachable twice
+    let inner = RecordingNetwork::default();
+    inner.unreachable_for("1", 2);
+    let network = inner
+        .clone()
+        .with_layer(RetryLayer::new(3, Duration::from_millis(1)));
+
+    // when a proposal is made
+    let responses = network
+        .propose_inconsistent(&members(), "client".to_string(), 7, "m".to_string(), None)
+        .await;
+
+    // then both nodes respond
+    assert!(responses.iter().all(|(_node, response)| response.is_ok()));
+
+    // and only the unreachable node was sent the proposal again, with the same sequence
+    assert_eq!(
+        *inner.sent.lock().unwrap(),
+        vec![
+            Sent::Propose("1".to_string(), 7),
+            Sent::Propose("2".to_string(), 7),
+            Sent::Propose("1".to_string(), 7),
+            Sent::Propose("1".to_string(), 7),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn retry_layer_reports_the_last_failure() {
+    // given a network where a node is always unreachable
+    let inner = RecordingNetwork::default();
+    inner.unreachable_for("1", 10);
+    let network = inner.with_layer(RetryLayer::new(2, Duration::from_millis(1)));
+
+    // when a proposal is made
+    let responses = network
+        .propose_consistent(&members()[..1], "client".to_string(), 1, "m".to_string())
+        .await;
+
+    // then the node is reported as unreachable
+    assert!(matches!(
+        responses.as_slice(),
+        [(_, Err(IRNetworkError::NodeUnreachable(_)))]
+    ));
+}
+
+#[tokio::test]
+async fn layers_compose() {
+    // given metrics recorded beneath a retry layer, with logging on the outside
+    let inner = RecordingNetwork::default();
+    inner.unreachable_for("1", 1);
+    let metrics = Arc::new(PrometheusMetrics::new());
+    let recorder = EventRecorder::default();
+    let _guard = tracing::subscriber::set_default(recorder.clone());
+    let network = inner
+        .with_layer(MetricsLayer::new(metrics.clone()))
+        .with_layer(RetryLayer::new(2, Duration::from_millis(1)))
+        .with_layer(LoggingLayer::new());
+
+    // when a proposal and a finalize are made
+    network
+        .propose_inconsistent(&members(), "client".to_string(), 1, "m".to_string(), None)
+        .await;
+    network
+        .async_finalize_inconsistent(&members(), "client".to_string(), 1, "m".to_string())
+        .await;
+
+    // then the metrics see every attempt
+    let proposals = || ("method", "propose_inconsistent".to_string());
+    assert_eq!(metrics.counter(NETWORK_CALLS_TOTAL, &[proposals()]), 2);
+    assert_eq!(
+        metrics.counter(
+            NETWORK_RESPONSES_TOTAL,
+            &[proposals(), ("outcome", "ok".to_string())]
+        ),
+        2
+    );
+    assert_eq!(
+        metrics.counter(
+            NETWORK_RESPONSES_TOTAL,
+            &[proposals(), ("outcome", "unreachable".to_string())]
+        ),
+        1
+    );
+    assert_eq!(
+        metrics.counter(NETWORK_FAILURES_TOTAL, &[("node", "\"1\"".to_string())]),
+        1
+    );
+    assert_eq!(
+        metrics.histogram_count(NETWORK_CALL_DURATION_SECONDS, &[proposals()]),
+        2
+    );
+    assert_eq!(
+        metrics.counter(
+            NETWORK_CALLS_TOTAL,
+            &[("method", "async_finalize_inconsistent".to_string())]
+        ),
+        1
+    );
+
+    // and the log sees each call once, with an event per response
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(events.len(), 6);
+    assert_eq!(events[0]["message"], "network call");
+    assert_eq!(events[0]["method"], "ProposeInconsistent");
+    assert_eq!(events[0]["trace_id"], "\"client\"/1");
+    assert_eq!(events[1]["message"], "network response");
+    assert_eq!(events[1]["trace_id"], "\"client\"/1");
+}
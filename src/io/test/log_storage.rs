@@ -0,0 +1,219 @@
+use crate::group::GroupStorage;
+use crate::io::StorageShared;
+use crate::server::{ChangeCursor, ChangeLogError, IROperation, View, ViewState};
+use crate::{IRStorage, LogStorage};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A log file in a fresh temporary directory, removed when dropped
+struct TempLog {
+    dir: PathBuf,
+}
+
+impl TempLog {
+    fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "ir-log-storage-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        TempLog { dir }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join("node.log")
+    }
+
+    fn open(&self) -> LogStorage<u64, String> {
+        LogStorage::open(self.path(), vec![1, 2, 3]).unwrap()
+    }
+}
+
+impl Drop for TempLog {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn view(view: u64) -> View<u64> {
+    View {
+        view,
+        members: vec![1, 2, 3],
+        learners: vec![],
+        state: ViewState::Normal,
+    }
+}
+
+fn finalize(sequence: u64) -> IROperation<u64, String> {
+    IROperation::InconsistentFinalize {
+        client: 9,
+        sequence,
+        message: format!("m{sequence}"),
+    }
+}
+
+#[tokio::test]
+async fn state_survives_reopening() {
+    // given a log with a started view, a finalized operation and a logged change
+    let log = TempLog::new();
+    let storage = log.open();
+    assert!(storage.is_fresh().await);
+    storage.record_current_view(view(1)).await;
+    storage
+        .record_tentative_inconsistent(9, 1, view(1), "m1".to_string())
+        .await;
+    storage
+        .promote_finalized_inconsistent(9, 1, view(1), "m1".to_string())
+        .await;
+    storage.append_changes(1, vec![finalize(1)]).await;
+    drop(storage);
+
+    // when it is reopened
+    let storage = log.open();
+
+    // then everything written is there
+    assert!(!storage.is_fresh().await);
+    assert_eq!(storage.recover_current_view().await, view(1));
+    assert_eq!(
+        storage.get_main_or_local_operation(view(1), 9, 1).await,
+        Some(finalize(1))
+    );
+    assert_eq!(
+        storage.read_change(None).await,
+        Ok(Some((
+            ChangeCursor {
+                view: 1,
+                position: 0
+            },
+            finalize(1)
+        )))
+    );
+}
+
+#[tokio::test]
+async fn retried_proposal_leaves_operation_finalized() {
+    // given a finalized operation
+    let log = TempLog::new();
+    let storage = log.open();
+    storage
+        .promote_finalized_inconsistent(9, 1, view(0), "m1".to_string())
+        .await;
+
+    // when a retry of its proposal arrives
+    storage
+        .record_tentative_inconsistent(9, 1, view(0), "m1".to_string())
+        .await;
+
+    // then it stays finalized
+    assert_eq!(
+        storage.get_main_or_local_operation(view(0), 9, 1).await,
+        Some(finalize(1))
+    );
+}
+
+#[tokio::test]
+async fn entry_cut_short_by_a_crash_is_ignored() {
+    // given a log whose last entry was only partly written
+    let log = TempLog::new();
+    let storage = log.open();
+    storage
+        .promote_finalized_inconsistent(9, 1, view(0), "m1".to_string())
+        .await;
+    drop(storage);
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(log.path())
+        .unwrap();
+    file.write_all(&[100, 0, 0, 0, 0, 0, 0, 0, 1, 2]).unwrap();
+    drop(file);
+
+    // when it is reopened
+    let storage = log.open();
+
+    // then the complete entries are kept, and the log can be written again
+    assert_eq!(
+        storage.get_main_or_local_operation(view(0), 9, 1).await,
+        Some(finalize(1))
+    );
+    storage
+        .promote_finalized_inconsistent(9, 2, view(0), "m2".to_string())
+        .await;
+    drop(storage);
+    assert_eq!(
+        log.open().get_main_or_local_operation(view(0), 9, 2).await,
+        Some(finalize(2))
+    );
+}
+
+#[tokio::test]
+async fn truncated_changes_stay_truncated_after_reopening() {
+    // given a change log truncated to its latest operation
+    let log = TempLog::new();
+    let storage = log.open();
+    storage
+        .append_changes(0, vec![finalize(1), finalize(2), finalize(3)])
+        .await;
+    storage.truncate_changes(1).await;
+    drop(storage);
+
+    // when it is reopened, and the truncated operations are appended again
+    let storage = log.open();
+    let appended = storage.append_changes(1, vec![finalize(1)]).await;
+
+    // then they are not logged twice, and a cursor before the oldest kept is too old
+    assert_eq!(appended, 0);
+    let oldest = ChangeCursor {
+        view: 0,
+        position: 2,
+    };
+    assert_eq!(
+        storage
+            .read_change(Some(ChangeCursor {
+                view: 0,
+                position: 1
+            }))
+            .await,
+        Err(ChangeLogError::CursorTooOld { oldest })
+    );
+    assert_eq!(
+        storage.read_change(None).await,
+        Ok(Some((oldest, finalize(3))))
+    );
+}
+
+#[tokio::test]
+async fn groups_sharing_a_log_are_kept_apart() {
+    // given a log shared by two groups, where the first has started a view and finalized an
+    // operation
+    let log = TempLog::new();
+    let storage = log.open();
+    let first = GroupStorage::<u64, u64, String>::group(&storage, &1);
+    first.record_current_view(view(1)).await;
+    first
+        .promote_finalized_inconsistent(9, 1, view(1), "m1".to_string())
+        .await;
+    drop((storage, first));
+
+    // when it is reopened
+    let storage = log.open();
+    let first = GroupStorage::<u64, u64, String>::group(&storage, &1);
+    let second = GroupStorage::<u64, u64, String>::group(&storage, &2);
+
+    // then the first group has its view and operation
+    assert!(!first.is_fresh().await);
+    assert_eq!(first.recover_current_view().await, view(1));
+    assert_eq!(
+        first.get_main_or_local_operation(view(1), 9, 1).await,
+        Some(finalize(1))
+    );
+
+    // and the second group and the log's own records have neither
+    for other in [second, storage] {
+        assert!(other.is_fresh().await);
+        assert_eq!(other.recover_current_view().await, view(0));
+        assert_eq!(other.get_main_or_local_operation(view(1), 9, 1).await, None);
+    }
+}
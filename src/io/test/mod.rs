@@ -0,0 +1,207 @@
+mod batching;
+mod codec;
+mod layers;
+mod log_storage;
+#[cfg(feature = "tcp")]
+mod tcp;
+
+use crate::io::{
+    collect_batch_responses, CarrierRequest, IRNetworkError, NodeBatchResponses, NodeResponses,
+};
+use crate::server::{IROperation, View, ViewState};
+use crate::types::OperationSequence;
+use crate::IRNetwork;
+use futures::future::join_all;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Sent {
+    Propose(String, OperationSequence),
+    Finalize(String, OperationSequence),
+    Batch(String, Vec<IROperation<String, String>>),
+    /// Finalizes carried by the request with this first sequence
+    Carried(String, Vec<IROperation<String, String>>, OperationSequence),
+}
+
+/// Records every message sent through it, and answers proposals with the message
+/// Nodes can be made unreachable for a number of calls, or slow to respond
+#[derive(Clone, Default)]
+pub(crate) struct RecordingNetwork {
+    pub(crate) sent: Arc<Mutex<Vec<Sent>>>,
+    unreachable: Arc<Mutex<BTreeMap<String, usize>>>,
+    delays: Arc<Mutex<BTreeMap<String, Duration>>>,
+}
+
+impl RecordingNetwork {
+    fn respond(&self, destinations: &[String], message: String) -> NodeResponses<String, String> {
+        let view = View {
+            view: 0,
+            members: destinations.to_vec(),
+            learners: vec![],
+            state: ViewState::Normal,
+        };
+        let mut unreachable = self.unreachable.lock().unwrap();
+        let responses: Vec<_> = destinations
+            .iter()
+            .map(|d| match unreachable.get_mut(d) {
+                Some(remaining) if *remaining > 0 => {
+                    *remaining -= 1;
+                    (d.clone(), Err(IRNetworkError::NodeUnreachable(d.clone())))
+                }
+                _ => (d.clone(), Ok((message.clone(), view.clone()))),
+            })
+            .collect();
+        let delays = self.delays.lock().unwrap();
+        let delay = destinations
+            .iter()
+            .filter_map(|d| delays.get(d))
+            .max()
+            .cloned()
+            .unwrap_or_default();
+        Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            responses
+        })
+    }
+
+    /// The node fails the next `calls` proposals
+    pub(crate) fn unreachable_for(&self, node: &str, calls: usize) {
+        self.unreachable
+            .lock()
+            .unwrap()
+            .insert(node.to_string(), calls);
+    }
+
+    /// The node takes this long to respond to proposals
+    pub(crate) fn delay(&self, node: &str, delay: Duration) {
+        self.delays.lock().unwrap().insert(node.to_string(), delay);
+    }
+
+    fn record(&self, destinations: &[String], sent: impl Fn(String) -> Sent) {
+        let mut lock = self.sent.lock().unwrap();
+        for destination in destinations {
+            lock.push(sent(destination.clone()));
+        }
+    }
+}
+
+impl IRNetwork<String, String> for RecordingNetwork {
+    fn propose_inconsistent(
+        &self,
+        destinations: &[String],
+        _client_id: String,
+        sequence: OperationSequence,
+        message: String,
+        _highest_observed_view: Option<View<String>>,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Vec<(
+                        String,
+                        Result<(String, View<String>), IRNetworkError<String>>,
+                    )>,
+                > + Send,
+        >,
+    > {
+        self.record(destinations, |d| Sent::Propose(d, sequence));
+        self.respond(destinations, message)
+    }
+
+    fn propose_consistent(
+        &self,
+        destinations: &[String],
+        _client_id: String,
+        sequence: OperationSequence,
+        message: String,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Vec<(
+                        String,
+                        Result<(String, View<String>), IRNetworkError<String>>,
+                    )>,
+                > + Send,
+        >,
+    > {
+        self.record(destinations, |d| Sent::Propose(d, sequence));
+        self.respond(destinations, message)
+    }
+
+    fn async_finalize_inconsistent(
+        &self,
+        destinations: &[String],
+        _client_id: String,
+        sequence: OperationSequence,
+        _message: String,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self.record(destinations, |d| Sent::Finalize(d, sequence));
+        Box::pin(async {})
+    }
+
+    fn async_finalize_consistent(
+        &self,
+        destinations: &[String],
+        _client_id: String,
+        sequence: OperationSequence,
+        _message: String,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self.record(destinations, |d| Sent::Finalize(d, sequence));
+        Box::pin(async {})
+    }
+
+    fn sync_finalize_consistent(
+        &self,
+        destinations: &[String],
+        _client_id: String,
+        sequence: OperationSequence,
+        message: String,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Vec<(
+                        String,
+                        Result<(String, View<String>), IRNetworkError<String>>,
+                    )>,
+                > + Send,
+        >,
+    > {
+        self.record(destinations, |d| Sent::Finalize(d, sequence));
+        self.respond(destinations, message)
+    }
+
+    fn finalize_batch(
+        &self,
+        destinations: &[String],
+        operations: Vec<IROperation<String, String>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self.record(destinations, |d| Sent::Batch(d, operations.clone()));
+        Box::pin(async {})
+    }
+
+    fn send_with_finalizes(
+        &self,
+        destinations: &[String],
+        finalizes: Vec<IROperation<String, String>>,
+        request: CarrierRequest<String, String>,
+    ) -> NodeBatchResponses<String, String> {
+        let sequence = request.first_sequence();
+        self.record(destinations, |d| {
+            Sent::Carried(d, finalizes.clone(), sequence)
+        });
+        let messages = match request {
+            CarrierRequest::ProposeInconsistent { messages, .. }
+            | CarrierRequest::ProposeConsistent { messages, .. } => messages,
+            CarrierRequest::FinalizeConsistent { message, .. } => vec![message],
+        };
+        let responses: Vec<_> = messages
+            .into_iter()
+            .map(|message| self.respond(destinations, message))
+            .collect();
+        let destinations = destinations.to_vec();
+        Box::pin(async move { collect_batch_responses(destinations, join_all(responses).await) })
+    }
+}
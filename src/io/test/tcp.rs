@@ -0,0 +1,90 @@
+use crate::io::IRNetworkError;
+use crate::tcp::TcpNetwork;
+use crate::IRNetwork;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// What a fake node does with a request frame
+#[derive(Clone, Copy)]
+enum Then {
+    /// Reply with an empty frame and keep the connection
+    Reply,
+    /// Reply with an empty frame and close the connection
+    ReplyAndClose,
+    /// Close the connection without replying
+    Close,
+}
+
+/// A node that counts the request frames it receives, and handles the nth with `script(n)`
+async fn fake_node(script: fn(usize) -> Then) -> (Arc<AtomicUsize>, BTreeMap<u64, SocketAddr>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addresses = BTreeMap::from([(1, listener.local_addr().unwrap())]);
+    let received = Arc::new(AtomicUsize::new(0));
+    let counter = received.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve_frames(stream, counter.clone(), script));
+        }
+    });
+    (received, addresses)
+}
+
+async fn serve_frames(
+    mut stream: TcpStream,
+    received: Arc<AtomicUsize>,
+    script: fn(usize) -> Then,
+) {
+    let mut len = [0; 8];
+    while stream.read_exact(&mut len).await.is_ok() {
+        let mut payload = vec![0; u64::from_le_bytes(len) as usize];
+        stream.read_exact(&mut payload).await.unwrap();
+        match script(received.fetch_add(1, Ordering::SeqCst)) {
+            Then::Reply => stream.write_all(&0u64.to_le_bytes()).await.unwrap(),
+            Then::ReplyAndClose => {
+                stream.write_all(&0u64.to_le_bytes()).await.unwrap();
+                return;
+            }
+            Then::Close => return,
+        }
+    }
+}
+
+/// Whether the node answered, though an empty frame is not a valid response
+async fn answered(network: &TcpNetwork<u64, u64>) -> bool {
+    let responses = network.propose_consistent(&[1], 0, 0, 7).await;
+    !matches!(responses[0].1, Err(IRNetworkError::NodeUnreachable(_)))
+}
+
+#[tokio::test]
+async fn a_request_is_resent_when_the_idle_connection_was_closed() {
+    // given a node that closes each connection after answering a request on it
+    let (received, addresses) = fake_node(|_| Then::ReplyAndClose).await;
+    let network = TcpNetwork::<u64, u64>::new(addresses);
+    assert!(answered(&network).await);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // when another request is sent
+    // then it is answered on a new connection, and the node received it once
+    assert!(answered(&network).await);
+    assert_eq!(received.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn a_request_the_node_received_is_not_resent() {
+    // given a node that answers the first request, and closes the connection on the second
+    let (received, addresses) = fake_node(|n| if n == 0 { Then::Reply } else { Then::Close }).await;
+    let network = TcpNetwork::<u64, u64>::new(addresses);
+    assert!(answered(&network).await);
+
+    // when the second request is sent on the idle connection
+    let second = answered(&network).await;
+
+    // then the node is unreachable, rather than receiving the request again
+    assert!(!second);
+    assert_eq!(received.load(Ordering::SeqCst), 2);
+}
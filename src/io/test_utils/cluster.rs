@@ -0,0 +1,52 @@
+use crate::io::test_utils::{FakeIRNetwork, FakeIRStorage};
+use crate::types::{IRMessage, NodeID};
+use crate::{IRApplication, InconsistentReplicationServer};
+
+/// Start a cluster on a fake network, every member bootstrapping view 0
+/// Returns the application of each member, in the order of `members`.
+pub async fn fake_cluster<I, M, A>(
+    network: &FakeIRNetwork<I, M, FakeIRStorage<I, M>, A>,
+    members: &[I],
+    application: impl Fn() -> A,
+) -> Vec<A>
+where
+    I: NodeID,
+    M: IRMessage,
+    A: IRApplication<I, M>,
+{
+    partitioned_fake_cluster(network, members, members, application).await
+}
+
+/// Start a cluster on a fake network of which only the `reachable` members are running
+/// The other members are never registered, so sending to them fails with `NodeUnreachable`
+/// as if they were partitioned away. Returns the application of each reachable member.
+pub async fn partitioned_fake_cluster<I, M, A>(
+    network: &FakeIRNetwork<I, M, FakeIRStorage<I, M>, A>,
+    members: &[I],
+    reachable: &[I],
+    application: impl Fn() -> A,
+) -> Vec<A>
+where
+    I: NodeID,
+    M: IRMessage,
+    A: IRApplication<I, M>,
+{
+    let mut applications = vec![];
+    for node_id in reachable {
+        let app = application();
+        let server = InconsistentReplicationServer::new(
+            network.clone(),
+            FakeIRStorage::new(members.to_vec()),
+            app.clone(),
+            node_id.clone(),
+        )
+        .await;
+        server
+            .bootstrap(members.to_vec())
+            .await
+            .expect("fresh nodes bootstrap");
+        network.register_node(node_id.clone(), server);
+        applications.push(app);
+    }
+    applications
+}
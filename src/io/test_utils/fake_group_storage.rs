@@ -0,0 +1,43 @@
+use crate::group::{GroupId, GroupStorage};
+use crate::io::test_utils::FakeIRStorage;
+use crate::types::{IRMessage, NodeID};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// A storage engine for a `MultiGroupHost`, keeping a `FakeIRStorage` per group
+/// Every group starts with the same members.
+pub struct FakeGroupStorage<G: GroupId, I: NodeID, M: IRMessage> {
+    members: Vec<I>,
+    groups: Arc<Mutex<BTreeMap<G, FakeIRStorage<I, M>>>>,
+}
+
+impl<G: GroupId, I: NodeID, M: IRMessage> Clone for FakeGroupStorage<G, I, M> {
+    fn clone(&self) -> Self {
+        FakeGroupStorage {
+            members: self.members.clone(),
+            groups: self.groups.clone(),
+        }
+    }
+}
+
+impl<G: GroupId, I: NodeID, M: IRMessage> FakeGroupStorage<G, I, M> {
+    pub fn new(members: Vec<I>) -> Self {
+        FakeGroupStorage {
+            members,
+            groups: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+}
+
+impl<G: GroupId, I: NodeID, M: IRMessage> GroupStorage<G, I, M> for FakeGroupStorage<G, I, M> {
+    type Storage = FakeIRStorage<I, M>;
+
+    fn group(&self, group: &G) -> FakeIRStorage<I, M> {
+        self.groups
+            .lock()
+            .unwrap()
+            .entry(group.clone())
+            .or_insert_with(|| FakeIRStorage::new(self.members.clone()))
+            .clone()
+    }
+}
@@ -1,7 +1,7 @@
-use crate::io::IRNetworkError;
-use crate::server::View;
+use crate::io::{CarrierRequest, IRNetworkError};
+use crate::server::{IROperation, View};
 use crate::types::{IRMessage, NodeID, OperationSequence};
-use crate::{IRNetwork, IRStorage, InconsistentReplicationServer};
+use crate::{IRApplication, IRNetwork, IRStorage, InconsistentReplicationServer};
 use std::collections::BTreeMap;
 use std::future::Future;
 use std::pin::Pin;
@@ -11,21 +11,30 @@ use tokio::sync::RwLock as TokioRwLock;
 
 type DropPacketCounter<ID> = Arc<StdRwLock<BTreeMap<ID, AtomicUsize>>>;
 
-enum SwitchableNode<ID: NodeID, MSG: IRMessage, STO: IRStorage<ID, MSG>> {
-    On(InconsistentReplicationServer<FakeIRNetwork<ID, MSG, STO>, STO, ID, MSG>),
-    Off((FakeIRNetwork<ID, MSG, STO>, STO, ID)),
+type Nodes<ID, MSG, STO, APP> = BTreeMap<ID, SwitchableNode<ID, MSG, STO, APP>>;
+
+enum SwitchableNode<
+    ID: NodeID,
+    MSG: IRMessage,
+    STO: IRStorage<ID, MSG>,
+    APP: IRApplication<ID, MSG>,
+> {
+    On(InconsistentReplicationServer<FakeIRNetwork<ID, MSG, STO, APP>, STO, APP, ID, MSG>),
+    Off((FakeIRNetwork<ID, MSG, STO, APP>, STO, APP, ID)),
 }
 
-impl<ID: NodeID, MSG: IRMessage, STO: IRStorage<ID, MSG>> SwitchableNode<ID, MSG, STO> {
+impl<ID: NodeID, MSG: IRMessage, STO: IRStorage<ID, MSG>, APP: IRApplication<ID, MSG>>
+    SwitchableNode<ID, MSG, STO, APP>
+{
     async fn switch(self) -> Self {
         match self {
             SwitchableNode::On(node) => {
-                let (net, sto, id, _view) = node.shutdown().await;
-                SwitchableNode::Off((net, sto, id))
-            }
-            SwitchableNode::Off((net, storage, id)) => {
-                SwitchableNode::On(InconsistentReplicationServer::new(net, storage, id).await)
+                let (net, sto, app, id, _view) = node.shutdown().await;
+                SwitchableNode::Off((net, sto, app, id))
             }
+            SwitchableNode::Off((net, storage, application, id)) => SwitchableNode::On(
+                InconsistentReplicationServer::new(net, storage, application, id).await,
+            ),
         }
     }
 }
@@ -34,17 +43,19 @@ pub struct FakeIRNetwork<
     ID: NodeID + 'static,
     MSG: IRMessage + 'static,
     STO: IRStorage<ID, MSG> + 'static,
+    APP: IRApplication<ID, MSG> + 'static,
 > {
-    nodes: Arc<TokioRwLock<BTreeMap<ID, SwitchableNode<ID, MSG, STO>>>>,
+    nodes: Arc<TokioRwLock<Nodes<ID, MSG, STO, APP>>>,
     drop_requests: DropPacketCounter<ID>,
     drop_responses: DropPacketCounter<ID>,
 }
 
-impl<ID, MSG, STO> Clone for FakeIRNetwork<ID, MSG, STO>
+impl<ID, MSG, STO, APP> Clone for FakeIRNetwork<ID, MSG, STO, APP>
 where
     ID: NodeID,
     MSG: IRMessage,
     STO: IRStorage<ID, MSG>,
+    APP: IRApplication<ID, MSG>,
 {
     fn clone(&self) -> Self {
         FakeIRNetwork {
@@ -55,7 +66,9 @@ where
     }
 }
 
-impl<I: NodeID, M: IRMessage, STO: IRStorage<I, M>> IRNetwork<I, M> for FakeIRNetwork<I, M, STO> {
+impl<I: NodeID, M: IRMessage, STO: IRStorage<I, M>, APP: IRApplication<I, M>> IRNetwork<I, M>
+    for FakeIRNetwork<I, M, STO, APP>
+{
     fn propose_inconsistent(
         &self,
         destinations: &[I],
@@ -63,19 +76,19 @@ impl<I: NodeID, M: IRMessage, STO: IRStorage<I, M>> IRNetwork<I, M> for FakeIRNe
         sequence: OperationSequence,
         message: M,
         highest_observed_view: Option<View<I>>,
-    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>>>> {
+    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + Send>>
+    {
         let nodes = self.nodes.clone();
         let drop_requests = self.drop_requests.clone();
         let drop_responses = self.drop_responses.clone();
-        let destinations: Vec<I> = destinations.iter().cloned().collect();
+        let destinations: Vec<I> = destinations.to_vec();
         Box::pin(async move {
             let rl = nodes.read().await;
             let mut responses = Vec::with_capacity(destinations.len());
             for destination in &destinations {
-                let node = rl.get(destination).unwrap();
-                match node {
-                    SwitchableNode::On(node) => {
-                        if Self::should_drop(drop_requests.clone(), &destination) {
+                match rl.get(destination) {
+                    Some(SwitchableNode::On(node)) => {
+                        if Self::should_drop(drop_requests.clone(), destination) {
                             responses.push((
                                 destination.clone(),
                                 Err(IRNetworkError::NodeUnreachable(destination.clone())),
@@ -99,7 +112,7 @@ impl<I: NodeID, M: IRMessage, STO: IRStorage<I, M>> IRNetwork<I, M> for FakeIRNe
                         }
                         responses.push((destination.clone(), msg.map_err(|e| e.into())));
                     }
-                    SwitchableNode::Off(_) => {
+                    Some(SwitchableNode::Off(_)) | None => {
                         responses.push((
                             destination.clone(),
                             Err(IRNetworkError::NodeUnreachable(destination.clone())),
@@ -117,17 +130,18 @@ impl<I: NodeID, M: IRMessage, STO: IRStorage<I, M>> IRNetwork<I, M> for FakeIRNe
         client_id: I,
         sequence: OperationSequence,
         message: M,
-    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>>>> {
+    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + Send>>
+    {
         let nodes = self.nodes.clone();
         let drop_requests = self.drop_requests.clone();
         let drop_responses = self.drop_responses.clone();
-        let destinations: Vec<I> = destinations.iter().cloned().collect();
+        let destinations: Vec<I> = destinations.to_vec();
         Box::pin(async move {
             let read_lock = nodes.read().await;
             let mut responses = Vec::with_capacity(destinations.len());
             for destination in &destinations {
                 let node = read_lock
-                    .get(&destination)
+                    .get(destination)
                     .ok_or(IRNetworkError::NodeUnreachable(destination.clone()));
                 match node {
                     Ok(SwitchableNode::On(node)) => {
@@ -167,18 +181,18 @@ impl<I: NodeID, M: IRMessage, STO: IRStorage<I, M>> IRNetwork<I, M> for FakeIRNe
         client_id: I,
         sequence: OperationSequence,
         message: M,
-    ) -> Pin<Box<dyn Future<Output = ()>>> {
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         let nodes = self.nodes.clone();
         let drop_requests = self.drop_requests.clone();
         let drop_responses = self.drop_responses.clone();
-        let destinations: Vec<I> = destinations.iter().cloned().collect();
+        let destinations: Vec<I> = destinations.to_vec();
         Box::pin(async move {
             let read_lock = nodes.read().await;
             // TODO unnecessary, because function is async
             let mut responses = Vec::with_capacity(destinations.len());
             for destination in &destinations {
                 let node = read_lock
-                    .get(&destination)
+                    .get(destination)
                     .ok_or(IRNetworkError::NodeUnreachable(destination.clone()));
                 match node {
                     Ok(SwitchableNode::On(node)) => {
@@ -227,11 +241,11 @@ impl<I: NodeID, M: IRMessage, STO: IRStorage<I, M>> IRNetwork<I, M> for FakeIRNe
         client_id: I,
         sequence: OperationSequence,
         message: M,
-    ) -> Pin<Box<dyn Future<Output = ()>>> {
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         let nodes = self.nodes.clone();
         let drop_requests = self.drop_requests.clone();
         let drop_responses = self.drop_responses.clone();
-        let destinations: Vec<I> = destinations.iter().cloned().collect();
+        let destinations: Vec<I> = destinations.to_vec();
         Box::pin(async move {
             let read_lock = nodes.read().await;
             for destination in destinations {
@@ -243,7 +257,7 @@ impl<I: NodeID, M: IRMessage, STO: IRStorage<I, M>> IRNetwork<I, M> for FakeIRNe
                         if Self::should_drop(drop_requests.clone(), &destination) {
                             continue;
                         }
-                        let resp = node
+                        let _resp = node
                             .finalize_consistent(client_id.clone(), sequence, message.clone(), None)
                             .await;
                         if Self::should_drop(drop_responses.clone(), &destination) {
@@ -263,17 +277,18 @@ impl<I: NodeID, M: IRMessage, STO: IRStorage<I, M>> IRNetwork<I, M> for FakeIRNe
         client_id: I,
         sequence: OperationSequence,
         message: M,
-    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>>>> {
+    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<(M, View<I>), IRNetworkError<I>>)>> + Send>>
+    {
         let nodes = self.nodes.clone();
         let drop_requests = self.drop_requests.clone();
         let drop_responses = self.drop_responses.clone();
-        let destinations: Vec<I> = destinations.iter().cloned().collect();
+        let destinations: Vec<I> = destinations.to_vec();
         Box::pin(async move {
             let read_lock = nodes.read().await;
             let mut responses = Vec::with_capacity(destinations.len());
             for destination in &destinations {
                 let node = read_lock
-                    .get(&destination)
+                    .get(destination)
                     .ok_or(IRNetworkError::NodeUnreachable(destination.clone()));
                 match node {
                     Ok(SwitchableNode::On(node)) => {
@@ -306,9 +321,174 @@ impl<I: NodeID, M: IRMessage, STO: IRStorage<I, M>> IRNetwork<I, M> for FakeIRNe
             responses
         })
     }
+
+    fn propose_inconsistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>)>> + Send>>
+    {
+        let nodes = self.nodes.clone();
+        let drop_requests = self.drop_requests.clone();
+        let drop_responses = self.drop_responses.clone();
+        let destinations: Vec<I> = destinations.to_vec();
+        Box::pin(async move {
+            let read_lock = nodes.read().await;
+            let mut responses = Vec::with_capacity(destinations.len());
+            for destination in destinations {
+                let unreachable = Err(IRNetworkError::NodeUnreachable(destination.clone()));
+                match read_lock.get(&destination) {
+                    Some(SwitchableNode::On(node)) => {
+                        if Self::should_drop(drop_requests.clone(), &destination) {
+                            responses.push((destination, unreachable));
+                            continue;
+                        }
+                        let resp = node
+                            .propose_inconsistent_batch(
+                                client_id.clone(),
+                                first_sequence,
+                                messages.clone(),
+                                highest_observed_view.clone(),
+                            )
+                            .await;
+                        if Self::should_drop(drop_responses.clone(), &destination) {
+                            responses.push((destination, unreachable));
+                            continue;
+                        }
+                        responses.push((destination, resp.map_err(|e| e.into())));
+                    }
+                    Some(SwitchableNode::Off(_)) | None => {
+                        responses.push((destination, unreachable));
+                    }
+                }
+            }
+            responses
+        })
+    }
+
+    fn propose_consistent_batch(
+        &self,
+        destinations: &[I],
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>)>> + Send>>
+    {
+        let nodes = self.nodes.clone();
+        let drop_requests = self.drop_requests.clone();
+        let drop_responses = self.drop_responses.clone();
+        let destinations: Vec<I> = destinations.to_vec();
+        Box::pin(async move {
+            let read_lock = nodes.read().await;
+            let mut responses = Vec::with_capacity(destinations.len());
+            for destination in destinations {
+                let unreachable = Err(IRNetworkError::NodeUnreachable(destination.clone()));
+                match read_lock.get(&destination) {
+                    Some(SwitchableNode::On(node)) => {
+                        if Self::should_drop(drop_requests.clone(), &destination) {
+                            responses.push((destination, unreachable));
+                            continue;
+                        }
+                        let resp = node
+                            .propose_consistent_batch(
+                                client_id.clone(),
+                                first_sequence,
+                                messages.clone(),
+                                None,
+                            )
+                            .await;
+                        if Self::should_drop(drop_responses.clone(), &destination) {
+                            responses.push((destination, unreachable));
+                            continue;
+                        }
+                        responses.push((destination, resp.map_err(|e| e.into())));
+                    }
+                    Some(SwitchableNode::Off(_)) | None => {
+                        responses.push((destination, unreachable));
+                    }
+                }
+            }
+            responses
+        })
+    }
+
+    fn send_with_finalizes(
+        &self,
+        destinations: &[I],
+        finalizes: Vec<IROperation<I, M>>,
+        request: CarrierRequest<I, M>,
+    ) -> Pin<Box<dyn Future<Output = Vec<(I, Result<Vec<(M, View<I>)>, IRNetworkError<I>>)>> + Send>>
+    {
+        let nodes = self.nodes.clone();
+        let drop_requests = self.drop_requests.clone();
+        let drop_responses = self.drop_responses.clone();
+        let destinations: Vec<I> = destinations.to_vec();
+        Box::pin(async move {
+            let read_lock = nodes.read().await;
+            let mut responses = Vec::with_capacity(destinations.len());
+            for destination in destinations {
+                let unreachable = Err(IRNetworkError::NodeUnreachable(destination.clone()));
+                match read_lock.get(&destination) {
+                    Some(SwitchableNode::On(node)) => {
+                        if Self::should_drop(drop_requests.clone(), &destination) {
+                            responses.push((destination, unreachable));
+                            continue;
+                        }
+                        let resp = node
+                            .handle_with_finalizes(finalizes.clone(), request.clone())
+                            .await;
+                        if Self::should_drop(drop_responses.clone(), &destination) {
+                            responses.push((destination, unreachable));
+                            continue;
+                        }
+                        responses.push((destination, resp.map_err(|e| e.into())));
+                    }
+                    Some(SwitchableNode::Off(_)) | None => {
+                        responses.push((destination, unreachable));
+                    }
+                }
+            }
+            responses
+        })
+    }
+
+    fn finalize_batch(
+        &self,
+        destinations: &[I],
+        operations: Vec<IROperation<I, M>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let nodes = self.nodes.clone();
+        let drop_requests = self.drop_requests.clone();
+        let destinations: Vec<I> = destinations.to_vec();
+        Box::pin(async move {
+            let read_lock = nodes.read().await;
+            for destination in destinations {
+                // The whole batch is a single message, so it is dropped as one
+                if let Some(SwitchableNode::On(node)) = read_lock.get(&destination) {
+                    if Self::should_drop(drop_requests.clone(), &destination) {
+                        continue;
+                    }
+                    let _resp = node.finalize_batch(operations.clone(), None).await;
+                }
+            }
+        })
+    }
 }
 
-impl<ID: NodeID, MSG: IRMessage, STO: IRStorage<ID, MSG>> FakeIRNetwork<ID, MSG, STO> {
+impl<ID: NodeID, MSG: IRMessage, STO: IRStorage<ID, MSG>, APP: IRApplication<ID, MSG>> Default
+    for FakeIRNetwork<ID, MSG, STO, APP>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<ID: NodeID, MSG: IRMessage, STO: IRStorage<ID, MSG>, APP: IRApplication<ID, MSG>>
+    FakeIRNetwork<ID, MSG, STO, APP>
+{
     pub fn new() -> Self {
         FakeIRNetwork {
             nodes: Arc::new(TokioRwLock::new(BTreeMap::new())),
@@ -322,12 +502,27 @@ impl<ID: NodeID, MSG: IRMessage, STO: IRStorage<ID, MSG>> FakeIRNetwork<ID, MSG,
     pub fn register_node(
         &self,
         node_id: ID,
-        server: InconsistentReplicationServer<FakeIRNetwork<ID, MSG, STO>, STO, ID, MSG>,
+        server: InconsistentReplicationServer<FakeIRNetwork<ID, MSG, STO, APP>, STO, APP, ID, MSG>,
     ) {
         let node = SwitchableNode::On(server);
         self.nodes.try_write().unwrap().insert(node_id, node);
     }
 
+    /// A network of this one's running nodes, from which only the `reachable` ones can be
+    /// reached
+    /// Clients created with it see their side of a partition, while the nodes themselves still
+    /// reach each other.
+    pub fn partition(&self, reachable: &[ID]) -> Self {
+        let partition = Self::new();
+        let nodes = self.nodes.try_read().unwrap();
+        for node_id in reachable {
+            if let Some(SwitchableNode::On(server)) = nodes.get(node_id) {
+                partition.register_node(node_id.clone(), server.clone());
+            }
+        }
+        partition
+    }
+
     pub fn drop_requests_add(&self, node_id: ID, drop_packets: usize) {
         self.drop_requests
             .try_write()
@@ -348,7 +543,7 @@ impl<ID: NodeID, MSG: IRMessage, STO: IRStorage<ID, MSG>> FakeIRNetwork<ID, MSG,
 
     pub async fn switch(&self, node_id: ID) {
         let mut write_lock = self.nodes.write().await;
-        let mut val = write_lock.remove(&node_id).unwrap();
+        let val = write_lock.remove(&node_id).unwrap();
         write_lock.insert(node_id, val.switch().await);
     }
 
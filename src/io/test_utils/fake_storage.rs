@@ -1,53 +1,78 @@
-use crate::debug::MaybeDebug;
 use crate::io::{IRClientStorage, StorageShared};
-use crate::server::{IROperation, View, ViewState};
-use crate::test_utils::mock_computers::MockOperationHandler;
-use crate::test_utils::mock_record_store::{FullState, MockRecordStore};
-use crate::types::{AsyncIterator, IRMessage, NodeID, OperationSequence};
+use crate::server::{ChangeCursor, ChangeLogError, IROperation, View, ViewState};
+use crate::test_utils::mock_record_store::MockRecordStore;
+use crate::types::{IRMessage, NodeID, OperationSequence};
 use crate::IRStorage;
-use std::collections::BTreeMap;
+use futures::{stream, Stream, StreamExt};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 use tokio::sync::RwLock as TokioRwLock;
 
+/// The result each node reported for the undecided operations of a view
+type UndecidedRecord<ID, MSG> = BTreeMap<(ID, OperationSequence), BTreeMap<ID, MSG>>;
+
+type UndecidedRecords<ID, MSG> = BTreeMap<View<ID>, UndecidedRecord<ID, MSG>>;
+
+/// The records received from each peer in a view change
+type PeerRecords<ID, MSG> = BTreeMap<(View<ID>, ID), MockRecordStore<ID, MSG>>;
+
+type MainRecords<ID, MSG> = BTreeMap<View<ID>, MockRecordStore<ID, MSG>>;
+
 #[derive(Clone)]
-pub struct FakeIRStorage<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> {
+pub struct FakeIRStorage<ID: NodeID, MSG: IRMessage> {
     /// Stores the local record store
     records: MockRecordStore<ID, MSG>,
     /// Stores received records from nodes during view change. Can be purged once a view change completes.
-    received_record_logs: Arc<RwLock<BTreeMap<(View<ID>, ID), MockRecordStore<ID, MSG>>>>,
+    received_record_logs: Arc<RwLock<PeerRecords<ID, MSG>>>,
+    /// Tracks which nodes have sent their full record during a view change
+    complete_record_logs: Arc<RwLock<BTreeMap<View<ID>, Vec<ID>>>>,
+    /// The main (master) record that is created by merging during a view change
+    main_records: Arc<RwLock<MainRecords<ID, MSG>>>,
+    /// Results of consensus operations that still need to be decided during a merge
+    undecided_records: Arc<RwLock<UndecidedRecords<ID, MSG>>>,
     /// Just a tracker for local view in case of restart
     current_view: Arc<TokioRwLock<View<ID>>>,
-    /// That thang that handles operation processing
-    computer_lol: CPU,
+    /// Whether the node has recorded a view it started, rather than only being configured
+    view_recorded: Arc<AtomicBool>,
+    /// The finalized operations logged for subscriptions
+    changes: Arc<RwLock<FakeChangeLog<ID, MSG>>>,
 }
 
-impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> StorageShared<ID>
-    for FakeIRStorage<ID, MSG, CPU>
-{
-    fn recover_current_view(&self) -> Pin<Box<dyn Future<Output = View<ID>>>> {
+struct FakeChangeLog<ID: NodeID, MSG: IRMessage> {
+    /// The operations kept of each view, with the position of the first one
+    views: BTreeMap<u64, (u64, VecDeque<IROperation<ID, MSG>>)>,
+    /// Every operation ever logged, including those truncated
+    logged: BTreeSet<(ID, OperationSequence)>,
+    /// The position of the oldest operation kept; earlier ones have been truncated
+    oldest: ChangeCursor,
+    kept: u64,
+}
+
+impl<ID: NodeID, MSG: IRMessage> StorageShared<ID> for FakeIRStorage<ID, MSG> {
+    fn recover_current_view(&self) -> Pin<Box<dyn Future<Output = View<ID>> + Send>> {
         let view = self.current_view.clone();
         Box::pin(async move { view.read().await.clone() })
     }
 }
 
-impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> IRStorage<ID, MSG>
-    for FakeIRStorage<ID, MSG, CPU>
-{
-    fn record_tentative_inconsistent_and_evaluate(
+impl<ID: NodeID, MSG: IRMessage> IRStorage<ID, MSG> for FakeIRStorage<ID, MSG> {
+    fn record_tentative_inconsistent(
         &self,
         client: ID,
         operation: u64,
         view: View<ID>,
         message: MSG,
-    ) -> Pin<Box<dyn Future<Output = MSG> + 'static>> {
-        println!(
-            "record_tentative_inconsistent operation: {}",
-            MaybeDebug::maybe_debug(&message)
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        tracing::trace!(
+            ?client,
+            operation,
+            ?message,
+            "record_tentative_inconsistent"
         );
         let records = self.records.clone();
-        let computer_lol = self.computer_lol.clone();
         Box::pin(async move {
             let existing = records.find_entry(client.clone(), operation).await;
             match existing {
@@ -59,32 +84,33 @@ impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> IRStorage<ID, M
                     assert!(state.ir_operation.message() == &message);
                     #[cfg(any(feature = "test", debug_assertions, test))]
                     match state.ir_operation {
-                        IROperation::InconsistentPropose { .. } => {}
+                        IROperation::InconsistentPropose { .. }
+                        | IROperation::InconsistentFinalize { .. } => {}
                         _ => panic!("invalid type"),
                     }
                 }
             }
             // TODO if finalized, should return finalized value and that it is finalized
             records
-                .propose_tentative_inconsistent(client, operation, view, message.clone())
+                .propose_tentative_inconsistent(client, operation, view, message)
                 .await;
-            computer_lol.evaluate_inconsistent(message)
         })
     }
 
-    fn promote_finalized_and_exec_inconsistent(
+    fn promote_finalized_inconsistent(
         &self,
         client: ID,
         operation: u64,
         view: View<ID>,
         message: MSG,
-    ) -> Pin<Box<dyn Future<Output = ()> + 'static>> {
-        println!(
-            "promote_finalized_and_exec_inconsistent: {}",
-            MaybeDebug::maybe_debug(&message)
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        tracing::trace!(
+            ?client,
+            operation,
+            ?message,
+            "promote_finalized_inconsistent"
         );
         let records = self.records.clone();
-        let computer = self.computer_lol.clone();
         Box::pin(async move {
             let existing = records.find_entry(client.clone(), operation).await;
             match existing {
@@ -95,27 +121,26 @@ impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> IRStorage<ID, M
                     assert_eq!(state.view, view);
                     // We do not assert message, as it may be different
                     match state.ir_operation {
-                        IROperation::InconsistentFinalize { .. } => {}
+                        IROperation::InconsistentPropose { .. }
+                        | IROperation::InconsistentFinalize { .. } => {}
                         _ => panic!("invalid type"),
                     }
                 }
             }
             records
-                .promote_finalized_inconsistent(client, operation, view, message.clone())
+                .promote_finalized_inconsistent(client, operation, view, message)
                 .await;
-            let _unused_msg = computer.exec_inconsistent(message);
         })
     }
 
-    fn record_tentative_and_exec_consistent(
+    fn record_tentative_consistent(
         &self,
         client: ID,
         sequence: u64,
         view: View<ID>,
         operation: MSG,
-    ) -> Pin<Box<dyn Future<Output = MSG> + 'static>> {
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
         let records = self.records.clone();
-        let computer = self.computer_lol.clone();
         Box::pin(async move {
             let existing = records.find_entry(client.clone(), sequence).await;
             match existing {
@@ -125,28 +150,26 @@ impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> IRStorage<ID, M
                 Some(state) => {
                     assert!(state.view == view);
                     match state.ir_operation {
-                        IROperation::ConsistentFinalize { .. } => {}
+                        IROperation::ConsistentPropose { .. }
+                        | IROperation::ConsistentFinalize { .. } => {}
                         _ => panic!("invalid type"),
                     }
                 }
             }
-            let response = computer.exec_consistent(operation.clone());
             records
-                .propose_tentative_consistent(client, sequence, view, operation.clone())
+                .propose_tentative_consistent(client, sequence, view, operation)
                 .await;
-            response
         })
     }
 
-    fn promote_finalized_and_reconcile_consistent(
+    fn promote_finalized_consistent(
         &self,
         client: ID,
         sequence: u64,
         view: View<ID>,
         operation: MSG,
-    ) -> Pin<Box<dyn Future<Output = MSG> + 'static>> {
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
         let records = self.records.clone();
-        let computer = self.computer_lol.clone();
         Box::pin(async move {
             let existing = records.find_entry(client.clone(), sequence).await;
             match existing {
@@ -156,20 +179,15 @@ impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> IRStorage<ID, M
                 Some(state) => {
                     assert!(state.view == view);
                     match state.ir_operation {
-                        IROperation::ConsistentFinalize { .. } => {}
+                        IROperation::ConsistentPropose { .. }
+                        | IROperation::ConsistentFinalize { .. } => {}
                         _ => panic!("invalid type"),
                     }
                 }
             }
-            let previous = records
-                .promote_finalized_consistent_returning_previous_evaluation(
-                    client,
-                    sequence,
-                    view,
-                    operation.clone(),
-                )
+            records
+                .promote_finalized_consistent(client, sequence, view, operation)
                 .await;
-            computer.reconcile_consistent(previous, operation)
         })
     }
 
@@ -178,14 +196,17 @@ impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> IRStorage<ID, M
         node_id: ID,
         view: View<ID>,
         operation: IROperation<ID, MSG>,
-    ) -> Pin<Box<dyn Future<Output = ()> + 'static>> {
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let record_store = self
+            .received_record_logs
+            .write()
+            .unwrap()
+            .entry((view.clone(), node_id))
+            .or_insert_with(|| MockRecordStore::new())
+            .clone();
         Box::pin(async move {
-            let mut wl = self.received_record_logs.write().unwrap();
-            let record_store = wl
-                .entry((view.clone(), node_id))
-                .or_insert_with(|| MockRecordStore::new());
             let found = record_store
-                .find_entry(operation.client().clone(), operation.sequence().clone())
+                .find_entry(operation.client().clone(), *operation.sequence())
                 .await;
             match (operation, found) {
                 // Inconsistent finalize is always recorded
@@ -215,13 +236,7 @@ impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> IRStorage<ID, M
                         .await;
                 }
                 // If we have a record then we keep it otherwise
-                (
-                    IROperation::InconsistentPropose { .. },
-                    Some(FullState {
-                        ir_operation: IROperation::InconsistentPropose { .. },
-                        view,
-                    }),
-                ) => {
+                (IROperation::InconsistentPropose { .. }, Some(_)) => {
                     // Noop
                 }
                 // Consistent finalize is always recorded
@@ -251,32 +266,52 @@ impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> IRStorage<ID, M
                         .await;
                 }
                 // If we have a consistent record then we keep it
-                (
-                    IROperation::ConsistentPropose { .. },
-                    Some(FullState {
-                        ir_operation: IROperation::ConsistentPropose { .. },
-                        view,
-                    }),
-                ) => {
+                (IROperation::ConsistentPropose { .. }, Some(_)) => {
                     // Noop
                 }
             }
         })
     }
 
+    fn complete_peer_view_change_record(
+        &self,
+        node_id: ID,
+        view: View<ID>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut wl = self.complete_record_logs.write().unwrap();
+        let peers = wl.entry(view).or_default();
+        if !peers.contains(&node_id) {
+            peers.push(node_id);
+        }
+        Box::pin(async {})
+    }
+
     fn get_peers_with_full_records(
         &self,
         view: View<ID>,
-    ) -> Pin<Box<dyn Future<Output = Vec<ID>> + 'static>> {
-        todo!()
+    ) -> Pin<Box<dyn Future<Output = Vec<ID>> + Send + 'static>> {
+        let peers = self
+            .complete_record_logs
+            .read()
+            .unwrap()
+            .get(&view)
+            .cloned()
+            .unwrap_or_default();
+        Box::pin(async move { peers })
     }
 
     fn get_view_record_operations(
         &self,
         node: ID,
         view: View<ID>,
-    ) -> impl AsyncIterator<Item = IROperation<ID, MSG>> {
-        todo!()
+    ) -> impl Stream<Item = IROperation<ID, MSG>> + Send {
+        MockRecordStore::stream(
+            self.received_record_logs
+                .read()
+                .unwrap()
+                .get(&(view, node))
+                .cloned(),
+        )
     }
 
     fn get_main_or_local_operation(
@@ -284,39 +319,242 @@ impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> IRStorage<ID, M
         view: View<ID>,
         client: ID,
         operation_sequence: OperationSequence,
-    ) -> Option<IROperation<ID, MSG>> {
-        todo!()
+    ) -> Pin<Box<dyn Future<Output = Option<IROperation<ID, MSG>>> + Send>> {
+        let main = self.main_records.read().unwrap().get(&view).cloned();
+        let local = self.records.clone();
+        Box::pin(async move {
+            if let Some(main) = main {
+                if let Some(found) = main.find_entry(client.clone(), operation_sequence).await {
+                    return Some(found.ir_operation);
+                }
+            }
+            local
+                .find_entry(client, operation_sequence)
+                .await
+                .map(|found| found.ir_operation)
+        })
+    }
+
+    fn record_main_operation(
+        &self,
+        view: View<ID>,
+        operation: IROperation<ID, MSG>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let main = self
+            .main_records
+            .write()
+            .unwrap()
+            .entry(view.clone())
+            .or_insert_with(MockRecordStore::new)
+            .clone();
+        Box::pin(async move { main.insert_operation(view, operation).await })
     }
 
-    fn record_main_operation(&self, view: View<ID>, operation: IROperation<ID, MSG>) {
-        todo!()
+    fn record_main_operation_add_undecided(
+        &self,
+        view: View<ID>,
+        node: ID,
+        operation: IROperation<ID, MSG>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut wl = self.undecided_records.write().unwrap();
+        // Duplicate writes are a noop, as a node reports one result
+        wl.entry(view)
+            .or_default()
+            .entry((operation.client().clone(), *operation.sequence()))
+            .or_default()
+            .entry(node)
+            .or_insert_with(|| operation.message().clone());
+        Box::pin(async {})
+    }
+
+    fn get_main_record_operations(
+        &self,
+        view: View<ID>,
+    ) -> impl Stream<Item = IROperation<ID, MSG>> + Send {
+        MockRecordStore::stream(self.main_records.read().unwrap().get(&view).cloned())
     }
 
     fn get_unresolved_record_operations(
         &self,
         view: View<ID>,
-    ) -> Pin<Box<dyn Future<Output = impl AsyncIterator<Item = Vec<IROperation<ID, MSG>>>>>> {
-        todo!()
+    ) -> impl Stream<Item = Vec<(ID, IROperation<ID, MSG>)>> + Send {
+        let undecided = self
+            .undecided_records
+            .read()
+            .unwrap()
+            .get(&view)
+            .cloned()
+            .unwrap_or_default();
+        let main = self.main_records.read().unwrap().get(&view).cloned();
+        stream::once(async move {
+            let mut unresolved = Vec::new();
+            for ((client, sequence), results) in undecided {
+                if let Some(main) = &main {
+                    let found = main.find_entry(client.clone(), sequence).await;
+                    if found.is_some_and(|found| found.ir_operation.finalized()) {
+                        continue;
+                    }
+                }
+                unresolved.push(
+                    results
+                        .into_iter()
+                        .map(|(node, message)| {
+                            let operation = IROperation::ConsistentPropose {
+                                client: client.clone(),
+                                sequence,
+                                message,
+                            };
+                            (node, operation)
+                        })
+                        .collect(),
+                );
+            }
+            unresolved
+        })
+        .flat_map(stream::iter)
+    }
+
+    fn append_changes(
+        &self,
+        view: u64,
+        operations: Vec<IROperation<ID, MSG>>,
+    ) -> Pin<Box<dyn Future<Output = u64> + Send + 'static>> {
+        let mut changes = self.changes.write().unwrap();
+        let mut appended = 0;
+        for operation in operations {
+            let key = (operation.client().clone(), *operation.sequence());
+            if changes.logged.insert(key) {
+                changes
+                    .views
+                    .entry(view)
+                    .or_default()
+                    .1
+                    .push_back(operation);
+                appended += 1;
+            }
+        }
+        changes.kept += appended;
+        Box::pin(async move { appended })
     }
-}
 
-impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> IRClientStorage<ID, MSG>
-    for FakeIRStorage<ID, MSG, CPU>
-{
+    fn read_change(
+        &self,
+        from: Option<ChangeCursor>,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Result<Option<(ChangeCursor, IROperation<ID, MSG>)>, ChangeLogError>,
+                > + Send
+                + 'static,
+        >,
+    > {
+        let changes = self.changes.read().unwrap();
+        let from = from.unwrap_or(changes.oldest);
+        let change = if from < changes.oldest {
+            Err(ChangeLogError::CursorTooOld {
+                oldest: changes.oldest,
+            })
+        } else {
+            Ok(changes
+                .views
+                .range(from.view..)
+                .find_map(|(view, (first, operations))| {
+                    let position = if *view == from.view {
+                        from.position.max(*first)
+                    } else {
+                        *first
+                    };
+                    let operation = operations.get((position - first) as usize)?;
+                    Some((
+                        ChangeCursor {
+                            view: *view,
+                            position,
+                        },
+                        operation.clone(),
+                    ))
+                }))
+        };
+        Box::pin(async move { change })
+    }
+
+    fn truncate_changes(&self, retain: u64) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut changes = self.changes.write().unwrap();
+        while changes.kept > retain {
+            let Some(mut oldest) = changes.views.first_entry() else {
+                break;
+            };
+            let view = *oldest.key();
+            let (first, operations) = oldest.get_mut();
+            operations.pop_front();
+            *first += 1;
+            let next = ChangeCursor {
+                view,
+                position: *first,
+            };
+            if operations.is_empty() {
+                oldest.remove();
+            }
+            changes.oldest = next;
+            changes.kept -= 1;
+        }
+        Box::pin(async {})
+    }
+
+    fn record_current_view(
+        &self,
+        view: View<ID>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let current_view = self.current_view.clone();
+        let view_recorded = self.view_recorded.clone();
+        Box::pin(async move {
+            *current_view.write().await = view;
+            view_recorded.store(true, Ordering::SeqCst);
+        })
+    }
+
+    fn is_fresh(&self) -> Pin<Box<dyn Future<Output = bool> + Send + 'static>> {
+        let records = self.records.clone();
+        let main_records = self.main_records.clone();
+        let received_record_logs = self.received_record_logs.clone();
+        let current_view = self.current_view.clone();
+        let view_recorded = self.view_recorded.load(Ordering::SeqCst);
+        Box::pin(async move {
+            !view_recorded
+                && current_view.read().await.view == 0
+                && main_records.read().unwrap().is_empty()
+                && received_record_logs.read().unwrap().is_empty()
+                && records.operations().await.is_empty()
+        })
+    }
 }
 
-impl<ID: NodeID, MSG: IRMessage, CPU: MockOperationHandler<MSG>> FakeIRStorage<ID, MSG, CPU> {
-    pub fn new(members: Vec<ID>, computer: CPU) -> Self {
+impl<ID: NodeID, MSG: IRMessage> IRClientStorage<ID, MSG> for FakeIRStorage<ID, MSG> {}
+
+impl<ID: NodeID, MSG: IRMessage> FakeIRStorage<ID, MSG> {
+    pub fn new(members: Vec<ID>) -> Self {
         FakeIRStorage {
             records: MockRecordStore::new(),
             received_record_logs: Arc::new(RwLock::new(BTreeMap::new())),
+            complete_record_logs: Arc::new(RwLock::new(BTreeMap::new())),
+            main_records: Arc::new(RwLock::new(BTreeMap::new())),
+            undecided_records: Arc::new(RwLock::new(BTreeMap::new())),
             current_view: Arc::new(TokioRwLock::new(View {
                 view: 0,
                 members,
                 // This is stored as normal to validate the nodes always load as recovering
+                learners: vec![],
                 state: ViewState::Normal,
             })),
-            computer_lol: computer,
+            view_recorded: Arc::new(AtomicBool::new(false)),
+            changes: Arc::new(RwLock::new(FakeChangeLog {
+                views: BTreeMap::new(),
+                logged: BTreeSet::new(),
+                oldest: ChangeCursor {
+                    view: 0,
+                    position: 0,
+                },
+                kept: 0,
+            })),
         }
     }
 
@@ -1,18 +1,21 @@
-use crate::types::IRMessage;
+use crate::application::IRApplication;
+use crate::server::IROperation;
+use crate::types::{IRMessage, NodeID};
+use std::future::Future;
+use std::pin::Pin;
 
-pub trait MockOperationHandler<M: IRMessage>: Clone + 'static {
-    fn evaluate_inconsistent(&self, message: M) -> M;
-    fn exec_inconsistent(&self, message: M) -> M;
-    fn exec_consistent(&self, message: M) -> M;
-    fn reconcile_consistent(&self, previous: Option<M>, message: M) -> M;
-}
-
-/// The operation engine that does nothing :)
+/// The application that does nothing :)
 #[derive(Clone)]
 pub struct NoopComputer<M: IRMessage> {
     pub _phantom: std::marker::PhantomData<M>,
 }
 
+impl<M: IRMessage> Default for NoopComputer<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<M: IRMessage> NoopComputer<M> {
     pub fn new() -> Self {
         NoopComputer {
@@ -21,19 +24,20 @@ impl<M: IRMessage> NoopComputer<M> {
     }
 }
 
-impl<M: IRMessage> MockOperationHandler<M> for NoopComputer<M> {
-    fn evaluate_inconsistent(&self, message: M) -> M {
-        message
+impl<I: NodeID, M: IRMessage> IRApplication<I, M> for NoopComputer<M> {
+    fn exec_inconsistent(&self, _message: M) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async {})
     }
-    fn exec_inconsistent(&self, message: M) -> M {
-        message
+
+    fn exec_consensus(&self, message: M) -> Pin<Box<dyn Future<Output = M> + Send>> {
+        Box::pin(async move { message })
     }
 
-    fn exec_consistent(&self, message: M) -> M {
-        message
+    fn decide(&self, choices: Vec<M>, _f: usize) -> M {
+        choices.into_iter().next().unwrap()
     }
 
-    fn reconcile_consistent(&self, _previous: Option<M>, message: M) -> M {
-        message
+    fn sync(&self, _record: Vec<IROperation<I, M>>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async {})
     }
 }
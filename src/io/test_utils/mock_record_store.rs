@@ -1,5 +1,6 @@
 use crate::server::{IROperation, View};
 use crate::types::{IRMessage, NodeID, OperationSequence};
+use futures::{stream, Stream, StreamExt};
 use std::collections::BTreeMap;
 use std::sync::Arc;
 use tokio::sync::RwLock as TokioRwLock;
@@ -58,43 +59,67 @@ impl<ID: NodeID, MSG: IRMessage> MockRecordStore<ID, MSG> {
             .await
             .iter()
             .filter(|(k, _v)| k.client == client && k.sequence == operation)
-            .map(|(k, v)| {
-                let op = match v.operation_type {
-                    OperationType::Consistent => match v.state {
-                        State::Tentative => IROperation::ConsistentPropose {
-                            client: k.client.clone(),
-                            sequence: k.sequence,
-                            message: v.operation.clone(),
-                        },
-                        State::Finalized => IROperation::ConsistentFinalize {
-                            client: k.client.clone(),
-                            sequence: k.sequence,
-                            message: v.operation.clone(),
-                        },
-                    },
-                    OperationType::Inconsistent => match v.state {
-                        State::Tentative => IROperation::InconsistentPropose {
-                            client: k.client.clone(),
-                            sequence: k.sequence,
-                            message: v.operation.clone(),
-                        },
-                        State::Finalized => IROperation::InconsistentFinalize {
-                            client: k.client.clone(),
-                            sequence: k.sequence,
-                            message: v.operation.clone(),
-                        },
-                    },
-                };
-                FullState {
-                    ir_operation: op,
-                    view: k.view.clone(),
-                }
+            .map(|(k, v)| FullState {
+                ir_operation: Self::to_ir_operation(k, v),
+                view: k.view.clone(),
             })
             .collect();
         assert!(found.len() <= 1);
         found.into_iter().next()
     }
 
+    /// Retrieve all the operations in the record, in key order
+    pub(crate) async fn operations(&self) -> Vec<IROperation<ID, MSG>> {
+        self.records
+            .read()
+            .await
+            .iter()
+            .map(|(k, v)| Self::to_ir_operation(k, v))
+            .collect()
+    }
+
+    /// Stream the operations of a record store
+    /// A missing record store is treated as an empty record
+    pub(crate) fn stream(
+        store: Option<Self>,
+    ) -> impl Stream<Item = IROperation<ID, MSG>> + Send + 'static {
+        stream::once(async move {
+            match store {
+                None => Vec::new(),
+                Some(store) => store.operations().await,
+            }
+        })
+        .flat_map(stream::iter)
+    }
+
+    fn to_ir_operation(key: &RecordKey<ID>, value: &RecordValue<MSG>) -> IROperation<ID, MSG> {
+        let client = key.client.clone();
+        let sequence = key.sequence;
+        let message = value.operation.clone();
+        match (&value.operation_type, &value.state) {
+            (OperationType::Consistent, State::Tentative) => IROperation::ConsistentPropose {
+                client,
+                sequence,
+                message,
+            },
+            (OperationType::Consistent, State::Finalized) => IROperation::ConsistentFinalize {
+                client,
+                sequence,
+                message,
+            },
+            (OperationType::Inconsistent, State::Tentative) => IROperation::InconsistentPropose {
+                client,
+                sequence,
+                message,
+            },
+            (OperationType::Inconsistent, State::Finalized) => IROperation::InconsistentFinalize {
+                client,
+                sequence,
+                message,
+            },
+        }
+    }
+
     pub(crate) async fn propose_tentative_inconsistent(
         &self,
         client: ID,
@@ -106,7 +131,7 @@ impl<ID: NodeID, MSG: IRMessage> MockRecordStore<ID, MSG> {
         write_lock.insert(
             RecordKey {
                 client,
-                sequence: sequence,
+                sequence,
                 view,
             },
             RecordValue {
@@ -161,27 +186,63 @@ impl<ID: NodeID, MSG: IRMessage> MockRecordStore<ID, MSG> {
         );
     }
 
-    pub(crate) async fn promote_finalized_consistent_returning_previous_evaluation(
+    pub(crate) async fn promote_finalized_consistent(
         &self,
         client: ID,
         sequence: OperationSequence,
         view: View<ID>,
         operation: MSG,
-    ) -> Option<MSG> {
+    ) {
         let mut write_lock = self.records.write().await;
-        let key = RecordKey {
-            client,
-            sequence,
-            view,
-        };
-        let previous = write_lock.insert(
-            key,
+        write_lock.insert(
+            RecordKey {
+                client,
+                sequence,
+                view,
+            },
             RecordValue {
                 state: State::Finalized,
                 operation_type: OperationType::Consistent,
-                operation: operation.clone(),
+                operation,
             },
         );
-        previous.map(|s| s.operation)
+    }
+
+    /// Record an operation in whichever state it is in
+    pub(crate) async fn insert_operation(&self, view: View<ID>, operation: IROperation<ID, MSG>) {
+        match operation {
+            IROperation::InconsistentPropose {
+                client,
+                sequence,
+                message,
+            } => {
+                self.propose_tentative_inconsistent(client, sequence, view, message)
+                    .await
+            }
+            IROperation::InconsistentFinalize {
+                client,
+                sequence,
+                message,
+            } => {
+                self.promote_finalized_inconsistent(client, sequence, view, message)
+                    .await
+            }
+            IROperation::ConsistentPropose {
+                client,
+                sequence,
+                message,
+            } => {
+                self.propose_tentative_consistent(client, sequence, view, message)
+                    .await
+            }
+            IROperation::ConsistentFinalize {
+                client,
+                sequence,
+                message,
+            } => {
+                self.promote_finalized_consistent(client, sequence, view, message)
+                    .await
+            }
+        }
     }
 }
@@ -1,40 +1,64 @@
 use crate::io::StorageShared;
-use crate::server::View;
+use crate::server::{ChangeCursor, ChangeLogError, IROperation, View};
 use crate::types::{IRMessage, NodeID, OperationSequence};
 use crate::IRStorage;
+use futures::{stream, Stream};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::{Arc, RwLock};
 
+type Log<T> = Arc<RwLock<Vec<T>>>;
+
+/// The calls made to a storage method, with their arguments
+type Invocations<ID, MSG> = Log<(ID, OperationSequence, View<ID>, MSG)>;
+
+/// Responds to a matching call to a storage method
+type Matcher<ID, MSG> =
+    Box<dyn Fn(ID, OperationSequence, View<ID>, MSG) -> Option<()> + Send + Sync>;
+
 #[derive(Clone)]
 /// MockStorage tracks all the calls that happen to the storage, and provides mocked responses
 /// Tbh this should be seldom used - it's much better to access state via real implementations
 pub struct MockStorage<ID: NodeID, MSG: IRMessage> {
     current_view: Arc<RwLock<View<ID>>>,
     record_recover_current_view: Arc<RwLock<Vec<View<ID>>>>,
+    record_current_view_log: Arc<RwLock<Vec<View<ID>>>>,
+
+    record_tentative_inconsistent_log: Invocations<ID, MSG>,
+    matcher_record_tentative_inconsistent: Arc<RwLock<Vec<Matcher<ID, MSG>>>>,
 
-    record_tentative_inconsistent_log: Arc<RwLock<Vec<(ID, OperationSequence, View<ID>, MSG)>>>,
-    matcher_record_tentative_inconsistent:
-        Arc<RwLock<Vec<Box<dyn Fn(ID, OperationSequence, View<ID>, MSG) -> Option<MSG>>>>>,
+    promote_finalized_inconsistent_log: Invocations<ID, MSG>,
+    matcher_promote_finalized_inconsistent: Arc<RwLock<Vec<Matcher<ID, MSG>>>>,
 
-    promote_finalized_inconsistent_log: Arc<RwLock<Vec<(ID, OperationSequence, View<ID>, MSG)>>>,
-    matcher_promote_finalized_inconsistent:
-        Arc<RwLock<Vec<Box<dyn Fn(ID, OperationSequence, View<ID>, MSG) -> Option<()>>>>>,
+    record_tentative_consistent_log: Invocations<ID, MSG>,
+    matcher_record_tentative_consistent: Arc<RwLock<Vec<Matcher<ID, MSG>>>>,
 
-    record_tentative_consistent_log: Arc<RwLock<Vec<(ID, OperationSequence, View<ID>, MSG)>>>,
-    matcher_record_tentative_consistent:
-        Arc<RwLock<Vec<Box<dyn Fn(ID, OperationSequence, View<ID>, MSG) -> Option<MSG>>>>>,
+    promote_finalized_consistent_log: Invocations<ID, MSG>,
+    matcher_promote_finalized_consistent: Arc<RwLock<Vec<Matcher<ID, MSG>>>>,
 
-    promote_finalized_consistent_log: Arc<RwLock<Vec<(ID, OperationSequence, View<ID>, MSG)>>>,
-    matcher_promote_finalized_consistent:
-        Arc<RwLock<Vec<Box<dyn Fn(ID, OperationSequence, View<ID>, MSG) -> Option<MSG>>>>>,
+    peer_view_change_log: Log<(ID, View<ID>, IROperation<ID, MSG>)>,
+    complete_peer_view_change_log: Log<(ID, View<ID>)>,
+    main_operation_log: Log<(View<ID>, IROperation<ID, MSG>)>,
+    undecided_operation_log: Log<(View<ID>, ID, IROperation<ID, MSG>)>,
+    changes: Arc<RwLock<MockChangeLog<ID, MSG>>>,
+}
+
+/// The change log, with each operation at its cursor
+struct MockChangeLog<ID: NodeID, MSG: IRMessage> {
+    kept: VecDeque<(ChangeCursor, IROperation<ID, MSG>)>,
+    /// Every operation ever logged, including those truncated
+    logged: BTreeSet<(ID, OperationSequence)>,
+    /// The position of the next operation logged in each view
+    next_positions: BTreeMap<u64, u64>,
+    /// The position of the oldest operation kept; earlier ones have been truncated
+    oldest: ChangeCursor,
 }
 
 impl<ID: NodeID, MSG: IRMessage> StorageShared<ID> for MockStorage<ID, MSG> {
-    fn recover_current_view(&self) -> Pin<Box<dyn Future<Output = View<ID>> + 'static>> {
+    fn recover_current_view(&self) -> Pin<Box<dyn Future<Output = View<ID>> + Send + 'static>> {
         let view = self.current_view.read().unwrap().clone();
-        let view_record = self
-            .record_recover_current_view
+        self.record_recover_current_view
             .write()
             .unwrap()
             .push(view.clone());
@@ -43,13 +67,13 @@ impl<ID: NodeID, MSG: IRMessage> StorageShared<ID> for MockStorage<ID, MSG> {
 }
 
 impl<ID: NodeID, MSG: IRMessage> IRStorage<ID, MSG> for MockStorage<ID, MSG> {
-    fn record_tentative_inconsistent_and_evaluate(
+    fn record_tentative_inconsistent(
         &self,
         client: ID,
         operation: OperationSequence,
         view: View<ID>,
         message: MSG,
-    ) -> Pin<Box<dyn Future<Output = MSG> + 'static>> {
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
         self.record_tentative_inconsistent_log
             .write()
             .unwrap()
@@ -63,17 +87,18 @@ impl<ID: NodeID, MSG: IRMessage> IRStorage<ID, MSG> for MockStorage<ID, MSG> {
                 .map(|f| f(client.clone(), operation, view.clone(), message.clone()))
                 .find(|res| res.is_some())
                 .flatten()
+                .ok_or("No matching mock for tentative inconsistent")
                 .unwrap()
         })
     }
 
-    fn promote_finalized_and_exec_inconsistent(
+    fn promote_finalized_inconsistent(
         &self,
         client: ID,
         operation: OperationSequence,
         view: View<ID>,
         message: MSG,
-    ) -> Pin<Box<dyn Future<Output = ()> + 'static>> {
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
         self.promote_finalized_inconsistent_log
             .write()
             .unwrap()
@@ -92,13 +117,13 @@ impl<ID: NodeID, MSG: IRMessage> IRStorage<ID, MSG> for MockStorage<ID, MSG> {
         })
     }
 
-    fn record_tentative_and_exec_consistent(
+    fn record_tentative_consistent(
         &self,
         client: ID,
         operation: OperationSequence,
         view: View<ID>,
         message: MSG,
-    ) -> Pin<Box<dyn Future<Output = MSG> + 'static>> {
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
         self.record_tentative_consistent_log.write().unwrap().push((
             client.clone(),
             operation,
@@ -119,13 +144,13 @@ impl<ID: NodeID, MSG: IRMessage> IRStorage<ID, MSG> for MockStorage<ID, MSG> {
         })
     }
 
-    fn promote_finalized_and_reconcile_consistent(
+    fn promote_finalized_consistent(
         &self,
         client: ID,
         operation: OperationSequence,
         view: View<ID>,
         message: MSG,
-    ) -> Pin<Box<dyn Future<Output = MSG> + 'static>> {
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
         self.promote_finalized_consistent_log
             .write()
             .unwrap()
@@ -139,9 +164,309 @@ impl<ID: NodeID, MSG: IRMessage> IRStorage<ID, MSG> for MockStorage<ID, MSG> {
                 .map(|f| f(client.clone(), operation, view.clone(), message.clone()))
                 .find(|f| f.is_some())
                 .flatten()
+                .ok_or("No matching mock for finalized consistent")
                 .unwrap()
         })
     }
+
+    fn add_peer_view_change_operation(
+        &self,
+        node_id: ID,
+        view: View<ID>,
+        operation: IROperation<ID, MSG>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.peer_view_change_log
+            .write()
+            .unwrap()
+            .push((node_id, view, operation));
+        Box::pin(async {})
+    }
+
+    fn complete_peer_view_change_record(
+        &self,
+        node_id: ID,
+        view: View<ID>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.complete_peer_view_change_log
+            .write()
+            .unwrap()
+            .push((node_id, view));
+        Box::pin(async {})
+    }
+
+    fn get_peers_with_full_records(
+        &self,
+        view: View<ID>,
+    ) -> Pin<Box<dyn Future<Output = Vec<ID>> + Send + 'static>> {
+        let mut peers = Vec::new();
+        for (node, completed) in self.complete_peer_view_change_log.read().unwrap().iter() {
+            if *completed == view && !peers.contains(node) {
+                peers.push(node.clone());
+            }
+        }
+        Box::pin(async move { peers })
+    }
+
+    fn get_view_record_operations(
+        &self,
+        node: ID,
+        view: View<ID>,
+    ) -> impl Stream<Item = IROperation<ID, MSG>> + Send {
+        let operations: Vec<_> = self
+            .peer_view_change_log
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(from, received_in, _)| *from == node && *received_in == view)
+            .map(|(_, _, operation)| operation.clone())
+            .collect();
+        stream::iter(operations)
+    }
+
+    fn get_main_or_local_operation(
+        &self,
+        view: View<ID>,
+        client: ID,
+        operation_sequence: OperationSequence,
+    ) -> Pin<Box<dyn Future<Output = Option<IROperation<ID, MSG>>> + Send>> {
+        let is_operation = |logged_client: &ID, logged_sequence: &OperationSequence| {
+            *logged_client == client && *logged_sequence == operation_sequence
+        };
+        let main = self
+            .main_operation_log
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|(main_view, operation)| {
+                *main_view == view && is_operation(operation.client(), operation.sequence())
+            })
+            .map(|(_, operation)| operation.clone());
+        // Otherwise the most advanced local record, with finalized operations before tentative
+        let local = |log: &Invocations<ID, MSG>| {
+            log.read()
+                .unwrap()
+                .iter()
+                .rev()
+                .find(|(logged_client, logged_sequence, _, _)| {
+                    is_operation(logged_client, logged_sequence)
+                })
+                .map(|(_, _, _, message)| message.clone())
+        };
+        let operation = main
+            .or_else(|| {
+                local(&self.promote_finalized_consistent_log).map(|message| {
+                    IROperation::ConsistentFinalize {
+                        client: client.clone(),
+                        sequence: operation_sequence,
+                        message,
+                    }
+                })
+            })
+            .or_else(|| {
+                local(&self.promote_finalized_inconsistent_log).map(|message| {
+                    IROperation::InconsistentFinalize {
+                        client: client.clone(),
+                        sequence: operation_sequence,
+                        message,
+                    }
+                })
+            })
+            .or_else(|| {
+                local(&self.record_tentative_consistent_log).map(|message| {
+                    IROperation::ConsistentPropose {
+                        client: client.clone(),
+                        sequence: operation_sequence,
+                        message,
+                    }
+                })
+            })
+            .or_else(|| {
+                local(&self.record_tentative_inconsistent_log).map(|message| {
+                    IROperation::InconsistentPropose {
+                        client: client.clone(),
+                        sequence: operation_sequence,
+                        message,
+                    }
+                })
+            });
+        Box::pin(async move { operation })
+    }
+
+    fn record_main_operation(
+        &self,
+        view: View<ID>,
+        operation: IROperation<ID, MSG>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.main_operation_log
+            .write()
+            .unwrap()
+            .push((view, operation));
+        Box::pin(async {})
+    }
+
+    fn record_main_operation_add_undecided(
+        &self,
+        view: View<ID>,
+        node: ID,
+        operation: IROperation<ID, MSG>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.undecided_operation_log
+            .write()
+            .unwrap()
+            .push((view, node, operation));
+        Box::pin(async {})
+    }
+
+    fn get_main_record_operations(
+        &self,
+        view: View<ID>,
+    ) -> impl Stream<Item = IROperation<ID, MSG>> + Send {
+        // Each operation once, as last recorded, in the order first recorded
+        let mut operations: Vec<IROperation<ID, MSG>> = Vec::new();
+        for (main_view, operation) in self.main_operation_log.read().unwrap().iter() {
+            if *main_view != view {
+                continue;
+            }
+            match operations.iter_mut().find(|recorded| {
+                recorded.client() == operation.client()
+                    && recorded.sequence() == operation.sequence()
+            }) {
+                Some(recorded) => *recorded = operation.clone(),
+                None => operations.push(operation.clone()),
+            }
+        }
+        stream::iter(operations)
+    }
+
+    fn get_unresolved_record_operations(
+        &self,
+        view: View<ID>,
+    ) -> impl Stream<Item = Vec<(ID, IROperation<ID, MSG>)>> + Send {
+        let finalized: Vec<(ID, OperationSequence)> = self
+            .main_operation_log
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(main_view, operation)| *main_view == view && operation.finalized())
+            .map(|(_, operation)| (operation.client().clone(), *operation.sequence()))
+            .collect();
+        // The first result each node reported, grouped by operation
+        let mut unresolved: BTreeMap<_, Vec<(ID, IROperation<ID, MSG>)>> = BTreeMap::new();
+        for (undecided_view, node, operation) in self.undecided_operation_log.read().unwrap().iter()
+        {
+            let key = (operation.client().clone(), *operation.sequence());
+            if *undecided_view != view || finalized.contains(&key) {
+                continue;
+            }
+            let results = unresolved.entry(key).or_default();
+            if !results.iter().any(|(reported_by, _)| reported_by == node) {
+                results.push((node.clone(), operation.clone()));
+            }
+        }
+        stream::iter(unresolved.into_values())
+    }
+
+    fn append_changes(
+        &self,
+        view: u64,
+        operations: Vec<IROperation<ID, MSG>>,
+    ) -> Pin<Box<dyn Future<Output = u64> + Send + 'static>> {
+        let mut changes = self.changes.write().unwrap();
+        let mut appended = 0;
+        for operation in operations {
+            let key = (operation.client().clone(), *operation.sequence());
+            if changes.logged.insert(key) {
+                let position = changes.next_positions.entry(view).or_insert(0);
+                let cursor = ChangeCursor {
+                    view,
+                    position: *position,
+                };
+                *position += 1;
+                changes.kept.push_back((cursor, operation));
+                appended += 1;
+            }
+        }
+        Box::pin(async move { appended })
+    }
+
+    fn read_change(
+        &self,
+        from: Option<ChangeCursor>,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Result<Option<(ChangeCursor, IROperation<ID, MSG>)>, ChangeLogError>,
+                > + Send
+                + 'static,
+        >,
+    > {
+        let changes = self.changes.read().unwrap();
+        let change = match from {
+            Some(from) if from < changes.oldest => Err(ChangeLogError::CursorTooOld {
+                oldest: changes.oldest,
+            }),
+            from => Ok(changes
+                .kept
+                .iter()
+                .filter(|(cursor, _)| from.is_none_or(|from| *cursor >= from))
+                .min_by_key(|(cursor, _)| *cursor)
+                .cloned()),
+        };
+        Box::pin(async move { change })
+    }
+
+    fn truncate_changes(&self, retain: u64) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut changes = self.changes.write().unwrap();
+        while changes.kept.len() as u64 > retain {
+            let Some((cursor, _)) = changes.kept.pop_front() else {
+                break;
+            };
+            changes.oldest = ChangeCursor {
+                position: cursor.position + 1,
+                ..cursor
+            };
+        }
+        Box::pin(async {})
+    }
+
+    fn record_current_view(
+        &self,
+        view: View<ID>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.record_current_view_log
+            .write()
+            .unwrap()
+            .push(view.clone());
+        *self.current_view.write().unwrap() = view;
+        Box::pin(async {})
+    }
+
+    fn is_fresh(&self) -> Pin<Box<dyn Future<Output = bool> + Send + 'static>> {
+        let fresh = self.current_view.read().unwrap().view == 0
+            && self.record_current_view_log.read().unwrap().is_empty()
+            && self
+                .record_tentative_inconsistent_log
+                .read()
+                .unwrap()
+                .is_empty()
+            && self
+                .promote_finalized_inconsistent_log
+                .read()
+                .unwrap()
+                .is_empty()
+            && self
+                .record_tentative_consistent_log
+                .read()
+                .unwrap()
+                .is_empty()
+            && self
+                .promote_finalized_consistent_log
+                .read()
+                .unwrap()
+                .is_empty();
+        Box::pin(async move { fresh })
+    }
 }
 
 impl<ID: NodeID, MSG: IRMessage> MockStorage<ID, MSG> {
@@ -149,6 +474,7 @@ impl<ID: NodeID, MSG: IRMessage> MockStorage<ID, MSG> {
         MockStorage {
             current_view: Arc::new(RwLock::new(current_view)),
             record_recover_current_view: Arc::new(Default::default()),
+            record_current_view_log: Arc::new(Default::default()),
             record_tentative_inconsistent_log: Arc::new(Default::default()),
             matcher_record_tentative_inconsistent: Arc::new(Default::default()),
             promote_finalized_inconsistent_log: Arc::new(Default::default()),
@@ -157,42 +483,43 @@ impl<ID: NodeID, MSG: IRMessage> MockStorage<ID, MSG> {
             matcher_record_tentative_consistent: Arc::new(Default::default()),
             promote_finalized_consistent_log: Arc::new(Default::default()),
             matcher_promote_finalized_consistent: Arc::new(Default::default()),
+            peer_view_change_log: Arc::new(Default::default()),
+            complete_peer_view_change_log: Arc::new(Default::default()),
+            main_operation_log: Arc::new(Default::default()),
+            undecided_operation_log: Arc::new(Default::default()),
+            changes: Arc::new(RwLock::new(MockChangeLog {
+                kept: VecDeque::new(),
+                logged: BTreeSet::new(),
+                next_positions: BTreeMap::new(),
+                oldest: ChangeCursor {
+                    view: 0,
+                    position: 0,
+                },
+            })),
         }
     }
-    pub fn mock_record_tentative_inconsistent_and_evaluate(
-        &self,
-        matcher: Box<dyn Fn(ID, OperationSequence, View<ID>, MSG) -> Option<MSG>>,
-    ) {
+    pub fn mock_record_tentative_inconsistent(&self, matcher: Matcher<ID, MSG>) {
         self.matcher_record_tentative_inconsistent
             .write()
             .unwrap()
             .push(matcher);
     }
 
-    pub fn mock_record_tentative_consistent(
-        &self,
-        matcher: Box<dyn Fn(ID, OperationSequence, View<ID>, MSG) -> Option<MSG>>,
-    ) {
+    pub fn mock_record_tentative_consistent(&self, matcher: Matcher<ID, MSG>) {
         self.matcher_record_tentative_consistent
             .write()
             .unwrap()
             .push(matcher);
     }
 
-    pub fn mock_promote_inconsistent(
-        &self,
-        matcher: Box<dyn Fn(ID, OperationSequence, View<ID>, MSG) -> Option<()>>,
-    ) {
+    pub fn mock_promote_inconsistent(&self, matcher: Matcher<ID, MSG>) {
         self.matcher_promote_finalized_inconsistent
             .write()
             .unwrap()
             .push(matcher);
     }
 
-    pub fn mock_promote_consistent(
-        &self,
-        matcher: Box<dyn Fn(ID, OperationSequence, View<ID>, MSG) -> Option<MSG>>,
-    ) {
+    pub fn mock_promote_consistent(&self, matcher: Matcher<ID, MSG>) {
         self.matcher_promote_finalized_consistent
             .write()
             .unwrap()
@@ -203,6 +530,10 @@ impl<ID: NodeID, MSG: IRMessage> MockStorage<ID, MSG> {
         self.record_recover_current_view.read().unwrap().clone()
     }
 
+    pub fn get_invocations_record_current_view(&self) -> Vec<View<ID>> {
+        self.record_current_view_log.read().unwrap().clone()
+    }
+
     pub fn get_invocations_record_tentative_consistent(
         &self,
     ) -> Vec<(ID, OperationSequence, View<ID>, MSG)> {
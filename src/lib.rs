@@ -1,15 +1,36 @@
-#![feature(specialization)]
-#![feature(async_iterator)]
-
+mod application;
+pub mod atomic;
 mod client;
-pub(crate) mod debug;
+pub mod crdt;
+pub mod datatypes;
+pub mod group;
+pub mod hlc;
 mod io;
+pub mod lock;
+pub mod metrics;
+pub mod quorum;
 mod server;
+#[cfg(feature = "tapir")]
+pub mod tapir;
 pub mod types;
 pub(crate) mod utils;
 
-pub use client::InconsistentReplicationClient;
+pub use application::IRApplication;
+pub use client::{
+    HashPartitioner, InFlightOperation, InconsistentReplicationClient, KeyedMessage,
+    OperationStage, Partitioner, RangePartitioner, ShardedClient,
+};
+pub use io::codec;
+pub use io::layers;
+#[cfg(feature = "tcp")]
+pub use io::tcp;
 #[cfg(any(test, feature = "test"))]
 pub use io::test_utils;
-pub use io::{IRNetwork, IRStorage};
-pub use server::InconsistentReplicationServer;
+pub use io::{
+    BatchingConfig, BatchingNetwork, CarrierRequest, IRNetwork, IRNetworkError, IRStorage,
+    LogStorage, MembersClientStorage,
+};
+pub use server::{
+    BootstrapError, ChangeCursor, ChangeLogError, IROperation, IRServerError,
+    InconsistentReplicationServer, StartViewError, View, ViewState,
+};
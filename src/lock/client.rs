@@ -0,0 +1,97 @@
+use crate::client::InconsistentReplicationClient;
+use crate::io::{IRClientStorage, IRNetwork};
+use crate::lock::{LockDecide, LockKey, LockMessage, LockResult};
+use crate::types::NodeID;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+/// Why a lock was not acquired
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LockError {
+    /// Another client holds the lock
+    Held,
+    /// The operation could not be replicated
+    Replication(&'static str),
+}
+
+/// A typed client of the lock service
+/// Locks are owned by the id of the underlying client, so each must have a different id.
+pub struct LockClient<N, S, I, K>
+where
+    N: IRNetwork<I, LockMessage<K, I>>,
+    S: IRClientStorage<I, LockMessage<K, I>>,
+    I: NodeID,
+    K: LockKey,
+{
+    client: InconsistentReplicationClient<N, S, I, LockMessage<K, I>>,
+    /// The locks this client acquired and has not released
+    held: Mutex<BTreeSet<K>>,
+}
+
+impl<N, S, I, K> LockClient<N, S, I, K>
+where
+    N: IRNetwork<I, LockMessage<K, I>> + 'static,
+    S: IRClientStorage<I, LockMessage<K, I>> + 'static,
+    I: NodeID,
+    K: LockKey,
+{
+    pub fn new(client: InconsistentReplicationClient<N, S, I, LockMessage<K, I>>) -> Self {
+        LockClient {
+            client,
+            held: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Acquire a lock, failing if another client holds it
+    /// Acquiring a lock the client already holds succeeds without asking the replicas, as a
+    /// refused attempt would release the grants it is holding.
+    pub async fn lock(&self, key: K) -> Result<(), LockError> {
+        if self.held.lock().unwrap().contains(&key) {
+            return Ok(());
+        }
+        let owner = self.client.client_id().clone();
+        let result = self
+            .client
+            .invoke_consistent(
+                LockMessage::Lock {
+                    key: key.clone(),
+                    owner: owner.clone(),
+                    result: None,
+                },
+                LockDecide,
+            )
+            .await
+            .map_err(LockError::Replication)?;
+        match result {
+            LockMessage::Lock {
+                result: Some(LockResult::Granted),
+                ..
+            } => {
+                self.held.lock().unwrap().insert(key);
+                Ok(())
+            }
+            _ => {
+                // Replicas that granted the lock tentatively release it
+                self.client
+                    .invoke_inconsistent(LockMessage::Unlock { key, owner })
+                    .await
+                    .map_err(LockError::Replication)?;
+                Err(LockError::Held)
+            }
+        }
+    }
+
+    /// Release a lock held by the client
+    pub async fn unlock(&self, key: K) -> Result<(), LockError> {
+        let owner = self.client.client_id().clone();
+        self.client
+            .invoke_inconsistent(LockMessage::Unlock {
+                key: key.clone(),
+                owner,
+            })
+            .await
+            .map_err(LockError::Replication)?;
+        self.held.lock().unwrap().remove(&key);
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+//! A replicated lock service, the running example of the IR paper.
+//!
+//! `Lock` is a consensus operation: each replica grants the lock if no other client holds it,
+//! and the lock is only acquired if the replicas agree. `Unlock` is an inconsistent
+//! operation, as releases of different locks commute.
+//!
+//! ```ignore
+//! let locks = LockClient::new(client);
+//! locks.lock("leader").await?;
+//! // ... only one client gets here at a time
+//! locks.unlock("leader").await?;
+//! ```
+//!
+//! Replicas run `LockReplica` as their `IRApplication`.
+
+mod client;
+mod replica;
+#[cfg(test)]
+mod test;
+
+pub use client::{LockClient, LockError};
+pub use replica::LockReplica;
+
+use crate::types::{DecideFunction, NodeID};
+use std::fmt::Debug;
+
+/// A lock name
+pub trait LockKey: Clone + Ord + Debug + Send + Sync + 'static {}
+
+impl<A> LockKey for A where A: Clone + Ord + Debug + Send + Sync + 'static {}
+
+/// A replica's answer to a lock request
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LockResult {
+    /// The lock is held by the requesting client
+    Granted,
+    /// Another client holds the lock
+    Held,
+}
+
+/// The operations of the lock service, with results filled in by replicas
+/// The owner is the id of the client, as the replicas' application does not see it otherwise.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LockMessage<K: LockKey, I: NodeID> {
+    /// Consensus
+    Lock {
+        key: K,
+        owner: I,
+        result: Option<LockResult>,
+    },
+    /// Inconsistent; only releases the lock if the owner holds it
+    Unlock { key: K, owner: I },
+}
+
+/// Decides lock results when replicas disagree, on the client and during view changes
+/// A lock is only granted if at least f+1 replicas granted it, where f is the number of
+/// failures the view tolerates, so a client that only reaches some of the members cannot
+/// take a lock that a client reaching the others also takes. Otherwise the lock is `Held`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LockDecide;
+
+impl<K: LockKey, I: NodeID> DecideFunction<LockMessage<K, I>> for LockDecide {
+    fn decide<'a, S: IntoIterator<Item = &'a LockMessage<K, I>>>(
+        &self,
+        choices: S,
+        f: usize,
+    ) -> LockMessage<K, I> {
+        decide(choices, f)
+    }
+}
+
+/// The decided choice, shared by `LockDecide` and `LockReplica::decide`
+fn decide<'a, K: LockKey, I: NodeID>(
+    choices: impl IntoIterator<Item = &'a LockMessage<K, I>>,
+    f: usize,
+) -> LockMessage<K, I> {
+    let choices: Vec<_> = choices.into_iter().collect();
+    let grants = choices
+        .iter()
+        .filter(|choice| {
+            matches!(
+                choice,
+                LockMessage::Lock {
+                    result: Some(LockResult::Granted),
+                    ..
+                }
+            )
+        })
+        .count();
+    let result = if grants > f {
+        LockResult::Granted
+    } else {
+        LockResult::Held
+    };
+    match choices.first() {
+        Some(LockMessage::Lock { key, owner, .. }) => LockMessage::Lock {
+            key: key.clone(),
+            owner: owner.clone(),
+            result: Some(result),
+        },
+        Some(unlock) => (*unlock).clone(),
+        None => panic!("a decision needs at least one choice"),
+    }
+}
@@ -0,0 +1,149 @@
+use crate::application::IRApplication;
+use crate::lock::{decide, LockKey, LockMessage, LockResult};
+use crate::server::IROperation;
+use crate::types::{NodeID, OperationSequence};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// The sequence numbers of an owner's latest granted `Lock` and latest `Unlock` of a key
+type LockHistory = (Option<OperationSequence>, Option<OperationSequence>);
+
+/// The replica side of the lock service, an `IRApplication` holding the lock table
+pub struct LockReplica<K: LockKey, I: NodeID> {
+    holders: Arc<Mutex<BTreeMap<K, I>>>,
+}
+
+impl<K: LockKey, I: NodeID> Clone for LockReplica<K, I> {
+    fn clone(&self) -> Self {
+        LockReplica {
+            holders: self.holders.clone(),
+        }
+    }
+}
+
+impl<K: LockKey, I: NodeID> Default for LockReplica<K, I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: LockKey, I: NodeID> LockReplica<K, I> {
+    pub fn new() -> Self {
+        LockReplica {
+            holders: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// The client this replica believes holds a lock
+    pub fn holder(&self, key: &K) -> Option<I> {
+        self.holders.lock().unwrap().get(key).cloned()
+    }
+}
+
+impl<K: LockKey, I: NodeID> IRApplication<I, LockMessage<K, I>> for LockReplica<K, I> {
+    fn exec_inconsistent(
+        &self,
+        message: LockMessage<K, I>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        if let LockMessage::Unlock { key, owner } = message {
+            let mut holders = self.holders.lock().unwrap();
+            if holders.get(&key) == Some(&owner) {
+                holders.remove(&key);
+            }
+        }
+        Box::pin(async {})
+    }
+
+    /// Grant the lock if it is free or already held by the owner, holding it tentatively
+    /// until the client unlocks it or a view change decides otherwise
+    fn exec_consensus(
+        &self,
+        message: LockMessage<K, I>,
+    ) -> Pin<Box<dyn Future<Output = LockMessage<K, I>> + Send + 'static>> {
+        let result = match message {
+            LockMessage::Lock { key, owner, .. } => {
+                let mut holders = self.holders.lock().unwrap();
+                let holder = holders.entry(key.clone()).or_insert_with(|| owner.clone());
+                let result = if *holder == owner {
+                    LockResult::Granted
+                } else {
+                    LockResult::Held
+                };
+                LockMessage::Lock {
+                    key,
+                    owner,
+                    result: Some(result),
+                }
+            }
+            unlock => unlock,
+        };
+        Box::pin(async move { result })
+    }
+
+    /// Learners hold the locks that were granted
+    fn exec_finalized_consensus(
+        &self,
+        message: LockMessage<K, I>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        if let LockMessage::Lock {
+            key,
+            owner,
+            result: Some(LockResult::Granted),
+        } = message
+        {
+            self.holders.lock().unwrap().insert(key, owner);
+        }
+        Box::pin(async {})
+    }
+
+    fn decide(&self, choices: Vec<LockMessage<K, I>>, f: usize) -> LockMessage<K, I> {
+        decide(&choices, f)
+    }
+
+    /// Rebuild the holders of the locks in the master record, dropping tentative grants that
+    /// were not decided
+    /// A client holds a lock if its latest granted `Lock` of it is after its latest `Unlock`.
+    fn sync(
+        &self,
+        record: Vec<IROperation<I, LockMessage<K, I>>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut latest: BTreeMap<K, BTreeMap<I, LockHistory>> = BTreeMap::new();
+        for operation in &record {
+            let sequence = *operation.sequence();
+            match operation.message() {
+                LockMessage::Lock { key, owner, result } => {
+                    let (locked, _) = latest
+                        .entry(key.clone())
+                        .or_default()
+                        .entry(owner.clone())
+                        .or_default();
+                    if operation.finalized() && *result == Some(LockResult::Granted) {
+                        *locked = (*locked).max(Some(sequence));
+                    }
+                }
+                LockMessage::Unlock { key, owner } => {
+                    let (_, unlocked) = latest
+                        .entry(key.clone())
+                        .or_default()
+                        .entry(owner.clone())
+                        .or_default();
+                    *unlocked = (*unlocked).max(Some(sequence));
+                }
+            }
+        }
+        let mut holders = self.holders.lock().unwrap();
+        for (key, owners) in latest {
+            let holder = owners
+                .into_iter()
+                .find(|(_, (locked, unlocked))| locked.is_some() && locked > unlocked)
+                .map(|(owner, _)| owner);
+            match holder {
+                Some(owner) => holders.insert(key, owner),
+                None => holders.remove(&key),
+            };
+        }
+        Box::pin(async {})
+    }
+}
@@ -0,0 +1,149 @@
+use crate::io::test_utils::{fake_cluster, FakeIRNetwork, FakeIRStorage};
+use crate::lock::{LockClient, LockError, LockMessage, LockReplica, LockResult};
+use crate::server::IROperation;
+use crate::{IRApplication, InconsistentReplicationClient};
+
+type Message = LockMessage<String, u64>;
+type Network = FakeIRNetwork<u64, Message, FakeIRStorage<u64, Message>, LockReplica<String, u64>>;
+
+async fn lock_client(
+    network: &Network,
+    members: Vec<u64>,
+    client_id: u64,
+) -> LockClient<Network, FakeIRStorage<u64, Message>, u64, String> {
+    LockClient::new(
+        InconsistentReplicationClient::new(network.clone(), FakeIRStorage::new(members), client_id)
+            .await,
+    )
+}
+
+#[tokio::test]
+async fn locks_are_exclusive_until_unlocked() {
+    // given a cluster and two clients
+    let network = Network::new();
+    let members = vec![1, 2, 3];
+    let replicas = fake_cluster(&network, &members, LockReplica::new).await;
+    let first = lock_client(&network, members.clone(), 100).await;
+    let second = lock_client(&network, members, 200).await;
+
+    // when the first takes the lock
+    assert_eq!(first.lock("leader".to_string()).await, Ok(()));
+
+    // then the second cannot, until the first releases it
+    assert_eq!(
+        second.lock("leader".to_string()).await,
+        Err(LockError::Held)
+    );
+    first.unlock("leader".to_string()).await.unwrap();
+    assert_eq!(second.lock("leader".to_string()).await, Ok(()));
+    for replica in &replicas {
+        assert_eq!(replica.holder(&"leader".to_string()), Some(200));
+    }
+}
+
+#[tokio::test]
+async fn contending_clients_on_either_side_of_a_partition_do_not_both_lock() {
+    // given 5 members, and two clients that each reach 3 of them, sharing only node 3
+    let network = Network::new();
+    let members = vec![1, 2, 3, 4, 5];
+    let replicas = fake_cluster(&network, &members, LockReplica::new).await;
+    let first = lock_client(&network.partition(&[1, 2, 3]), members.clone(), 100).await;
+    let second = lock_client(&network.partition(&[3, 4, 5]), members, 200).await;
+
+    // when the second takes the lock, and then the first tries to
+    assert_eq!(second.lock("leader".to_string()).await, Ok(()));
+    let result = first.lock("leader".to_string()).await;
+
+    // then the first's two grants are not f+1 of the members, so only the second holds it
+    assert_eq!(result, Err(LockError::Held));
+    let holders: Vec<_> = replicas
+        .iter()
+        .map(|replica| replica.holder(&"leader".to_string()))
+        .collect();
+    assert_eq!(holders, vec![None, None, Some(200), Some(200), Some(200)]);
+}
+
+#[tokio::test]
+async fn sync_keeps_only_the_locks_the_master_record_granted() {
+    let lock = |client, sequence, key: &str, result| IROperation::ConsistentFinalize {
+        client,
+        sequence,
+        message: LockMessage::Lock {
+            key: key.to_string(),
+            owner: client,
+            result: Some(result),
+        },
+    };
+    let unlock = |client, sequence, key: &str| IROperation::InconsistentFinalize {
+        client,
+        sequence,
+        message: LockMessage::Unlock {
+            key: key.to_string(),
+            owner: client,
+        },
+    };
+
+    // given a replica that tentatively granted a lock the master record refused
+    let replica = LockReplica::<String, u64>::new();
+    IRApplication::<u64, Message>::exec_consensus(
+        &replica,
+        LockMessage::Lock {
+            key: "a".to_string(),
+            owner: 300,
+            result: None,
+        },
+    )
+    .await;
+
+    // when it syncs with a master record where the lock went elsewhere
+    replica
+        .sync(vec![
+            lock(300, 0, "a", LockResult::Held),
+            lock(100, 0, "a", LockResult::Granted),
+            unlock(100, 1, "a"),
+            lock(200, 0, "a", LockResult::Granted),
+            lock(100, 2, "b", LockResult::Granted),
+        ])
+        .await;
+
+    // then it holds the locks as the master record decided
+    assert_eq!(replica.holder(&"a".to_string()), Some(200));
+    assert_eq!(replica.holder(&"b".to_string()), Some(100));
+}
+
+#[tokio::test]
+async fn relocking_a_held_lock_keeps_it() {
+    // given 5 members, where another client's attempt tentatively holds the lock on two
+    let network = Network::new();
+    let members = vec![1, 2, 3, 4, 5];
+    let replicas = fake_cluster(&network, &members, LockReplica::new).await;
+    for replica in &replicas[..2] {
+        IRApplication::<u64, Message>::exec_consensus(
+            replica,
+            LockMessage::Lock {
+                key: "leader".to_string(),
+                owner: 300,
+                result: None,
+            },
+        )
+        .await;
+    }
+    let client = lock_client(&network, members, 200).await;
+    assert_eq!(client.lock("leader".to_string()).await, Ok(()));
+
+    // when the client locks it again, while two of its grants are unreachable
+    network.drop_requests_add(4, 100);
+    network.drop_requests_add(5, 100);
+    let result = client.lock("leader".to_string()).await;
+
+    // then it still holds the lock on every replica that granted it
+    assert_eq!(result, Ok(()));
+    let holders: Vec<_> = replicas
+        .iter()
+        .map(|replica| replica.holder(&"leader".to_string()))
+        .collect();
+    assert_eq!(
+        holders,
+        vec![Some(300), Some(300), Some(200), Some(200), Some(200)]
+    );
+}
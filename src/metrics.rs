@@ -0,0 +1,287 @@
+//! Counters and histograms reported by the client and server.
+//!
+//! Implement `Metrics` to forward to your own metrics system, or use `PrometheusMetrics`
+//! and serve `PrometheusMetrics::render` from a scrape endpoint.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Quorums found by clients, labelled `type` of `fast` or `slow`
+pub const QUORUM_TOTAL: &str = "ir_quorum_total";
+/// Operations for which clients could not find a quorum, labelled by `operation`
+pub const NO_QUORUM_TOTAL: &str = "ir_no_quorum_total";
+/// Requests to a replica that were unreachable or timed out, labelled by `node`
+pub const NODE_UNREACHABLE_TOTAL: &str = "ir_node_unreachable_total";
+/// Client operation latency in seconds, labelled by `operation`
+pub const OPERATION_DURATION_SECONDS: &str = "ir_operation_duration_seconds";
+/// Views that a replica has started
+pub const VIEW_CHANGES_TOTAL: &str = "ir_view_changes_total";
+/// Time from a replica starting in recovery to it resuming normal operation
+pub const RECOVERY_DURATION_SECONDS: &str = "ir_recovery_duration_seconds";
+/// Operations in the master record produced by a merge
+pub const MERGE_OPERATIONS: &str = "ir_merge_operations";
+/// Calls made through a `layers::MetricsLayer`, labelled by `method`
+pub const NETWORK_CALLS_TOTAL: &str = "ir_network_calls_total";
+/// Responses to those calls, labelled by `method` and `outcome`: `ok`, `unreachable`,
+/// `timeout` or `server_error`
+pub const NETWORK_RESPONSES_TOTAL: &str = "ir_network_responses_total";
+/// Destinations of those calls that were unreachable or timed out, labelled by `node`
+pub const NETWORK_FAILURES_TOTAL: &str = "ir_network_failures_total";
+/// Time waiting on those calls in seconds, labelled by `method`
+pub const NETWORK_CALL_DURATION_SECONDS: &str = "ir_network_call_duration_seconds";
+
+/// Metric labels, as name and value pairs
+pub type Labels<'a> = &'a [(&'static str, String)];
+
+/// Receives the metrics of clients and servers
+pub trait Metrics: Send + Sync + 'static {
+    fn increment_counter(&self, name: &'static str, labels: Labels);
+
+    fn observe_histogram(&self, name: &'static str, labels: Labels, value: f64);
+}
+
+/// Discards all metrics; the default for clients and servers
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopMetrics;
+
+impl Metrics for NoopMetrics {
+    fn increment_counter(&self, _name: &'static str, _labels: Labels) {}
+
+    fn observe_histogram(&self, _name: &'static str, _labels: Labels, _value: f64) {}
+}
+
+/// Observes the time since it was started, in seconds, when dropped
+pub(crate) struct Timer {
+    metrics: Arc<dyn Metrics>,
+    name: &'static str,
+    labels: Vec<(&'static str, String)>,
+    started: Instant,
+}
+
+impl Timer {
+    pub(crate) fn start(
+        metrics: Arc<dyn Metrics>,
+        name: &'static str,
+        labels: Vec<(&'static str, String)>,
+    ) -> Self {
+        Timer {
+            metrics,
+            name,
+            labels,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.metrics.observe_histogram(
+            self.name,
+            &self.labels,
+            self.started.elapsed().as_secs_f64(),
+        );
+    }
+}
+
+/// The default histogram buckets, in seconds, matching the Prometheus client libraries
+const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Buckets for `MERGE_OPERATIONS`, which counts operations rather than seconds
+const MERGE_BUCKETS: [f64; 6] = [1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0];
+
+type Series = (&'static str, Vec<(&'static str, String)>);
+
+#[derive(Clone, Debug)]
+struct Histogram {
+    buckets: Vec<f64>,
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Collects metrics in memory and renders them in the Prometheus text exposition format
+#[derive(Debug)]
+pub struct PrometheusMetrics {
+    counters: Mutex<BTreeMap<Series, u64>>,
+    histograms: Mutex<BTreeMap<Series, Histogram>>,
+    buckets: Mutex<BTreeMap<&'static str, Vec<f64>>>,
+}
+
+impl Default for PrometheusMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrometheusMetrics {
+    pub fn new() -> Self {
+        let mut buckets = BTreeMap::new();
+        buckets.insert(MERGE_OPERATIONS, MERGE_BUCKETS.to_vec());
+        PrometheusMetrics {
+            counters: Mutex::new(BTreeMap::new()),
+            histograms: Mutex::new(BTreeMap::new()),
+            buckets: Mutex::new(buckets),
+        }
+    }
+
+    /// Use these upper bounds for a histogram, instead of the defaults
+    /// Only affects series that have not been observed yet.
+    pub fn with_buckets(self, name: &'static str, mut buckets: Vec<f64>) -> Self {
+        buckets.sort_by(f64::total_cmp);
+        self.buckets.lock().unwrap().insert(name, buckets);
+        self
+    }
+
+    /// The value of a counter, mostly useful for tests
+    pub fn counter(&self, name: &'static str, labels: Labels) -> u64 {
+        self.counters
+            .lock()
+            .unwrap()
+            .get(&(name, labels.to_vec()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// The number of observations of a histogram, mostly useful for tests
+    pub fn histogram_count(&self, name: &'static str, labels: Labels) -> u64 {
+        self.histograms
+            .lock()
+            .unwrap()
+            .get(&(name, labels.to_vec()))
+            .map(|histogram| histogram.count)
+            .unwrap_or(0)
+    }
+
+    /// Render every metric in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut previous = None;
+        for ((name, labels), value) in self.counters.lock().unwrap().iter() {
+            if previous != Some(*name) {
+                writeln!(out, "# TYPE {} counter", name).unwrap();
+                previous = Some(*name);
+            }
+            writeln!(out, "{}{} {}", name, render_labels(labels, None), value).unwrap();
+        }
+        for ((name, labels), histogram) in self.histograms.lock().unwrap().iter() {
+            if previous != Some(*name) {
+                writeln!(out, "# TYPE {} histogram", name).unwrap();
+                previous = Some(*name);
+            }
+            for (bound, count) in histogram.buckets.iter().zip(&histogram.counts) {
+                let le = bound.to_string();
+                writeln!(
+                    out,
+                    "{}_bucket{} {}",
+                    name,
+                    render_labels(labels, Some(&le)),
+                    count
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "{}_bucket{} {}",
+                name,
+                render_labels(labels, Some("+Inf")),
+                histogram.count
+            )
+            .unwrap();
+            let labels = render_labels(labels, None);
+            writeln!(out, "{}_sum{} {}", name, labels, histogram.sum).unwrap();
+            writeln!(out, "{}_count{} {}", name, labels, histogram.count).unwrap();
+        }
+        out
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn increment_counter(&self, name: &'static str, labels: Labels) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .entry((name, labels.to_vec()))
+            .or_insert(0) += 1;
+    }
+
+    fn observe_histogram(&self, name: &'static str, labels: Labels, value: f64) {
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = histograms
+            .entry((name, labels.to_vec()))
+            .or_insert_with(|| {
+                let buckets = self
+                    .buckets
+                    .lock()
+                    .unwrap()
+                    .get(name)
+                    .cloned()
+                    .unwrap_or_else(|| DEFAULT_BUCKETS.to_vec());
+                Histogram {
+                    counts: vec![0; buckets.len()],
+                    buckets,
+                    sum: 0.0,
+                    count: 0,
+                }
+            });
+        // Prometheus buckets are cumulative
+        for (bound, count) in histogram.buckets.iter().zip(histogram.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+}
+
+fn render_labels(labels: &[(&'static str, String)], le: Option<&str>) -> String {
+    let mut rendered: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect();
+    if let Some(le) = le {
+        rendered.push(format!("le=\"{}\"", le));
+    }
+    if rendered.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", rendered.join(","))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::metrics::{Metrics, PrometheusMetrics};
+
+    #[test]
+    fn renders_prometheus_text_format() {
+        let metrics = PrometheusMetrics::new().with_buckets("latency", vec![1.0, 0.1]);
+        metrics.increment_counter("requests", &[("node", "a\"b".to_string())]);
+        metrics.increment_counter("requests", &[("node", "a\"b".to_string())]);
+        metrics.observe_histogram("latency", &[], 0.5);
+
+        assert_eq!(
+            metrics.render(),
+            "# TYPE requests counter\n\
+             requests{node=\"a\\\"b\"} 2\n\
+             # TYPE latency histogram\n\
+             latency_bucket{le=\"0.1\"} 0\n\
+             latency_bucket{le=\"1\"} 1\n\
+             latency_bucket{le=\"+Inf\"} 1\n\
+             latency_sum 0.5\n\
+             latency_count 1\n"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Quorum sizes for a membership, as used by `find_quorum` and the view change merge.
+//!
+//! IR needs two quorum sizes. Any two slow quorums must intersect, and a fast quorum must
+//! intersect every slow quorum in a majority of that slow quorum, so that a result decided
+//! with a fast quorum survives a view change. With `n = 2f + 1` members these are the
+//! paper's `f + 1` and `⌈3f/2⌉ + 1`; `QuorumPolicy` generalises them to any cluster size,
+//! an explicitly configured `f` and weighted members.
+
+use crate::types::NodeID;
+use std::collections::BTreeMap;
+
+/// Clusters smaller than this cannot tolerate a failure
+const MINIMUM_MEMBERS: usize = 3;
+
+/// Why a membership cannot be used with a quorum policy
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum QuorumError<I: NodeID> {
+    /// A member is listed more than once, so it would vote more than once
+    DuplicateMember(I),
+    /// A node is both a voting member and a learner
+    LearnerIsMember(I),
+    /// There are fewer members than IR needs to tolerate a single failure
+    TooFewMembers(usize),
+    /// The configured number of failures would leave less than a majority
+    TooManyFailures { f: usize, members: usize },
+    /// Two slow quorums could be disjoint
+    SlowQuorumsDisjoint { slow_quorum: u64, total: u64 },
+    /// A fast quorum could hold less than a majority of some slow quorum
+    FastQuorumTooSmall { fast_quorum: u64, required: u64 },
+}
+
+/// The quorum sizes of a membership under a policy, in units of weight
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct QuorumSizes {
+    pub f: usize,
+    pub total: u64,
+    pub slow: u64,
+    pub fast: u64,
+}
+
+/// Derives quorum sizes from a membership
+/// By default every member has a weight of 1 and a slow quorum is a majority, so f is
+/// `⌊(n-1)/2⌋` for both odd and even sized clusters.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QuorumPolicy<I: NodeID> {
+    failures: Option<usize>,
+    weights: BTreeMap<I, u64>,
+}
+
+impl<I: NodeID> Default for QuorumPolicy<I> {
+    fn default() -> Self {
+        Self::majority()
+    }
+}
+
+impl<I: NodeID> QuorumPolicy<I> {
+    /// Slow quorums are a majority of members
+    pub fn majority() -> Self {
+        QuorumPolicy {
+            failures: None,
+            weights: BTreeMap::new(),
+        }
+    }
+
+    /// Tolerate exactly f failures; slow quorums are every member but the f heaviest
+    /// Tolerating fewer failures than a majority allows makes slow quorums larger and fast
+    /// quorums smaller.
+    pub fn with_f(mut self, f: usize) -> Self {
+        self.failures = Some(f);
+        self
+    }
+
+    /// Give a member more, or less, say in quorums than the default weight of 1
+    /// The view change merge also tallies the results in the records it receives by the weight
+    /// of the member that sent each.
+    pub fn with_weight(mut self, node: I, weight: u64) -> Self {
+        self.weights.insert(node, weight);
+        self
+    }
+
+    pub fn weight(&self, node: &I) -> u64 {
+        self.weights.get(node).copied().unwrap_or(1)
+    }
+
+    /// The combined weight of some nodes
+    pub fn weight_of<'a, N: IntoIterator<Item = &'a I>>(&self, nodes: N) -> u64 {
+        nodes.into_iter().map(|node| self.weight(node)).sum()
+    }
+
+    /// The quorum sizes for these members, if they satisfy IR's intersection requirements
+    pub fn sizes(&self, members: &[I]) -> Result<QuorumSizes, QuorumError<I>> {
+        if members.len() < MINIMUM_MEMBERS {
+            return Err(QuorumError::TooFewMembers(members.len()));
+        }
+        let total = self.weight_of(members);
+        let mut heaviest: Vec<u64> = members.iter().map(|node| self.weight(node)).collect();
+        heaviest.sort_unstable_by(|a, b| b.cmp(a));
+        let without_heaviest = |f: usize| total - heaviest.iter().take(f).sum::<u64>();
+        let (f, slow) = match self.failures {
+            Some(f) => {
+                let slow = without_heaviest(f);
+                if f >= members.len() || 2 * slow <= total {
+                    return Err(QuorumError::TooManyFailures {
+                        f,
+                        members: members.len(),
+                    });
+                }
+                (f, slow)
+            }
+            None => {
+                let slow = total / 2 + 1;
+                let f = (0..members.len())
+                    .take_while(|f| without_heaviest(*f) >= slow)
+                    .last()
+                    .unwrap_or(0);
+                (f, slow)
+            }
+        };
+        // As in the paper, the fast path tolerates ⌊f/2⌋ failures, which check_intersection
+        // then verifies against the slow quorum
+        let fast = without_heaviest(f / 2);
+        let sizes = QuorumSizes {
+            f,
+            total,
+            slow,
+            fast,
+        };
+        Self::check_intersection(sizes)?;
+        Ok(sizes)
+    }
+
+    /// The number of failures tolerated by these members
+    pub fn f(&self, members: &[I]) -> Result<usize, QuorumError<I>> {
+        self.sizes(members).map(|sizes| sizes.f)
+    }
+
+    /// The weight needed for a slow (normal) quorum
+    pub fn slow_quorum(&self, members: &[I]) -> Result<u64, QuorumError<I>> {
+        self.sizes(members).map(|sizes| sizes.slow)
+    }
+
+    /// The weight needed for a fast quorum
+    pub fn fast_quorum(&self, members: &[I]) -> Result<u64, QuorumError<I>> {
+        self.sizes(members).map(|sizes| sizes.fast)
+    }
+
+    /// How much weight of the records in a view change merge must agree on a consensus result
+    /// for it to be kept, rather than decided by the application
+    pub fn merge_majority(&self, slow_quorum: u64) -> u64 {
+        slow_quorum / 2 + 1
+    }
+
+    /// Any two slow quorums intersect, and a fast quorum intersects every slow quorum in a
+    /// majority of it
+    fn check_intersection(sizes: QuorumSizes) -> Result<(), QuorumError<I>> {
+        if 2 * sizes.slow <= sizes.total {
+            return Err(QuorumError::SlowQuorumsDisjoint {
+                slow_quorum: sizes.slow,
+                total: sizes.total,
+            });
+        }
+        let required = sizes.total - sizes.slow + sizes.slow / 2 + 1;
+        if sizes.fast < required || sizes.fast > sizes.total {
+            return Err(QuorumError::FastQuorumTooSmall {
+                fast_quorum: sizes.fast,
+                required,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::quorum::{QuorumError, QuorumPolicy, QuorumSizes};
+
+    fn members(n: u64) -> Vec<u64> {
+        (1..=n).collect()
+    }
+
+    #[test]
+    fn majority_matches_the_paper_for_odd_clusters() {
+        let policy = QuorumPolicy::majority();
+        for (n, f, slow, fast) in [(3, 1, 2, 3), (5, 2, 3, 4), (7, 3, 4, 6), (9, 4, 5, 7)] {
+            assert_eq!(
+                policy.sizes(&members(n)),
+                Ok(QuorumSizes {
+                    f,
+                    total: n,
+                    slow,
+                    fast
+                }),
+                "{} members",
+                n
+            );
+        }
+    }
+
+    #[test]
+    fn even_clusters_do_not_overstate_f() {
+        let policy = QuorumPolicy::majority();
+        // 4 nodes tolerate one failure, and a slow quorum is still a majority
+        assert_eq!(policy.f(&members(4)), Ok(1));
+        assert_eq!(policy.slow_quorum(&members(4)), Ok(3));
+        assert_eq!(policy.fast_quorum(&members(4)), Ok(4));
+        assert_eq!(policy.f(&members(6)), Ok(2));
+        assert_eq!(policy.slow_quorum(&members(6)), Ok(4));
+        assert_eq!(policy.fast_quorum(&members(6)), Ok(5));
+    }
+
+    #[test]
+    fn explicit_f_is_validated() {
+        assert_eq!(
+            QuorumPolicy::majority().with_f(1).sizes(&members(5)),
+            Ok(QuorumSizes {
+                f: 1,
+                total: 5,
+                slow: 4,
+                fast: 5
+            })
+        );
+        assert_eq!(
+            QuorumPolicy::majority().with_f(2).sizes(&members(4)),
+            Err(QuorumError::TooManyFailures { f: 2, members: 4 })
+        );
+        assert_eq!(
+            QuorumPolicy::<u64>::majority().sizes(&members(2)),
+            Err(QuorumError::TooFewMembers(2))
+        );
+    }
+
+    #[test]
+    fn weights_count_towards_quorums() {
+        // given a heavy node, which cannot fail without losing the majority
+        let policy = QuorumPolicy::majority().with_weight(1, 3);
+
+        // then quorums are measured in weight
+        let sizes = policy.sizes(&members(3)).unwrap();
+        assert_eq!(
+            sizes,
+            QuorumSizes {
+                f: 0,
+                total: 5,
+                slow: 3,
+                fast: 5
+            }
+        );
+        assert_eq!(policy.weight_of(&[1]), 3);
+        assert_eq!(policy.weight_of(&[2, 3]), 2);
+    }
+
+    #[test]
+    fn fast_quorums_that_miss_a_majority_of_a_slow_quorum_are_refused() {
+        // given a heavy node among light ones, so the fast path would tolerate its failure
+        let policy = QuorumPolicy::majority().with_weight(1, 3);
+
+        // then a fast quorum without it could hold less than a majority of a slow quorum
+        assert_eq!(
+            policy.sizes(&members(9)),
+            Err(QuorumError::FastQuorumTooSmall {
+                fast_quorum: 8,
+                required: 9
+            })
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use crate::io::IRStorage;
+use crate::server::IROperation;
+use crate::types::{IRMessage, NodeID};
+use futures::{stream, Stream};
+use std::marker::PhantomData;
+use tokio::sync::watch;
+
+/// The number of finalized operations a replica keeps for subscriptions, unless configured
+/// otherwise
+pub(crate) const DEFAULT_CHANGE_RETENTION: u64 = 100_000;
+
+/// The position of a finalized operation in a replica's change log
+/// Cursors are local to a replica; pass the last cursor received to resume a subscription.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ChangeCursor {
+    pub view: u64,
+    pub position: u64,
+}
+
+/// Why a subscription to the change log ended
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChangeLogError {
+    /// The operations after the cursor have been truncated; this is the oldest one kept
+    CursorTooOld { oldest: ChangeCursor },
+}
+
+/// The finalized operations of a replica, in the order they were finalized
+/// Each view has its own log. When a view starts, the finalized operations of its master
+/// record that are not already logged are appended, so that the views together have every
+/// finalized operation exactly once. The log is kept in `IRStorage`, so cursors survive
+/// restarts, and only the latest `retention` operations are kept.
+pub(crate) struct ChangeLog<I: NodeID, M: IRMessage, S: IRStorage<I, M>> {
+    storage: S,
+    retention: u64,
+    appended: watch::Sender<u64>,
+    _a: PhantomData<(I, M)>,
+}
+
+impl<I: NodeID, M: IRMessage, S: IRStorage<I, M>> Clone for ChangeLog<I, M, S> {
+    fn clone(&self) -> Self {
+        ChangeLog {
+            storage: self.storage.clone(),
+            retention: self.retention,
+            appended: self.appended.clone(),
+            _a: PhantomData,
+        }
+    }
+}
+
+impl<I: NodeID, M: IRMessage, S: IRStorage<I, M>> ChangeLog<I, M, S> {
+    pub(crate) fn new(storage: S, retention: u64) -> Self {
+        ChangeLog {
+            storage,
+            retention,
+            appended: watch::Sender::new(0),
+            _a: PhantomData,
+        }
+    }
+
+    /// Log finalized operations in a view, skipping any that are already logged, and truncate
+    /// the log to the retained operations
+    pub(crate) async fn append<OPS: IntoIterator<Item = IROperation<I, M>>>(
+        &self,
+        view: u64,
+        operations: OPS,
+    ) {
+        let finalized = operations
+            .into_iter()
+            .filter(IROperation::finalized)
+            .collect();
+        let appended = self.storage.append_changes(view, finalized).await;
+        if appended > 0 {
+            self.storage.truncate_changes(self.retention).await;
+            self.appended.send_modify(|count| *count += appended);
+        }
+    }
+
+    /// Every operation after the cursor, or from the oldest one kept, waiting for more
+    /// The stream ends with an error if it falls behind the operations kept.
+    pub(crate) fn subscribe(
+        &self,
+        after: Option<ChangeCursor>,
+    ) -> impl Stream<Item = Result<(ChangeCursor, IROperation<I, M>), ChangeLogError>> + Send {
+        let next = after.map(|cursor| ChangeCursor {
+            position: cursor.position + 1,
+            ..cursor
+        });
+        let storage = self.storage.clone();
+        let appended = self.appended.subscribe();
+        stream::unfold(Some((storage, appended, next)), |state| async move {
+            let (storage, mut appended, next) = state?;
+            loop {
+                // Mark the log as seen before reading it, so an append is never missed
+                appended.borrow_and_update();
+                match storage.read_change(next).await {
+                    Ok(Some((cursor, operation))) => {
+                        let next = Some(ChangeCursor {
+                            position: cursor.position + 1,
+                            ..cursor
+                        });
+                        return Some((Ok((cursor, operation)), Some((storage, appended, next))));
+                    }
+                    Ok(None) => appended.changed().await.ok()?,
+                    Err(error) => return Some((Err(error), None)),
+                }
+            }
+        })
+    }
+}
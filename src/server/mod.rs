@@ -1,36 +1,67 @@
+mod changes;
 #[cfg(test)]
 mod test;
 
-use crate::debug::MaybeDebug;
-use crate::io::{IRNetwork, IRStorage};
-use crate::types::{AsyncIterator, IRMessage, NodeID, OperationSequence};
-use crate::utils::{f, find_quorum, QuorumVote};
-use futures::StreamExt;
+pub use changes::{ChangeCursor, ChangeLogError};
+
+use crate::application::IRApplication;
+use crate::io::{CarrierRequest, IRNetwork, IRStorage};
+use crate::metrics::{
+    Metrics, NoopMetrics, MERGE_OPERATIONS, RECOVERY_DURATION_SECONDS, VIEW_CHANGES_TOTAL,
+};
+use crate::quorum::{QuorumError, QuorumPolicy};
+use crate::server::changes::{ChangeLog, DEFAULT_CHANGE_RETENTION};
+use crate::types::{IRMessage, NodeID, OperationSequence, TraceId};
+use crate::utils::validate_view;
+use futures::{stream, Stream, StreamExt};
+use std::collections::BTreeMap;
 use std::future::Future;
 use std::marker::PhantomData;
-use std::pin::Pin;
-use std::sync::Arc;
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio::sync::RwLock;
+use tracing::{Instrument, Span};
+
+/// A replica's result for an operation
+pub(crate) type ServerResult<I, M> =
+    Pin<Box<dyn Future<Output = Result<(M, View<I>), IRServerError<I>>> + Send>>;
+
+/// A replica's results for a batch of operations, or why it refused the batch
+pub(crate) type ServerBatchResult<I, M> =
+    Pin<Box<dyn Future<Output = Result<Vec<(M, View<I>)>, IRServerError<I>>> + Send>>;
+
+/// A replica's result for each of a batch of operations
+pub(crate) type ServerResults<I, M> =
+    Pin<Box<dyn Future<Output = Vec<Result<(M, View<I>), IRServerError<I>>>> + Send>>;
 
 /// Implementation of a server node for receiving and handling operations according to the
 /// Inconsistent Replication algorithm.
 pub struct InconsistentReplicationServer<
     NET: IRNetwork<ID, MSG>,
     STO: IRStorage<ID, MSG>,
+    APP: IRApplication<ID, MSG>,
     ID: NodeID,
     MSG: IRMessage,
 > {
     network: NET,
     storage: STO,
+    application: APP,
     node_id: ID,
     view: Arc<RwLock<View<ID>>>,
+    metrics: Arc<dyn Metrics>,
+    quorum_policy: QuorumPolicy<ID>,
+    changes: ChangeLog<ID, MSG, STO>,
+    /// When the node went into recovery, until it resumes normal operation
+    recovery_started: Arc<Mutex<Option<Instant>>>,
     _a: PhantomData<MSG>,
 }
 
-impl<N, S, I, M> Clone for InconsistentReplicationServer<N, S, I, M>
+impl<N, S, A, I, M> Clone for InconsistentReplicationServer<N, S, A, I, M>
 where
     N: IRNetwork<I, M> + Clone,
     S: IRStorage<I, M> + Clone,
+    A: IRApplication<I, M>,
     I: NodeID,
     M: IRMessage,
 {
@@ -38,24 +69,31 @@ where
         InconsistentReplicationServer {
             network: self.network.clone(),
             storage: self.storage.clone(),
+            application: self.application.clone(),
             node_id: self.node_id.clone(),
             view: self.view.clone(),
+            metrics: self.metrics.clone(),
+            quorum_policy: self.quorum_policy.clone(),
+            changes: self.changes.clone(),
+            recovery_started: self.recovery_started.clone(),
             _a: PhantomData,
         }
     }
 }
 
-///
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
-#[cfg_attr(any(test, debug_assertions), derive(Debug))]
+/// A view is a numbered membership of the cluster, together with the state of this node in it
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct View<ID: NodeID> {
     pub view: u64,
+    /// The replicas that vote in quorums
     pub members: Vec<ID>,
+    /// Non-voting replicas that receive finalized operations and merged records, but are not
+    /// counted in quorums
+    pub learners: Vec<ID>,
     pub state: ViewState,
 }
 
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
-#[cfg_attr(any(test, debug_assertions), derive(Debug))]
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub enum ViewState {
     Normal,
     ViewChanging,
@@ -65,22 +103,73 @@ pub enum ViewState {
 impl<
         N: IRNetwork<I, M> + 'static,
         S: IRStorage<I, M> + 'static,
+        A: IRApplication<I, M>,
         I: NodeID + 'static,
         M: IRMessage + 'static,
-    > InconsistentReplicationServer<N, S, I, M>
+    > InconsistentReplicationServer<N, S, A, I, M>
 {
-    pub async fn new(network: N, storage: S, node_id: I) -> Self {
+    pub async fn new(network: N, storage: S, application: A, node_id: I) -> Self {
         let mut view = storage.recover_current_view().await;
         view.state = ViewState::Recovery;
         InconsistentReplicationServer {
             network,
+            changes: ChangeLog::new(storage.clone(), DEFAULT_CHANGE_RETENTION),
             storage,
+            application,
             node_id,
             view: Arc::new(RwLock::new(view)),
+            metrics: Arc::new(NoopMetrics),
+            quorum_policy: QuorumPolicy::majority(),
+            recovery_started: Arc::new(Mutex::new(Some(Instant::now()))),
             _a: PhantomData,
         }
     }
 
+    /// Report view changes, recovery duration and merge sizes
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Keep this many finalized operations for subscriptions, rather than the default of
+    /// 100,000. Subscriptions that fall further behind end with `ChangeLogError::CursorTooOld`.
+    pub fn with_change_retention(mut self, operations: u64) -> Self {
+        self.changes = ChangeLog::new(self.storage.clone(), operations);
+        self
+    }
+
+    /// Use a quorum policy other than a majority of equally weighted members
+    /// Clients should be configured with the same policy.
+    pub fn with_quorum_policy(mut self, quorum_policy: QuorumPolicy<I>) -> Self {
+        self.quorum_policy = quorum_policy;
+        self
+    }
+
+    /// Stream the operations finalized on this replica, in the order they were finalized
+    /// Pass the cursor of the last operation received to resume after it. Operations that a
+    /// view change brings in are delivered once, after those of the previous view, so a
+    /// subscription sees every finalized operation exactly once across view changes.
+    /// The log is kept in storage, so cursors stay valid across restarts until the operations
+    /// they follow are truncated; the stream then ends with `ChangeLogError::CursorTooOld`.
+    pub fn subscribe(
+        &self,
+        after: Option<ChangeCursor>,
+    ) -> impl Stream<Item = Result<(ChangeCursor, IROperation<I, M>), ChangeLogError>> + Send {
+        self.changes.subscribe(after)
+    }
+
+    /// The span of a protocol step, with the trace id that the client uses for the operation
+    fn span(&self, step: &'static str, client_id: &I, sequence: OperationSequence) -> Span {
+        tracing::debug_span!(
+            "ir_server",
+            step,
+            node = ?self.node_id,
+            client = ?client_id,
+            sequence,
+            trace_id = %TraceId::new(client_id.clone(), sequence),
+        )
+    }
+
     /// Invoked on propose message
     pub fn propose_inconsistent(
         &self,
@@ -89,28 +178,36 @@ impl<
         message: M,
         // TODO
         _highest_observed_view: Option<View<I>>,
-    ) -> Pin<Box<dyn Future<Output = Result<(M, View<I>), IRServerError<I>>>>> {
-        #[cfg(any(feature = "test", test))]
-        println!(
-            "propose_inconsistent: {}",
-            MaybeDebug::maybe_debug(&message)
-        );
+    ) -> ServerResult<I, M> {
+        let span = self.span("propose_inconsistent", &client_id, operation_sequence);
         let storage = self.storage.clone();
+        let application = self.application.clone();
         let view = self.view.clone();
-        Box::pin(async move {
-            let view_lock = view.read().await;
-            let view = view_lock.clone();
-            assert_eq!(view.state, ViewState::Normal);
-            let m = storage
-                .record_tentative_inconsistent_and_evaluate(
-                    client_id,
-                    operation_sequence,
-                    view.clone(),
-                    message,
-                )
-                .await;
-            Ok((m, view))
-        })
+        Box::pin(
+            async move {
+                let view_lock = view.read().await;
+                let view = view_lock.clone();
+                if view.state != ViewState::Normal {
+                    tracing::debug!(
+                        view = view.view,
+                        "rejecting operation outside a normal view"
+                    );
+                    return Err(IRServerError::Recovering(view));
+                }
+                tracing::debug!(view = view.view, ?message, "recording tentative operation");
+                storage
+                    .record_tentative_inconsistent(
+                        client_id,
+                        operation_sequence,
+                        view.clone(),
+                        message.clone(),
+                    )
+                    .await;
+                let reply = application.reply_inconsistent(message).await;
+                Ok((reply, view))
+            }
+            .instrument(span),
+        )
     }
 
     /// Invoked on finalize message
@@ -121,28 +218,47 @@ impl<
         message: M,
         // TODO
         _highest_observed_view: Option<View<I>>,
-    ) -> Pin<Box<dyn Future<Output = Result<(M, View<I>), IRServerError<I>>>>> {
-        #[cfg(any(feature = "test", test))]
-        println!(
-            "finalize_inconsistent: {}",
-            MaybeDebug::maybe_debug(&message)
-        );
+    ) -> ServerResult<I, M> {
+        let span = self.span("finalize_inconsistent", &client_id, operation_sequence);
         let storage = self.storage.clone();
+        let application = self.application.clone();
         let view = self.view.clone();
-        Box::pin(async move {
-            let view_lock = view.read().await;
-            let view = view_lock.clone();
-            assert_eq!(view.state, ViewState::Normal);
-            let _ = storage
-                .promote_finalized_and_exec_inconsistent(
-                    client_id,
-                    operation_sequence,
-                    view.clone(),
-                    message.clone(),
-                )
-                .await;
-            Ok((message, view))
-        })
+        let changes = self.changes.clone();
+        Box::pin(
+            async move {
+                let view_lock = view.read().await;
+                let view = view_lock.clone();
+                if view.state != ViewState::Normal {
+                    tracing::debug!(
+                        view = view.view,
+                        "rejecting operation outside a normal view"
+                    );
+                    return Err(IRServerError::Recovering(view));
+                }
+                tracing::debug!(view = view.view, ?message, "finalizing operation");
+                storage
+                    .promote_finalized_inconsistent(
+                        client_id.clone(),
+                        operation_sequence,
+                        view.clone(),
+                        message.clone(),
+                    )
+                    .await;
+                application.exec_inconsistent(message.clone()).await;
+                changes
+                    .append(
+                        view.view,
+                        [IROperation::InconsistentFinalize {
+                            client: client_id,
+                            sequence: operation_sequence,
+                            message: message.clone(),
+                        }],
+                    )
+                    .await;
+                Ok((message, view))
+            }
+            .instrument(span),
+        )
     }
 
     /// Proposes a consistent operation
@@ -153,25 +269,36 @@ impl<
         message: M,
         // TODO
         _highest_observed_view: Option<View<I>>,
-    ) -> Pin<Box<dyn Future<Output = Result<(M, View<I>), IRServerError<I>>>>> {
+    ) -> ServerResult<I, M> {
+        let span = self.span("propose_consistent", &client_id, operation_sequence);
         let view = self.view.clone();
         let storage = self.storage.clone();
-        Box::pin(async move {
-            let view_lock = view.read().await;
-            let view = view_lock.clone();
-            if view.state == ViewState::Recovery {
-                return Err(IRServerError::Recovering(view));
+        let application = self.application.clone();
+        Box::pin(
+            async move {
+                let view_lock = view.read().await;
+                let view = view_lock.clone();
+                if view.state != ViewState::Normal {
+                    tracing::debug!(
+                        view = view.view,
+                        "rejecting operation outside a normal view"
+                    );
+                    return Err(IRServerError::Recovering(view));
+                }
+                let result = application.exec_consensus(message).await;
+                tracing::debug!(view = view.view, ?result, "recording tentative result");
+                storage
+                    .record_tentative_consistent(
+                        client_id,
+                        operation_sequence,
+                        view.clone(),
+                        result.clone(),
+                    )
+                    .await;
+                Ok((result, view))
             }
-            let resolved_message = storage
-                .record_tentative_and_exec_consistent(
-                    client_id,
-                    operation_sequence,
-                    view.clone(),
-                    message,
-                )
-                .await;
-            Ok((resolved_message, view))
-        })
+            .instrument(span),
+        )
     }
 
     /// Finalize and execute a consistent operation
@@ -182,22 +309,197 @@ impl<
         message: M,
         // TODO
         _highest_observed_view: Option<View<I>>,
-    ) -> Pin<Box<dyn Future<Output = Result<(M, View<I>), IRServerError<I>>>>> {
+    ) -> ServerResult<I, M> {
+        let span = self.span("finalize_consistent", &client_id, operation_sequence);
         let view = self.view.clone();
         let storage = self.storage.clone();
+        let application = self.application.clone();
+        let node_id = self.node_id.clone();
+        let changes = self.changes.clone();
+        Box::pin(
+            async move {
+                let view_lock = view.read().await;
+                let view = view_lock.clone();
+                if view.state != ViewState::Normal {
+                    tracing::debug!(
+                        view = view.view,
+                        "rejecting operation outside a normal view"
+                    );
+                    return Err(IRServerError::Recovering(view));
+                }
+                tracing::debug!(view = view.view, ?message, "finalizing operation");
+                storage
+                    .promote_finalized_consistent(
+                        client_id.clone(),
+                        operation_sequence,
+                        view.clone(),
+                        message.clone(),
+                    )
+                    .await;
+                // Members executed the operation when it was proposed, learners only see the
+                // result
+                if view.learners.contains(&node_id) {
+                    application.exec_finalized_consensus(message.clone()).await;
+                }
+                changes
+                    .append(
+                        view.view,
+                        [IROperation::ConsistentFinalize {
+                            client: client_id,
+                            sequence: operation_sequence,
+                            message: message.clone(),
+                        }],
+                    )
+                    .await;
+                Ok((message, view))
+            }
+            .instrument(span),
+        )
+    }
+
+    /// Invoked on a batch of inconsistent proposals with consecutive sequence numbers
+    pub fn propose_inconsistent_batch(
+        &self,
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> ServerBatchResult<I, M> {
+        let proposals: Vec<_> = (first_sequence..)
+            .zip(messages)
+            .map(|(sequence, message)| {
+                self.propose_inconsistent(
+                    client_id.clone(),
+                    sequence,
+                    message,
+                    highest_observed_view.clone(),
+                )
+            })
+            .collect();
         Box::pin(async move {
-            let view_lock = view.read().await;
-            let view = view_lock.clone();
-            assert_eq!(view.state, ViewState::Normal);
-            let m = storage
-                .promote_finalized_and_reconcile_consistent(
-                    client_id,
-                    operation_sequence,
-                    view.clone(),
+            let mut results = Vec::with_capacity(proposals.len());
+            for proposal in proposals {
+                results.push(proposal.await?);
+            }
+            Ok(results)
+        })
+    }
+
+    /// Invoked on a batch of consistent proposals with consecutive sequence numbers
+    pub fn propose_consistent_batch(
+        &self,
+        client_id: I,
+        first_sequence: OperationSequence,
+        messages: Vec<M>,
+        highest_observed_view: Option<View<I>>,
+    ) -> ServerBatchResult<I, M> {
+        let proposals: Vec<_> = (first_sequence..)
+            .zip(messages)
+            .map(|(sequence, message)| {
+                self.propose_consistent(
+                    client_id.clone(),
+                    sequence,
                     message,
+                    highest_observed_view.clone(),
                 )
-                .await;
-            Ok((m, view))
+            })
+            .collect();
+        Box::pin(async move {
+            let mut results = Vec::with_capacity(proposals.len());
+            for proposal in proposals {
+                results.push(proposal.await?);
+            }
+            Ok(results)
+        })
+    }
+
+    /// Invoked on a request carrying finalizes that were held back for this node, see
+    /// `IRNetwork::send_with_finalizes`
+    /// The finalizes are applied before the request is handled. Like other asynchronous
+    /// finalizes they have no results, so the response is the request's results.
+    pub fn handle_with_finalizes(
+        &self,
+        finalizes: Vec<IROperation<I, M>>,
+        request: CarrierRequest<I, M>,
+    ) -> ServerBatchResult<I, M> {
+        let finalized = self.finalize_batch(finalizes, None);
+        let handled = match request {
+            CarrierRequest::ProposeInconsistent {
+                client,
+                first_sequence,
+                messages,
+                highest_observed_view,
+            } => self.propose_inconsistent_batch(
+                client,
+                first_sequence,
+                messages,
+                highest_observed_view,
+            ),
+            CarrierRequest::ProposeConsistent {
+                client,
+                first_sequence,
+                messages,
+            } => self.propose_consistent_batch(client, first_sequence, messages, None),
+            CarrierRequest::FinalizeConsistent {
+                client,
+                sequence,
+                message,
+            } => {
+                let finalize = self.finalize_consistent(client, sequence, message, None);
+                Box::pin(async move { finalize.await.map(|result| vec![result]) })
+            }
+        };
+        Box::pin(async move {
+            finalized.await;
+            handled.await
+        })
+    }
+
+    /// Invoked on a batch of finalize messages, such as those sent by `BatchingNetwork`
+    /// Each finalize is applied in order and has its own result
+    pub fn finalize_batch(
+        &self,
+        operations: Vec<IROperation<I, M>>,
+        highest_observed_view: Option<View<I>>,
+    ) -> ServerResults<I, M> {
+        let finalizes: Vec<_> = operations
+            .into_iter()
+            .map(|operation| match operation {
+                IROperation::InconsistentFinalize {
+                    client,
+                    sequence,
+                    message,
+                } => self.finalize_inconsistent(
+                    client,
+                    sequence,
+                    message,
+                    highest_observed_view.clone(),
+                ),
+                IROperation::ConsistentFinalize {
+                    client,
+                    sequence,
+                    message,
+                } => self.finalize_consistent(
+                    client,
+                    sequence,
+                    message,
+                    highest_observed_view.clone(),
+                ),
+                IROperation::InconsistentPropose { .. } | IROperation::ConsistentPropose { .. } => {
+                    Box::pin(async {
+                        Err(IRServerError::InternalError(
+                            "only finalize operations can be batched".into(),
+                        ))
+                    })
+                }
+            })
+            .collect();
+        Box::pin(async move {
+            let mut results = Vec::with_capacity(finalizes.len());
+            for finalize in finalizes {
+                results.push(finalize.await);
+            }
+            results
         })
     }
 
@@ -205,78 +507,218 @@ impl<
     /// The actual implementation includes self records, so you can do optimisations behind
     /// the scenes, such as passively uploading, or tracking which operations already exist on
     /// the leader node (this node).
-    pub async fn process_incoming_operations<ITER: AsyncIterator<Item = IROperation<I, M>>>(
+    pub async fn process_incoming_operations<OPS: Stream<Item = IROperation<I, M>> + Send>(
         &self,
         from_who: I,
         view: View<I>,
-        operations: ITER,
+        operations: OPS,
     ) {
+        let mut operations = pin!(operations);
         while let Some(operation) = operations.next().await {
             self.storage
                 .add_peer_view_change_operation(from_who.clone(), view.clone(), operation)
                 .await;
         }
+        tracing::debug!(
+            node = ?self.node_id,
+            peer = ?from_who,
+            view = view.view,
+            "received peer view change record"
+        );
+        self.storage
+            .complete_peer_view_change_record(from_who, view)
+            .await;
         let view = self.view.read().await;
-        let full_records = self.storage.get_peers_with_full_records(view.clone()).await;
-        // if we have f+1 full records we can start merge
-        if full_records.len() >= f(view.members.len()).unwrap() + 1 {
+        let mut full_records = self.storage.get_peers_with_full_records(view.clone()).await;
+        // Learners receive the merged record, but do not contribute to it
+        full_records.retain(|node| view.members.contains(node));
+        // if we have a slow quorum of full records (f+1 of 2f+1) we can start merge
+        let slow_quorum = self.quorum_policy.slow_quorum(&view.members).unwrap();
+        if self.quorum_policy.weight_of(&full_records) >= slow_quorum {
             self.merge(full_records, view.clone()).await;
         }
     }
 
     async fn merge(&self, full_record_members: Vec<I>, view: View<I>) {
         for node in full_record_members {
-            let ops_iter = self.storage.get_view_record_operations(node, view.clone());
+            let mut ops_iter = pin!(self
+                .storage
+                .get_view_record_operations(node.clone(), view.clone()));
             // This is the IR-MERGE-RECORDS(records) part of the paper
-            for op in ops_iter.next().await {
+            while let Some(op) = ops_iter.next().await {
                 let existing_main_record_op = self
                     .storage
-                    .get_main_or_local_operation(view.clone(), op.client(), op.sequence().clone())
+                    .get_main_or_local_operation(view.clone(), op.client().clone(), *op.sequence())
                     .await;
-                self.resolve_record_merge(view.clone(), op, existing_main_record_op)
+                self.resolve_record_merge(view.clone(), node.clone(), op, existing_main_record_op)
                     .await
             }
-            // now we resolve all undecided operations
-            let unresolved_iter = self
-                .storage
-                .get_unresolved_record_operations(view.clone())
-                .await;
-
-            while let Some(op) = unresolved_iter.next().await {
-                // TODO validate it hasn't already been finalised miraculously (or if storage engine has a bad implementation)
-                let quorum = find_quorum(op.iter().map(|op| QuorumVote {
-                    node: op.client(),
-                    message: op.message(),
-                    view: &view,
-                }));
-                let first_op = op.first().unwrap();
-                let quorum = quorum.expect("We should always have a quorum");
-                self.storage
-                    .record_main_operation(
-                        quorum.view.clone(),
-                        match first_op.consistent() {
-                            true => IROperation::ConsistentFinalize {
-                                client: first_op.client().clone(),
-                                sequence: first_op.sequence().clone(),
-                                message: quorum.message.clone(),
-                            },
-                            false => IROperation::InconsistentFinalize {
-                                client: first_op.client().clone(),
-                                sequence: first_op.sequence().clone(),
-                                message: quorum.message.clone(),
-                            },
-                        },
-                    )
-                    .await;
+        }
+        // now we split the undecided operations into those with a majority result (d) and
+        // those without (u), as per the paper
+        let slow_quorum = self.quorum_policy.slow_quorum(&view.members).unwrap();
+        let majority = self.quorum_policy.merge_majority(slow_quorum);
+        let mut unresolved_iter = pin!(self.storage.get_unresolved_record_operations(view.clone()));
+        let mut decided = Vec::new();
+        let mut undecided = Vec::new();
+        while let Some(ops) = unresolved_iter.next().await {
+            // TODO validate it hasn't already been finalised miraculously (or if storage engine has a bad implementation)
+            // Each result counts with the weight of the member that reported it
+            let mut tally: BTreeMap<&M, u64> = BTreeMap::new();
+            for (node, op) in &ops {
+                *tally.entry(op.message()).or_insert(0) += self.quorum_policy.weight(node);
+            }
+            let majority_message = tally
+                .into_iter()
+                .find(|(_msg, weight)| *weight >= majority)
+                .map(|(msg, _weight)| msg.clone());
+            let ops: Vec<IROperation<I, M>> = ops.into_iter().map(|(_node, op)| op).collect();
+            match (majority_message, ops.first()) {
+                (Some(message), Some(first_op)) => decided.push(IROperation::ConsistentPropose {
+                    client: first_op.client().clone(),
+                    sequence: *first_op.sequence(),
+                    message,
+                }),
+                (None, Some(_)) => undecided.push(ops),
+                (_, None) => {}
             }
         }
+        let f = self.quorum_policy.f(&view.members).unwrap();
+        for operation in self.application.merge(decided, undecided, f).await {
+            self.storage
+                .record_main_operation(view.clone(), operation)
+                .await;
+        }
         // Completed merge!
+        let view_number = view.view;
+        let merged = self.sync_master_record(view).await;
+        tracing::info!(
+            node = ?self.node_id,
+            view = view_number,
+            operations = merged,
+            "merged master record"
+        );
+        self.metrics
+            .observe_histogram(MERGE_OPERATIONS, &[], merged as f64);
         // TODO Now ship to all nodes, wait for f+1 confirmations and proceed to new view
     }
 
+    /// Start view 0 of a new cluster, in `Normal` rather than recovering
+    /// Every member bootstraps with the same members. A node that has started a view or whose
+    /// storage holds state is refused, as it already belongs to a cluster and must recover.
+    /// Bootstrapping is not a view change, so it is not reported as one or as a recovery.
+    pub async fn bootstrap(&self, members: Vec<I>) -> Result<(), BootstrapError<I>> {
+        let view = View {
+            view: 0,
+            members,
+            learners: vec![],
+            state: ViewState::Normal,
+        };
+        validate_view(&self.quorum_policy, &view).map_err(BootstrapError::Quorum)?;
+        if !view.members.contains(&self.node_id) {
+            return Err(BootstrapError::NotAMember(self.node_id.clone()));
+        }
+        // The view stays locked from the check until the view is installed, so that
+        // concurrent bootstraps and view changes cannot both succeed
+        let mut view_lock = self.view.write().await;
+        if view_lock.state != ViewState::Recovery
+            || view_lock.view != 0
+            || !self.storage.is_fresh().await
+        {
+            return Err(BootstrapError::ExistingState(view_lock.clone()));
+        }
+        tracing::info!(node = ?self.node_id, members = ?view.members, "bootstrapping cluster");
+        self.install_view(&mut view_lock, view, stream::empty())
+            .await;
+        self.recovery_started.lock().unwrap().take();
+        Ok(())
+    }
+
+    /// Invoked when the leader of a view change has sent the merged (master) record.
+    /// The record replaces our own, the application is synchronised with it and the
+    /// node proceeds to the new view. Learners of the new view receive it too.
+    /// A view whose quorums would not intersect under the node's quorum policy is refused,
+    /// leaving the node in its current view, as is a view older than the current one or with
+    /// the current number but other members, which would roll the node back. The current view
+    /// itself may be started again.
+    pub async fn start_view<OPS: Stream<Item = IROperation<I, M>> + Send>(
+        &self,
+        view: View<I>,
+        master_record: OPS,
+    ) -> Result<(), StartViewError<I>> {
+        validate_view(&self.quorum_policy, &view).map_err(StartViewError::Quorum)?;
+        let mut view_lock = self.view.write().await;
+        let reconfirmed = view.view == view_lock.view
+            && view.members == view_lock.members
+            && view.learners == view_lock.learners;
+        if view.view < view_lock.view || (view.view == view_lock.view && !reconfirmed) {
+            tracing::debug!(
+                node = ?self.node_id,
+                view = view.view,
+                current = view_lock.view,
+                "refusing stale view"
+            );
+            return Err(StartViewError::Stale(view_lock.clone()));
+        }
+        self.install_view(&mut view_lock, view, master_record).await;
+        self.metrics.increment_counter(VIEW_CHANGES_TOTAL, &[]);
+        let recovery_started = self.recovery_started.lock().unwrap().take();
+        if let Some(recovery_started) = recovery_started {
+            self.metrics.observe_histogram(
+                RECOVERY_DURATION_SECONDS,
+                &[],
+                recovery_started.elapsed().as_secs_f64(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Record the master record, sync the application with it and persist the view before
+    /// proceeding to it
+    async fn install_view<OPS: Stream<Item = IROperation<I, M>> + Send>(
+        &self,
+        view_lock: &mut View<I>,
+        view: View<I>,
+        master_record: OPS,
+    ) {
+        let mut master_record = pin!(master_record);
+        let mut finalized = Vec::new();
+        while let Some(operation) = master_record.next().await {
+            if operation.finalized() {
+                finalized.push(operation.clone());
+            }
+            self.storage
+                .record_main_operation(view.clone(), operation)
+                .await;
+        }
+        self.sync_master_record(view.clone()).await;
+        self.changes.append(view.view, finalized).await;
+        let view = View {
+            state: ViewState::Normal,
+            ..view
+        };
+        self.storage.record_current_view(view.clone()).await;
+        *view_lock = view;
+        tracing::info!(node = ?self.node_id, view = view_lock.view, "started view");
+    }
+
+    /// Sync(R) from the paper, the application state converges on the master record
+    /// Returns the size of the master record
+    async fn sync_master_record(&self, view: View<I>) -> usize {
+        let master_record: Vec<_> = self
+            .storage
+            .get_main_record_operations(view)
+            .collect()
+            .await;
+        let size = master_record.len();
+        self.application.sync(master_record).await;
+        size
+    }
+
     async fn resolve_record_merge(
         &self,
         view: View<I>,
+        from: I,
         received: IROperation<I, M>,
         ours: Option<IROperation<I, M>>,
     ) {
@@ -292,11 +734,11 @@ impl<
             )
             | (
                 _,
-                IROperation::InconsistentFinalize {
+                Some(IROperation::InconsistentFinalize {
                     client,
                     sequence,
                     message,
-                },
+                }),
             ) => {
                 self.storage
                     .record_main_operation(
@@ -320,11 +762,11 @@ impl<
             )
             | (
                 _,
-                IROperation::ConsistentFinalize {
+                Some(IROperation::ConsistentFinalize {
                     client,
                     sequence,
                     message,
-                },
+                }),
             ) => {
                 self.storage
                     .record_main_operation(
@@ -344,15 +786,16 @@ impl<
                     sequence: sequence_left,
                     message: message_left,
                 },
-                IROperation::ConsistentPropose {
+                Some(IROperation::ConsistentPropose {
                     client: client_right,
                     sequence: sequence_right,
                     message: message_right,
-                },
+                }),
             ) => {
                 self.storage
                     .record_main_operation_add_undecided(
                         view.clone(),
+                        from,
                         IROperation::ConsistentPropose {
                             client: client_left,
                             sequence: sequence_left,
@@ -363,6 +806,7 @@ impl<
                 self.storage
                     .record_main_operation_add_undecided(
                         view.clone(),
+                        self.node_id.clone(),
                         IROperation::ConsistentPropose {
                             client: client_right,
                             sequence: sequence_right,
@@ -371,19 +815,29 @@ impl<
                     )
                     .await;
             }
-            // All inconsistent tentative messages need to be added for tallying (this is not in the paper)
+            // A consistent tentative message we have not seen still needs to be tallied
             (
-                IROperation::InconsistentPropose {
-                    client: client_left,
-                    sequence: sequence_left,
-                    message: message_left,
-                },
-                IROperation::InconsistentPropose {
-                    client: client_right,
-                    sequence: sequence_right,
-                    message: message_right,
+                IROperation::ConsistentPropose {
+                    client,
+                    sequence,
+                    message,
                 },
-            ) => {}
+                _,
+            ) => {
+                self.storage
+                    .record_main_operation_add_undecided(
+                        view.clone(),
+                        from,
+                        IROperation::ConsistentPropose {
+                            client,
+                            sequence,
+                            message,
+                        },
+                    )
+                    .await;
+            }
+            // All inconsistent tentative messages need to be added for tallying (this is not in the paper)
+            (IROperation::InconsistentPropose { .. }, _) => {}
         }
     }
 
@@ -391,19 +845,47 @@ impl<
     pub async fn perform_maintenance(&self) {}
 
     #[cfg(any(feature = "test", test))]
-    pub async fn shutdown(self) -> (N, S, I, View<I>) {
+    pub async fn shutdown(self) -> (N, S, A, I, View<I>) {
         let view_guard = self.view.write().await;
         let view = view_guard.to_owned();
-        (self.network, self.storage, self.node_id, view)
+        (
+            self.network,
+            self.storage,
+            self.application,
+            self.node_id,
+            view,
+        )
     }
 }
 
+/// Why a node could not bootstrap a cluster
+#[derive(Debug, Eq, PartialEq)]
+pub enum BootstrapError<ID: NodeID> {
+    /// The node has started a view or its storage holds state; this is its current view
+    ExistingState(View<ID>),
+    /// The node is not one of the members
+    NotAMember(ID),
+    /// The members cannot form quorums under the node's quorum policy
+    Quorum(QuorumError<ID>),
+}
+
+/// Why a node did not start a view
+#[derive(Debug, Eq, PartialEq)]
+pub enum StartViewError<ID: NodeID> {
+    /// The view is older than the node's current view, or has its number but other members;
+    /// this is the node's current view
+    Stale(View<ID>),
+    /// The members cannot form quorums under the node's quorum policy
+    Quorum(QuorumError<ID>),
+}
+
 #[derive(Debug)]
 pub enum IRServerError<ID: NodeID> {
-    InternalError(Box<dyn std::error::Error>),
+    InternalError(Box<dyn std::error::Error + Send + Sync>),
     Recovering(View<ID>),
 }
 
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum IROperation<ID: NodeID, MSG: IRMessage> {
     InconsistentPropose {
         client: ID,
@@ -474,4 +956,29 @@ impl<ID: NodeID, MSG: IRMessage> IROperation<ID, MSG> {
             }
         }
     }
+
+    /// Convert a tentative operation into its finalized equivalent
+    pub fn into_finalized(self) -> Self {
+        match self {
+            IROperation::InconsistentPropose {
+                client,
+                sequence,
+                message,
+            } => IROperation::InconsistentFinalize {
+                client,
+                sequence,
+                message,
+            },
+            IROperation::ConsistentPropose {
+                client,
+                sequence,
+                message,
+            } => IROperation::ConsistentFinalize {
+                client,
+                sequence,
+                message,
+            },
+            finalized => finalized,
+        }
+    }
 }
@@ -0,0 +1,153 @@
+use crate::quorum::QuorumError;
+use crate::server::{BootstrapError, View, ViewState};
+use crate::test_utils::mock_computers::NoopComputer;
+use crate::test_utils::{FakeIRNetwork, FakeIRStorage};
+use crate::{InconsistentReplicationClient, InconsistentReplicationServer};
+
+type Network = FakeIRNetwork<u64, String, FakeIRStorage<u64, String>, NoopComputer<String>>;
+
+async fn fresh_server(
+    network: &Network,
+    node_id: u64,
+) -> InconsistentReplicationServer<
+    Network,
+    FakeIRStorage<u64, String>,
+    NoopComputer<String>,
+    u64,
+    String,
+> {
+    InconsistentReplicationServer::new(
+        network.clone(),
+        FakeIRStorage::new(vec![]),
+        NoopComputer::new(),
+        node_id,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn fresh_nodes_bootstrap_into_view_zero() {
+    // given fresh nodes
+    let network = Network::new();
+    let members = vec![1, 2, 3];
+    let mut servers = vec![];
+    for node_id in &members {
+        let server = fresh_server(&network, *node_id).await;
+        network.register_node(*node_id, server.clone());
+        servers.push(server);
+    }
+
+    // when each bootstraps the cluster
+    for server in &servers {
+        assert_eq!(server.bootstrap(members.clone()).await, Ok(()));
+    }
+
+    // then they are in view 0 and serve operations
+    for server in &servers {
+        assert_eq!(
+            *server.view.read().await,
+            View {
+                view: 0,
+                members: members.clone(),
+                learners: vec![],
+                state: ViewState::Normal,
+            }
+        );
+    }
+    let client =
+        InconsistentReplicationClient::new(network.clone(), FakeIRStorage::new(members), 100).await;
+    assert_eq!(
+        client.invoke_inconsistent("hello".to_string()).await,
+        Ok("hello".to_string())
+    );
+}
+
+#[tokio::test]
+async fn refuses_nodes_that_already_have_state() {
+    // given a node whose storage is past view 0, and a node that has bootstrapped
+    let network = Network::new();
+    let members = vec![1, 2, 3];
+    let storage = FakeIRStorage::new(members.clone());
+    let recovered = View {
+        view: 3,
+        members: members.clone(),
+        learners: vec![],
+        state: ViewState::Normal,
+    };
+    storage.set_current_view(recovered.clone()).await;
+    let existing =
+        InconsistentReplicationServer::new(network.clone(), storage, NoopComputer::new(), 1).await;
+    let bootstrapped = fresh_server(&network, 2).await;
+    bootstrapped.bootstrap(members.clone()).await.unwrap();
+
+    // when they bootstrap
+    let existing = existing.bootstrap(members.clone()).await;
+    let again = bootstrapped.bootstrap(members.clone()).await;
+
+    // then both are refused, leaving their views as they were
+    assert_eq!(
+        existing,
+        Err(BootstrapError::ExistingState(View {
+            state: ViewState::Recovery,
+            ..recovered
+        }))
+    );
+    assert!(matches!(again, Err(BootstrapError::ExistingState(view)) if view.view == 0));
+}
+
+#[tokio::test]
+async fn refuses_memberships_the_node_cannot_serve() {
+    // given a fresh node
+    let network = Network::new();
+    let server = fresh_server(&network, 1).await;
+
+    // when it bootstraps a cluster it is not part of, one too small, or one listing a member twice
+    let outsider = server.bootstrap(vec![2, 3, 4]).await;
+    let too_small = server.bootstrap(vec![1, 2]).await;
+    let duplicated = server.bootstrap(vec![1, 2, 2]).await;
+
+    // then it is refused and keeps recovering
+    assert_eq!(outsider, Err(BootstrapError::NotAMember(1)));
+    assert_eq!(
+        too_small,
+        Err(BootstrapError::Quorum(QuorumError::TooFewMembers(2)))
+    );
+    assert_eq!(
+        duplicated,
+        Err(BootstrapError::Quorum(QuorumError::DuplicateMember(2)))
+    );
+    assert_eq!(server.view.read().await.state, ViewState::Recovery);
+}
+
+#[tokio::test]
+async fn restarted_nodes_recover_the_bootstrapped_view() {
+    // given a node that has bootstrapped
+    let network = Network::new();
+    let members = vec![1, 2, 3];
+    let storage = FakeIRStorage::new(vec![]);
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        storage.clone(),
+        NoopComputer::new(),
+        1,
+    )
+    .await;
+    server.bootstrap(members.clone()).await.unwrap();
+
+    // when it restarts on the same storage
+    let restarted =
+        InconsistentReplicationServer::new(network.clone(), storage, NoopComputer::new(), 1).await;
+
+    // then it recovers into the bootstrapped view rather than bootstrapping again
+    let recovered = View {
+        view: 0,
+        members: members.clone(),
+        learners: vec![],
+        state: ViewState::Recovery,
+    };
+    assert_eq!(*restarted.view.read().await, recovered);
+    assert_eq!(
+        restarted.bootstrap(members).await,
+        Err(BootstrapError::ExistingState(recovered))
+    );
+}
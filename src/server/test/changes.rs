@@ -0,0 +1,193 @@
+use crate::server::{ChangeCursor, ChangeLogError, IROperation, View, ViewState};
+use crate::test_utils::mock_computers::NoopComputer;
+use crate::test_utils::{FakeIRNetwork, FakeIRStorage};
+use crate::InconsistentReplicationServer;
+use futures::{stream, StreamExt};
+
+fn finalize(sequence: u64, message: &str) -> IROperation<String, String> {
+    IROperation::InconsistentFinalize {
+        client: "client".to_string(),
+        sequence,
+        message: message.to_string(),
+    }
+}
+
+#[tokio::test]
+pub async fn subscriptions_resume_across_view_changes_without_duplicates() {
+    // given a server in normal operation
+    let network = FakeIRNetwork::<String, String, FakeIRStorage<_, _>, NoopComputer<_>>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        FakeIRStorage::new(members.clone()),
+        NoopComputer::new(),
+        "1".to_string(),
+    )
+    .await;
+    let view = View {
+        view: 1,
+        members: members.clone(),
+        learners: vec![],
+        state: ViewState::Normal,
+    };
+    *server.view.write().await = view.clone();
+
+    // when two operations are finalized, one of them twice
+    for (sequence, message) in [(1, "A"), (2, "B"), (1, "A")] {
+        server
+            .finalize_inconsistent("client".to_string(), sequence, message.to_string(), None)
+            .await
+            .unwrap();
+    }
+
+    // then a subscriber sees each once
+    let mut subscription = Box::pin(server.subscribe(None));
+    let first = subscription.next().await.unwrap().unwrap();
+    assert_eq!(
+        first,
+        (
+            ChangeCursor {
+                view: 1,
+                position: 0
+            },
+            finalize(1, "A")
+        )
+    );
+    assert_eq!(
+        subscription.next().await.unwrap().unwrap().1,
+        finalize(2, "B")
+    );
+
+    // when a view change brings in an operation this replica missed
+    let master_record = vec![finalize(1, "A"), finalize(2, "B"), finalize(3, "C")];
+    server
+        .start_view(View { view: 2, ..view }, stream::iter(master_record))
+        .await
+        .unwrap();
+
+    // then only the missed operation follows, and resuming gives the same operations
+    let third = subscription.next().await.unwrap().unwrap();
+    assert_eq!(
+        third,
+        (
+            ChangeCursor {
+                view: 2,
+                position: 0
+            },
+            finalize(3, "C")
+        )
+    );
+    let resumed: Vec<_> = server.subscribe(Some(first.0)).take(2).collect().await;
+    assert_eq!(
+        resumed
+            .into_iter()
+            .map(|change| change.unwrap().1)
+            .collect::<Vec<_>>(),
+        vec![finalize(2, "B"), finalize(3, "C")]
+    );
+}
+
+#[tokio::test]
+pub async fn subscriptions_resume_after_a_restart() {
+    // given a server that has finalized two operations
+    let network = FakeIRNetwork::<String, String, FakeIRStorage<_, _>, NoopComputer<_>>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let storage = FakeIRStorage::new(members.clone());
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        storage.clone(),
+        NoopComputer::new(),
+        "1".to_string(),
+    )
+    .await;
+    server.bootstrap(members).await.unwrap();
+    for (sequence, message) in [(1, "A"), (2, "B")] {
+        server
+            .finalize_inconsistent("client".to_string(), sequence, message.to_string(), None)
+            .await
+            .unwrap();
+    }
+    let first = Box::pin(server.subscribe(None))
+        .next()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // when it restarts on the same storage
+    let restarted = InconsistentReplicationServer::new(
+        network.clone(),
+        storage,
+        NoopComputer::new(),
+        "1".to_string(),
+    )
+    .await;
+
+    // then a subscription resumes after the cursor received before the restart
+    let resumed = Box::pin(restarted.subscribe(Some(first.0)))
+        .next()
+        .await
+        .unwrap();
+    assert_eq!(
+        resumed,
+        Ok((
+            ChangeCursor {
+                view: 0,
+                position: 1
+            },
+            finalize(2, "B")
+        ))
+    );
+}
+
+#[tokio::test]
+pub async fn subscriptions_behind_the_retained_operations_are_ended() {
+    // given a server that keeps one operation
+    let network = FakeIRNetwork::<String, String, FakeIRStorage<_, _>, NoopComputer<_>>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        FakeIRStorage::new(members.clone()),
+        NoopComputer::new(),
+        "1".to_string(),
+    )
+    .await
+    .with_change_retention(1);
+    server.bootstrap(members).await.unwrap();
+
+    // when three operations are finalized
+    for (sequence, message) in [(1, "A"), (2, "B"), (3, "C")] {
+        server
+            .finalize_inconsistent("client".to_string(), sequence, message.to_string(), None)
+            .await
+            .unwrap();
+    }
+
+    // then a new subscription starts from the oldest operation kept
+    let oldest = ChangeCursor {
+        view: 0,
+        position: 2,
+    };
+    let from_start = Box::pin(server.subscribe(None)).next().await.unwrap();
+    assert_eq!(from_start, Ok((oldest, finalize(3, "C"))));
+
+    // and a subscription resuming before it is told its cursor is too old, while one
+    // resuming just before it is not
+    let behind: Vec<_> = server
+        .subscribe(Some(ChangeCursor {
+            view: 0,
+            position: 0,
+        }))
+        .take(1)
+        .collect()
+        .await;
+    let resumable: Vec<_> = server
+        .subscribe(Some(ChangeCursor {
+            view: 0,
+            position: 1,
+        }))
+        .take(1)
+        .collect()
+        .await;
+    assert_eq!(behind, vec![Err(ChangeLogError::CursorTooOld { oldest })]);
+    assert_eq!(resumable, vec![Ok((oldest, finalize(3, "C")))]);
+}
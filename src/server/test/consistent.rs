@@ -1,16 +1,18 @@
-use crate::server::{IRServerError, View, ViewState};
+use crate::io::CarrierRequest;
+use crate::server::{IROperation, IRServerError, View, ViewState};
 use crate::test_utils::mock_computers::NoopComputer;
 use crate::test_utils::{FakeIRNetwork, FakeIRStorage, MockStorage, StorageMethod};
-use crate::InconsistentReplicationServer;
+use crate::{IRApplication, InconsistentReplicationServer};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 
 #[tokio::test]
 pub async fn propose_rejected_if_recovering() {
     let server = InconsistentReplicationServer::new(
-        FakeIRNetwork::<String, String, FakeIRStorage<_, _, NoopComputer<_>>>::new(),
-        FakeIRStorage::new(
-            vec!["1".to_string(), "2".to_string(), "3".to_string()],
-            NoopComputer::new(),
-        ),
+        FakeIRNetwork::<String, String, FakeIRStorage<_, _>, NoopComputer<_>>::new(),
+        FakeIRStorage::new(vec!["1".to_string(), "2".to_string(), "3".to_string()]),
+        NoopComputer::new(),
         "1".to_string(),
     )
     .await;
@@ -27,6 +29,7 @@ pub async fn propose_rejected_if_recovering() {
                 View {
                     view: 0,
                     members: vec!["1".to_string(), "2".to_string(), "3".to_string()],
+                    learners: vec![],
                     state: ViewState::Recovery,
                 }
             ),
@@ -42,7 +45,35 @@ pub async fn finalise_rejected_if_recovering() {
 
 #[tokio::test]
 pub async fn propose_rejected_if_changing_view() {
-    todo!()
+    // given a member in the middle of a view change
+    let network = FakeIRNetwork::<String, String, MockStorage<_, _>, NoopComputer<_>>::new();
+    let view = View {
+        view: 1,
+        members: ["1", "2", "3"].iter().map(|x| x.to_string()).collect(),
+        learners: vec![],
+        state: ViewState::ViewChanging,
+    };
+    let storage = MockStorage::new(view.clone());
+    let server = InconsistentReplicationServer::new(
+        network,
+        storage.clone(),
+        NoopComputer::new(),
+        "1".to_string(),
+    )
+    .await;
+    *server.view.write().await = view.clone();
+
+    // when it receives a consensus proposal
+    let resp = server
+        .propose_consistent("client-id".to_string(), 1, "message".to_string(), None)
+        .await;
+
+    // then the proposal is rejected with the current view and nothing is recorded
+    match resp {
+        Err(IRServerError::Recovering(rejected_in)) => assert_eq!(rejected_in, view),
+        _ => panic!("Should be rejected"),
+    }
+    storage.assert_invocations_no_calls(&[StorageMethod::ProposeConsistent]);
 }
 
 #[tokio::test]
@@ -62,24 +93,31 @@ pub async fn finalise_changes_view_if_receives_higher() {
 
 #[tokio::test]
 pub async fn propose_consistent() {
-    let network = FakeIRNetwork::<String, String, MockStorage<_, _>>::new();
-    let members: Vec<String> = vec!["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let network = FakeIRNetwork::<String, String, MockStorage<_, _>, NoopComputer<_>>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
     let view = View {
         view: 1,
         members: members.clone(),
+        learners: vec![],
         state: ViewState::Normal,
     };
     let storage = MockStorage::new(view.clone());
-    let server =
-        InconsistentReplicationServer::new(network.clone(), storage.clone(), "1".to_string()).await;
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        storage.clone(),
+        NoopComputer::new(),
+        "1".to_string(),
+    )
+    .await;
+    server.view.write().await.state = ViewState::Normal;
 
     // and
     storage.mock_record_tentative_consistent(Box::new(
-        |client, seq, view, msg| -> Option<String> { Some(msg) },
+        |_client, _seq, _view, _msg| -> Option<()> { Some(()) },
     ));
 
     // when
-    let val = server
+    let _val = server
         .propose_consistent("client-id".to_string(), 3, String::from("msg"), None)
         .await;
 
@@ -112,3 +150,209 @@ pub async fn finalize_already_finalised() {
 pub async fn finalize_not_proposed() {
     todo!()
 }
+
+#[tokio::test]
+pub async fn propose_consistent_records_application_result() {
+    #[derive(Clone)]
+    struct UppercaseApplication;
+
+    impl IRApplication<String, String> for UppercaseApplication {
+        fn exec_inconsistent(&self, _message: String) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(async {})
+        }
+
+        fn exec_consensus(&self, message: String) -> Pin<Box<dyn Future<Output = String> + Send>> {
+            Box::pin(async move { message.to_uppercase() })
+        }
+
+        fn decide(&self, choices: Vec<String>, _f: usize) -> String {
+            choices.into_iter().next().unwrap()
+        }
+
+        fn sync(
+            &self,
+            _record: Vec<IROperation<String, String>>,
+        ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(async {})
+        }
+    }
+
+    let network = FakeIRNetwork::<String, String, MockStorage<_, _>, UppercaseApplication>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let view = View {
+        view: 1,
+        members: members.clone(),
+        learners: vec![],
+        state: ViewState::Normal,
+    };
+    let storage = MockStorage::new(view.clone());
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        storage.clone(),
+        UppercaseApplication,
+        "1".to_string(),
+    )
+    .await;
+    server.view.write().await.state = ViewState::Normal;
+    storage.mock_record_tentative_consistent(Box::new(|_, _, _, _| Some(())));
+
+    // when
+    let (result, _view) = server
+        .propose_consistent("client-id".to_string(), 3, String::from("msg"), None)
+        .await
+        .unwrap();
+
+    // then the application result is returned and recorded
+    assert_eq!(result, "MSG".to_string());
+    assert!(
+        storage.get_invocations_record_tentative_consistent()
+            == vec![("client-id".to_string(), 3, view, "MSG".to_string())]
+    );
+}
+
+#[tokio::test]
+pub async fn finalize_consistent_executes_result_on_learners() {
+    #[derive(Clone)]
+    struct RecordingApplication {
+        executed: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl IRApplication<String, String> for RecordingApplication {
+        fn exec_inconsistent(&self, _message: String) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(async {})
+        }
+
+        fn exec_consensus(&self, message: String) -> Pin<Box<dyn Future<Output = String> + Send>> {
+            self.executed.lock().unwrap().push(message.clone());
+            Box::pin(async move { message })
+        }
+
+        fn decide(&self, choices: Vec<String>, _f: usize) -> String {
+            choices.into_iter().next().unwrap()
+        }
+
+        fn sync(
+            &self,
+            _record: Vec<IROperation<String, String>>,
+        ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(async {})
+        }
+    }
+
+    // given a member and a learner
+    let network = FakeIRNetwork::<String, String, FakeIRStorage<_, _>, RecordingApplication>::new();
+    let view = View {
+        view: 1,
+        members: ["1", "2", "3"].iter().map(|x| x.to_string()).collect(),
+        learners: vec!["4".to_string()],
+        state: ViewState::Normal,
+    };
+    let mut nodes = vec![];
+    for node in ["1", "4"] {
+        let application = RecordingApplication {
+            executed: Arc::new(Mutex::new(vec![])),
+        };
+        let server = InconsistentReplicationServer::new(
+            network.clone(),
+            FakeIRStorage::new(view.members.clone()),
+            application.clone(),
+            node.to_string(),
+        )
+        .await;
+        *server.view.write().await = view.clone();
+        nodes.push((server, application));
+    }
+
+    // when both receive a finalized consensus result
+    for (server, _) in &nodes {
+        server
+            .finalize_consistent("client-id".to_string(), 1, "result".to_string(), None)
+            .await
+            .unwrap();
+    }
+
+    // then only the learner executes it, as the member did so when it was proposed
+    assert!(nodes[0].1.executed.lock().unwrap().is_empty());
+    assert_eq!(
+        *nodes[1].1.executed.lock().unwrap(),
+        vec!["result".to_string()]
+    );
+}
+
+#[tokio::test]
+pub async fn finalizes_carried_by_a_proposal_are_applied_first() {
+    #[derive(Clone, Default)]
+    struct RecordingApplication {
+        executed: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl IRApplication<String, String> for RecordingApplication {
+        fn exec_inconsistent(&self, message: String) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            self.executed
+                .lock()
+                .unwrap()
+                .push(format!("finalized {}", message));
+            Box::pin(async {})
+        }
+
+        fn exec_consensus(&self, message: String) -> Pin<Box<dyn Future<Output = String> + Send>> {
+            self.executed
+                .lock()
+                .unwrap()
+                .push(format!("proposed {}", message));
+            Box::pin(async move { message })
+        }
+
+        fn decide(&self, choices: Vec<String>, _f: usize) -> String {
+            choices.into_iter().next().unwrap()
+        }
+
+        fn sync(
+            &self,
+            _record: Vec<IROperation<String, String>>,
+        ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(async {})
+        }
+    }
+
+    // given a server in a normal view
+    let network = FakeIRNetwork::<String, String, FakeIRStorage<_, _>, RecordingApplication>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let application = RecordingApplication::default();
+    let server = InconsistentReplicationServer::new(
+        network,
+        FakeIRStorage::new(members),
+        application.clone(),
+        "1".to_string(),
+    )
+    .await;
+    server.view.write().await.state = ViewState::Normal;
+
+    // when a consensus proposal arrives carrying an inconsistent finalize
+    let results = server
+        .handle_with_finalizes(
+            vec![IROperation::InconsistentFinalize {
+                client: "client-id".to_string(),
+                sequence: 1,
+                message: "a".to_string(),
+            }],
+            CarrierRequest::ProposeConsistent {
+                client: "client-id".to_string(),
+                first_sequence: 2,
+                messages: vec!["b".to_string()],
+            },
+        )
+        .await
+        .unwrap();
+
+    // then the finalize is applied before the proposal, which alone has a result
+    assert_eq!(
+        *application.executed.lock().unwrap(),
+        vec!["finalized a".to_string(), "proposed b".to_string()]
+    );
+    let messages: Vec<_> = results
+        .into_iter()
+        .map(|(message, _view)| message)
+        .collect();
+    assert_eq!(messages, vec!["b".to_string()]);
+}
@@ -6,14 +6,13 @@ use std::sync::Arc;
 
 #[tokio::test]
 pub async fn inconsistent_requests_rejected_if_not_normal() {
-    let network = FakeIRNetwork::<_, _, FakeIRStorage<String, String, NoopComputer<String>>>::new();
-    let members: Vec<_> = vec!["1", "2", "3"]
-        .iter()
-        .map(ToString::to_string)
-        .collect();
-    let storage = FakeIRStorage::new(members.clone(), NoopComputer::new());
+    let network = FakeIRNetwork::<_, _, FakeIRStorage<String, String>, NoopComputer<String>>::new();
+    let members: Vec<_> = ["1", "2", "3"].iter().map(ToString::to_string).collect();
+    let storage = FakeIRStorage::new(members.clone());
 
-    let server = InconsistentReplicationServer::new(network, storage, "1".to_string()).await;
+    let server =
+        InconsistentReplicationServer::new(network, storage, NoopComputer::new(), "1".to_string())
+            .await;
 
     let resp = server
         .propose_inconsistent("client-id".to_string(), 1, "message".to_string(), None)
@@ -27,8 +26,9 @@ pub async fn inconsistent_requests_rejected_if_not_normal() {
                 assert_eq!(
                     e_view,
                     View {
-                        view: 1,
+                        view: 0,
                         members,
+                        learners: vec![],
                         state: ViewState::Recovery,
                     }
                 )
@@ -42,25 +42,32 @@ pub async fn inconsistent_requests_rejected_if_not_normal() {
 
 #[tokio::test]
 pub async fn inconsistent_changes_view_if_receives_higher() {
-    let network = FakeIRNetwork::<String, String, FakeIRStorage<_, _, NoopComputer<String>>>::new();
+    let network = FakeIRNetwork::<String, String, FakeIRStorage<_, _>, NoopComputer<String>>::new();
     let members = vec!["1".to_string(), "2".to_string(), "3".to_string()];
-    let storage = FakeIRStorage::new(members.clone(), NoopComputer::new());
+    let storage = FakeIRStorage::new(members.clone());
     storage
         .set_current_view(View {
             view: 3,
             members: members.clone(),
+            learners: vec![],
             state: ViewState::Normal,
         })
         .await;
 
-    let server =
-        InconsistentReplicationServer::new(network.clone(), storage, "1".to_string()).await;
-    let new_view = View::<Arc<String>> {
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        storage,
+        NoopComputer::new(),
+        "1".to_string(),
+    )
+    .await;
+    let _new_view = View::<Arc<String>> {
         view: 4,
         members: vec![],
+        learners: vec![],
         state: ViewState::Normal,
     };
-    server.propose_inconsistent("1".to_string(), 1, "msg".to_string(), None);
+    let _response = server.propose_inconsistent("1".to_string(), 1, "msg".to_string(), None);
 }
 
 #[tokio::test]
@@ -68,6 +75,7 @@ pub async fn propose_already_finalised() {
     todo!()
 }
 
+#[tokio::test]
 pub async fn finalize_already_finalised() {
     todo!()
 }
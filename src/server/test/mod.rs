@@ -1,3 +1,5 @@
+mod bootstrap;
+mod changes;
 mod consistent;
 mod inconsistent;
 mod on_init;
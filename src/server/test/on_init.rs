@@ -6,19 +6,25 @@ use crate::InconsistentReplicationServer;
 #[tokio::test]
 pub async fn recovers_view_from_storage_and_goes_into_recovery() {
     // when
-    let network = FakeIRNetwork::<String, String, FakeIRStorage<_, _, _>>::new();
+    let network = FakeIRNetwork::<String, String, FakeIRStorage<_, _>, NoopComputer<_>>::new();
     let members = vec!["1".to_string(), "2".to_string(), "3".to_string()];
-    let storage = FakeIRStorage::new(members.clone(), NoopComputer::new());
+    let storage = FakeIRStorage::new(members.clone());
     storage
         .set_current_view(View {
             view: 3,
             members: members.clone(),
+            learners: vec![],
             state: ViewState::Normal,
         })
         .await;
 
-    let server =
-        InconsistentReplicationServer::new(network.clone(), storage, "1".to_string()).await;
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        storage,
+        NoopComputer::new(),
+        "1".to_string(),
+    )
+    .await;
     network.register_node("1".to_string(), server.clone());
     let lock = server.view.read().await;
     assert_eq!(
@@ -26,6 +32,7 @@ pub async fn recovers_view_from_storage_and_goes_into_recovery() {
         &View {
             view: 3,
             members: members.clone(),
+            learners: vec![],
             state: ViewState::Recovery,
         }
     );
@@ -1,4 +1,358 @@
+use crate::metrics::{PrometheusMetrics, RECOVERY_DURATION_SECONDS, VIEW_CHANGES_TOTAL};
+use crate::quorum::{QuorumError, QuorumPolicy};
+use crate::server::{IROperation, StartViewError, View, ViewState};
+use crate::test_utils::{FakeIRNetwork, FakeIRStorage};
+use crate::{IRApplication, InconsistentReplicationServer};
+use futures::stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+type Record = Vec<IROperation<String, String>>;
+
+#[derive(Clone)]
+struct SyncRecordingApplication {
+    synced: Arc<Mutex<Vec<Record>>>,
+}
+
+impl IRApplication<String, String> for SyncRecordingApplication {
+    fn exec_inconsistent(&self, _message: String) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async {})
+    }
+
+    fn exec_consensus(&self, message: String) -> Pin<Box<dyn Future<Output = String> + Send>> {
+        Box::pin(async move { message })
+    }
+
+    fn decide(&self, choices: Vec<String>, _f: usize) -> String {
+        choices.into_iter().max().unwrap()
+    }
+
+    fn sync(
+        &self,
+        record: Vec<IROperation<String, String>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self.synced.lock().unwrap().push(record);
+        Box::pin(async {})
+    }
+}
+
 #[tokio::test]
 pub async fn higher_view_puts_server_in_recovery() {
     todo!()
 }
+
+#[tokio::test]
+pub async fn merge_syncs_application_with_master_record() {
+    // given a server in a view change
+    let network =
+        FakeIRNetwork::<String, String, FakeIRStorage<_, _>, SyncRecordingApplication>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let application = SyncRecordingApplication {
+        synced: Arc::new(Mutex::new(vec![])),
+    };
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        FakeIRStorage::new(members.clone()),
+        application.clone(),
+        "1".to_string(),
+    )
+    .await;
+    let view = View {
+        view: 1,
+        members,
+        learners: vec![],
+        state: ViewState::ViewChanging,
+    };
+    *server.view.write().await = view.clone();
+
+    // when f+1 peers send their records, with disagreeing consensus results
+    server
+        .process_incoming_operations(
+            "2".to_string(),
+            view.clone(),
+            stream::iter(vec![
+                IROperation::ConsistentPropose {
+                    client: "client".to_string(),
+                    sequence: 1,
+                    message: "A".to_string(),
+                },
+                IROperation::InconsistentFinalize {
+                    client: "client".to_string(),
+                    sequence: 2,
+                    message: "C".to_string(),
+                },
+            ]),
+        )
+        .await;
+    assert!(application.synced.lock().unwrap().is_empty());
+    server
+        .process_incoming_operations(
+            "3".to_string(),
+            view.clone(),
+            stream::iter(vec![
+                IROperation::ConsistentPropose {
+                    client: "client".to_string(),
+                    sequence: 1,
+                    message: "B".to_string(),
+                },
+                IROperation::InconsistentPropose {
+                    client: "client".to_string(),
+                    sequence: 2,
+                    message: "C".to_string(),
+                },
+            ]),
+        )
+        .await;
+
+    // then the application is synced once with the full master record
+    let synced = application.synced.lock().unwrap().clone();
+    assert_eq!(
+        synced,
+        vec![vec![
+            IROperation::ConsistentFinalize {
+                client: "client".to_string(),
+                sequence: 1,
+                message: "B".to_string(),
+            },
+            IROperation::InconsistentFinalize {
+                client: "client".to_string(),
+                sequence: 2,
+                message: "C".to_string(),
+            },
+        ]]
+    );
+}
+
+#[tokio::test]
+pub async fn merge_tallies_consensus_results_by_weight() {
+    // given a server in a view change, among members of which "4" weighs 2
+    let network =
+        FakeIRNetwork::<String, String, FakeIRStorage<_, _>, SyncRecordingApplication>::new();
+    let members: Vec<String> = ["1", "2", "3", "4"].iter().map(|x| x.to_string()).collect();
+    let application = SyncRecordingApplication {
+        synced: Arc::new(Mutex::new(vec![])),
+    };
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        FakeIRStorage::new(members.clone()),
+        application.clone(),
+        "1".to_string(),
+    )
+    .await
+    .with_quorum_policy(QuorumPolicy::majority().with_weight("4".to_string(), 2));
+    let view = View {
+        view: 1,
+        members,
+        learners: vec![],
+        state: ViewState::ViewChanging,
+    };
+    *server.view.write().await = view.clone();
+
+    // when the heavy peer and a light peer send disagreeing consensus results
+    for (peer, message) in [("4", "A"), ("2", "B")] {
+        server
+            .process_incoming_operations(
+                peer.to_string(),
+                view.clone(),
+                stream::iter(vec![IROperation::ConsistentPropose {
+                    client: "client".to_string(),
+                    sequence: 1,
+                    message: message.to_string(),
+                }]),
+            )
+            .await;
+    }
+
+    // then the heavy peer's result is a majority of the slow quorum, so it is kept rather
+    // than decided by the application
+    let synced = application.synced.lock().unwrap().clone();
+    assert_eq!(
+        synced,
+        vec![vec![IROperation::ConsistentFinalize {
+            client: "client".to_string(),
+            sequence: 1,
+            message: "A".to_string(),
+        }]]
+    );
+}
+
+#[tokio::test]
+pub async fn start_view_syncs_application_and_resumes_normal_operation() {
+    // given a server in a view change
+    let network =
+        FakeIRNetwork::<String, String, FakeIRStorage<_, _>, SyncRecordingApplication>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let application = SyncRecordingApplication {
+        synced: Arc::new(Mutex::new(vec![])),
+    };
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        FakeIRStorage::new(members.clone()),
+        application.clone(),
+        "2".to_string(),
+    )
+    .await;
+    let new_view = View {
+        view: 2,
+        members: members.clone(),
+        learners: vec![],
+        state: ViewState::Normal,
+    };
+
+    // when the leader sends the master record
+    let master_record = vec![IROperation::ConsistentFinalize {
+        client: "client".to_string(),
+        sequence: 1,
+        message: "A".to_string(),
+    }];
+    server
+        .start_view(new_view.clone(), stream::iter(master_record.clone()))
+        .await
+        .unwrap();
+
+    // then the application is synced and the node is in the new view
+    assert_eq!(
+        application.synced.lock().unwrap().clone(),
+        vec![master_record]
+    );
+    assert_eq!(*server.view.read().await, new_view);
+}
+
+#[tokio::test]
+pub async fn start_view_reports_view_change_and_recovery_duration() {
+    // given a recovering server with metrics
+    let network =
+        FakeIRNetwork::<String, String, FakeIRStorage<_, _>, SyncRecordingApplication>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let application = SyncRecordingApplication {
+        synced: Arc::new(Mutex::new(vec![])),
+    };
+    let metrics = Arc::new(PrometheusMetrics::new());
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        FakeIRStorage::new(members.clone()),
+        application,
+        "2".to_string(),
+    )
+    .await
+    .with_metrics(metrics.clone());
+
+    // when it starts two views
+    for view in [2, 3] {
+        let new_view = View {
+            view,
+            members: members.clone(),
+            learners: vec![],
+            state: ViewState::Normal,
+        };
+        server.start_view(new_view, stream::empty()).await.unwrap();
+    }
+
+    // then both view changes are counted, but recovery only ended once
+    assert_eq!(metrics.counter(VIEW_CHANGES_TOTAL, &[]), 2);
+    assert_eq!(metrics.histogram_count(RECOVERY_DURATION_SECONDS, &[]), 1);
+}
+
+#[tokio::test]
+pub async fn start_view_refuses_invalid_views() {
+    // given a recovering server
+    let network =
+        FakeIRNetwork::<String, String, FakeIRStorage<_, _>, SyncRecordingApplication>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let application = SyncRecordingApplication {
+        synced: Arc::new(Mutex::new(vec![])),
+    };
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        FakeIRStorage::new(members.clone()),
+        application.clone(),
+        "2".to_string(),
+    )
+    .await;
+    let before = server.view.read().await.clone();
+
+    // when it is sent a view in which a member is listed twice
+    let result = server
+        .start_view(
+            View {
+                view: 2,
+                members: vec!["1".to_string(), "2".to_string(), "2".to_string()],
+                learners: vec![],
+                state: ViewState::Normal,
+            },
+            stream::empty(),
+        )
+        .await;
+
+    // then the view is refused, and the server stays where it was
+    assert_eq!(
+        result,
+        Err(StartViewError::Quorum(QuorumError::DuplicateMember(
+            "2".to_string()
+        )))
+    );
+    assert_eq!(*server.view.read().await, before);
+    assert!(application.synced.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+pub async fn start_view_refuses_stale_views() {
+    // given a server that has started view 2
+    let network =
+        FakeIRNetwork::<String, String, FakeIRStorage<_, _>, SyncRecordingApplication>::new();
+    let members: Vec<String> = ["1", "2", "3"].iter().map(|x| x.to_string()).collect();
+    let application = SyncRecordingApplication {
+        synced: Arc::new(Mutex::new(vec![])),
+    };
+    let server = InconsistentReplicationServer::new(
+        network.clone(),
+        FakeIRStorage::new(members.clone()),
+        application.clone(),
+        "2".to_string(),
+    )
+    .await;
+    let current = View {
+        view: 2,
+        members: members.clone(),
+        learners: vec![],
+        state: ViewState::Normal,
+    };
+    server
+        .start_view(current.clone(), stream::empty())
+        .await
+        .unwrap();
+
+    // when it is sent an older view, and the same view number with other members
+    let older = server
+        .start_view(
+            View {
+                view: 1,
+                ..current.clone()
+            },
+            stream::empty(),
+        )
+        .await;
+    let other_members = server
+        .start_view(
+            View {
+                members: vec!["1".to_string(), "2".to_string(), "4".to_string()],
+                ..current.clone()
+            },
+            stream::empty(),
+        )
+        .await;
+
+    // then both are refused, and the server stays in its view
+    assert_eq!(older, Err(StartViewError::Stale(current.clone())));
+    assert_eq!(other_members, Err(StartViewError::Stale(current.clone())));
+    assert_eq!(*server.view.read().await, current);
+    assert_eq!(application.synced.lock().unwrap().len(), 1);
+
+    // when the current view is started again
+    let reconfirmed = server.start_view(current.clone(), stream::empty()).await;
+
+    // then it is accepted
+    assert_eq!(reconfirmed, Ok(()));
+    assert_eq!(*server.view.read().await, current);
+}
@@ -0,0 +1,172 @@
+use crate::client::InconsistentReplicationClient;
+use crate::hlc::{HybridLogicalClock, TimeSource};
+use crate::io::{IRClientStorage, IRNetwork};
+use crate::tapir::{
+    OccDecide, TapirKey, TapirMessage, TapirValue, Timestamp, TransactionId, TransactionRecord,
+    Vote,
+};
+use crate::types::NodeID;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Why a transaction did not commit
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TapirError {
+    /// The replicas voted against the transaction; it may succeed if run again
+    Aborted(Vote),
+    /// The operation could not be replicated
+    Replication(&'static str),
+}
+
+/// A transaction in progress, buffering its reads and writes until it commits
+/// Nothing reaches the replicas before `commit`, so a transaction is aborted by dropping it.
+#[derive(Clone, Debug)]
+pub struct Transaction<K: TapirKey, V: TapirValue> {
+    id: TransactionId,
+    read_set: BTreeMap<K, Timestamp>,
+    write_set: BTreeMap<K, V>,
+}
+
+impl<K: TapirKey, V: TapirValue> Transaction<K, V> {
+    pub fn id(&self) -> TransactionId {
+        self.id
+    }
+
+    /// Buffer a write, which later reads of the transaction will see
+    pub fn write(&mut self, key: K, value: V) {
+        self.write_set.insert(key, value);
+    }
+}
+
+/// A transactional key-value client for a cluster of `TapirReplica`s
+/// Commit timestamps come from a hybrid logical clock, which observes the versions the client
+/// reads so that a transaction's timestamp is after the versions it read.
+pub struct TapirClient<N, S, I, K, V, T>
+where
+    N: IRNetwork<I, TapirMessage<K, V>>,
+    S: IRClientStorage<I, TapirMessage<K, V>>,
+    I: NodeID,
+    K: TapirKey,
+    V: TapirValue,
+    T: TimeSource,
+{
+    client: InconsistentReplicationClient<N, S, I, TapirMessage<K, V>>,
+    /// Included in transaction ids and timestamps, so it must be unique among clients
+    client_number: u64,
+    transactions: AtomicU64,
+    clock: HybridLogicalClock<T>,
+}
+
+impl<N, S, I, K, V, T> TapirClient<N, S, I, K, V, T>
+where
+    N: IRNetwork<I, TapirMessage<K, V>> + 'static,
+    S: IRClientStorage<I, TapirMessage<K, V>> + 'static,
+    I: NodeID,
+    K: TapirKey,
+    V: TapirValue,
+    T: TimeSource,
+{
+    /// `client_number` orders the transactions of clients that choose the same timestamp, so
+    /// every client must have a different one
+    pub fn new(
+        client: InconsistentReplicationClient<N, S, I, TapirMessage<K, V>>,
+        client_number: u64,
+        clock: HybridLogicalClock<T>,
+    ) -> Self {
+        TapirClient {
+            client,
+            client_number,
+            transactions: AtomicU64::new(0),
+            clock,
+        }
+    }
+
+    pub fn begin(&self) -> Transaction<K, V> {
+        Transaction {
+            id: TransactionId {
+                client: self.client_number,
+                sequence: self.transactions.fetch_add(1, Ordering::SeqCst),
+            },
+            read_set: BTreeMap::new(),
+            write_set: BTreeMap::new(),
+        }
+    }
+
+    /// Read a key, from the transaction's own writes or else the replicas
+    /// The version read is validated when the transaction commits.
+    pub async fn read(
+        &self,
+        transaction: &mut Transaction<K, V>,
+        key: K,
+    ) -> Result<Option<V>, TapirError> {
+        if let Some(value) = transaction.write_set.get(&key) {
+            return Ok(Some(value.clone()));
+        }
+        let result = self
+            .client
+            .invoke_consistent(
+                TapirMessage::Read {
+                    key: key.clone(),
+                    value: None,
+                    version: None,
+                },
+                OccDecide,
+            )
+            .await
+            .map_err(TapirError::Replication)?;
+        self.clock.observe(&result);
+        match result {
+            TapirMessage::Read { value, version, .. } => {
+                let version = version.unwrap_or_default();
+                transaction.read_set.entry(key).or_insert(version);
+                Ok(value)
+            }
+            _ => Err(TapirError::Replication("Unexpected response to read")),
+        }
+    }
+
+    /// Prepare the transaction on the replicas and commit it if they vote for it
+    /// Returns the commit timestamp, which is the version of the values written.
+    pub async fn commit(&self, transaction: Transaction<K, V>) -> Result<Timestamp, TapirError> {
+        let timestamp = Timestamp {
+            time: self.clock.now(),
+            client: self.client_number,
+        };
+        let record = TransactionRecord {
+            id: transaction.id,
+            read_set: transaction.read_set,
+            write_set: transaction.write_set,
+        };
+        let prepared = self
+            .client
+            .invoke_consistent(
+                TapirMessage::Prepare {
+                    transaction: record.clone(),
+                    timestamp,
+                    vote: None,
+                },
+                OccDecide,
+            )
+            .await
+            .map_err(TapirError::Replication)?;
+        let vote = match prepared {
+            TapirMessage::Prepare { vote, .. } => vote.unwrap_or(Vote::Abort),
+            _ => Vote::Abort,
+        };
+        if vote != Vote::Ok {
+            self.client
+                .invoke_inconsistent(TapirMessage::Abort { id: record.id })
+                .await
+                .map_err(TapirError::Replication)?;
+            return Err(TapirError::Aborted(vote));
+        }
+        self.client
+            .invoke_inconsistent(TapirMessage::Commit {
+                transaction: record,
+                timestamp,
+            })
+            .await
+            .map_err(TapirError::Replication)?;
+        Ok(timestamp)
+    }
+}
@@ -0,0 +1,133 @@
+use crate::codec::{Codec, DecodeError};
+use crate::hlc::HlcTimestamp;
+use crate::tapir::{
+    TapirKey, TapirMessage, TapirValue, Timestamp, TransactionId, TransactionRecord, Vote,
+};
+use std::collections::BTreeMap;
+
+impl Codec for Timestamp {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.time.encode(out);
+        self.client.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Timestamp {
+            time: HlcTimestamp::decode(input)?,
+            client: u64::decode(input)?,
+        })
+    }
+}
+
+impl Codec for TransactionId {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.client.encode(out);
+        self.sequence.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(TransactionId {
+            client: u64::decode(input)?,
+            sequence: u64::decode(input)?,
+        })
+    }
+}
+
+impl Codec for Vote {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(match self {
+            Vote::Ok => 0,
+            Vote::Abort => 1,
+            Vote::Abstain => 2,
+            Vote::Retry => 3,
+        });
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(Vote::Ok),
+            1 => Ok(Vote::Abort),
+            2 => Ok(Vote::Abstain),
+            3 => Ok(Vote::Retry),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+impl<K: TapirKey + Codec, V: TapirValue + Codec> Codec for TransactionRecord<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.id.encode(out);
+        self.read_set.encode(out);
+        self.write_set.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(TransactionRecord {
+            id: TransactionId::decode(input)?,
+            read_set: BTreeMap::decode(input)?,
+            write_set: BTreeMap::decode(input)?,
+        })
+    }
+}
+
+impl<K: TapirKey + Codec, V: TapirValue + Codec> Codec for TapirMessage<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            TapirMessage::Read {
+                key,
+                value,
+                version,
+            } => {
+                out.push(0);
+                key.encode(out);
+                value.encode(out);
+                version.encode(out);
+            }
+            TapirMessage::Prepare {
+                transaction,
+                timestamp,
+                vote,
+            } => {
+                out.push(1);
+                transaction.encode(out);
+                timestamp.encode(out);
+                vote.encode(out);
+            }
+            TapirMessage::Commit {
+                transaction,
+                timestamp,
+            } => {
+                out.push(2);
+                transaction.encode(out);
+                timestamp.encode(out);
+            }
+            TapirMessage::Abort { id } => {
+                out.push(3);
+                id.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(TapirMessage::Read {
+                key: K::decode(input)?,
+                value: Option::decode(input)?,
+                version: Option::decode(input)?,
+            }),
+            1 => Ok(TapirMessage::Prepare {
+                transaction: TransactionRecord::decode(input)?,
+                timestamp: Timestamp::decode(input)?,
+                vote: Option::decode(input)?,
+            }),
+            2 => Ok(TapirMessage::Commit {
+                transaction: TransactionRecord::decode(input)?,
+                timestamp: Timestamp::decode(input)?,
+            }),
+            3 => Ok(TapirMessage::Abort {
+                id: TransactionId::decode(input)?,
+            }),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! TAPIR, the transactional key-value store that IR was designed for.
+//!
+//! Transactions buffer their reads and writes on the client. Committing one proposes a
+//! `Prepare` as a consensus operation, which every replica validates with optimistic
+//! concurrency control (OCC) and votes on. The client decides the outcome from the votes and
+//! then commits or aborts it everywhere with an inconsistent operation.
+//!
+//! ```ignore
+//! let mut transaction = client.begin();
+//! let balance = client.read(&mut transaction, "alice").await?;
+//! transaction.write("alice", balance.unwrap_or(0) + 10);
+//! client.commit(transaction).await?;
+//! ```
+//!
+//! Replicas run `TapirReplica` as their `IRApplication`.
+
+mod client;
+mod codec;
+mod replica;
+#[cfg(test)]
+mod test;
+
+pub use client::{TapirClient, TapirError, Transaction};
+pub use replica::TapirReplica;
+
+use crate::hlc::{HlcTimestamp, Timestamped};
+use crate::types::DecideFunction;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+/// A key of the store
+pub trait TapirKey: Clone + Ord + Debug + Send + Sync + 'static {}
+
+impl<A> TapirKey for A where A: Clone + Ord + Debug + Send + Sync + 'static {}
+
+/// A value of the store
+pub trait TapirValue: Clone + Ord + Debug + Send + Sync + 'static {}
+
+impl<A> TapirValue for A where A: Clone + Ord + Debug + Send + Sync + 'static {}
+
+/// The commit timestamp of a transaction, which is also the version of the values it wrote
+/// Timestamps are unique, as they include the client that chose them.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Timestamp {
+    /// Taken from the client's hybrid logical clock
+    pub time: HlcTimestamp,
+    pub client: u64,
+}
+
+/// Identifies a transaction across prepare, commit and abort
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TransactionId {
+    pub client: u64,
+    pub sequence: u64,
+}
+
+/// A replica's OCC validation result for a prepared transaction
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Vote {
+    /// The transaction does not conflict and has been prepared
+    Ok,
+    /// The transaction conflicts with one that committed, and can never commit
+    Abort,
+    /// The transaction conflicts with one that is prepared, and may succeed if retried
+    Abstain,
+    /// Too few replicas voted for the outcome to be decided, so the transaction may be retried
+    /// Only decided on the client or during a view change, replicas do not vote it.
+    Retry,
+}
+
+/// The reads and writes of a transaction, as sent to replicas to be prepared
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct TransactionRecord<K: TapirKey, V: TapirValue> {
+    pub id: TransactionId,
+    /// The version of each key that was read
+    pub read_set: BTreeMap<K, Timestamp>,
+    pub write_set: BTreeMap<K, V>,
+}
+
+/// The operations of TAPIR, with their results filled in by replicas
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum TapirMessage<K: TapirKey, V: TapirValue> {
+    /// Consensus; replicas fill in the latest committed value and its version
+    Read {
+        key: K,
+        value: Option<V>,
+        version: Option<Timestamp>,
+    },
+    /// Consensus; replicas fill in their OCC vote
+    Prepare {
+        transaction: TransactionRecord<K, V>,
+        timestamp: Timestamp,
+        vote: Option<Vote>,
+    },
+    /// Inconsistent; applies the writes of a prepared transaction
+    Commit {
+        transaction: TransactionRecord<K, V>,
+        timestamp: Timestamp,
+    },
+    /// Inconsistent; forgets a prepared transaction
+    Abort { id: TransactionId },
+}
+
+impl<K: TapirKey, V: TapirValue> Timestamped for TapirMessage<K, V> {
+    /// The version read, or the timestamp of the transaction
+    fn timestamp(&self) -> Option<HlcTimestamp> {
+        match self {
+            TapirMessage::Read { version, .. } => version.map(|version| version.time),
+            TapirMessage::Prepare { timestamp, .. } | TapirMessage::Commit { timestamp, .. } => {
+                Some(timestamp.time)
+            }
+            TapirMessage::Abort { .. } => None,
+        }
+    }
+}
+
+/// Decides consensus results when replicas disagree, on the client and during view changes
+/// As in the paper's TAPIR-DECIDE, a prepare is `Ok` only if at least f+1 replicas voted `Ok`,
+/// where f is the number of failures the view tolerates, so an unreachable replica never
+/// counts towards it. Any `Abort` vote, or f+1 `Abstain` votes, abort it, and anything else is
+/// `Retry`. A read takes the most recent version reported.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OccDecide;
+
+impl<K: TapirKey, V: TapirValue> DecideFunction<TapirMessage<K, V>> for OccDecide {
+    fn decide<'a, S: IntoIterator<Item = &'a TapirMessage<K, V>>>(
+        &self,
+        choices: S,
+        f: usize,
+    ) -> TapirMessage<K, V> {
+        decide(choices, f)
+    }
+}
+
+/// The decided choice, shared by `OccDecide` and `TapirReplica::decide`
+fn decide<'a, K: TapirKey, V: TapirValue>(
+    choices: impl IntoIterator<Item = &'a TapirMessage<K, V>>,
+    f: usize,
+) -> TapirMessage<K, V> {
+    let choices: Vec<_> = choices.into_iter().collect();
+    let count = |wanted: Vote| {
+        choices
+            .iter()
+            .filter(|choice| matches!(choice, TapirMessage::Prepare { vote, .. } if *vote == Some(wanted)))
+            .count()
+    };
+    let decided = if count(Vote::Abort) > 0 {
+        Vote::Abort
+    } else if count(Vote::Ok) > f {
+        Vote::Ok
+    } else if count(Vote::Abstain) > f {
+        Vote::Abort
+    } else {
+        Vote::Retry
+    };
+    let prepare = choices
+        .iter()
+        .find(|choice| matches!(choice, TapirMessage::Prepare { .. }));
+    if let Some(TapirMessage::Prepare {
+        transaction,
+        timestamp,
+        ..
+    }) = prepare
+    {
+        return TapirMessage::Prepare {
+            transaction: transaction.clone(),
+            timestamp: *timestamp,
+            vote: Some(decided),
+        };
+    }
+    // Reads, and anything else, take the latest version
+    choices
+        .iter()
+        .max_by_key(|choice| match choice {
+            TapirMessage::Read { version, .. } => *version,
+            _ => None,
+        })
+        .map(|choice| (*choice).clone())
+        .expect("a decision needs at least one choice")
+}
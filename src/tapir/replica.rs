@@ -0,0 +1,270 @@
+use crate::application::IRApplication;
+use crate::server::IROperation;
+use crate::tapir::{
+    decide, TapirKey, TapirMessage, TapirValue, Timestamp, TransactionId, TransactionRecord, Vote,
+};
+use crate::types::NodeID;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+struct Store<K: TapirKey, V: TapirValue> {
+    /// The latest committed value of each key, with its version
+    committed: BTreeMap<K, (V, Timestamp)>,
+    /// Transactions that voted `Ok`, and have neither committed nor aborted
+    prepared: BTreeMap<TransactionId, (TransactionRecord<K, V>, Timestamp)>,
+    /// Transactions that have committed or aborted, which must not be prepared again
+    finished: BTreeSet<TransactionId>,
+}
+
+impl<K: TapirKey, V: TapirValue> Store<K, V> {
+    fn version(&self, key: &K) -> Timestamp {
+        self.committed
+            .get(key)
+            .map(|(_, version)| *version)
+            .unwrap_or_default()
+    }
+
+    /// OCC validation against committed and prepared transactions
+    fn validate(&self, transaction: &TransactionRecord<K, V>, timestamp: Timestamp) -> Vote {
+        if self.finished.contains(&transaction.id) {
+            return Vote::Abort;
+        }
+        if self.prepared.contains_key(&transaction.id) {
+            return Vote::Ok;
+        }
+        for (key, read_version) in &transaction.read_set {
+            // A newer version was committed since the read
+            if self.version(key) > *read_version {
+                return Vote::Abort;
+            }
+        }
+        for key in transaction.write_set.keys() {
+            // Writes must be ordered after every committed version
+            if self.version(key) > timestamp {
+                return Vote::Abort;
+            }
+        }
+        for (prepared, _) in self.prepared.values() {
+            let conflicts = transaction
+                .read_set
+                .keys()
+                .any(|key| prepared.write_set.contains_key(key))
+                || transaction.write_set.keys().any(|key| {
+                    prepared.write_set.contains_key(key) || prepared.read_set.contains_key(key)
+                });
+            if conflicts {
+                return Vote::Abstain;
+            }
+        }
+        Vote::Ok
+    }
+
+    fn prepare(&mut self, transaction: TransactionRecord<K, V>, timestamp: Timestamp) -> Vote {
+        let vote = self.validate(&transaction, timestamp);
+        if vote == Vote::Ok {
+            self.prepared
+                .insert(transaction.id, (transaction, timestamp));
+        }
+        vote
+    }
+
+    fn commit(&mut self, transaction: TransactionRecord<K, V>, timestamp: Timestamp) {
+        self.prepared.remove(&transaction.id);
+        if !self.finished.insert(transaction.id) {
+            return;
+        }
+        for (key, value) in transaction.write_set {
+            if self.version(&key) < timestamp {
+                self.committed.insert(key, (value, timestamp));
+            }
+        }
+    }
+
+    fn abort(&mut self, id: TransactionId) {
+        self.prepared.remove(&id);
+        self.finished.insert(id);
+    }
+}
+
+/// The replica side of TAPIR, an `IRApplication` holding a versioned store
+/// Prepares are validated with OCC, and the latest committed values can be inspected with
+/// `get`.
+pub struct TapirReplica<K: TapirKey, V: TapirValue> {
+    store: Arc<Mutex<Store<K, V>>>,
+}
+
+impl<K: TapirKey, V: TapirValue> Clone for TapirReplica<K, V> {
+    fn clone(&self) -> Self {
+        TapirReplica {
+            store: self.store.clone(),
+        }
+    }
+}
+
+impl<K: TapirKey, V: TapirValue> Default for TapirReplica<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: TapirKey, V: TapirValue> TapirReplica<K, V> {
+    pub fn new() -> Self {
+        TapirReplica {
+            store: Arc::new(Mutex::new(Store {
+                committed: BTreeMap::new(),
+                prepared: BTreeMap::new(),
+                finished: BTreeSet::new(),
+            })),
+        }
+    }
+
+    /// The latest committed value of a key, and its version
+    pub fn get(&self, key: &K) -> Option<(V, Timestamp)> {
+        self.store.lock().unwrap().committed.get(key).cloned()
+    }
+
+    /// The transactions that are prepared but not yet committed or aborted
+    pub fn prepared(&self) -> Vec<TransactionId> {
+        self.store
+            .lock()
+            .unwrap()
+            .prepared
+            .keys()
+            .copied()
+            .collect()
+    }
+
+    fn apply(&self, message: TapirMessage<K, V>) -> TapirMessage<K, V> {
+        let mut store = self.store.lock().unwrap();
+        match message {
+            TapirMessage::Read { key, .. } => {
+                let (value, version) = match store.committed.get(&key) {
+                    Some((value, version)) => (Some(value.clone()), *version),
+                    None => (None, Timestamp::default()),
+                };
+                TapirMessage::Read {
+                    key,
+                    value,
+                    version: Some(version),
+                }
+            }
+            TapirMessage::Prepare {
+                transaction,
+                timestamp,
+                ..
+            } => {
+                let vote = store.prepare(transaction.clone(), timestamp);
+                TapirMessage::Prepare {
+                    transaction,
+                    timestamp,
+                    vote: Some(vote),
+                }
+            }
+            TapirMessage::Commit {
+                transaction,
+                timestamp,
+            } => {
+                store.commit(transaction.clone(), timestamp);
+                TapirMessage::Commit {
+                    transaction,
+                    timestamp,
+                }
+            }
+            TapirMessage::Abort { id } => {
+                store.abort(id);
+                TapirMessage::Abort { id }
+            }
+        }
+    }
+}
+
+impl<I: NodeID, K: TapirKey, V: TapirValue> IRApplication<I, TapirMessage<K, V>>
+    for TapirReplica<K, V>
+{
+    fn exec_inconsistent(
+        &self,
+        message: TapirMessage<K, V>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        self.apply(message);
+        Box::pin(async {})
+    }
+
+    fn exec_consensus(
+        &self,
+        message: TapirMessage<K, V>,
+    ) -> Pin<Box<dyn Future<Output = TapirMessage<K, V>> + Send + 'static>> {
+        let result = self.apply(message);
+        Box::pin(async move { result })
+    }
+
+    /// A learner sees the decided vote, so it only prepares transactions that were accepted
+    fn exec_finalized_consensus(
+        &self,
+        message: TapirMessage<K, V>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        if let TapirMessage::Prepare {
+            transaction,
+            timestamp,
+            vote: Some(Vote::Ok),
+        } = message
+        {
+            let mut store = self.store.lock().unwrap();
+            if !store.finished.contains(&transaction.id) {
+                store
+                    .prepared
+                    .insert(transaction.id, (transaction, timestamp));
+            }
+        }
+        Box::pin(async {})
+    }
+
+    fn decide(&self, choices: Vec<TapirMessage<K, V>>, f: usize) -> TapirMessage<K, V> {
+        decide(&choices, f)
+    }
+
+    /// Rebuild the prepared transactions from the master record, applying any commits and
+    /// aborts this replica missed
+    fn sync(
+        &self,
+        record: Vec<IROperation<I, TapirMessage<K, V>>>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
+        let mut store = self.store.lock().unwrap();
+        for operation in record {
+            match operation {
+                IROperation::ConsistentFinalize {
+                    message:
+                        TapirMessage::Prepare {
+                            transaction,
+                            timestamp,
+                            vote,
+                        },
+                    ..
+                } => {
+                    if vote == Some(Vote::Ok) && !store.finished.contains(&transaction.id) {
+                        store
+                            .prepared
+                            .insert(transaction.id, (transaction, timestamp));
+                    } else {
+                        store.prepared.remove(&transaction.id);
+                    }
+                }
+                IROperation::InconsistentFinalize {
+                    message:
+                        TapirMessage::Commit {
+                            transaction,
+                            timestamp,
+                        },
+                    ..
+                } => store.commit(transaction, timestamp),
+                IROperation::InconsistentFinalize {
+                    message: TapirMessage::Abort { id },
+                    ..
+                } => store.abort(id),
+                _ => {}
+            }
+        }
+        Box::pin(async {})
+    }
+}
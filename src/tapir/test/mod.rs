@@ -0,0 +1,194 @@
+use crate::hlc::{HybridLogicalClock, ManualTimeSource};
+use crate::io::test_utils::{fake_cluster, partitioned_fake_cluster, FakeIRNetwork, FakeIRStorage};
+use crate::tapir::{
+    OccDecide, TapirClient, TapirError, TapirMessage, TapirReplica, Timestamp, TransactionId,
+    TransactionRecord, Vote,
+};
+use crate::types::DecideFunction;
+use crate::{IRApplication, InconsistentReplicationClient};
+use std::collections::BTreeMap;
+
+type Message = TapirMessage<String, u64>;
+type Network = FakeIRNetwork<u64, Message, FakeIRStorage<u64, Message>, TapirReplica<String, u64>>;
+
+async fn tapir_client(
+    network: &Network,
+    members: Vec<u64>,
+    client_number: u64,
+) -> TapirClient<Network, FakeIRStorage<u64, Message>, u64, String, u64, ManualTimeSource> {
+    let client = InconsistentReplicationClient::new(
+        network.clone(),
+        FakeIRStorage::new(members),
+        100 + client_number,
+    )
+    .await;
+    TapirClient::new(
+        client,
+        client_number,
+        HybridLogicalClock::new(ManualTimeSource::new(0)),
+    )
+}
+
+#[tokio::test]
+async fn committed_writes_are_read_by_later_transactions() {
+    // given a cluster and a client
+    let network = Network::new();
+    let members = vec![1, 2, 3];
+    let replicas = fake_cluster(&network, &members, TapirReplica::new).await;
+    let client = tapir_client(&network, members, 1).await;
+
+    // when a transaction writes a key and commits
+    let mut transaction = client.begin();
+    transaction.write("alice".to_string(), 10);
+    let timestamp = client.commit(transaction).await.unwrap();
+
+    // then every replica has the write at the commit timestamp
+    for replica in &replicas {
+        assert_eq!(replica.get(&"alice".to_string()), Some((10, timestamp)));
+        assert!(replica.prepared().is_empty());
+    }
+
+    // and a later transaction reads it
+    let mut transaction = client.begin();
+    let balance = client
+        .read(&mut transaction, "alice".to_string())
+        .await
+        .unwrap();
+    assert_eq!(balance, Some(10));
+}
+
+#[tokio::test]
+async fn transactions_with_stale_reads_are_aborted() {
+    // given two clients that read the same key
+    let network = Network::new();
+    let members = vec![1, 2, 3];
+    let replicas = fake_cluster(&network, &members, TapirReplica::new).await;
+    let first = tapir_client(&network, members.clone(), 1).await;
+    let second = tapir_client(&network, members, 2).await;
+    let mut first_transaction = first.begin();
+    let mut second_transaction = second.begin();
+    first
+        .read(&mut first_transaction, "alice".to_string())
+        .await
+        .unwrap();
+    second
+        .read(&mut second_transaction, "alice".to_string())
+        .await
+        .unwrap();
+
+    // when the second commits a write to it
+    second_transaction.write("alice".to_string(), 20);
+    second.commit(second_transaction).await.unwrap();
+
+    // then the first can no longer commit, as its read is stale
+    first_transaction.write("alice".to_string(), 10);
+    let result = first.commit(first_transaction).await;
+    assert_eq!(result, Err(TapirError::Aborted(Vote::Abort)));
+    for replica in &replicas {
+        assert_eq!(replica.get(&"alice".to_string()).unwrap().0, 20);
+        assert!(replica.prepared().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn prepares_retry_without_f_plus_one_ok_votes_from_the_members() {
+    // given 5 members of which only 3 are reachable, one holding a conflicting prepare
+    let network = Network::new();
+    let members = vec![1, 2, 3, 4, 5];
+    let replicas =
+        partitioned_fake_cluster(&network, &members, &[1, 2, 3], TapirReplica::new).await;
+    let conflicting = TransactionRecord {
+        id: TransactionId {
+            client: 9,
+            sequence: 0,
+        },
+        read_set: BTreeMap::new(),
+        write_set: BTreeMap::from([("alice".to_string(), 1)]),
+    };
+    IRApplication::<u64, Message>::exec_consensus(
+        &replicas[2],
+        TapirMessage::Prepare {
+            transaction: conflicting,
+            timestamp: Timestamp::default(),
+            vote: None,
+        },
+    )
+    .await;
+    let client = tapir_client(&network, members, 1).await;
+
+    // when a transaction writing the key commits, with two Ok votes and an abstain
+    let mut transaction = client.begin();
+    transaction.write("alice".to_string(), 10);
+    let result = client.commit(transaction).await;
+
+    // then two votes are not f+1 of the 5 members, so it is retried rather than committed
+    assert_eq!(result, Err(TapirError::Aborted(Vote::Retry)));
+    for replica in &replicas {
+        assert_eq!(replica.get(&"alice".to_string()), None);
+    }
+    assert!(replicas[0].prepared().is_empty());
+    assert!(replicas[1].prepared().is_empty());
+}
+
+#[test]
+fn prepares_need_f_plus_one_ok_votes() {
+    let prepare = |vote| Message::Prepare {
+        transaction: TransactionRecord {
+            id: TransactionId {
+                client: 1,
+                sequence: 0,
+            },
+            read_set: BTreeMap::new(),
+            write_set: BTreeMap::new(),
+        },
+        timestamp: Timestamp::default(),
+        vote: Some(vote),
+    };
+    let vote = |choices: &[Message], f| match OccDecide.decide(choices, f) {
+        TapirMessage::Prepare { vote, .. } => vote.unwrap(),
+        _ => panic!("Prepare expected"),
+    };
+
+    // any abort vote aborts
+    assert_eq!(
+        vote(
+            &[prepare(Vote::Ok), prepare(Vote::Ok), prepare(Vote::Abort)],
+            1
+        ),
+        Vote::Abort
+    );
+    assert_eq!(
+        vote(
+            &[prepare(Vote::Ok), prepare(Vote::Ok), prepare(Vote::Abstain)],
+            1
+        ),
+        Vote::Ok
+    );
+    // f+1 abstains abort
+    assert_eq!(
+        vote(
+            &[
+                prepare(Vote::Ok),
+                prepare(Vote::Abstain),
+                prepare(Vote::Abstain)
+            ],
+            1
+        ),
+        Vote::Abort
+    );
+    // with 5 members and 2 unreachable, a majority of the replies is not f+1 of the members
+    assert_eq!(
+        vote(
+            &[prepare(Vote::Ok), prepare(Vote::Ok), prepare(Vote::Abstain)],
+            2
+        ),
+        Vote::Retry
+    );
+    assert_eq!(
+        vote(
+            &[prepare(Vote::Ok), prepare(Vote::Ok), prepare(Vote::Ok)],
+            2
+        ),
+        Vote::Ok
+    );
+}
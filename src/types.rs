@@ -1,28 +1,45 @@
 use std::fmt::Debug;
-use std::future::Future;
-use std::pin::Pin;
 
 /// The representation of a node id in a cluster, or a client id
 /// This requires the Debug trait since it is used in errors
-pub trait NodeID: Clone + PartialEq + Ord + PartialOrd + Debug + 'static {}
+/// Send and Sync are required so that operations can be run on multi-threaded runtimes
+pub trait NodeID: Clone + PartialEq + Ord + PartialOrd + Debug + Send + Sync + 'static {}
 
-impl<A> NodeID for A where A: Clone + PartialEq + Ord + PartialOrd + Debug + 'static {}
+impl<A> NodeID for A where A: Clone + PartialEq + Ord + PartialOrd + Debug + Send + Sync + 'static {}
 
-pub trait IRMessage: Clone + PartialEq + Ord + PartialOrd + 'static {}
+/// A message is both an operation and its result
+/// This requires the Debug trait so that operations can be logged
+pub trait IRMessage: Clone + PartialEq + Ord + PartialOrd + Debug + Send + Sync + 'static {}
 
-impl<A> IRMessage for A where A: Clone + PartialEq + Ord + PartialOrd + 'static {}
+impl<A> IRMessage for A where A: Clone + PartialEq + Ord + PartialOrd + Debug + Send + Sync + 'static
+{}
 
+/// Decides the result of a consensus operation from the replies of a slow quorum
+/// `f` is the number of failures the view's `QuorumPolicy` tolerates, so a result reported by
+/// `f + 1` replicas was reported by at least one correct replica.
 pub trait DecideFunction<M: IRMessage> {
-    fn decide<'a, S: IntoIterator<Item = &'a M>>(&self, choices: S) -> &'a M;
+    fn decide<'a, S: IntoIterator<Item = &'a M>>(&self, choices: S, f: usize) -> M;
 }
 
 pub type OperationSequence = u64;
 
-/// An asynchronous iterator
-/// This is used in lieu of the unstable feature `async_iterator`
-/// Once that stabilises then we can switch
-/// The signature is quite different but I believe this one is better
-pub trait AsyncIterator {
-    type Item;
-    fn next(&self) -> Pin<Box<dyn Future<Output = Option<Self::Item>>>>;
+/// Identifies one operation in logs and traces, on the client and on every replica
+/// An operation is already identified by its client and sequence, which every `IRNetwork`
+/// call carries, so replicas derive the same trace id without it being sent separately.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct TraceId<I: NodeID> {
+    pub client: I,
+    pub sequence: OperationSequence,
+}
+
+impl<I: NodeID> TraceId<I> {
+    pub fn new(client: I, sequence: OperationSequence) -> Self {
+        TraceId { client, sequence }
+    }
+}
+
+impl<I: NodeID> std::fmt::Display for TraceId<I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}/{}", self.client, self.sequence)
+    }
 }
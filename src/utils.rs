@@ -1,27 +1,9 @@
+use crate::quorum::{QuorumError, QuorumPolicy, QuorumSizes};
 use crate::server::View;
 use crate::types::{IRMessage, NodeID};
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Debug, Formatter};
 
-/// Derive f (number of tolerable failures) from the number of nodes in the cluster
-pub fn f(nodes: usize) -> Result<usize, ()> {
-    if nodes < 3 {
-        Err(())
-    } else {
-        Ok(((nodes - 1) as f32 / 2.0f32).ceil() as usize)
-    }
-}
-
-/// Derive the fast quorum size from the number of nodes in the cluster
-pub fn fast_quorum(nodes: usize) -> Result<usize, ()> {
-    Ok(((3 * f(nodes)? + 1) as f32 / 2f32 + 1f32).floor() as usize)
-}
-
-/// Derive the normal quorum size from the number of nodes in the cluster
-pub fn slow_quorum(nodes: usize) -> Result<usize, ()> {
-    Ok(f(nodes)? + 1)
-}
-
 pub struct QuorumVote<'a, ID: NodeID, MSG: IRMessage> {
     pub(crate) node: &'a ID,
     pub(crate) message: &'a MSG,
@@ -42,9 +24,9 @@ impl<'a, ID: NodeID, MSG: IRMessage> Clone for QuorumVote<'a, ID, MSG> {
 #[derive(Eq, PartialEq)]
 /// A quorum was achieved and the details are included in this struct
 pub(crate) struct Quorum<'a, ID: NodeID, MSG: IRMessage> {
-    pub(crate) count: usize,
-    pub(crate) fast_minimum: usize,
-    pub(crate) quorum_minimum: usize,
+    pub(crate) count: u64,
+    pub(crate) fast_minimum: u64,
+    pub(crate) quorum_minimum: u64,
     pub(crate) message: &'a MSG,
     pub(crate) nodes_with: Vec<&'a ID>,
     pub(crate) nodes_without: Vec<&'a ID>,
@@ -93,6 +75,7 @@ where
 /// Find a quorum where the set agrees to a value
 /// All views must match for quorum
 /// Quorum can only be from largest view; Membership comes from largest quorum.
+/// Votes are weighed, and quorum sizes derived from the view, by the policy.
 /// Err if no quorum
 /// TODO change to return borrows not clones
 pub fn find_quorum<
@@ -101,6 +84,7 @@ pub fn find_quorum<
     MSG: IRMessage,
     ITER: Iterator<Item = QuorumVote<'a, ID, MSG>>,
 >(
+    policy: &QuorumPolicy<ID>,
     iterable: ITER,
 ) -> Result<Quorum<'a, ID, MSG>, Option<NoQuorum<'a, ID, MSG>>> {
     let mut votes: BTreeMap<&View<ID>, BTreeMap<&MSG, BTreeSet<&ID>>> = BTreeMap::new();
@@ -108,11 +92,15 @@ pub fn find_quorum<
     let mut all_nodes = BTreeSet::new();
     // Tally up all the votes
     for item in iterable {
-        let view_entry = votes.entry(item.view).or_insert(BTreeMap::new());
+        let view_entry = votes.entry(item.view).or_default();
         if highest_view.is_none() || item.view.view > highest_view.unwrap().view {
             highest_view = Some(item.view);
         }
-        let message_entry = view_entry.entry(item.message).or_insert(BTreeSet::new());
+        if !item.view.members.contains(item.node) {
+            // Learners, and other nodes outside the view, do not vote
+            continue;
+        }
+        let message_entry = view_entry.entry(item.message).or_default();
         if !all_nodes.contains(item.node) || Some(item.view) == highest_view {
             // We don't want a node voting twice, but we also don't want to fail check
             // So we only count the second vote if it is potentially valid
@@ -128,19 +116,20 @@ pub fn find_quorum<
         .get(highest_view)
         .ok_or(None)?
         .iter()
-        .max_by(|a, b| a.1.len().cmp(&b.1.len()))
+        .max_by_key(|(_msg, voters)| policy.weight_of(voters.iter().copied()))
         .ok_or(None)?;
+    let quorum_vote_weight = policy.weight_of(quorum_vote_nodes.iter().copied());
     // Handle pathological situations where there are multiple quorums
     let many_quorums: Vec<_> = votes
         .get(highest_view)
         .ok_or(None)?
         .iter()
-        .filter(|(_msg, votes)| votes.len() >= quorum_vote_nodes.len())
+        .filter(|(_msg, votes)| policy.weight_of(votes.iter().copied()) >= quorum_vote_weight)
         .collect();
     if many_quorums.len() > 1 {
         let mut votes = BTreeMap::new();
         for (msg, voters) in many_quorums {
-            votes.insert(*msg, voters.iter().map(|a| *a).collect());
+            votes.insert(*msg, voters.iter().copied().collect());
         }
         return Err(Some(NoQuorum {
             view: highest_view,
@@ -155,47 +144,71 @@ pub fn find_quorum<
     for node in quorum_vote_nodes {
         opposing_nodes.remove(node);
     }
-    // Check quorum against view
-    let count_fast_quorum = fast_quorum(highest_view.members.len()).unwrap();
-    let count_slow_quorum = slow_quorum(highest_view.members.len()).unwrap();
-    if quorum_vote_nodes.len() >= count_fast_quorum {
+    // Check quorum against view, a view too small for any quorum has no quorum
+    let (count_fast_quorum, count_slow_quorum) = match policy.sizes(&highest_view.members) {
+        Ok(sizes) => (sizes.fast, sizes.slow),
+        Err(_) => (u64::MAX, u64::MAX),
+    };
+    if quorum_vote_weight >= count_fast_quorum {
         Ok(Quorum {
-            count: quorum_vote_nodes.len(),
+            count: quorum_vote_weight,
             fast_minimum: count_fast_quorum,
             quorum_minimum: count_slow_quorum,
             message: quorum_vote_message,
-            nodes_with: quorum_vote_nodes.into_iter().map(|a| *a).collect(),
+            nodes_with: quorum_vote_nodes.iter().copied().collect(),
             nodes_without: opposing_nodes.into_iter().collect(),
             view: highest_view,
             quorum_type: QuorumType::FastQuorum,
         })
-    } else if quorum_vote_nodes.len() >= count_slow_quorum {
+    } else if quorum_vote_weight >= count_slow_quorum {
         Ok(Quorum {
-            count: quorum_vote_nodes.len(),
+            count: quorum_vote_weight,
             fast_minimum: count_fast_quorum,
             quorum_minimum: count_slow_quorum,
             message: quorum_vote_message,
-            nodes_with: quorum_vote_nodes.into_iter().map(|a| *a).collect(),
+            nodes_with: quorum_vote_nodes.iter().copied().collect(),
             nodes_without: opposing_nodes.into_iter().collect(),
             view: highest_view,
             quorum_type: QuorumType::NormalQuorum,
         })
     } else {
-        return Err(Some(NoQuorum {
+        Err(Some(NoQuorum {
             view: highest_view,
             votes: votes
                 .get(highest_view)
                 .ok_or(None)?
                 .iter()
-                .map(|(msg, voters)| (*msg, voters.iter().map(|a| *a).collect()))
+                .map(|(msg, voters)| (*msg, voters.iter().copied().collect()))
                 .collect(),
-        }));
+        }))
     }
 }
 
-#[derive(Eq, PartialEq)]
-#[cfg(debug_assertions)]
-#[derive(Debug)]
+/// Check that a proposed view can be installed under a quorum policy
+/// Members must be distinct and not learners, any two slow quorums must intersect, and a fast quorum must
+/// share a majority (f/2+1 of 2f+1) with every slow quorum, so that results decided with a
+/// fast quorum survive the next view change.
+pub fn validate_view<ID: NodeID>(
+    policy: &QuorumPolicy<ID>,
+    view: &View<ID>,
+) -> Result<QuorumSizes, QuorumError<ID>> {
+    let mut members = BTreeSet::new();
+    for member in &view.members {
+        if !members.insert(member) {
+            return Err(QuorumError::DuplicateMember(member.clone()));
+        }
+    }
+    if let Some(learner) = view
+        .learners
+        .iter()
+        .find(|learner| members.contains(learner))
+    {
+        return Err(QuorumError::LearnerIsMember(learner.clone()));
+    }
+    policy.sizes(&view.members)
+}
+
+#[derive(Debug, Eq, PartialEq)]
 pub enum QuorumType {
     FastQuorum,
     NormalQuorum,
@@ -203,55 +216,44 @@ pub enum QuorumType {
 
 #[cfg(test)]
 mod test {
+    use crate::quorum::{QuorumError, QuorumPolicy};
     use crate::server::{View, ViewState};
     use crate::utils::{NoQuorum, Quorum, QuorumType, QuorumVote};
     use std::collections::BTreeMap;
 
     #[test]
-    fn test_f() {
-        assert!(super::f(0).is_err());
-        assert!(super::f(1).is_err());
-        assert!(super::f(2).is_err());
-        assert_eq!(super::f(3), Ok(1));
-        assert_eq!(super::f(4), Ok(2));
-        assert_eq!(super::f(5), Ok(2));
-        assert_eq!(super::f(6), Ok(3));
-        assert_eq!(super::f(7), Ok(3));
-    }
-
-    #[test]
-    fn test_fast_quorum() {
-        assert!(super::fast_quorum(0).is_err());
-        assert!(super::fast_quorum(1).is_err());
-        assert!(super::fast_quorum(2).is_err());
-        // f = 1; 3f/2+1 = 3/2+1 = 1.5+1 = 2.5 = 3
-        assert_eq!(super::fast_quorum(3), Ok(3));
-        // f = 2; 3f/2+1 = 6/2+1 = 3+1 = 4
-        assert_eq!(super::fast_quorum(4), Ok(4));
-        // f = 2; 3f/2+1 = 6/2+1 = 3+1 = 4
-        assert_eq!(super::fast_quorum(5), Ok(4));
-        // f = 3; 3f/2+1 = 9/2+1 = 4.5+1 = 5.5 = 6
-        assert_eq!(super::fast_quorum(6), Ok(6));
-        // f = 3; 3f/2+1 = 9/2+1 = 4.5+1 = 5.5 = 6
-        assert_eq!(super::fast_quorum(7), Ok(6));
-        // f = 4; 3f/2+1 = 12/2+1 = 6+1 = 7
-        assert_eq!(super::fast_quorum(8), Ok(7));
-        // f = 4; 3f/2+1 = 12/2+1 = 6+1 = 7
-        assert_eq!(super::fast_quorum(9), Ok(7));
-    }
+    fn test_validate_view() {
+        let view = |members: &[u64]| View {
+            view: 2,
+            members: members.to_vec(),
+            learners: vec![],
+            state: ViewState::ViewChanging,
+        };
+        let majority = QuorumPolicy::majority();
 
-    #[test]
-    fn test_slow_quorum() {
-        assert!(super::slow_quorum(0).is_err());
-        assert!(super::slow_quorum(1).is_err());
-        assert!(super::slow_quorum(2).is_err());
-        assert_eq!(super::slow_quorum(3), Ok(2));
-        assert_eq!(super::slow_quorum(4), Ok(3));
-        assert_eq!(super::slow_quorum(5), Ok(3));
-        assert_eq!(super::slow_quorum(6), Ok(4));
-        assert_eq!(super::slow_quorum(7), Ok(4));
-        assert_eq!(super::slow_quorum(8), Ok(5));
-        assert_eq!(super::slow_quorum(9), Ok(5));
+        assert!(super::validate_view(&majority, &view(&[1, 2, 3, 4])).is_ok());
+        assert_eq!(
+            super::validate_view(&majority, &view(&[1, 2, 2])),
+            Err(QuorumError::DuplicateMember(2))
+        );
+        assert_eq!(
+            super::validate_view(
+                &majority,
+                &View {
+                    learners: vec![3],
+                    ..view(&[1, 2, 3])
+                }
+            ),
+            Err(QuorumError::LearnerIsMember(3))
+        );
+        assert_eq!(
+            super::validate_view(&majority, &view(&[1, 2])),
+            Err(QuorumError::TooFewMembers(2))
+        );
+        assert_eq!(
+            super::validate_view(&majority.clone().with_f(2), &view(&[1, 2, 3, 4])),
+            Err(QuorumError::TooManyFailures { f: 2, members: 4 })
+        );
     }
 
     #[test]
@@ -260,6 +262,7 @@ mod test {
             name: &'a str,
             line_number: u32,
             votes: Vec<QuorumVote<'a, String, String>>,
+            #[allow(dead_code)]
             quorum_type: QuorumType,
             expected: Result<Quorum<'a, String, String>, Option<NoQuorum<'a, String, String>>>,
         }
@@ -268,6 +271,7 @@ mod test {
             View {
                 view: num,
                 members: members.iter().map(|s| s.to_string()).collect(),
+                learners: vec![],
                 state,
             }
         }
@@ -285,10 +289,15 @@ mod test {
         let view_3_1_normal = View {
             view: 1,
             members: vec![one.clone(), two.clone(), three.clone()],
+            learners: vec![],
             state: ViewState::Normal,
         };
         let view_3_2_normal = view(2, &["1", "2", "3"], ViewState::Normal);
         let view_4_1_normal = view(1, &["1", "2", "3", "4"], ViewState::Normal);
+        let view_3_1_learner = View {
+            learners: vec![four.clone()],
+            ..view(1, &["1", "2", "3"], ViewState::Normal)
+        };
 
         let cases: Vec<TestCase> = vec![
             // TODO quorum not achieved if no votes
@@ -475,6 +484,38 @@ mod test {
                     ]),
                 })),
             },
+            TestCase {
+                name: "Learners do not count towards quorum",
+                line_number: line!(),
+                votes: vec![
+                    QuorumVote {
+                        node: &one,
+                        message: &msg_a,
+                        view: &view_3_1_learner,
+                    },
+                    QuorumVote {
+                        node: &two,
+                        message: &msg_a,
+                        view: &view_3_1_learner,
+                    },
+                    QuorumVote {
+                        node: &four,
+                        message: &msg_a,
+                        view: &view_3_1_learner,
+                    },
+                ],
+                quorum_type: QuorumType::NormalQuorum,
+                expected: Ok(Quorum {
+                    count: 2,
+                    fast_minimum: 3,
+                    quorum_minimum: 2,
+                    message: &msg_a,
+                    nodes_with: vec![&one, &two],
+                    nodes_without: vec![&three],
+                    view: &view_3_1_learner,
+                    quorum_type: QuorumType::NormalQuorum,
+                }),
+            },
             TestCase {
                 name: "Double votes do not count",
                 line_number: line!(),
@@ -533,7 +574,7 @@ mod test {
         ];
 
         for case in cases {
-            let result = super::find_quorum(case.votes.iter().cloned());
+            let result = super::find_quorum(&QuorumPolicy::majority(), case.votes.iter().cloned());
             assert_eq!(
                 result, case.expected,
                 "{} - {}",
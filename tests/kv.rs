@@ -0,0 +1,213 @@
+//! A TAPIR key-value cluster over the TCP transport and log storage, as run by the `kv`
+//! example, with every node and client in this process.
+#![cfg(all(feature = "tapir", feature = "tcp"))]
+
+use inconsistent_replication_ir::hlc::{HybridLogicalClock, SystemTimeSource};
+use inconsistent_replication_ir::tapir::{TapirClient, TapirMessage, TapirReplica, TransactionId};
+use inconsistent_replication_ir::tcp::{self, TcpNetwork};
+use inconsistent_replication_ir::{
+    BootstrapError, IRServerError, InconsistentReplicationClient, InconsistentReplicationServer,
+    LogStorage, MembersClientStorage, ViewState,
+};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+type Message = TapirMessage<String, String>;
+type Server = InconsistentReplicationServer<
+    TcpNetwork<u64, Message>,
+    LogStorage<u64, Message>,
+    TapirReplica<String, String>,
+    u64,
+    Message,
+>;
+type Client = TapirClient<
+    TcpNetwork<u64, Message>,
+    MembersClientStorage<u64>,
+    u64,
+    String,
+    String,
+    SystemTimeSource,
+>;
+
+const MEMBERS: [u64; 3] = [1, 2, 3];
+
+/// A fresh temporary directory for the nodes' logs, removed when dropped
+struct DataDir(PathBuf);
+
+impl DataDir {
+    fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "ir-kv-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        DataDir(dir)
+    }
+
+    fn log(&self, node: u64) -> PathBuf {
+        self.0.join(format!("node-{node}.log"))
+    }
+}
+
+impl Drop for DataDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Listeners on free ports for every member, with the addresses they are bound to
+async fn listeners() -> (BTreeMap<u64, TcpListener>, BTreeMap<u64, SocketAddr>) {
+    let mut listeners = BTreeMap::new();
+    let mut addresses = BTreeMap::new();
+    for node in MEMBERS {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        addresses.insert(node, listener.local_addr().unwrap());
+        listeners.insert(node, listener);
+    }
+    (listeners, addresses)
+}
+
+async fn open_node(node: u64, log: &Path, addresses: &BTreeMap<u64, SocketAddr>) -> Server {
+    InconsistentReplicationServer::new(
+        TcpNetwork::new(addresses.clone()),
+        LogStorage::open(log, MEMBERS.to_vec()).unwrap(),
+        TapirReplica::new(),
+        node,
+    )
+    .await
+}
+
+fn serve(listener: TcpListener, server: Server) -> JoinHandle<std::io::Result<()>> {
+    tokio::spawn(tcp::serve(listener, Arc::new(server)))
+}
+
+/// Bootstrap and serve every member
+async fn start_cluster(
+    data: &DataDir,
+) -> (
+    BTreeMap<u64, JoinHandle<std::io::Result<()>>>,
+    BTreeMap<u64, SocketAddr>,
+) {
+    let (listeners, addresses) = listeners().await;
+    let mut nodes = BTreeMap::new();
+    for (node, listener) in listeners {
+        let server = open_node(node, &data.log(node), &addresses).await;
+        server.bootstrap(MEMBERS.to_vec()).await.unwrap();
+        nodes.insert(node, serve(listener, server));
+    }
+    (nodes, addresses)
+}
+
+async fn client(addresses: &BTreeMap<u64, SocketAddr>, client_number: u64) -> Client {
+    let client = InconsistentReplicationClient::new(
+        TcpNetwork::new(addresses.clone()),
+        MembersClientStorage::new(MEMBERS.to_vec()),
+        100 + client_number,
+    )
+    .await;
+    TapirClient::new(
+        client,
+        client_number,
+        HybridLogicalClock::new(SystemTimeSource),
+    )
+}
+
+async fn put(client: &Client, key: &str, value: &str) {
+    let mut transaction = client.begin();
+    transaction.write(key.to_string(), value.to_string());
+    client.commit(transaction).await.unwrap();
+}
+
+async fn get(client: &Client, key: &str) -> Option<String> {
+    let mut transaction = client.begin();
+    client
+        .read(&mut transaction, key.to_string())
+        .await
+        .unwrap()
+}
+
+/// Write `new` if the key holds `expected`
+async fn cas(client: &Client, key: &str, expected: &str, new: &str) -> bool {
+    let mut transaction = client.begin();
+    let current = client
+        .read(&mut transaction, key.to_string())
+        .await
+        .unwrap();
+    if current.as_deref() != Some(expected) {
+        return false;
+    }
+    transaction.write(key.to_string(), new.to_string());
+    client.commit(transaction).await.is_ok()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_share_a_store_over_tcp() {
+    // given a bootstrapped cluster served over TCP
+    let data = DataDir::new();
+    let (_nodes, addresses) = start_cluster(&data).await;
+    let alice = client(&addresses, 1).await;
+    let bob = client(&addresses, 2).await;
+
+    // when one client writes and compares-and-sets a key
+    put(&alice, "account", "10").await;
+    let swapped = cas(&alice, "account", "10", "20").await;
+    let stale = cas(&alice, "account", "10", "30").await;
+
+    // then only the first compare-and-set applies, and another client reads its value
+    assert!(swapped);
+    assert!(!stale);
+    assert_eq!(get(&bob, "account").await, Some("20".to_string()));
+    assert_eq!(get(&bob, "missing").await, None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn restarted_node_recovers_from_its_log() {
+    // given a cluster that has committed a write
+    let data = DataDir::new();
+    let (mut nodes, addresses) = start_cluster(&data).await;
+    let first = client(&addresses, 1).await;
+    put(&first, "account", "10").await;
+    drop(first);
+
+    // when a node stops and is started again on its log
+    let stopped = nodes.remove(&3).unwrap();
+    stopped.abort();
+    let _ = stopped.await;
+    let restarted = open_node(3, &data.log(3), &addresses).await;
+
+    // then it keeps its view rather than bootstrapping again, and refuses operations until a
+    // view change brings it back
+    let Err(BootstrapError::ExistingState(view)) = restarted.bootstrap(MEMBERS.to_vec()).await
+    else {
+        panic!("a node with a log must not bootstrap");
+    };
+    assert_eq!((view.view, view.state), (0, ViewState::Recovery));
+    let refused = restarted
+        .propose_inconsistent(
+            100,
+            0,
+            TapirMessage::Abort {
+                id: TransactionId {
+                    client: 0,
+                    sequence: 0,
+                },
+            },
+            None,
+        )
+        .await;
+    assert!(matches!(refused, Err(IRServerError::Recovering(_))));
+
+    // and the others keep serving with it
+    let listener = TcpListener::bind(addresses[&3]).await.unwrap();
+    let _restarted = serve(listener, restarted);
+    let second = client(&addresses, 2).await;
+    assert!(cas(&second, "account", "10", "20").await);
+    assert_eq!(get(&second, "account").await, Some("20".to_string()));
+}